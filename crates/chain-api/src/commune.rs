@@ -0,0 +1,564 @@
+//! Modules of a Commune subnet and a validator's weights on it, through
+//! the python chain scripts.
+//!
+//! The scripts print JSON; nothing about it is trusted. Every module is
+//! validated as it is deserialized, so a [`CommuneModule`] always carries
+//! a decodable SS58 address, a name and UTF-8 metadata.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+use crate::ss58;
+use crate::weights::{WeightPayload, WeightsChain, WeightsError};
+
+/// Default bound on one chain script run, including connecting to the
+/// node.
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default number of chain scripts run at once.
+pub const DEFAULT_MAX_CONCURRENT_SCRIPTS: usize = 4;
+
+/// Errors produced while reading chain script output.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CommuneError {
+    /// The output is not valid JSON or a module in it is malformed.
+    #[error("Failed to deserialize Commune output: {0}")]
+    DeserializationFailed(String),
+
+    /// The script did not finish in time, usually because the node is
+    /// unreachable. The script was killed.
+    #[error("Commune script timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// The key used lacks the permission or balance the call needs.
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    /// The node could not be reached.
+    #[error("Network unreachable: {0}")]
+    NetworkUnreachable(String),
+
+    /// The subnet, module or key asked for does not exist.
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// The script exited unsuccessfully without reporting a recognized
+    /// error.
+    #[error("Commune script failed ({status}): {stderr}")]
+    ScriptFailed { status: String, stderr: String },
+
+    /// The script could not be run.
+    #[error("Failed to run Commune script: {0}")]
+    Io(String),
+}
+
+impl From<std::io::Error> for CommuneError {
+    fn from(err: std::io::Error) -> Self {
+        CommuneError::Io(err.to_string())
+    }
+}
+
+/// A module registered on a Commune subnet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawCommuneModule")]
+pub struct CommuneModule {
+    /// Subnet the module is registered on.
+    pub netuid: u16,
+    /// Uid of the module within the subnet.
+    pub uid: u16,
+    /// SS58 address of the module's key.
+    pub key: String,
+    pub name: String,
+    /// Total stake, in the chain's smallest unit.
+    pub stake: u64,
+    #[serde(default)]
+    pub metadata: Option<String>,
+}
+
+/// Metadata as printed by the scripts: a string, or the raw bytes when
+/// python could not decode them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMetadata {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+/// A module as printed by the scripts, before validation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCommuneModule {
+    netuid: u16,
+    uid: u16,
+    key: String,
+    name: String,
+    stake: u64,
+    #[serde(default)]
+    metadata: Option<RawMetadata>,
+}
+
+impl TryFrom<RawCommuneModule> for CommuneModule {
+    type Error = String;
+
+    fn try_from(raw: RawCommuneModule) -> Result<Self, Self::Error> {
+        if let Err(e) = ss58::validate_address(&raw.key) {
+            return Err(format!(
+                "module {} has invalid key {:?}: {}",
+                raw.uid, raw.key, e
+            ));
+        }
+        if raw.name.trim().is_empty() {
+            return Err(format!("module {} has an empty name", raw.uid));
+        }
+        let metadata = match raw.metadata {
+            None => None,
+            Some(RawMetadata::Text(text)) => Some(text),
+            Some(RawMetadata::Bytes(bytes)) => Some(String::from_utf8(bytes).map_err(|e| {
+                format!("module {} has metadata that is not UTF-8: {}", raw.uid, e)
+            })?),
+        };
+        Ok(Self {
+            netuid: raw.netuid,
+            uid: raw.uid,
+            key: raw.key,
+            name: raw.name,
+            stake: raw.stake,
+            metadata,
+        })
+    }
+}
+
+impl CommuneModule {
+    /// Parses one module from script output.
+    pub fn from_json(json: &str) -> Result<Self, CommuneError> {
+        serde_json::from_str(json).map_err(|e| CommuneError::DeserializationFailed(e.to_string()))
+    }
+}
+
+/// Parses a list of modules from script output.
+pub fn parse_modules(json: &str) -> Result<Vec<CommuneModule>, CommuneError> {
+    serde_json::from_str(json).map_err(|e| CommuneError::DeserializationFailed(e.to_string()))
+}
+
+/// Error a script reports on stderr when it fails, as the last line of
+/// output, e.g. `{"error_type": "permission_denied", "message": "..."}`.
+#[derive(Deserialize)]
+struct ScriptError {
+    error_type: String,
+    message: String,
+}
+
+/// Turns the stderr of a failed script into an error. Structured errors
+/// map to their variant; unknown error types and plain text, as printed by
+/// older scripts, are kept verbatim in [`CommuneError::ScriptFailed`].
+fn parse_script_error(status: String, stderr: &str) -> CommuneError {
+    let stderr = stderr.trim();
+    let reported = stderr
+        .lines()
+        .last()
+        .and_then(|line| serde_json::from_str::<ScriptError>(line).ok());
+    match reported {
+        Some(e) => match e.error_type.as_str() {
+            "permission_denied" => CommuneError::PermissionDenied(e.message),
+            "network_unreachable" => CommuneError::NetworkUnreachable(e.message),
+            "not_found" => CommuneError::NotFound(e.message),
+            other => CommuneError::ScriptFailed {
+                status,
+                stderr: format!("{}: {}", other, e.message),
+            },
+        },
+        None => CommuneError::ScriptFailed {
+            status,
+            stderr: stderr.to_string(),
+        },
+    }
+}
+
+/// Queries a Commune node by running the python chain script. Clones
+/// share the bound on scripts run at once.
+#[derive(Debug, Clone)]
+pub struct CommuneRpc {
+    interpreter: PathBuf,
+    script: PathBuf,
+    node_url: String,
+    timeout: Duration,
+    /// One permit per script allowed to run at once.
+    scripts: Arc<Semaphore>,
+}
+
+impl CommuneRpc {
+    /// Runs `script` with `python3` against the node at `node_url`.
+    pub fn new(script: impl Into<PathBuf>, node_url: impl Into<String>) -> Self {
+        Self {
+            interpreter: PathBuf::from("python3"),
+            script: script.into(),
+            node_url: node_url.into(),
+            timeout: DEFAULT_RPC_TIMEOUT,
+            scripts: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_SCRIPTS)),
+        }
+    }
+
+    /// Sets the program the script is run with.
+    pub fn with_interpreter(mut self, interpreter: impl Into<PathBuf>) -> Self {
+        self.interpreter = interpreter.into();
+        self
+    }
+
+    /// Sets how long one script run may take, connecting to the node
+    /// included, before it is killed.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many scripts may run at once; further calls wait for one
+    /// to finish. The timeout only starts once a call's script is spawned.
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.scripts = Arc::new(Semaphore::new(max.max(1)));
+        self
+    }
+
+    /// Lists the modules registered on `netuid`.
+    pub async fn modules(&self, netuid: u16) -> Result<Vec<CommuneModule>, CommuneError> {
+        let output = self
+            .run(&["modules", "--netuid", &netuid.to_string()])
+            .await?;
+        parse_modules(&output)
+    }
+
+    /// Runs the script with `args` and returns what it printed.
+    async fn run(&self, args: &[&str]) -> Result<String, CommuneError> {
+        let _permit = self
+            .scripts
+            .acquire()
+            .await
+            .expect("script semaphore is never closed");
+        let mut child = Command::new(&self.interpreter)
+            .arg(&self.script)
+            .arg("--url")
+            .arg(&self.node_url)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        // Read both pipes while waiting so a chatty script cannot block on
+        // a full pipe.
+        let finished = tokio::time::timeout(self.timeout, async {
+            tokio::try_join!(
+                child.wait(),
+                stdout.read_to_end(&mut out),
+                stderr.read_to_end(&mut err)
+            )
+        })
+        .await;
+        let status = match finished {
+            Ok(result) => result?.0,
+            Err(_) => {
+                // Kills the script and waits for it, so it is not left
+                // behind as a zombie.
+                if let Err(e) = child.kill().await {
+                    tracing::warn!("Failed to kill timed out Commune script: {}", e);
+                }
+                return Err(CommuneError::Timeout(self.timeout));
+            }
+        };
+        if !status.success() {
+            return Err(parse_script_error(
+                status.to_string(),
+                &String::from_utf8_lossy(&err),
+            ));
+        }
+        String::from_utf8(out)
+            .map_err(|e| CommuneError::DeserializationFailed(format!("output is not UTF-8: {}", e)))
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+    values.join(",")
+}
+
+/// Reads and sets a validator's weights through the chain script, which
+/// signs with the named key from its keystore.
+#[derive(Debug, Clone)]
+pub struct CommuneWeights {
+    rpc: CommuneRpc,
+    key: String,
+}
+
+impl CommuneWeights {
+    /// Sets weights as the validator whose key is named `key`.
+    pub fn new(rpc: CommuneRpc, key: impl Into<String>) -> Self {
+        Self {
+            rpc,
+            key: key.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl WeightsChain for CommuneWeights {
+    async fn current_weights(&self, netuid: u16) -> Result<BTreeMap<u16, u16>, WeightsError> {
+        let output = self
+            .rpc
+            .run(&[
+                "weights",
+                "--netuid",
+                &netuid.to_string(),
+                "--key",
+                &self.key,
+            ])
+            .await
+            .map_err(|e| WeightsError::Chain(e.to_string()))?;
+        serde_json::from_str(&output)
+            .map_err(|e| WeightsError::Chain(format!("Failed to deserialize weights: {}", e)))
+    }
+
+    async fn submit_weights(&self, payload: &WeightPayload) -> Result<(), WeightsError> {
+        self.rpc
+            .run(&[
+                "set-weights",
+                "--netuid",
+                &payload.netuid.to_string(),
+                "--key",
+                &self.key,
+                "--uids",
+                &join(&payload.uids),
+                "--weights",
+                &join(&payload.weights),
+            ])
+            .await
+            .map_err(|e| WeightsError::Chain(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> String {
+        ss58::encode(&[7; ss58::PUBLIC_KEY_LEN], ss58::DEFAULT_PREFIX)
+    }
+
+    #[test]
+    fn test_valid_modules_parse() {
+        let json = format!(
+            r#"[{{"netuid": 3, "uid": 0, "key": "{}", "name": "echo", "stake": 1000,
+                 "metadata": [104, 105]}}]"#,
+            address()
+        );
+
+        let modules = parse_modules(&json).unwrap();
+
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].key, address());
+        assert_eq!(modules[0].metadata.as_deref(), Some("hi"));
+    }
+
+    #[test]
+    fn test_malformed_modules_are_rejected() {
+        let address = address();
+        let cases = [
+            (
+                r#"{"netuid": 3, "uid": 1, "key": "5Gnot-an-address", "name": "echo", "stake": 1}"#
+                    .to_string(),
+                "invalid key",
+            ),
+            (
+                format!(
+                    r#"{{"netuid": 3, "uid": 1, "key": "{}", "name": " ", "stake": 1}}"#,
+                    address
+                ),
+                "empty name",
+            ),
+            (
+                format!(
+                    r#"{{"netuid": 3, "uid": 1, "key": "{}", "name": "echo", "stake": -5}}"#,
+                    address
+                ),
+                "invalid value: integer `-5`",
+            ),
+            (
+                format!(
+                    r#"{{"netuid": 3, "uid": 1, "key": "{}", "name": "echo", "stake": 1,
+                         "metadata": [255, 254]}}"#,
+                    address
+                ),
+                "not UTF-8",
+            ),
+        ];
+
+        for (json, expected) in cases {
+            match CommuneModule::from_json(&json) {
+                Err(CommuneError::DeserializationFailed(message)) => {
+                    assert!(message.contains(expected), "{}: {}", expected, message)
+                }
+                other => panic!("{}: unexpected {:?}", expected, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_structured_script_errors_are_parsed() {
+        let stderr = "Traceback (most recent call last):\n  ...\n\
+            {\"error_type\": \"permission_denied\", \"message\": \"key is not registered\"}\n";
+        assert_eq!(
+            parse_script_error("exit status: 1".into(), stderr),
+            CommuneError::PermissionDenied("key is not registered".into())
+        );
+
+        assert_eq!(
+            parse_script_error("exit status: 1".into(), "Permission denied\n"),
+            CommuneError::ScriptFailed {
+                status: "exit status: 1".into(),
+                stderr: "Permission denied".into(),
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    fn script(dir: &std::path::Path, body: &str) -> PathBuf {
+        let path = dir.join("commune.sh");
+        std::fs::write(&path, body).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unreachable_node_times_out_and_script_is_reaped() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        // Stands in for a script stuck connecting to a node that never
+        // answers.
+        let script = script(
+            dir.path(),
+            &format!("echo $$ > {}\nexec sleep 30\n", pid_file.display()),
+        );
+        let rpc = CommuneRpc::new(script, "ws://10.255.255.1:9944")
+            .with_interpreter("sh")
+            .with_timeout(Duration::from_millis(300));
+
+        let start = std::time::Instant::now();
+        let result = rpc.modules(3).await;
+
+        assert_eq!(
+            result,
+            Err(CommuneError::Timeout(Duration::from_millis(300)))
+        );
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            start.elapsed()
+        );
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        assert!(
+            !std::path::Path::new(&format!("/proc/{}", pid.trim())).exists(),
+            "script {} was not reaped",
+            pid.trim()
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_concurrent_scripts_never_exceed_the_bound() {
+        let dir = tempfile::tempdir().unwrap();
+        let running = dir.path().join("running");
+        let counts = dir.path().join("counts");
+        std::fs::create_dir(&running).unwrap();
+        // Each run notes how many runs are in progress, itself included.
+        let script = script(
+            dir.path(),
+            &format!(
+                "touch {running}/$$\nls {running} | wc -l >> {counts}\nsleep 0.1\nrm {running}/$$\necho '[]'\n",
+                running = running.display(),
+                counts = counts.display()
+            ),
+        );
+        let rpc = CommuneRpc::new(script, "ws://127.0.0.1:9944")
+            .with_interpreter("sh")
+            .with_max_concurrency(3);
+
+        let handles: Vec<_> = (0..12)
+            .map(|netuid| {
+                let rpc = rpc.clone();
+                tokio::spawn(async move { rpc.modules(netuid).await })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), Ok(Vec::new()));
+        }
+
+        let counts: Vec<usize> = std::fs::read_to_string(&counts)
+            .unwrap()
+            .lines()
+            .map(|line| line.trim().parse().unwrap())
+            .collect();
+        assert_eq!(counts.len(), 12);
+        assert!(counts.iter().all(|&n| n <= 3), "{:?}", counts);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failed_script_is_not_a_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(dir.path(), "echo 'connection refused' >&2\nexit 2\n");
+        let rpc = CommuneRpc::new(script, "ws://127.0.0.1:1").with_interpreter("sh");
+
+        match rpc.modules(3).await {
+            Err(CommuneError::ScriptFailed { stderr, .. }) => {
+                assert_eq!(stderr, "connection refused")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_weights_set_and_read_with_the_configured_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls = dir.path().join("calls");
+        let script = script(
+            dir.path(),
+            &format!(
+                "echo \"$@\" >> {}\necho '{{\"1\": 65535, \"4\": 100}}'\n",
+                calls.display()
+            ),
+        );
+        let rpc = CommuneRpc::new(script, "ws://127.0.0.1:9944").with_interpreter("sh");
+        let chain = CommuneWeights::new(rpc, "validator");
+
+        let payload = WeightPayload {
+            netuid: 7,
+            uids: vec![1, 2],
+            weights: vec![65535, 32768],
+        };
+        chain.submit_weights(&payload).await.unwrap();
+        assert_eq!(
+            chain.current_weights(7).await.unwrap(),
+            BTreeMap::from([(1, 65535), (4, 100)])
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(&calls).unwrap(),
+            "--url ws://127.0.0.1:9944 set-weights --netuid 7 --key validator \
+             --uids 1,2 --weights 65535,32768\n\
+             --url ws://127.0.0.1:9944 weights --netuid 7 --key validator\n"
+        );
+    }
+}
@@ -0,0 +1,541 @@
+//! Encrypted on-disk keystore for signing keys.
+//!
+//! Each key is stored as a JSON file named `<name>.json`. The ed25519 secret
+//! is encrypted with ChaCha20-Poly1305 under a key derived from the user's
+//! password with scrypt; the public key is stored in the clear and bound to
+//! the ciphertext as associated data.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Current key file format version.
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const KDF_SCRYPT: &str = "scrypt";
+const CIPHER_CHACHA20POLY1305: &str = "chacha20poly1305";
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Errors produced by keystore operations.
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+
+    #[error("Key already exists: {0}")]
+    KeyExists(String),
+
+    #[error("Invalid key name: {0}")]
+    InvalidName(String),
+
+    #[error("File already exists: {}", .0.display())]
+    FileExists(PathBuf),
+
+    #[error("Wrong password or corrupted key file")]
+    Decryption,
+
+    #[error("Invalid key file: {0}")]
+    InvalidFormat(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Creates `path` readable and writable only by its owner and writes
+/// `contents` to it. Fails if the file already exists, so an existing file
+/// or a link planted at `path` is never written through.
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+/// scrypt cost parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfCost {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+/// Largest scrypt `log_n` a key file may ask for.
+pub const MAX_KDF_LOG_N: u8 = 20;
+
+/// Largest scrypt `r` a key file may ask for.
+pub const MAX_KDF_R: u32 = 32;
+
+/// Largest scrypt `p` a key file may ask for.
+pub const MAX_KDF_P: u32 = 16;
+
+/// Most memory deriving a key may take, 1 GiB.
+pub const MAX_KDF_MEMORY_BYTES: u64 = 1 << 30;
+
+impl KdfCost {
+    /// Checks that deriving a key with this cost takes bounded time and
+    /// memory, so a crafted key file cannot exhaust either.
+    pub fn validate(&self) -> Result<(), KeystoreError> {
+        if self.log_n > MAX_KDF_LOG_N || self.r > MAX_KDF_R || self.p > MAX_KDF_P {
+            return Err(KeystoreError::InvalidFormat(format!(
+                "scrypt cost log_n={} r={} p={} exceeds log_n={} r={} p={}",
+                self.log_n, self.r, self.p, MAX_KDF_LOG_N, MAX_KDF_R, MAX_KDF_P
+            )));
+        }
+        // scrypt holds 128 * r * 2^log_n bytes at once.
+        let memory = (128 * u64::from(self.r)) << self.log_n;
+        if memory > MAX_KDF_MEMORY_BYTES {
+            return Err(KeystoreError::InvalidFormat(format!(
+                "scrypt cost needs {} bytes of memory, more than {}",
+                memory, MAX_KDF_MEMORY_BYTES
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KdfCost {
+    fn default() -> Self {
+        Self {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+/// scrypt parameters stored alongside the ciphertext.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    #[serde(flatten)]
+    pub cost: KdfCost,
+    /// Hex-encoded salt.
+    pub salt: String,
+}
+
+/// On-disk representation of an encrypted key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyFile {
+    pub version: u32,
+    pub name: String,
+    /// Hex-encoded ed25519 public key.
+    pub public_key: String,
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+    pub cipher: String,
+    /// Hex-encoded nonce.
+    pub nonce: String,
+    /// Hex-encoded encrypted secret key.
+    pub ciphertext: String,
+}
+
+fn derive_key(password: &str, salt: &[u8], cost: KdfCost) -> Result<[u8; 32], KeystoreError> {
+    let params = scrypt::Params::new(cost.log_n, cost.r, cost.p, 32)
+        .map_err(|e| KeystoreError::InvalidFormat(format!("bad scrypt parameters: {}", e)))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)
+        .map_err(|e| KeystoreError::InvalidFormat(format!("bad scrypt parameters: {}", e)))?;
+    Ok(key)
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    hex::decode(value)
+        .map_err(|_| KeystoreError::InvalidFormat(format!("{} is not valid hex", field)))
+}
+
+impl KeyFile {
+    /// Encrypts `signing_key` under `password`.
+    pub fn encrypt(
+        name: &str,
+        signing_key: &SigningKey,
+        password: &str,
+        cost: KdfCost,
+    ) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let public_key = signing_key.verifying_key().to_bytes();
+        let key = derive_key(password, &salt, cost)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: signing_key.as_bytes(),
+                    aad: &public_key,
+                },
+            )
+            .map_err(|_| KeystoreError::Decryption)?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            name: name.to_string(),
+            public_key: hex::encode(public_key),
+            kdf: KDF_SCRYPT.to_string(),
+            kdf_params: KdfParams {
+                cost,
+                salt: hex::encode(salt),
+            },
+            cipher: CIPHER_CHACHA20POLY1305.to_string(),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Checks that the file uses a supported format.
+    pub fn validate(&self) -> Result<(), KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::InvalidFormat(format!(
+                "unsupported version {}",
+                self.version
+            )));
+        }
+        if self.kdf != KDF_SCRYPT {
+            return Err(KeystoreError::InvalidFormat(format!(
+                "unsupported kdf {}",
+                self.kdf
+            )));
+        }
+        if self.cipher != CIPHER_CHACHA20POLY1305 {
+            return Err(KeystoreError::InvalidFormat(format!(
+                "unsupported cipher {}",
+                self.cipher
+            )));
+        }
+        self.kdf_params.cost.validate()?;
+        self.verifying_key()?;
+        Ok(())
+    }
+
+    /// Returns the public key stored in the file.
+    pub fn verifying_key(&self) -> Result<VerifyingKey, KeystoreError> {
+        let bytes: [u8; 32] = decode_hex("public_key", &self.public_key)?
+            .try_into()
+            .map_err(|_| KeystoreError::InvalidFormat("public_key must be 32 bytes".into()))?;
+        VerifyingKey::from_bytes(&bytes)
+            .map_err(|_| KeystoreError::InvalidFormat("public_key is not a valid key".into()))
+    }
+
+    /// Decrypts the secret key with `password`.
+    pub fn decrypt(&self, password: &str) -> Result<SigningKey, KeystoreError> {
+        self.validate()?;
+        let salt = decode_hex("salt", &self.kdf_params.salt)?;
+        let nonce = decode_hex("nonce", &self.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(KeystoreError::InvalidFormat(
+                "nonce must be 12 bytes".into(),
+            ));
+        }
+        let ciphertext = decode_hex("ciphertext", &self.ciphertext)?;
+        let public_key = self.verifying_key()?;
+
+        let key = derive_key(password, &salt, self.kdf_params.cost)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let secret = cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: public_key.as_bytes(),
+                },
+            )
+            .map_err(|_| KeystoreError::Decryption)?;
+
+        let secret: [u8; 32] = secret.try_into().map_err(|_| KeystoreError::Decryption)?;
+        let signing_key = SigningKey::from_bytes(&secret);
+        if signing_key.verifying_key() != public_key {
+            return Err(KeystoreError::Decryption);
+        }
+        Ok(signing_key)
+    }
+}
+
+/// A directory of encrypted key files.
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    dir: PathBuf,
+    cost: KdfCost,
+}
+
+impl Keystore {
+    /// Opens the keystore rooted at `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            cost: KdfCost::default(),
+        }
+    }
+
+    /// Returns the default keystore location, `~/.synapse/keys`.
+    pub fn default_dir() -> PathBuf {
+        let home = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        home.join(".synapse").join("keys")
+    }
+
+    /// Sets the scrypt cost used for newly written keys.
+    pub fn with_kdf_cost(mut self, cost: KdfCost) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, KeystoreError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(KeystoreError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+
+    fn write_new(&self, file: &KeyFile) -> Result<(), KeystoreError> {
+        let path = self.path(&file.name)?;
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string_pretty(file)
+            .map_err(|e| KeystoreError::InvalidFormat(e.to_string()))?;
+        write_private(&path, &json).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => KeystoreError::KeyExists(file.name.clone()),
+            _ => e.into(),
+        })
+    }
+
+    /// Generates a new key and stores it encrypted under `password`.
+    pub fn generate(&self, name: &str, password: &str) -> Result<VerifyingKey, KeystoreError> {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let file = KeyFile::encrypt(name, &signing_key, password, self.cost)?;
+        self.write_new(&file)?;
+        Ok(signing_key.verifying_key())
+    }
+
+    /// Reads a key file without decrypting it.
+    pub fn load(&self, name: &str) -> Result<KeyFile, KeystoreError> {
+        let path = self.path(name)?;
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(KeystoreError::KeyNotFound(name.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let file: KeyFile =
+            serde_json::from_str(&json).map_err(|e| KeystoreError::InvalidFormat(e.to_string()))?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    /// Lists the names of stored keys.
+    pub fn list(&self) -> Result<Vec<String>, KeystoreError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(stem.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Writes the encrypted key file for `name` to `dest`, which must not
+    /// exist yet.
+    pub fn export(&self, name: &str, dest: &Path) -> Result<(), KeystoreError> {
+        let file = self.load(name)?;
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| KeystoreError::InvalidFormat(e.to_string()))?;
+        write_private(dest, &json).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => KeystoreError::FileExists(dest.to_path_buf()),
+            _ => e.into(),
+        })
+    }
+
+    /// Imports an exported key file, optionally under a different name.
+    /// Returns the name the key was stored under.
+    pub fn import(&self, src: &Path, name: Option<&str>) -> Result<String, KeystoreError> {
+        let json = fs::read_to_string(src)?;
+        let mut file: KeyFile =
+            serde_json::from_str(&json).map_err(|e| KeystoreError::InvalidFormat(e.to_string()))?;
+        file.validate()?;
+        if let Some(name) = name {
+            file.name = name.to_string();
+        }
+        self.write_new(&file)?;
+        Ok(file.name)
+    }
+
+    /// Decrypts the key `name` and signs `message` with it.
+    pub fn sign(
+        &self,
+        name: &str,
+        password: &str,
+        message: &[u8],
+    ) -> Result<Signature, KeystoreError> {
+        let signing_key = self.load(name)?.decrypt(password)?;
+        Ok(signing_key.sign(message))
+    }
+}
+
+/// Verifies a hex-encoded signature of `message` by a hex-encoded public key.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<bool, KeystoreError> {
+    let bytes: [u8; 32] = decode_hex("public key", public_key)?
+        .try_into()
+        .map_err(|_| KeystoreError::InvalidFormat("public key must be 32 bytes".into()))?;
+    let key = VerifyingKey::from_bytes(&bytes)
+        .map_err(|_| KeystoreError::InvalidFormat("public key is not a valid key".into()))?;
+    let sig: [u8; 64] = decode_hex("signature", signature)?
+        .try_into()
+        .map_err(|_| KeystoreError::InvalidFormat("signature must be 64 bytes".into()))?;
+    Ok(key.verify(message, &Signature::from_bytes(&sig)).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_COST: KdfCost = KdfCost {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn test_generate_export_import_sign() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Keystore::new(dir.path().join("source")).with_kdf_cost(TEST_COST);
+        let target = Keystore::new(dir.path().join("target")).with_kdf_cost(TEST_COST);
+
+        let public_key = source.generate("alice", "hunter2").unwrap();
+        let exported = dir.path().join("alice-export.json");
+        source.export("alice", &exported).unwrap();
+
+        let name = target.import(&exported, Some("alice-copy")).unwrap();
+        assert_eq!(name, "alice-copy");
+        assert_eq!(target.list().unwrap(), vec!["alice-copy"]);
+
+        let message = b"register module echo";
+        let original = source.sign("alice", "hunter2", message).unwrap();
+        let imported = target.sign("alice-copy", "hunter2", message).unwrap();
+        assert_eq!(original, imported);
+
+        let public_hex = hex::encode(public_key.to_bytes());
+        let sig_hex = hex::encode(imported.to_bytes());
+        assert!(verify(&public_hex, message, &sig_hex).unwrap());
+        assert!(!verify(&public_hex, b"tampered", &sig_hex).unwrap());
+    }
+
+    #[test]
+    fn test_wrong_password_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::new(dir.path()).with_kdf_cost(TEST_COST);
+        keystore.generate("bob", "correct").unwrap();
+
+        assert!(matches!(
+            keystore.sign("bob", "wrong", b"msg"),
+            Err(KeystoreError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn test_secret_not_stored_in_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::new(dir.path()).with_kdf_cost(TEST_COST);
+        keystore.generate("carol", "pw").unwrap();
+
+        let signing_key = keystore.load("carol").unwrap().decrypt("pw").unwrap();
+        let raw = fs::read_to_string(dir.path().join("carol.json")).unwrap();
+        assert!(!raw.contains(&hex::encode(signing_key.to_bytes())));
+    }
+
+    #[test]
+    fn test_existing_files_never_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::new(dir.path().join("keys")).with_kdf_cost(TEST_COST);
+        keystore.generate("dave", "pw").unwrap();
+        assert!(matches!(
+            keystore.generate("dave", "pw"),
+            Err(KeystoreError::KeyExists(name)) if name == "dave"
+        ));
+
+        let dest = dir.path().join("dave-export.json");
+        fs::write(&dest, "keep me").unwrap();
+        assert!(matches!(
+            keystore.export("dave", &dest),
+            Err(KeystoreError::FileExists(path)) if path == dest
+        ));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "keep me");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_files_private_to_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::new(dir.path().join("keys")).with_kdf_cost(TEST_COST);
+        keystore.generate("erin", "pw").unwrap();
+        let dest = dir.path().join("erin-export.json");
+        keystore.export("erin", &dest).unwrap();
+
+        for path in [dir.path().join("keys").join("erin.json"), dest] {
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_excessive_kdf_cost_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::new(dir.path()).with_kdf_cost(TEST_COST);
+        keystore.generate("frank", "pw").unwrap();
+        let path = dir.path().join("frank.json");
+        let mut file: KeyFile = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        for cost in [
+            KdfCost {
+                log_n: 40,
+                ..TEST_COST
+            },
+            KdfCost {
+                r: 1 << 20,
+                ..TEST_COST
+            },
+            KdfCost {
+                p: u32::MAX,
+                ..TEST_COST
+            },
+            KdfCost {
+                log_n: MAX_KDF_LOG_N,
+                r: MAX_KDF_R,
+                p: 1,
+            },
+        ] {
+            file.kdf_params.cost = cost;
+            assert!(
+                matches!(file.decrypt("pw"), Err(KeystoreError::InvalidFormat(_))),
+                "{:?}",
+                cost
+            );
+        }
+        assert_eq!(KdfCost::default().validate().unwrap(), ());
+    }
+}
@@ -1,7 +1,12 @@
 //! Chain API implementation for the Synapse Subnet project.
-//! 
+//!
 //! This crate provides the blockchain integration interface for the subnet.
 
+pub mod commune;
+pub mod keystore;
+pub mod ss58;
+pub mod weights;
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,177 @@
+//! SS58 address encoding, as used by Substrate chains for account keys.
+//!
+//! An address is the base58 encoding of a network prefix, a 32-byte public
+//! key and a two-byte checksum: the start of the BLAKE2b-512 hash of
+//! `"SS58PRE"`, the prefix and the key. The key, or account id, of an
+//! ed25519 account is its public key.
+//!
+//! This is the one implementation of addresses in the workspace; anything
+//! that accepts an address validates it here.
+
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::SigningKey;
+use thiserror::Error;
+
+/// Generic Substrate network prefix, used by the subnet.
+pub const DEFAULT_PREFIX: u16 = 42;
+
+/// Length of the public key carried by an address.
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+const CHECKSUM_LEN: usize = 2;
+const CHECKSUM_PREAMBLE: &[u8] = b"SS58PRE";
+/// Largest prefix that can be encoded; prefixes of 64 and above take two
+/// bytes.
+const MAX_PREFIX: u16 = 16383;
+
+/// Errors produced while decoding an address.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum Ss58Error {
+    #[error("Invalid base58: {0}")]
+    InvalidBase58(String),
+
+    /// The address does not carry a 32-byte public key.
+    #[error("Invalid address length: {0} bytes")]
+    InvalidLength(usize),
+
+    /// The prefix is reserved or out of range.
+    #[error("Invalid network prefix")]
+    InvalidPrefix,
+
+    #[error("Invalid checksum")]
+    InvalidChecksum,
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = Blake2b512::new()
+        .chain_update(CHECKSUM_PREAMBLE)
+        .chain_update(data)
+        .finalize();
+    [hash[0], hash[1]]
+}
+
+fn encode_prefix(prefix: u16) -> Vec<u8> {
+    if prefix < 64 {
+        vec![prefix as u8]
+    } else {
+        vec![
+            ((prefix & 0b1111_1100) >> 2) as u8 | 0b0100_0000,
+            ((prefix >> 8) as u8) | ((prefix & 0b11) << 6) as u8,
+        ]
+    }
+}
+
+/// Encodes `public_key` as an address on the network with `prefix`.
+///
+/// # Panics
+///
+/// Panics if `prefix` is above 16383, the largest encodable prefix.
+pub fn encode(public_key: &[u8; PUBLIC_KEY_LEN], prefix: u16) -> String {
+    assert!(prefix <= MAX_PREFIX, "SS58 prefix {} out of range", prefix);
+    let mut data = encode_prefix(prefix);
+    data.extend_from_slice(public_key);
+    let checksum = checksum(&data);
+    data.extend_from_slice(&checksum);
+    bs58::encode(data).into_string()
+}
+
+/// Decodes an address into its network prefix and public key, verifying
+/// its checksum.
+pub fn decode(address: &str) -> Result<(u16, [u8; PUBLIC_KEY_LEN]), Ss58Error> {
+    let data = bs58::decode(address)
+        .into_vec()
+        .map_err(|e| Ss58Error::InvalidBase58(e.to_string()))?;
+    let (prefix, prefix_len) = match data.first() {
+        Some(&b) if b < 64 => (u16::from(b), 1),
+        Some(&b) if b < 128 => {
+            let second = *data.get(1).ok_or(Ss58Error::InvalidLength(data.len()))?;
+            let lower = (b << 2) | (second >> 6);
+            let upper = second & 0b0011_1111;
+            (u16::from(lower) | (u16::from(upper) << 8), 2)
+        }
+        Some(_) => return Err(Ss58Error::InvalidPrefix),
+        None => return Err(Ss58Error::InvalidLength(0)),
+    };
+    if data.len() != prefix_len + PUBLIC_KEY_LEN + CHECKSUM_LEN {
+        return Err(Ss58Error::InvalidLength(data.len()));
+    }
+    let (body, check) = data.split_at(prefix_len + PUBLIC_KEY_LEN);
+    if checksum(body) != check {
+        return Err(Ss58Error::InvalidChecksum);
+    }
+    let mut public_key = [0; PUBLIC_KEY_LEN];
+    public_key.copy_from_slice(&body[prefix_len..]);
+    Ok((prefix, public_key))
+}
+
+/// Checks that `address` is well formed and has a valid checksum.
+pub fn validate_address(address: &str) -> Result<(), Ss58Error> {
+    decode(address).map(|_| ())
+}
+
+/// Whether `address` is a well-formed address with a valid checksum.
+pub fn is_valid(address: &str) -> bool {
+    validate_address(address).is_ok()
+}
+
+/// Account id of the ed25519 key with the given 32-byte secret seed.
+pub fn account_id_from_seed(seed: &[u8; 32]) -> [u8; PUBLIC_KEY_LEN] {
+    SigningKey::from_bytes(seed).verifying_key().to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+    const ALICE_KEY: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+
+    #[test]
+    fn test_known_address_round_trips() {
+        let key: [u8; 32] = hex::decode(ALICE_KEY).unwrap().try_into().unwrap();
+        assert_eq!(encode(&key, DEFAULT_PREFIX), ALICE);
+        assert_eq!(decode(ALICE), Ok((DEFAULT_PREFIX, key)));
+
+        // Two-byte prefixes round-trip too.
+        let address = encode(&key, 1284);
+        assert_eq!(decode(&address), Ok((1284, key)));
+    }
+
+    #[test]
+    fn test_account_id_from_known_seeds() {
+        // Test vectors 1 and 2 of RFC 8032.
+        let pairs = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            ),
+        ];
+        for (seed, account_id) in pairs {
+            let seed: [u8; 32] = hex::decode(seed).unwrap().try_into().unwrap();
+            let id = account_id_from_seed(&seed);
+            assert_eq!(hex::encode(id), account_id);
+
+            let address = encode(&id, DEFAULT_PREFIX);
+            assert_eq!(validate_address(&address), Ok(()));
+            assert_eq!(decode(&address), Ok((DEFAULT_PREFIX, id)));
+        }
+    }
+
+    #[test]
+    fn test_malformed_addresses_rejected() {
+        let mut corrupted = ALICE.to_string();
+        corrupted.replace_range(47..48, "Z");
+        assert_eq!(decode(&corrupted), Err(Ss58Error::InvalidChecksum));
+        assert_eq!(decode(&ALICE[..40]), Err(Ss58Error::InvalidLength(29)));
+        assert!(matches!(decode("0OIl"), Err(Ss58Error::InvalidBase58(_))));
+        assert!(!is_valid(""));
+        assert_eq!(
+            validate_address(&corrupted),
+            Err(Ss58Error::InvalidChecksum)
+        );
+    }
+}
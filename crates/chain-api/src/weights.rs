@@ -0,0 +1,247 @@
+//! Setting a validator's weights on a subnet.
+//!
+//! Weights are given as non-negative floats per uid and submitted as
+//! integers scaled so the largest is [`MAX_WEIGHT`]. Building the payload
+//! is separate from submitting it, so [`simulate_set_weights`] can show
+//! exactly what [`set_weights`] would send.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Weight given to the highest-weighted uid.
+pub const MAX_WEIGHT: u16 = u16::MAX;
+
+/// Errors produced while setting weights.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum WeightsError {
+    #[error("{uids} uids given with {weights} weights")]
+    LengthMismatch { uids: usize, weights: usize },
+
+    #[error("Uid {0} is given more than once")]
+    DuplicateUid(u16),
+
+    /// A weight is negative or not finite.
+    #[error("Invalid weight {weight} for uid {uid}")]
+    InvalidWeight { uid: u16, weight: f64 },
+
+    #[error("No uid has a positive weight")]
+    AllZero,
+
+    /// The chain refused or failed the request.
+    #[error("Chain error: {0}")]
+    Chain(String),
+}
+
+/// Weights exactly as submitted on chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeightPayload {
+    pub netuid: u16,
+    /// Uids in ascending order; uids whose weight rounds to zero are left
+    /// out.
+    pub uids: Vec<u16>,
+    /// Weight of each uid in `uids`.
+    pub weights: Vec<u16>,
+}
+
+impl WeightPayload {
+    /// Normalizes `weights`, given per uid in `uids`, into a payload.
+    pub fn new(netuid: u16, uids: &[u16], weights: &[f64]) -> Result<Self, WeightsError> {
+        if uids.len() != weights.len() {
+            return Err(WeightsError::LengthMismatch {
+                uids: uids.len(),
+                weights: weights.len(),
+            });
+        }
+        let mut seen = BTreeSet::new();
+        for (&uid, &weight) in uids.iter().zip(weights) {
+            if !seen.insert(uid) {
+                return Err(WeightsError::DuplicateUid(uid));
+            }
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(WeightsError::InvalidWeight { uid, weight });
+            }
+        }
+        let max = weights.iter().cloned().fold(0.0, f64::max);
+        if max == 0.0 {
+            return Err(WeightsError::AllZero);
+        }
+        let scaled: BTreeMap<u16, u16> = uids
+            .iter()
+            .zip(weights)
+            .map(|(&uid, &weight)| (uid, (weight / max * f64::from(MAX_WEIGHT)).round() as u16))
+            .filter(|&(_, weight)| weight > 0)
+            .collect();
+        Ok(Self {
+            netuid,
+            uids: scaled.keys().copied().collect(),
+            weights: scaled.values().copied().collect(),
+        })
+    }
+
+    /// The payload's weights keyed by uid.
+    pub fn as_map(&self) -> BTreeMap<u16, u16> {
+        self.uids
+            .iter()
+            .copied()
+            .zip(self.weights.iter().copied())
+            .collect()
+    }
+}
+
+/// How a uid's weight would change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeightChange {
+    pub uid: u16,
+    /// Weight currently on chain; `None` when the uid has none.
+    pub current: Option<u16>,
+    /// Weight that would be submitted; `None` when it would be dropped.
+    pub proposed: Option<u16>,
+}
+
+/// What [`set_weights`] would do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeightSimulation {
+    /// Payload that would be submitted.
+    pub payload: WeightPayload,
+    /// Uids whose weight would change, in ascending order.
+    pub changes: Vec<WeightChange>,
+}
+
+/// The chain calls needed to set weights.
+#[async_trait]
+pub trait WeightsChain: Send + Sync {
+    /// Weights currently set by this validator on `netuid`.
+    async fn current_weights(&self, netuid: u16) -> Result<BTreeMap<u16, u16>, WeightsError>;
+
+    /// Submits `payload` in a transaction.
+    async fn submit_weights(&self, payload: &WeightPayload) -> Result<(), WeightsError>;
+}
+
+/// Normalizes and submits weights, returning the payload sent.
+pub async fn set_weights(
+    chain: &dyn WeightsChain,
+    netuid: u16,
+    uids: &[u16],
+    weights: &[f64],
+) -> Result<WeightPayload, WeightsError> {
+    let payload = WeightPayload::new(netuid, uids, weights)?;
+    chain.submit_weights(&payload).await?;
+    Ok(payload)
+}
+
+/// Does everything [`set_weights`] does except submit: returns the payload
+/// it would send and how it differs from the weights currently on chain.
+pub async fn simulate_set_weights(
+    chain: &dyn WeightsChain,
+    netuid: u16,
+    uids: &[u16],
+    weights: &[f64],
+) -> Result<WeightSimulation, WeightsError> {
+    let payload = WeightPayload::new(netuid, uids, weights)?;
+    let current = chain.current_weights(netuid).await?;
+    let proposed = payload.as_map();
+    let changes = current
+        .keys()
+        .chain(proposed.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|uid| WeightChange {
+            uid: *uid,
+            current: current.get(uid).copied(),
+            proposed: proposed.get(uid).copied(),
+        })
+        .filter(|change| change.current != change.proposed)
+        .collect();
+    Ok(WeightSimulation { payload, changes })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingChain {
+        current: BTreeMap<u16, u16>,
+        submitted: Mutex<Vec<WeightPayload>>,
+    }
+
+    #[async_trait]
+    impl WeightsChain for RecordingChain {
+        async fn current_weights(&self, _netuid: u16) -> Result<BTreeMap<u16, u16>, WeightsError> {
+            Ok(self.current.clone())
+        }
+
+        async fn submit_weights(&self, payload: &WeightPayload) -> Result<(), WeightsError> {
+            self.submitted.lock().unwrap().push(payload.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulated_payload_matches_submitted_payload() {
+        let chain = RecordingChain {
+            current: BTreeMap::from([(1, MAX_WEIGHT), (4, 100)]),
+            ..Default::default()
+        };
+        let uids = [3, 1, 2];
+        let weights = [0.5, 1.0, 0.0];
+
+        let simulation = simulate_set_weights(&chain, 7, &uids, &weights)
+            .await
+            .unwrap();
+        assert!(chain.submitted.lock().unwrap().is_empty());
+
+        let submitted = set_weights(&chain, 7, &uids, &weights).await.unwrap();
+        assert_eq!(simulation.payload, submitted);
+        assert_eq!(*chain.submitted.lock().unwrap(), vec![submitted]);
+
+        assert_eq!(simulation.payload.uids, vec![1, 3]);
+        assert_eq!(simulation.payload.weights, vec![MAX_WEIGHT, 32768]);
+        assert_eq!(
+            simulation.changes,
+            vec![
+                WeightChange {
+                    uid: 3,
+                    current: None,
+                    proposed: Some(32768),
+                },
+                WeightChange {
+                    uid: 4,
+                    current: Some(100),
+                    proposed: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_weights_rejected() {
+        assert_eq!(
+            WeightPayload::new(1, &[1, 2], &[1.0]),
+            Err(WeightsError::LengthMismatch {
+                uids: 2,
+                weights: 1
+            })
+        );
+        assert_eq!(
+            WeightPayload::new(1, &[1, 1], &[1.0, 1.0]),
+            Err(WeightsError::DuplicateUid(1))
+        );
+        assert_eq!(
+            WeightPayload::new(1, &[1], &[-1.0]),
+            Err(WeightsError::InvalidWeight {
+                uid: 1,
+                weight: -1.0
+            })
+        );
+        assert_eq!(
+            WeightPayload::new(1, &[1], &[0.0]),
+            Err(WeightsError::AllZero)
+        );
+    }
+}
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS miner_modules (
+    name TEXT PRIMARY KEY,
+    stake INTEGER NOT NULL,
+    active INTEGER NOT NULL,
+    registered_at TEXT NOT NULL
+);
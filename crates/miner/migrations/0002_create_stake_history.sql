@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS stake_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    module TEXT NOT NULL REFERENCES miner_modules(name) ON DELETE CASCADE,
+    stake INTEGER NOT NULL,
+    timestamp TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_stake_history_module_timestamp
+    ON stake_history(module, timestamp);
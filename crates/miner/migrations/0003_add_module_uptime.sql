@@ -0,0 +1,4 @@
+ALTER TABLE miner_modules ADD COLUMN started_at TEXT;
+ALTER TABLE miner_modules ADD COLUMN accumulated_uptime INTEGER NOT NULL DEFAULT 0;
+
+UPDATE miner_modules SET started_at = registered_at WHERE active = 1;
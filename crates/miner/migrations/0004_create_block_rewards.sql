@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS block_rewards (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    module TEXT NOT NULL REFERENCES miner_modules(name) ON DELETE CASCADE,
+    block INTEGER NOT NULL,
+    reward INTEGER NOT NULL,
+    timestamp TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_block_rewards_module_timestamp
+    ON block_rewards(module, timestamp);
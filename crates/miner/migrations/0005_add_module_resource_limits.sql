@@ -0,0 +1,2 @@
+ALTER TABLE miner_modules ADD COLUMN memory_bytes INTEGER;
+ALTER TABLE miner_modules ADD COLUMN cpu_millis INTEGER;
@@ -0,0 +1,550 @@
+//! HTTP API for the miner.
+
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::error::MinerError;
+use crate::inference::{InferenceRequest, InferenceResponse};
+use crate::service::{
+    BlockRecord, MetricsSnapshot, MinerMetrics, MinerService, MiningConfig, ModuleStatus,
+    RegisterRequest, StakeHistoryEntry, StakeUpdate,
+};
+
+/// Builds the miner router, mounted under `/api/miner`.
+pub fn create_router(service: MinerService) -> Router {
+    let routes = Router::new()
+        .route("/modules", get(list_modules).post(register_module))
+        .route("/modules/:name", get(get_module))
+        .route("/modules/:name/start", post(start_module))
+        .route("/modules/:name/stop", post(stop_module))
+        .route("/modules/:name/stake", put(update_stake))
+        .route("/modules/:name/stake/history", get(stake_history))
+        .route("/modules/:name/blocks", post(record_block))
+        .route("/modules/:name/infer", post(infer))
+        .route("/metrics/snapshot", get(metrics_snapshot))
+        .with_state(service);
+    Router::new().nest("/api/miner", routes)
+}
+
+/// `POST /api/miner/modules`
+async fn register_module(
+    State(service): State<MinerService>,
+    Json(request): Json<RegisterRequest>,
+) -> Result<(StatusCode, Json<ModuleStatus>), MinerError> {
+    let status = service.register(request).await?;
+    Ok((StatusCode::CREATED, Json(status)))
+}
+
+/// `GET /api/miner/modules`
+async fn list_modules(
+    State(service): State<MinerService>,
+) -> Result<Json<Vec<ModuleStatus>>, MinerError> {
+    Ok(Json(service.list().await?))
+}
+
+/// `GET /api/miner/modules/:name`
+async fn get_module(
+    State(service): State<MinerService>,
+    Path(name): Path<String>,
+) -> Result<Json<ModuleStatus>, MinerError> {
+    Ok(Json(service.status(&name).await?))
+}
+
+/// `POST /api/miner/modules/:name/start`, optionally with a
+/// [`MiningConfig`] body. An empty body starts with the defaults; a body
+/// that is not a valid config is rejected with 400 rather than ignored.
+async fn start_module(
+    State(service): State<MinerService>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<Json<ModuleStatus>, MinerError> {
+    let mining: MiningConfig = if body.trim_ascii().is_empty() {
+        MiningConfig::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| MinerError::InvalidRequest(format!("invalid mining config: {}", e)))?
+    };
+    Ok(Json(service.start_with(&name, &mining).await?))
+}
+
+/// `POST /api/miner/modules/:name/stop`
+async fn stop_module(
+    State(service): State<MinerService>,
+    Path(name): Path<String>,
+) -> Result<Json<ModuleStatus>, MinerError> {
+    Ok(Json(service.stop(&name).await?))
+}
+
+/// `PUT /api/miner/modules/:name/stake`
+async fn update_stake(
+    State(service): State<MinerService>,
+    Path(name): Path<String>,
+    Json(update): Json<StakeUpdate>,
+) -> Result<Json<ModuleStatus>, MinerError> {
+    Ok(Json(service.update_stake(&name, update).await?))
+}
+
+/// Query parameters for `GET /api/miner/modules/:name/stake/history`.
+#[derive(Debug, Default, Deserialize)]
+pub struct StakeHistoryParams {
+    /// Only return changes at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only return changes at or before this time.
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// `GET /api/miner/modules/:name/stake/history`
+async fn stake_history(
+    State(service): State<MinerService>,
+    Path(name): Path<String>,
+    Query(params): Query<StakeHistoryParams>,
+) -> Result<Json<Vec<StakeHistoryEntry>>, MinerError> {
+    Ok(Json(
+        service
+            .stake_history(&name, params.since, params.until)
+            .await?,
+    ))
+}
+
+/// `POST /api/miner/modules/:name/blocks`
+async fn record_block(
+    State(service): State<MinerService>,
+    Path(name): Path<String>,
+    Json(record): Json<BlockRecord>,
+) -> Result<(StatusCode, Json<MinerMetrics>), MinerError> {
+    let metrics = service.record_block(&name, record).await?;
+    Ok((StatusCode::CREATED, Json(metrics)))
+}
+
+/// `POST /api/miner/modules/:name/infer`
+async fn infer(
+    State(service): State<MinerService>,
+    Path(name): Path<String>,
+    Json(request): Json<InferenceRequest>,
+) -> Result<Json<InferenceResponse>, MinerError> {
+    Ok(Json(service.infer(&name, request).await?))
+}
+
+/// `GET /api/miner/metrics/snapshot`
+async fn metrics_snapshot(
+    State(service): State<MinerService>,
+) -> Result<Json<MetricsSnapshot>, MinerError> {
+    Ok(Json(service.metrics_snapshot().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::concurrency::{ConcurrencyConfig, SaturationPolicy};
+    use crate::db::MinerDb;
+    use crate::inference::InferenceBackend;
+    use crate::retry::{Priority, RetryPolicy};
+    use crate::service::{MinerConfig, ResourceLimits};
+    use async_trait::async_trait;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::{Notify, Semaphore};
+    use tower::ServiceExt;
+
+    async fn app(config: MinerConfig) -> Router {
+        let db = MinerDb::in_memory().await.unwrap();
+        create_router(MinerService::new(db, config))
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str, body: Option<Value>) -> StatusCode {
+        send(app, method, uri, body).await.0
+    }
+
+    async fn send(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let builder = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => builder
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+            None => builder.body(Body::empty()).unwrap(),
+        };
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_handlers_map_errors_to_status() {
+        let config = MinerConfig {
+            min_stake: 10,
+            max_modules: 1,
+            ..Default::default()
+        };
+        let app = app(config).await;
+
+        assert_eq!(
+            call(&app, "GET", "/api/miner/modules/missing", None).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            call(
+                &app,
+                "POST",
+                "/api/miner/modules",
+                Some(json!({"name": "a", "stake": 5}))
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            call(
+                &app,
+                "POST",
+                "/api/miner/modules",
+                Some(json!({"name": "a", "stake": 10}))
+            )
+            .await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            call(
+                &app,
+                "POST",
+                "/api/miner/modules",
+                Some(json!({"name": "b", "stake": 10}))
+            )
+            .await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            call(
+                &app,
+                "PUT",
+                "/api/miner/modules/missing/stake",
+                Some(json!({"stake": 10}))
+            )
+            .await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stake_history_records_updates() {
+        let app = app(MinerConfig::default()).await;
+        call(
+            &app,
+            "POST",
+            "/api/miner/modules",
+            Some(json!({"name": "a", "stake": 10})),
+        )
+        .await;
+
+        for stake in [20, 30] {
+            assert_eq!(
+                call(
+                    &app,
+                    "PUT",
+                    "/api/miner/modules/a/stake",
+                    Some(json!({"stake": stake}))
+                )
+                .await,
+                StatusCode::OK
+            );
+        }
+
+        let (status, body) = send(&app, "GET", "/api/miner/modules/a/stake/history", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let entries: Vec<StakeHistoryEntry> = serde_json::from_value(body).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.stake).collect::<Vec<_>>(),
+            vec![20, 30]
+        );
+
+        let since = (entries[1].timestamp + chrono::Duration::microseconds(1)).to_rfc3339();
+        let uri = format!(
+            "/api/miner/modules/a/stake/history?since={}",
+            since.replace('+', "%2B")
+        );
+        let (_, body) = send(&app, "GET", &uri, None).await;
+        assert_eq!(body, json!([]));
+
+        assert_eq!(
+            call(
+                &app,
+                "GET",
+                "/api/miner/modules/missing/stake/history",
+                None
+            )
+            .await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metrics_snapshot_reports_recorded_blocks() {
+        let app = app(MinerConfig::default()).await;
+        call(
+            &app,
+            "POST",
+            "/api/miner/modules",
+            Some(json!({"name": "a", "stake": 10})),
+        )
+        .await;
+        for block in [100, 101] {
+            assert_eq!(
+                call(
+                    &app,
+                    "POST",
+                    "/api/miner/modules/a/blocks",
+                    Some(json!({"block": block, "reward": 5}))
+                )
+                .await,
+                StatusCode::CREATED
+            );
+        }
+
+        let (status, body) = send(&app, "GET", "/api/miner/metrics/snapshot", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let snapshot: MetricsSnapshot = serde_json::from_value(body).unwrap();
+        assert_eq!(snapshot.miners.len(), 1);
+        assert_eq!(snapshot.miners[0].blocks, 2);
+        assert_eq!(snapshot.miners[0].total_rewards, 10);
+
+        assert_eq!(
+            call(
+                &app,
+                "POST",
+                "/api/miner/modules/missing/blocks",
+                Some(json!({"block": 1, "reward": 1}))
+            )
+            .await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_without_limits_uses_configured_defaults() {
+        let config = MinerConfig {
+            default_resource_limits: ResourceLimits {
+                memory_bytes: Some(512 << 20),
+                cpu_millis: Some(1000),
+            },
+            max_resource_limits: ResourceLimits {
+                memory_bytes: Some(1 << 30),
+                cpu_millis: Some(2000),
+            },
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+        let app = app(config.clone()).await;
+        call(
+            &app,
+            "POST",
+            "/api/miner/modules",
+            Some(json!({"name": "a", "stake": 10})),
+        )
+        .await;
+        call(&app, "POST", "/api/miner/modules/a/stop", None).await;
+
+        let (status, body) = send(&app, "POST", "/api/miner/modules/a/start", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let module: ModuleStatus = serde_json::from_value(body).unwrap();
+        assert_eq!(module.resource_limits, config.default_resource_limits);
+
+        // Explicit limits override the defaults resource by resource.
+        let (_, body) = send(
+            &app,
+            "POST",
+            "/api/miner/modules/a/start",
+            Some(json!({"resource_limits": {"cpu_millis": 1500}})),
+        )
+        .await;
+        assert_eq!(
+            body["resource_limits"],
+            json!({"memory_bytes": 512 << 20, "cpu_millis": 1500})
+        );
+
+        assert_eq!(
+            call(
+                &app,
+                "POST",
+                "/api/miner/modules/a/start",
+                Some(json!({"resource_limits": {"cpu_millis": 4000}}))
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+
+        // A malformed config is an error, not a start with the defaults.
+        for body in ["{\"resource_limits\": ", "{\"resource_limits\": 5}"] {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/api/miner/modules/a/start")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", body);
+        }
+
+        let too_generous = MinerConfig {
+            max_resource_limits: ResourceLimits {
+                memory_bytes: Some(256 << 20),
+                cpu_millis: None,
+            },
+            ..config
+        };
+        assert!(matches!(
+            too_generous.validate(),
+            Err(MinerError::InvalidConfig(_))
+        ));
+    }
+
+    /// Holds requests for the `heavy` module until the test releases them
+    /// and answers others at once with the module they were sent to.
+    #[derive(Clone)]
+    struct GatedBackend {
+        entered: Arc<Notify>,
+        release: Arc<Semaphore>,
+    }
+
+    #[async_trait]
+    impl InferenceBackend for GatedBackend {
+        async fn infer(&self, request: &InferenceRequest) -> Result<InferenceResponse, MinerError> {
+            if request.module == "heavy" {
+                self.entered.notify_one();
+                self.release.acquire().await.unwrap().forget();
+            }
+            Ok(InferenceResponse {
+                model: request.model.clone(),
+                output: request.module.clone(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inference_capped_per_module_from_config() {
+        let config = MinerConfig {
+            inference_concurrency: ConcurrencyConfig {
+                max_concurrent: BTreeMap::from([("heavy".to_string(), 1)]),
+                when_saturated: SaturationPolicy::Reject,
+            },
+            ..Default::default()
+        };
+        let backend = GatedBackend {
+            entered: Arc::new(Notify::new()),
+            release: Arc::new(Semaphore::new(0)),
+        };
+        let db = MinerDb::in_memory().await.unwrap();
+        let app = create_router(
+            MinerService::new(db, config).with_inference(backend.clone(), Default::default()),
+        );
+        for name in ["heavy", "light"] {
+            call(
+                &app,
+                "POST",
+                "/api/miner/modules",
+                Some(json!({"name": name, "stake": 10})),
+            )
+            .await;
+        }
+        let prompt = json!({"model": "llama2", "prompt": "hello", "max_tokens": 16});
+
+        let first = {
+            let (app, prompt) = (app.clone(), prompt.clone());
+            tokio::spawn(async move {
+                send(&app, "POST", "/api/miner/modules/heavy/infer", Some(prompt)).await
+            })
+        };
+        backend.entered.notified().await;
+
+        let uri = "/api/miner/modules/heavy/infer";
+        assert_eq!(
+            call(&app, "POST", uri, Some(prompt.clone())).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let uri = "/api/miner/modules/light/infer";
+        let (status, body) = send(&app, "POST", uri, Some(prompt.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["output"], "light");
+
+        backend.release.add_permits(1);
+        let (status, body) = first.await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["output"], "heavy");
+
+        let uri = "/api/miner/modules/missing/infer";
+        assert_eq!(
+            call(&app, "POST", uri, Some(prompt)).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    /// Fails every request until `failures` have been returned.
+    #[derive(Clone)]
+    struct FlakyBackend {
+        calls: Arc<AtomicU32>,
+        failures: u32,
+    }
+
+    #[async_trait]
+    impl InferenceBackend for FlakyBackend {
+        async fn infer(&self, request: &InferenceRequest) -> Result<InferenceResponse, MinerError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(MinerError::InferenceFailed("model not loaded".into()));
+            }
+            Ok(InferenceResponse {
+                model: request.model.clone(),
+                output: "ok".into(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inference_retried_with_configured_policy() {
+        let prompt = json!({"model": "llama2", "prompt": "hello", "max_tokens": 16});
+        for (max_retries, status, calls) in
+            [(0, StatusCode::BAD_GATEWAY, 1), (1, StatusCode::OK, 2)]
+        {
+            let mut config = MinerConfig::default();
+            config.retry.high = RetryPolicy {
+                max_retries,
+                base_delay: Duration::from_millis(1),
+                ..Priority::High.default_policy()
+            };
+            let backend = FlakyBackend {
+                calls: Arc::new(AtomicU32::new(0)),
+                failures: 1,
+            };
+            let db = MinerDb::in_memory().await.unwrap();
+            let app = create_router(
+                MinerService::new(db, config).with_inference(backend.clone(), Default::default()),
+            );
+            call(
+                &app,
+                "POST",
+                "/api/miner/modules",
+                Some(json!({"name": "a", "stake": 10})),
+            )
+            .await;
+
+            let uri = "/api/miner/modules/a/infer";
+            assert_eq!(call(&app, "POST", uri, Some(prompt.clone())).await, status);
+            assert_eq!(backend.calls.load(Ordering::SeqCst), calls);
+        }
+    }
+}
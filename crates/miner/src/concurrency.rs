@@ -0,0 +1,81 @@
+//! Per-module bounds on concurrent inference.
+//!
+//! Each module, named by the `module` of the requests sent to it, may be
+//! given a cap on the inferences it runs at once, so one expensive module
+//! cannot take every worker. Modules without a cap are unbounded.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::error::MinerError;
+
+/// What happens to a request for a module already running its maximum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SaturationPolicy {
+    /// Wait for one of the module's requests to finish.
+    #[default]
+    Queue,
+    /// Fail at once with [`MinerError::ResourceExceeded`].
+    Reject,
+}
+
+/// Per-module inference concurrency settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Most inferences each listed module runs at once.
+    pub max_concurrent: BTreeMap<String, usize>,
+    pub when_saturated: SaturationPolicy,
+}
+
+impl ConcurrencyConfig {
+    /// Checks that every cap allows at least one request.
+    pub fn validate(&self) -> Result<(), MinerError> {
+        match self.max_concurrent.iter().find(|(_, max)| **max == 0) {
+            Some((module, _)) => Err(MinerError::InvalidConfig(format!(
+                "max_concurrent for {} must be at least 1",
+                module
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Enforces a [`ConcurrencyConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct ModuleConcurrency {
+    slots: HashMap<String, Arc<Semaphore>>,
+    when_saturated: SaturationPolicy,
+}
+
+impl ModuleConcurrency {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        Self {
+            slots: config
+                .max_concurrent
+                .iter()
+                .map(|(module, max)| (module.clone(), Arc::new(Semaphore::new(*max))))
+                .collect(),
+            when_saturated: config.when_saturated,
+        }
+    }
+
+    /// Takes one of `module`'s slots, held until the returned permit is
+    /// dropped. Returns `None` for a module without a cap.
+    pub async fn acquire(&self, module: &str) -> Result<Option<OwnedSemaphorePermit>, MinerError> {
+        let Some(slots) = self.slots.get(module) else {
+            return Ok(None);
+        };
+        let permit = match self.when_saturated {
+            SaturationPolicy::Queue => slots.clone().acquire_owned().await.ok(),
+            SaturationPolicy::Reject => slots.clone().try_acquire_owned().ok(),
+        };
+        permit.map(Some).ok_or_else(|| {
+            MinerError::ResourceExceeded(format!("{} is at its concurrency limit", module))
+        })
+    }
+}
@@ -0,0 +1,446 @@
+//! SQLite persistence for the miner.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::Row;
+
+use crate::error::MinerError;
+use crate::service::{MinerMetrics, ModuleStatus, ResourceLimits, StakeHistoryEntry};
+
+/// Miner database handle.
+#[derive(Clone)]
+pub struct MinerDb {
+    pool: SqlitePool,
+}
+
+fn to_db(field: &str, value: u64) -> Result<i64, MinerError> {
+    i64::try_from(value)
+        .map_err(|_| MinerError::InvalidRequest(format!("{} {} is too large", field, value)))
+}
+
+fn stake_to_db(value: u64) -> Result<i64, MinerError> {
+    i64::try_from(value).map_err(|_| MinerError::InvalidStake(format!("{} is too large", value)))
+}
+
+fn limit_to_db(value: Option<u64>) -> Result<Option<i64>, MinerError> {
+    value
+        .map(|value| {
+            i64::try_from(value)
+                .map_err(|_| MinerError::InvalidRequest(format!("limit {} is too large", value)))
+        })
+        .transpose()
+}
+
+const MODULE_COLUMNS: &str =
+    "name, stake, active, started_at, accumulated_uptime, memory_bytes, cpu_millis";
+
+/// Seconds between `started_at` and `now`, clamped at zero.
+fn elapsed_secs(started_at: DateTime<Utc>, now: DateTime<Utc>) -> u64 {
+    (now - started_at).num_seconds().max(0) as u64
+}
+
+fn module_from_row(row: &SqliteRow, now: DateTime<Utc>) -> Result<ModuleStatus, MinerError> {
+    let stake: i64 = row.try_get("stake")?;
+    let accumulated: i64 = row.try_get("accumulated_uptime")?;
+    let started_at: Option<DateTime<Utc>> = row.try_get("started_at")?;
+    let uptime = accumulated as u64 + started_at.map_or(0, |at| elapsed_secs(at, now));
+    let memory_bytes: Option<i64> = row.try_get("memory_bytes")?;
+    let cpu_millis: Option<i64> = row.try_get("cpu_millis")?;
+    Ok(ModuleStatus {
+        name: row.try_get("name")?,
+        active: row.try_get("active")?,
+        stake: stake as u64,
+        uptime,
+        started_at,
+        resource_limits: ResourceLimits {
+            memory_bytes: memory_bytes.map(|bytes| bytes as u64),
+            cpu_millis: cpu_millis.map(|millis| millis as u64),
+        },
+    })
+}
+
+const METRICS_QUERY: &str = "SELECT m.name, COUNT(b.id) AS blocks, \
+     COALESCE(SUM(b.reward), 0) AS total_rewards, \
+     MIN(b.timestamp) AS first_block_at, MAX(b.timestamp) AS last_block_at \
+     FROM miner_modules m LEFT JOIN block_rewards b ON b.module = m.name";
+
+fn metrics_from_row(row: &SqliteRow, now: DateTime<Utc>) -> Result<MinerMetrics, MinerError> {
+    let blocks: i64 = row.try_get("blocks")?;
+    let total_rewards: i64 = row.try_get("total_rewards")?;
+    let first_block_at: Option<String> = row.try_get("first_block_at")?;
+    let last_block_at: Option<String> = row.try_get("last_block_at")?;
+    let parse = |at: Option<String>| {
+        at.map(|at| {
+            DateTime::parse_from_rfc3339(&at)
+                .map(|at| at.with_timezone(&Utc))
+                .map_err(|e| MinerError::DatabaseError(e.to_string()))
+        })
+        .transpose()
+    };
+    Ok(MinerMetrics::new(
+        row.try_get("name")?,
+        blocks as u64,
+        total_rewards as u64,
+        parse(first_block_at)?,
+        parse(last_block_at)?,
+        now,
+    ))
+}
+
+impl MinerDb {
+    /// Connects to the database at `url`, creating it if missing, and runs
+    /// pending migrations.
+    pub async fn connect(url: &str) -> Result<Self, MinerError> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await?;
+        Self::from_pool(pool).await
+    }
+
+    /// Creates a database backed by a private in-memory SQLite instance.
+    pub async fn in_memory() -> Result<Self, MinerError> {
+        // Every in-memory connection is a separate database, so keep exactly one.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        Self::from_pool(pool).await
+    }
+
+    async fn from_pool(pool: SqlitePool) -> Result<Self, MinerError> {
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    /// Returns the underlying connection pool.
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    /// Inserts a newly registered, active module, running since
+    /// `registered_at`.
+    pub async fn insert_module(
+        &self,
+        name: &str,
+        stake: u64,
+        registered_at: DateTime<Utc>,
+    ) -> Result<(), MinerError> {
+        sqlx::query(
+            "INSERT INTO miner_modules (name, stake, active, registered_at, started_at) \
+             VALUES (?, ?, 1, ?, ?)",
+        )
+        .bind(name)
+        .bind(stake_to_db(stake)?)
+        .bind(registered_at)
+        .bind(registered_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                MinerError::ModuleExists(name.to_string())
+            }
+            other => other.into(),
+        })?;
+        Ok(())
+    }
+
+    /// Looks up a module by name, computing its uptime as of `now`.
+    pub async fn get_module(
+        &self,
+        name: &str,
+        now: DateTime<Utc>,
+    ) -> Result<ModuleStatus, MinerError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM miner_modules WHERE name = ?",
+            MODULE_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| MinerError::ModuleNotFound(name.to_string()))?;
+        module_from_row(&row, now)
+    }
+
+    /// Lists all modules ordered by name, computing uptimes as of `now`.
+    pub async fn list_modules(&self, now: DateTime<Utc>) -> Result<Vec<ModuleStatus>, MinerError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM miner_modules ORDER BY name",
+            MODULE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(|row| module_from_row(row, now)).collect()
+    }
+
+    /// Marks a module as running since `at`. Starting an active module has
+    /// no effect.
+    pub async fn start_module(&self, name: &str, at: DateTime<Utc>) -> Result<(), MinerError> {
+        let result = sqlx::query(
+            "UPDATE miner_modules SET active = 1, started_at = COALESCE(started_at, ?) \
+             WHERE name = ?",
+        )
+        .bind(at)
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(MinerError::ModuleNotFound(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Sets the resource limits a module runs with.
+    pub async fn set_resource_limits(
+        &self,
+        name: &str,
+        limits: &ResourceLimits,
+    ) -> Result<(), MinerError> {
+        let result =
+            sqlx::query("UPDATE miner_modules SET memory_bytes = ?, cpu_millis = ? WHERE name = ?")
+                .bind(limit_to_db(limits.memory_bytes)?)
+                .bind(limit_to_db(limits.cpu_millis)?)
+                .bind(name)
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(MinerError::ModuleNotFound(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Marks a module as stopped at `at`, folding the time it has been
+    /// running into its accumulated uptime. Stopping an inactive module has
+    /// no effect.
+    pub async fn stop_module(&self, name: &str, at: DateTime<Utc>) -> Result<(), MinerError> {
+        let mut tx = self.pool.begin().await?;
+        let started_at: Option<DateTime<Utc>> =
+            sqlx::query("SELECT started_at FROM miner_modules WHERE name = ?")
+                .bind(name)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or_else(|| MinerError::ModuleNotFound(name.to_string()))?
+                .try_get("started_at")?;
+        let elapsed = started_at.map_or(0, |started| elapsed_secs(started, at));
+        sqlx::query(
+            "UPDATE miner_modules \
+             SET active = 0, started_at = NULL, accumulated_uptime = accumulated_uptime + ? \
+             WHERE name = ?",
+        )
+        .bind(to_db("uptime", elapsed)?)
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Counts registered modules.
+    pub async fn count_modules(&self) -> Result<u64, MinerError> {
+        let count: i64 = sqlx::query("SELECT COUNT(*) AS count FROM miner_modules")
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("count")?;
+        Ok(count as u64)
+    }
+
+    /// Sets the current stake of a module and records the change in its
+    /// stake history.
+    pub async fn set_stake(
+        &self,
+        name: &str,
+        stake: u64,
+        at: DateTime<Utc>,
+    ) -> Result<(), MinerError> {
+        let stake = stake_to_db(stake)?;
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query("UPDATE miner_modules SET stake = ? WHERE name = ?")
+            .bind(stake)
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(MinerError::ModuleNotFound(name.to_string()));
+        }
+        sqlx::query("INSERT INTO stake_history (module, stake, timestamp) VALUES (?, ?, ?)")
+            .bind(name)
+            .bind(stake)
+            .bind(at)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Records a block produced by a module at `at`.
+    pub async fn insert_block(
+        &self,
+        name: &str,
+        block: u64,
+        reward: u64,
+        at: DateTime<Utc>,
+    ) -> Result<(), MinerError> {
+        let exists = sqlx::query("SELECT 1 FROM miner_modules WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(MinerError::ModuleNotFound(name.to_string()));
+        }
+        sqlx::query(
+            "INSERT INTO block_rewards (module, block, reward, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(name)
+        .bind(to_db("block", block)?)
+        .bind(to_db("reward", reward)?)
+        .bind(at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the block metrics of a module, with rates as of `now`.
+    pub async fn module_metrics(
+        &self,
+        name: &str,
+        now: DateTime<Utc>,
+    ) -> Result<MinerMetrics, MinerError> {
+        let row = sqlx::query(&format!(
+            "{} WHERE m.name = ? GROUP BY m.name",
+            METRICS_QUERY
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| MinerError::ModuleNotFound(name.to_string()))?;
+        metrics_from_row(&row, now)
+    }
+
+    /// Returns the block metrics of every active module ordered by name,
+    /// with rates as of `now`.
+    pub async fn active_metrics(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<MinerMetrics>, MinerError> {
+        let rows = sqlx::query(&format!(
+            "{} WHERE m.active = 1 GROUP BY m.name ORDER BY m.name",
+            METRICS_QUERY
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(|row| metrics_from_row(row, now)).collect()
+    }
+
+    /// Returns the stake history of a module, oldest first, optionally
+    /// restricted to `[since, until]`.
+    pub async fn stake_history(
+        &self,
+        name: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<StakeHistoryEntry>, MinerError> {
+        let rows = sqlx::query(
+            "SELECT stake, timestamp FROM stake_history \
+             WHERE module = ? AND (? IS NULL OR timestamp >= ?) AND (? IS NULL OR timestamp <= ?) \
+             ORDER BY timestamp, id",
+        )
+        .bind(name)
+        .bind(since)
+        .bind(since)
+        .bind(until)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                let stake: i64 = row.try_get("stake")?;
+                Ok(StakeHistoryEntry {
+                    stake: stake as u64,
+                    timestamp: row.try_get("timestamp")?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_uptime_grows_and_survives_restart() {
+        let db = MinerDb::in_memory().await.unwrap();
+        let t0 = Utc::now();
+        let at = |secs| t0 + Duration::seconds(secs);
+        db.insert_module("a", 10, t0).await.unwrap();
+
+        let status = db.get_module("a", at(10)).await.unwrap();
+        assert!(status.active);
+        assert_eq!(status.uptime, 10);
+        assert_eq!(db.get_module("a", at(25)).await.unwrap().uptime, 25);
+
+        db.stop_module("a", at(30)).await.unwrap();
+        let status = db.get_module("a", at(100)).await.unwrap();
+        assert!(!status.active);
+        assert_eq!(status.started_at, None);
+        assert_eq!(status.uptime, 30);
+
+        db.start_module("a", at(200)).await.unwrap();
+        // A second start must not reset the running interval.
+        db.start_module("a", at(210)).await.unwrap();
+        assert_eq!(db.get_module("a", at(215)).await.unwrap().uptime, 45);
+    }
+
+    #[tokio::test]
+    async fn test_block_rates_derived_from_timestamps() {
+        let db = MinerDb::in_memory().await.unwrap();
+        let t0 = Utc::now();
+        let at = |minutes| t0 + Duration::minutes(minutes);
+        db.insert_module("a", 10, t0).await.unwrap();
+        db.insert_module("idle", 10, t0).await.unwrap();
+        db.insert_module("stopped", 10, t0).await.unwrap();
+        db.stop_module("stopped", t0).await.unwrap();
+        for (block, minutes) in [(100, 0), (101, 30), (102, 60)] {
+            db.insert_block("a", block, 4, at(minutes)).await.unwrap();
+        }
+
+        let metrics = db.active_metrics(at(120)).await.unwrap();
+        assert_eq!(
+            metrics.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(),
+            vec!["a", "idle"]
+        );
+        let a = &metrics[0];
+        assert_eq!((a.blocks, a.total_rewards), (3, 12));
+        assert_eq!(a.first_block_at, Some(at(0)));
+        assert_eq!(a.last_block_at, Some(at(60)));
+        // Three blocks and twelve rewards over the two hours since the first.
+        assert_eq!(a.blocks_per_hour, 1.5);
+        assert_eq!(a.reward_rate, 6.0);
+        assert_eq!((metrics[1].blocks, metrics[1].blocks_per_hour), (0, 0.0));
+
+        assert_eq!(
+            db.insert_block("missing", 1, 1, t0).await,
+            Err(MinerError::ModuleNotFound("missing".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_block_reported_by_field() {
+        let db = MinerDb::in_memory().await.unwrap();
+        db.insert_module("a", 10, Utc::now()).await.unwrap();
+
+        for (block, reward, field) in [(u64::MAX, 1, "block"), (1, u64::MAX, "reward")] {
+            match db.insert_block("a", block, reward, Utc::now()).await {
+                Err(MinerError::InvalidRequest(message)) => {
+                    assert!(message.starts_with(field), "{}", message)
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+}
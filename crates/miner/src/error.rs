@@ -0,0 +1,125 @@
+//! Error types for miner operations.
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use thiserror::Error;
+
+/// Errors produced by the miner.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MinerError {
+    /// The inference request failed validation and was not dispatched.
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    /// The inference backend failed to produce a result.
+    #[error("Inference failed: {0}")]
+    InferenceFailed(String),
+
+    /// No module with the given name is registered with the miner.
+    #[error("Module not found: {0}")]
+    ModuleNotFound(String),
+
+    /// A module with the given name is already registered.
+    #[error("Module already registered: {0}")]
+    ModuleExists(String),
+
+    /// The stake is below the minimum or otherwise unacceptable.
+    #[error("Invalid stake: {0}")]
+    InvalidStake(String),
+
+    /// The miner has no capacity left for the operation.
+    #[error("Resource limit exceeded: {0}")]
+    ResourceExceeded(String),
+
+    /// The miner's own configuration is inconsistent.
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    /// The miner database failed.
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+}
+
+impl MinerError {
+    /// HTTP status code that best describes the error.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            MinerError::InvalidRequest(_) | MinerError::InvalidStake(_) => StatusCode::BAD_REQUEST,
+            MinerError::ModuleNotFound(_) => StatusCode::NOT_FOUND,
+            MinerError::ModuleExists(_) => StatusCode::CONFLICT,
+            MinerError::ResourceExceeded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            MinerError::InferenceFailed(_) => StatusCode::BAD_GATEWAY,
+            MinerError::InvalidConfig(_) | MinerError::DatabaseError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl IntoResponse for MinerError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!("{}", self);
+        }
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+impl From<sqlx::Error> for MinerError {
+    fn from(err: sqlx::Error) -> Self {
+        MinerError::DatabaseError(err.to_string())
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for MinerError {
+    fn from(err: sqlx::migrate::MigrateError) -> Self {
+        MinerError::DatabaseError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code_mapping() {
+        let cases = [
+            (
+                MinerError::InvalidRequest("x".into()),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                MinerError::InvalidStake("x".into()),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                MinerError::ModuleNotFound("x".into()),
+                StatusCode::NOT_FOUND,
+            ),
+            (MinerError::ModuleExists("x".into()), StatusCode::CONFLICT),
+            (
+                MinerError::ResourceExceeded("x".into()),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                MinerError::InferenceFailed("x".into()),
+                StatusCode::BAD_GATEWAY,
+            ),
+            (
+                MinerError::InvalidConfig("x".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                MinerError::DatabaseError("x".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.status_code(), expected, "{:?}", error);
+            assert_eq!(error.into_response().status(), expected);
+        }
+    }
+}
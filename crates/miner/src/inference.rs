@@ -0,0 +1,305 @@
+//! Inference request handling.
+//!
+//! Requests are validated against the miner's [`InferenceLimits`] before they
+//! are dispatched to an [`InferenceBackend`], so malformed payloads never
+//! reach the model.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::concurrency::ModuleConcurrency;
+use crate::error::MinerError;
+use crate::retry::{Priority, RetryManager};
+
+/// Default upper bound for `max_tokens` on a single request.
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// An inference request submitted to the miner.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InferenceRequest {
+    /// Module the request is sent to. Requests for the same module share
+    /// its concurrency cap. Taken from the URL when submitted through the
+    /// API.
+    #[serde(default)]
+    pub module: String,
+    /// Name of the model to run.
+    pub model: String,
+    /// Prompt text.
+    pub prompt: String,
+    /// Maximum number of tokens to generate.
+    pub max_tokens: u32,
+}
+
+/// The result of a successful inference.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InferenceResponse {
+    /// Model that produced the output.
+    pub model: String,
+    /// Generated text.
+    pub output: String,
+}
+
+/// Limits applied to incoming inference requests.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InferenceLimits {
+    /// Largest accepted `max_tokens` value.
+    pub max_tokens: u32,
+    /// Models this miner serves. An empty list allows any model.
+    pub allowed_models: Vec<String>,
+}
+
+impl Default for InferenceLimits {
+    fn default() -> Self {
+        Self {
+            max_tokens: DEFAULT_MAX_TOKENS,
+            allowed_models: Vec::new(),
+        }
+    }
+}
+
+impl InferenceRequest {
+    /// Validates the request against the given limits.
+    pub fn validate(&self, limits: &InferenceLimits) -> Result<(), MinerError> {
+        if self.prompt.trim().is_empty() {
+            return Err(MinerError::InvalidRequest(
+                "prompt must not be empty".into(),
+            ));
+        }
+
+        if self.max_tokens == 0 || self.max_tokens > limits.max_tokens {
+            return Err(MinerError::InvalidRequest(format!(
+                "max_tokens must be between 1 and {}, got {}",
+                limits.max_tokens, self.max_tokens
+            )));
+        }
+
+        if !limits.allowed_models.is_empty() && !limits.allowed_models.contains(&self.model) {
+            return Err(MinerError::InvalidRequest(format!(
+                "model '{}' is not served by this miner",
+                self.model
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Backend that executes validated inference requests.
+#[async_trait]
+pub trait InferenceBackend: Send + Sync {
+    /// Runs inference for a request that has already passed validation.
+    async fn infer(&self, request: &InferenceRequest) -> Result<InferenceResponse, MinerError>;
+}
+
+#[async_trait]
+impl<B: InferenceBackend + ?Sized> InferenceBackend for Arc<B> {
+    async fn infer(&self, request: &InferenceRequest) -> Result<InferenceResponse, MinerError> {
+        (**self).infer(request).await
+    }
+}
+
+/// Validates inference requests and dispatches them to a backend.
+pub struct InferenceHandler<B> {
+    backend: B,
+    limits: InferenceLimits,
+    concurrency: ModuleConcurrency,
+    retry: Option<RetryManager>,
+}
+
+impl<B: InferenceBackend> InferenceHandler<B> {
+    /// Creates a new handler with the given backend and limits.
+    pub fn new(backend: B, limits: InferenceLimits) -> Self {
+        Self {
+            backend,
+            limits,
+            concurrency: ModuleConcurrency::default(),
+            retry: None,
+        }
+    }
+
+    /// Retries failed dispatches with `retry`'s [`Priority::High`] policy,
+    /// inference being what validators wait on.
+    pub fn with_retry(mut self, retry: RetryManager) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Caps how many requests for each module are dispatched at once.
+    pub fn with_concurrency(mut self, concurrency: ModuleConcurrency) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Returns the limits enforced by this handler.
+    pub fn limits(&self) -> &InferenceLimits {
+        &self.limits
+    }
+
+    /// Validates the request and, if valid, dispatches it to the backend
+    /// once its module has a free slot. The slot is held across retries.
+    pub async fn handle(&self, request: InferenceRequest) -> Result<InferenceResponse, MinerError> {
+        request.validate(&self.limits)?;
+        let _slot = self.concurrency.acquire(&request.module).await?;
+        match &self.retry {
+            Some(retry) => Ok(retry
+                .run(Priority::High, || self.backend.infer(&request))
+                .await?),
+            None => self.backend.infer(&request).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::concurrency::{ConcurrencyConfig, SaturationPolicy};
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[derive(Default, Clone)]
+    struct CountingBackend {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl InferenceBackend for CountingBackend {
+        async fn infer(&self, request: &InferenceRequest) -> Result<InferenceResponse, MinerError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(InferenceResponse {
+                model: request.model.clone(),
+                output: "ok".into(),
+            })
+        }
+    }
+
+    fn request(prompt: &str, max_tokens: u32) -> InferenceRequest {
+        InferenceRequest {
+            module: "chat".into(),
+            model: "llama2".into(),
+            prompt: prompt.into(),
+            max_tokens,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_prompt_rejected_before_dispatch() {
+        let backend = CountingBackend::default();
+        let handler = InferenceHandler::new(backend.clone(), InferenceLimits::default());
+
+        let result = handler.handle(request("   ", 16)).await;
+        assert!(matches!(result, Err(MinerError::InvalidRequest(_))));
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_over_limit_max_tokens_rejected() {
+        let backend = CountingBackend::default();
+        let limits = InferenceLimits {
+            max_tokens: 128,
+            allowed_models: vec![],
+        };
+        let handler = InferenceHandler::new(backend.clone(), limits);
+
+        let result = handler.handle(request("hello", 129)).await;
+        assert!(matches!(result, Err(MinerError::InvalidRequest(_))));
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 0);
+
+        assert!(handler.handle(request("hello", 128)).await.is_ok());
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_disallowed_model_rejected() {
+        let limits = InferenceLimits {
+            max_tokens: 128,
+            allowed_models: vec!["mistral".into()],
+        };
+        assert!(matches!(
+            request("hello", 16).validate(&limits),
+            Err(MinerError::InvalidRequest(_))
+        ));
+    }
+
+    /// Takes 100ms per request, tracking the most requests in flight at once
+    /// per module.
+    #[derive(Default, Clone)]
+    struct SlowBackend {
+        in_flight: Arc<std::sync::Mutex<HashMap<String, (usize, usize)>>>,
+    }
+
+    #[async_trait]
+    impl InferenceBackend for SlowBackend {
+        async fn infer(&self, request: &InferenceRequest) -> Result<InferenceResponse, MinerError> {
+            {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                let (now, max) = in_flight.entry(request.module.clone()).or_default();
+                *now += 1;
+                *max = (*max).max(*now);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            self.in_flight
+                .lock()
+                .unwrap()
+                .get_mut(&request.module)
+                .unwrap()
+                .0 -= 1;
+            Ok(InferenceResponse {
+                model: request.model.clone(),
+                output: "ok".into(),
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_capped_module_serializes_while_other_proceeds() {
+        let backend = SlowBackend::default();
+        let config = ConcurrencyConfig {
+            max_concurrent: BTreeMap::from([("heavy".to_string(), 1)]),
+            ..Default::default()
+        };
+        let handler = InferenceHandler::new(backend.clone(), InferenceLimits::default())
+            .with_concurrency(ModuleConcurrency::new(&config));
+        let call = |module: &str| {
+            let handler = &handler;
+            let request = InferenceRequest {
+                module: module.into(),
+                ..request("hello", 16)
+            };
+            async move {
+                let started = Instant::now();
+                handler.handle(request).await.unwrap();
+                started.elapsed()
+            }
+        };
+
+        let (heavy_a, heavy_b, light_a, light_b) =
+            tokio::join!(call("heavy"), call("heavy"), call("light"), call("light"));
+
+        assert_eq!(heavy_a.max(heavy_b), Duration::from_millis(200));
+        assert_eq!(light_a.max(light_b), Duration::from_millis(100));
+        let in_flight = backend.in_flight.lock().unwrap();
+        assert_eq!(in_flight["heavy"].1, 1);
+        assert_eq!(in_flight["light"].1, 2);
+    }
+
+    #[tokio::test]
+    async fn test_saturated_module_rejected_under_reject_policy() {
+        let config = ConcurrencyConfig {
+            max_concurrent: BTreeMap::from([("heavy".to_string(), 1)]),
+            when_saturated: SaturationPolicy::Reject,
+        };
+        let concurrency = ModuleConcurrency::new(&config);
+        let _held = concurrency.acquire("heavy").await.unwrap();
+
+        assert!(matches!(
+            concurrency.acquire("heavy").await,
+            Err(MinerError::ResourceExceeded(_))
+        ));
+        assert!(concurrency.acquire("light").await.unwrap().is_none());
+    }
+}
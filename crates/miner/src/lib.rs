@@ -1,8 +1,23 @@
 //! Miner implementation for the Synapse Subnet project.
-//! 
+//!
 //! This crate provides the miner functionality for executing inference
 //! requests using Ollama models.
 
+pub mod api;
+pub mod concurrency;
+pub mod db;
+pub mod error;
+pub mod inference;
+pub mod retry;
+pub mod service;
+
+pub use error::MinerError;
+pub use inference::{InferenceHandler, InferenceLimits, InferenceRequest, InferenceResponse};
+pub use service::{
+    MetricsSnapshot, MinerConfig, MinerMetrics, MinerService, MiningConfig, ModuleStatus,
+    ResourceLimits, StakeHistoryEntry,
+};
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,507 @@
+//! Retrying miner operations, spaced out and bounded by priority.
+//!
+//! Each [`Priority`] has a [`RetryPolicy`]. The defaults can be replaced
+//! per priority through a [`RetryConfig`], e.g. as part of the miner's
+//! config.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::MinerError;
+
+/// Histogram of the attempts each operation made, whatever its outcome.
+pub const ATTEMPTS_METRIC: &str = "synapse_miner_retry_attempts";
+
+/// Counter of operations that succeeded after at least one retry.
+pub const SUCCESS_AFTER_RETRY_METRIC: &str = "synapse_miner_retry_successes_after_retry_total";
+
+/// Counter of attempts cut off by their timeout.
+pub const TIMEOUTS_METRIC: &str = "synapse_miner_retry_timeouts_total";
+
+/// Counter of operations that failed on every attempt.
+pub const EXHAUSTED_METRIC: &str = "synapse_miner_retry_max_retries_exceeded_total";
+
+/// How urgent an operation is. More urgent operations retry sooner but
+/// give up after fewer attempts; less urgent ones keep trying for longer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    Normal,
+    Low,
+}
+
+impl Priority {
+    /// Label the priority's metrics carry.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    /// Policy used for the priority unless overridden: `High` makes 3
+    /// retries starting 1s apart, `Normal` 5 starting 2s apart and `Low` 7
+    /// starting 5s apart.
+    pub fn default_policy(self) -> RetryPolicy {
+        let (max_retries, base_delay, max_delay) = match self {
+            Priority::High => (3, Duration::from_secs(1), Duration::from_secs(10)),
+            Priority::Normal => (5, Duration::from_secs(2), Duration::from_secs(30)),
+            Priority::Low => (7, Duration::from_secs(5), Duration::from_secs(60)),
+        };
+        RetryPolicy {
+            max_retries,
+            base_delay,
+            max_delay,
+            attempt_timeout: DEFAULT_ATTEMPT_TIMEOUT,
+            jitter: false,
+        }
+    }
+}
+
+/// Default time a single attempt may take.
+pub const DEFAULT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How attempts at an operation are spaced out and bounded. In config,
+/// durations are given in milliseconds, e.g. `base_delay_ms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry; it doubles with each retry after.
+    #[serde(rename = "base_delay_ms", with = "millis")]
+    pub base_delay: Duration,
+    /// Upper bound on any single delay.
+    #[serde(rename = "max_delay_ms", with = "millis")]
+    pub max_delay: Duration,
+    /// Time an attempt may take before it is abandoned and counted as
+    /// failed.
+    #[serde(rename = "timeout_ms", with = "millis")]
+    pub attempt_timeout: Duration,
+    /// Sleep a random duration between zero and the computed delay, so
+    /// miners failing against the same dependency do not retry in
+    /// lockstep. Off by default, keeping delays deterministic.
+    #[serde(default)]
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Enables or disables full jitter.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Delay before retry number `retry` (zero-based), capped at
+    /// `max_delay` and without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry.min(31)))
+            .min(self.max_delay)
+    }
+
+    /// Time actually slept before retry number `retry`.
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        if self.jitter {
+            backoff.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Durations as whole milliseconds.
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// Retry policy of each priority. A priority left out of the config keeps
+/// its [default policy](Priority::default_policy).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub high: RetryPolicy,
+    pub normal: RetryPolicy,
+    pub low: RetryPolicy,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            high: Priority::High.default_policy(),
+            normal: Priority::Normal.default_policy(),
+            low: Priority::Low.default_policy(),
+        }
+    }
+}
+
+/// Why an operation failed on its last attempt.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum RetryError<E> {
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("{0}")]
+    Failed(E),
+
+    /// The operation was cancelled before any attempt succeeded.
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+impl From<RetryError<MinerError>> for MinerError {
+    fn from(err: RetryError<MinerError>) -> Self {
+        match err {
+            RetryError::Failed(e) => e,
+            other => MinerError::InferenceFailed(other.to_string()),
+        }
+    }
+}
+
+/// Runs operations with the retry policy of their priority.
+#[derive(Debug, Clone, Default)]
+pub struct RetryManager {
+    config: RetryConfig,
+}
+
+impl RetryManager {
+    /// A manager using each priority's default policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A manager using the policies in `config`.
+    pub fn from_config(config: RetryConfig) -> Self {
+        Self { config }
+    }
+
+    /// Retries operations of `priority` according to `policy`.
+    pub fn with_policy(mut self, priority: Priority, policy: RetryPolicy) -> Self {
+        match priority {
+            Priority::High => self.config.high = policy,
+            Priority::Normal => self.config.normal = policy,
+            Priority::Low => self.config.low = policy,
+        }
+        self
+    }
+
+    /// Enables or disables full jitter for every priority.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        for policy in [
+            &mut self.config.high,
+            &mut self.config.normal,
+            &mut self.config.low,
+        ] {
+            policy.jitter = jitter;
+        }
+        self
+    }
+
+    /// The policy applied to operations of `priority`.
+    pub fn policy(&self, priority: Priority) -> &RetryPolicy {
+        match priority {
+            Priority::High => &self.config.high,
+            Priority::Normal => &self.config.normal,
+            Priority::Low => &self.config.low,
+        }
+    }
+
+    /// Runs `operation` until an attempt succeeds or the policy of
+    /// `priority` runs out of retries, returning the last attempt's error.
+    /// Errors and timed-out attempts are both retried.
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        priority: Priority,
+        operation: F,
+    ) -> Result<T, RetryError<E>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::fmt::Display,
+    {
+        self.run_until_cancelled(priority, &CancellationToken::new(), operation)
+            .await
+    }
+
+    /// Like [`run`](Self::run), but gives up with [`RetryError::Cancelled`]
+    /// as soon as `token` fires, whether an attempt is running or the
+    /// manager is waiting to retry.
+    pub async fn run_until_cancelled<T, E, F, Fut>(
+        &self,
+        priority: Priority,
+        token: &CancellationToken,
+        mut operation: F,
+    ) -> Result<T, RetryError<E>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::fmt::Display,
+    {
+        let policy = self.policy(priority);
+        let label = priority.as_str();
+        let mut retries = 0;
+        loop {
+            let attempt = tokio::select! {
+                attempt = tokio::time::timeout(policy.attempt_timeout, operation()) => attempt,
+                _ = token.cancelled() => return Err(RetryError::Cancelled),
+            };
+            let error = match attempt {
+                Ok(Ok(value)) => {
+                    metrics::histogram!(ATTEMPTS_METRIC, "priority" => label)
+                        .record(f64::from(retries + 1));
+                    if retries > 0 {
+                        metrics::counter!(SUCCESS_AFTER_RETRY_METRIC, "priority" => label)
+                            .increment(1);
+                    }
+                    return Ok(value);
+                }
+                Ok(Err(e)) => RetryError::Failed(e),
+                Err(_) => {
+                    metrics::counter!(TIMEOUTS_METRIC, "priority" => label).increment(1);
+                    RetryError::Timeout(policy.attempt_timeout)
+                }
+            };
+            if retries == policy.max_retries {
+                metrics::histogram!(ATTEMPTS_METRIC, "priority" => label)
+                    .record(f64::from(retries + 1));
+                metrics::counter!(EXHAUSTED_METRIC, "priority" => label).increment(1);
+                tracing::warn!(
+                    "{} priority operation failed after {} attempts: {}",
+                    label,
+                    retries + 1,
+                    error
+                );
+                return Err(error);
+            }
+            let delay = policy.delay(retries);
+            tracing::debug!(
+                "{} priority attempt {} failed, retrying in {:?}: {}",
+                label,
+                retries + 1,
+                delay,
+                error
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = token.cancelled() => {
+                    tracing::debug!("{} priority operation cancelled", label);
+                    return Err(RetryError::Cancelled);
+                }
+            }
+            retries += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+    use super::*;
+
+    /// Runs `test` on a paused runtime with a local recorder, returning the
+    /// recorded metrics as (name, priority label, value).
+    fn recorded<F, Fut>(test: F) -> Vec<(String, String, DebugValue)>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter: Snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .start_paused(true)
+                .build()
+                .unwrap()
+                .block_on(test());
+        });
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let priority = key
+                    .key()
+                    .labels()
+                    .find(|label| label.key() == "priority")
+                    .map(|label| label.value().to_string())
+                    .unwrap_or_default();
+                (key.key().name().to_string(), priority, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_timed_out_attempts_are_counted() {
+        let manager = RetryManager::new().with_policy(
+            Priority::Low,
+            RetryPolicy {
+                max_retries: 1,
+                base_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(1),
+                attempt_timeout: Duration::from_secs(5),
+                jitter: false,
+            },
+        );
+        let metrics = recorded(|| async {
+            let result: Result<(), RetryError<String>> = manager
+                .run(Priority::Low, || async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(())
+                })
+                .await;
+            assert_eq!(result, Err(RetryError::Timeout(Duration::from_secs(5))));
+        });
+
+        assert!(metrics.contains(&(
+            TIMEOUTS_METRIC.to_string(),
+            "low".to_string(),
+            DebugValue::Counter(2)
+        )));
+        assert!(metrics.contains(&(
+            EXHAUSTED_METRIC.to_string(),
+            "low".to_string(),
+            DebugValue::Counter(1)
+        )));
+    }
+
+    #[test]
+    fn test_success_after_retry_is_counted() {
+        let manager = RetryManager::new();
+        let attempts = AtomicU32::new(0);
+        let metrics = recorded(|| async {
+            let result = manager
+                .run(Priority::High, || async {
+                    match attempts.fetch_add(1, Ordering::SeqCst) {
+                        0 | 1 => Err("busy"),
+                        n => Ok(n),
+                    }
+                })
+                .await;
+            assert_eq!(result, Ok(2));
+        });
+
+        assert!(metrics.contains(&(
+            SUCCESS_AFTER_RETRY_METRIC.to_string(),
+            "high".to_string(),
+            DebugValue::Counter(1)
+        )));
+        assert!(metrics.contains(&(
+            ATTEMPTS_METRIC.to_string(),
+            "high".to_string(),
+            DebugValue::Histogram(vec![3.0.into()])
+        )));
+        assert!(!metrics.iter().any(|(name, _, _)| name == TIMEOUTS_METRIC));
+    }
+
+    #[test]
+    fn test_default_policies_match_documented_constants() {
+        let high = Priority::High.default_policy();
+        assert_eq!(
+            (high.max_retries, high.base_delay),
+            (3, Duration::from_secs(1))
+        );
+        let low = Priority::Low.default_policy();
+        assert_eq!(
+            (low.max_retries, low.base_delay),
+            (7, Duration::from_secs(5))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_configured_policy_overrides_default() {
+        let config: RetryConfig = serde_json::from_value(serde_json::json!({
+            "high": {
+                "max_retries": 1,
+                "base_delay_ms": 10,
+                "max_delay_ms": 100,
+                "timeout_ms": 1000
+            }
+        }))
+        .unwrap();
+        assert_eq!(config.normal, Priority::Normal.default_policy());
+        let manager = RetryManager::from_config(config);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = manager
+            .run(Priority::High, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("busy")
+            })
+            .await;
+        assert_eq!(result, Err(RetryError::Failed("busy")));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_jittered_delays_vary_within_max_delay() {
+        let manager = RetryManager::new().with_jitter(true);
+        let policy = manager.policy(Priority::Normal);
+        for retry in 0..8 {
+            let backoff = policy.backoff(retry);
+            assert!(backoff <= policy.max_delay);
+            let delays: Vec<Duration> = (0..100).map(|_| policy.delay(retry)).collect();
+            assert!(delays.iter().all(|delay| *delay <= backoff));
+            assert!(delays.iter().any(|delay| *delay != delays[0]));
+        }
+
+        let deterministic = Priority::Normal.default_policy();
+        assert_eq!(deterministic.delay(2), Duration::from_secs(8));
+        assert_eq!(deterministic.delay(10), deterministic.max_delay);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelling_mid_backoff_returns_promptly() {
+        let manager = RetryManager::new().with_policy(
+            Priority::Low,
+            RetryPolicy {
+                max_retries: 5,
+                base_delay: Duration::from_secs(60),
+                max_delay: Duration::from_secs(600),
+                attempt_timeout: Duration::from_secs(5),
+                jitter: false,
+            },
+        );
+        let token = CancellationToken::new();
+        let attempts = AtomicU32::new(0);
+        let started = tokio::time::Instant::now();
+        let cancel = {
+            let token = token.clone();
+            async move {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                token.cancel();
+            }
+        };
+
+        let (result, _) = tokio::join!(
+            manager.run_until_cancelled(Priority::Low, &token, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>("unavailable")
+            }),
+            cancel
+        );
+        assert_eq!(result, Err(RetryError::Cancelled));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(started.elapsed(), Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,391 @@
+//! Miner module management.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::concurrency::{ConcurrencyConfig, ModuleConcurrency};
+use crate::db::MinerDb;
+use crate::error::MinerError;
+use crate::inference::{
+    InferenceBackend, InferenceHandler, InferenceLimits, InferenceRequest, InferenceResponse,
+};
+use crate::retry::{RetryConfig, RetryManager};
+
+/// Miner-wide settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinerConfig {
+    /// Smallest stake a module may register or be updated with.
+    pub min_stake: u64,
+    /// Maximum number of modules this miner will serve.
+    pub max_modules: u64,
+    /// Limits a module runs with when it is started without its own.
+    #[serde(default)]
+    pub default_resource_limits: ResourceLimits,
+    /// Host-wide ceiling no module's limits may exceed.
+    #[serde(default)]
+    pub max_resource_limits: ResourceLimits,
+    /// Retry policy of each operation priority.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Per-module caps on concurrent inference.
+    #[serde(default)]
+    pub inference_concurrency: ConcurrencyConfig,
+}
+
+impl Default for MinerConfig {
+    fn default() -> Self {
+        Self {
+            min_stake: 1,
+            max_modules: 16,
+            default_resource_limits: ResourceLimits::default(),
+            max_resource_limits: ResourceLimits::default(),
+            retry: RetryConfig::default(),
+            inference_concurrency: ConcurrencyConfig::default(),
+        }
+    }
+}
+
+impl MinerConfig {
+    /// Checks that the default resource limits fit within the host ceiling
+    /// and that every concurrency cap allows a request.
+    pub fn validate(&self) -> Result<(), MinerError> {
+        self.default_resource_limits
+            .check_within(&self.max_resource_limits)
+            .map_err(|e| MinerError::InvalidConfig(format!("default resource limits: {}", e)))?;
+        self.inference_concurrency.validate()
+    }
+}
+
+/// Resources a module may use. `None` leaves a resource unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Memory limit in bytes.
+    pub memory_bytes: Option<u64>,
+    /// CPU limit in thousandths of a CPU; `1500` is one and a half CPUs.
+    pub cpu_millis: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// These limits, with any resource they leave unset taken from
+    /// `defaults`.
+    pub fn or(self, defaults: ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            memory_bytes: self.memory_bytes.or(defaults.memory_bytes),
+            cpu_millis: self.cpu_millis.or(defaults.cpu_millis),
+        }
+    }
+
+    /// Checks that no resource exceeds `ceiling`. A resource left unlimited
+    /// exceeds any ceiling set for it.
+    pub fn check_within(&self, ceiling: &ResourceLimits) -> Result<(), String> {
+        let resources = [
+            ("memory_bytes", self.memory_bytes, ceiling.memory_bytes),
+            ("cpu_millis", self.cpu_millis, ceiling.cpu_millis),
+        ];
+        for (resource, limit, max) in resources {
+            match (limit, max) {
+                (_, None) => {}
+                (None, Some(max)) => {
+                    return Err(format!(
+                        "{} is unlimited but the host allows {}",
+                        resource, max
+                    ))
+                }
+                (Some(limit), Some(max)) if limit > max => {
+                    return Err(format!(
+                        "{} of {} exceeds the host limit of {}",
+                        resource, limit, max
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a module is started.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MiningConfig {
+    /// Limits for this module; resources left unset fall back to the
+    /// miner's `default_resource_limits`.
+    #[serde(default)]
+    pub resource_limits: Option<ResourceLimits>,
+}
+
+/// Status of a module served by the miner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleStatus {
+    pub name: String,
+    pub active: bool,
+    /// Current stake.
+    pub stake: u64,
+    /// Total seconds the module has been running, across restarts.
+    pub uptime: u64,
+    /// When the module was last started, if it is running.
+    pub started_at: Option<DateTime<Utc>>,
+    /// Limits the module was last started with.
+    pub resource_limits: ResourceLimits,
+}
+
+/// Request to register a module with the miner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub name: String,
+    pub stake: u64,
+}
+
+/// A change of a module's stake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StakeUpdate {
+    pub stake: u64,
+}
+
+/// A recorded stake change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StakeHistoryEntry {
+    /// Stake after the change.
+    pub stake: u64,
+    /// When the change was applied.
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A block produced by a module and the reward it earned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRecord {
+    pub block: u64,
+    pub reward: u64,
+}
+
+/// Block and reward totals of a module, with rates derived from them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinerMetrics {
+    pub name: String,
+    pub blocks: u64,
+    pub total_rewards: u64,
+    /// When the module's first recorded block was produced.
+    pub first_block_at: Option<DateTime<Utc>>,
+    pub last_block_at: Option<DateTime<Utc>>,
+    /// Blocks per hour since the first recorded block.
+    pub blocks_per_hour: f64,
+    /// Rewards per hour since the first recorded block.
+    pub reward_rate: f64,
+}
+
+impl MinerMetrics {
+    /// Derives the rates of `name` from its totals as of `now`. Rates are
+    /// zero until time has passed since the first block.
+    pub fn new(
+        name: String,
+        blocks: u64,
+        total_rewards: u64,
+        first_block_at: Option<DateTime<Utc>>,
+        last_block_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let hours = first_block_at.map_or(0.0, |first| {
+            (now - first).num_milliseconds().max(0) as f64 / 3_600_000.0
+        });
+        let per_hour = |total: u64| {
+            if hours > 0.0 {
+                total as f64 / hours
+            } else {
+                0.0
+            }
+        };
+        Self {
+            blocks_per_hour: per_hour(blocks),
+            reward_rate: per_hour(total_rewards),
+            name,
+            blocks,
+            total_rewards,
+            first_block_at,
+            last_block_at,
+        }
+    }
+}
+
+/// Point-in-time metrics of every active module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub taken_at: DateTime<Utc>,
+    pub miners: Vec<MinerMetrics>,
+}
+
+/// Business logic for modules served by the miner.
+#[derive(Clone)]
+pub struct MinerService {
+    db: MinerDb,
+    config: MinerConfig,
+    inference: Option<Arc<InferenceHandler<Arc<dyn InferenceBackend>>>>,
+}
+
+impl MinerService {
+    pub fn new(db: MinerDb, config: MinerConfig) -> Self {
+        Self {
+            db,
+            config,
+            inference: None,
+        }
+    }
+
+    /// Serves inference requests with `backend`, capping each module's
+    /// concurrent requests as the config's `inference_concurrency` sets and
+    /// retrying failures as its `retry` policies set.
+    pub fn with_inference(
+        mut self,
+        backend: impl InferenceBackend + 'static,
+        limits: InferenceLimits,
+    ) -> Self {
+        let backend: Arc<dyn InferenceBackend> = Arc::new(backend);
+        let handler = InferenceHandler::new(backend, limits)
+            .with_concurrency(ModuleConcurrency::new(&self.config.inference_concurrency))
+            .with_retry(RetryManager::from_config(self.config.retry.clone()));
+        self.inference = Some(Arc::new(handler));
+        self
+    }
+
+    /// Returns the miner configuration.
+    pub fn config(&self) -> &MinerConfig {
+        &self.config
+    }
+
+    /// Resolves the limits a module starts with under `mining`, rejecting
+    /// any above the host ceiling.
+    fn resource_limits(&self, mining: &MiningConfig) -> Result<ResourceLimits, MinerError> {
+        let limits = mining
+            .resource_limits
+            .unwrap_or_default()
+            .or(self.config.default_resource_limits);
+        limits
+            .check_within(&self.config.max_resource_limits)
+            .map_err(MinerError::InvalidRequest)?;
+        Ok(limits)
+    }
+
+    fn validate_stake(&self, stake: u64) -> Result<(), MinerError> {
+        if stake < self.config.min_stake {
+            return Err(MinerError::InvalidStake(format!(
+                "stake {} is below the minimum of {}",
+                stake, self.config.min_stake
+            )));
+        }
+        Ok(())
+    }
+
+    /// Registers a module, running with the default resource limits.
+    pub async fn register(&self, request: RegisterRequest) -> Result<ModuleStatus, MinerError> {
+        self.validate_stake(request.stake)?;
+        let limits = self.resource_limits(&MiningConfig::default())?;
+        if self.db.count_modules().await? >= self.config.max_modules {
+            return Err(MinerError::ResourceExceeded(format!(
+                "miner already serves the maximum of {} modules",
+                self.config.max_modules
+            )));
+        }
+        self.db
+            .insert_module(&request.name, request.stake, Utc::now())
+            .await?;
+        self.db.set_resource_limits(&request.name, &limits).await?;
+        self.status(&request.name).await
+    }
+
+    /// Applies a stake update to a module.
+    pub async fn update_stake(
+        &self,
+        name: &str,
+        update: StakeUpdate,
+    ) -> Result<ModuleStatus, MinerError> {
+        self.validate_stake(update.stake)?;
+        self.db.set_stake(name, update.stake, Utc::now()).await?;
+        self.status(name).await
+    }
+
+    /// Returns the stake changes of a module within an optional time range.
+    pub async fn stake_history(
+        &self,
+        name: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<StakeHistoryEntry>, MinerError> {
+        // Distinguish an unknown module from one with no stake changes.
+        self.status(name).await?;
+        self.db.stake_history(name, since, until).await
+    }
+
+    /// Starts serving a module with the default resource limits.
+    pub async fn start(&self, name: &str) -> Result<ModuleStatus, MinerError> {
+        self.start_with(name, &MiningConfig::default()).await
+    }
+
+    /// Starts serving a module as `mining` describes.
+    pub async fn start_with(
+        &self,
+        name: &str,
+        mining: &MiningConfig,
+    ) -> Result<ModuleStatus, MinerError> {
+        let limits = self.resource_limits(mining)?;
+        self.db.set_resource_limits(name, &limits).await?;
+        self.db.start_module(name, Utc::now()).await?;
+        self.status(name).await
+    }
+
+    /// Stops serving a module, keeping the uptime it accumulated so far.
+    pub async fn stop(&self, name: &str) -> Result<ModuleStatus, MinerError> {
+        self.db.stop_module(name, Utc::now()).await?;
+        self.status(name).await
+    }
+
+    /// Returns the status of a module.
+    pub async fn status(&self, name: &str) -> Result<ModuleStatus, MinerError> {
+        self.db.get_module(name, Utc::now()).await
+    }
+
+    /// Records a block produced by a module.
+    pub async fn record_block(
+        &self,
+        name: &str,
+        record: BlockRecord,
+    ) -> Result<MinerMetrics, MinerError> {
+        let now = Utc::now();
+        self.db
+            .insert_block(name, record.block, record.reward, now)
+            .await?;
+        self.db.module_metrics(name, now).await
+    }
+
+    /// Takes a snapshot of the metrics of every active module.
+    pub async fn metrics_snapshot(&self) -> Result<MetricsSnapshot, MinerError> {
+        let now = Utc::now();
+        Ok(MetricsSnapshot {
+            taken_at: now,
+            miners: self.db.active_metrics(now).await?,
+        })
+    }
+
+    /// Runs an inference request on the running module `name`.
+    pub async fn infer(
+        &self,
+        name: &str,
+        mut request: InferenceRequest,
+    ) -> Result<InferenceResponse, MinerError> {
+        let handler = self.inference.as_ref().ok_or_else(|| {
+            MinerError::InferenceFailed("no inference backend is configured".into())
+        })?;
+        if !self.status(name).await?.active {
+            return Err(MinerError::InvalidRequest(format!(
+                "module {} is not running",
+                name
+            )));
+        }
+        request.module = name.to_string();
+        handler.handle(request).await
+    }
+
+    /// Lists all modules.
+    pub async fn list(&self) -> Result<Vec<ModuleStatus>, MinerError> {
+        self.db.list_modules(Utc::now()).await
+    }
+}
@@ -0,0 +1 @@
+ALTER TABLE modules ADD COLUMN config TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp ON audit_log (timestamp);
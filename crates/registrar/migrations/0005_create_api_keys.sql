@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS api_keys (
+    public_key TEXT PRIMARY KEY,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE modules ADD COLUMN repo_url TEXT;
+ALTER TABLE modules ADD COLUMN git_ref TEXT;
+ALTER TABLE modules ADD COLUMN commit_sha TEXT;
+ALTER TABLE modules ADD COLUMN source_path TEXT;
@@ -0,0 +1 @@
+ALTER TABLE modules ADD COLUMN downloads INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS miners (
+    uid INTEGER PRIMARY KEY,
+    key TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    registered_at TEXT NOT NULL
+);
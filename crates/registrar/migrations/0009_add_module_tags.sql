@@ -0,0 +1 @@
+ALTER TABLE modules ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,4 @@
+-- `modules.name` is already indexed by its UNIQUE constraint.
+CREATE INDEX IF NOT EXISTS idx_modules_status ON modules (status);
+CREATE INDEX IF NOT EXISTS idx_modules_module_type ON modules (module_type);
+CREATE INDEX IF NOT EXISTS idx_audit_log_module ON audit_log (module);
@@ -0,0 +1 @@
+ALTER TABLE modules ADD COLUMN owner TEXT;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS module_capabilities (
+    module_id INTEGER NOT NULL REFERENCES modules(id) ON DELETE CASCADE,
+    capability TEXT NOT NULL,
+    PRIMARY KEY (capability, module_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_module_capabilities_module ON module_capabilities (module_id);
+
+INSERT OR IGNORE INTO module_capabilities (module_id, capability)
+    SELECT modules.id, capabilities.value
+    FROM modules, json_each(modules.config, '$.capabilities') AS capabilities;
@@ -0,0 +1,146 @@
+//! Audit log handlers.
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::{status_for, AppState};
+use crate::audit::{AuditPage, AuditQuery};
+
+/// Query parameters for `GET /audit`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditParams {
+    pub module: Option<String>,
+    pub limit: Option<u32>,
+    /// RFC3339 lower bound on the entry timestamp.
+    pub since: Option<DateTime<Utc>>,
+    /// RFC3339 upper bound on the entry timestamp.
+    pub until: Option<DateTime<Utc>>,
+    /// Cursor returned as `next_cursor` by a previous page.
+    pub cursor: Option<String>,
+}
+
+/// `GET /audit?module=&limit=&since=&until=&cursor=`
+pub async fn list_audit(
+    State(state): State<AppState>,
+    Query(params): Query<AuditParams>,
+) -> Result<Json<AuditPage>, StatusCode> {
+    let before_id = params
+        .cursor
+        .as_deref()
+        .map(str::parse::<i64>)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let query = AuditQuery {
+        module: params.module,
+        limit: params.limit,
+        since: params.since,
+        until: params.until,
+        before_id,
+    };
+    state
+        .registry
+        .list_audit(&query)
+        .await
+        .map(Json)
+        .map_err(|e| status_for(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::api::test_support::{module_body, send, send_with_headers, test_app};
+    use crate::audit::{AuditAction, NewAuditEntry};
+    use crate::registry::Registry;
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_create_and_delete_produce_audit_rows() {
+        let (app, _) = test_app().await;
+        let headers = [("x-actor", "alice")];
+
+        let (status, _) = send_with_headers(
+            &app,
+            "POST",
+            "/modules",
+            Some(module_body("echo", "docker")),
+            &headers,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, _) = send_with_headers(&app, "DELETE", "/modules/echo", None, &headers).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, body) = send(&app, "GET", "/audit?module=echo&limit=10", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let rows = body["items"].as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["action"], "delete");
+        assert_eq!(rows[0]["before_status"], "stopped");
+        assert_eq!(rows[0]["after_status"], json!(null));
+        assert_eq!(rows[1]["action"], "create");
+        assert_eq!(rows[1]["after_status"], "stopped");
+        assert!(rows.iter().all(|r| r["actor"] == "alice"));
+    }
+
+    #[tokio::test]
+    async fn test_time_window_and_cursor() {
+        let (app, registry) = test_app().await;
+        let early = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let late = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        for (module, timestamp) in [("a", early), ("b", late), ("c", late)] {
+            registry
+                .record_audit(NewAuditEntry {
+                    module: module.into(),
+                    action: AuditAction::Create,
+                    actor: "tester".into(),
+                    timestamp,
+                    before_status: None,
+                    after_status: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let (status, body) = send(&app, "GET", "/audit?since=2024-03-01T00:00:00Z", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let modules: Vec<_> = body["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["module"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(modules, vec!["c", "b"]);
+        assert_eq!(body["next_cursor"], json!(null));
+
+        let (_, first) = send(&app, "GET", "/audit?limit=2", None).await;
+        assert_eq!(first["items"].as_array().unwrap().len(), 2);
+        let cursor = first["next_cursor"].as_str().unwrap().to_string();
+
+        let (_, second) = send(
+            &app,
+            "GET",
+            &format!("/audit?limit=2&cursor={}", cursor),
+            None,
+        )
+        .await;
+        let rest = second["items"].as_array().unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0]["module"], "a");
+        assert_eq!(second["next_cursor"], json!(null));
+        for (body, page) in [(&first, 1), (&second, 2)] {
+            assert_eq!(body["total"], 3);
+            assert_eq!(body["per_page"], 2);
+            assert_eq!(body["page"], page);
+        }
+
+        let (status, _) = send(&app, "GET", "/audit?cursor=bogus", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,358 @@
+//! Authentication and authorization handlers.
+
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{Actor, AppState};
+use crate::auth::{AuthError, AuthManager, AuthRequest, Challenge, Role};
+
+/// Response body for a successful authentication.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthResponse {
+    pub public_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn status_for(err: &AuthError) -> StatusCode {
+    match err {
+        AuthError::Malformed(_) => StatusCode::BAD_REQUEST,
+        AuthError::InvalidSignature | AuthError::InvalidChallenge | AuthError::InvalidSession => {
+            StatusCode::UNAUTHORIZED
+        }
+        AuthError::UnknownKey(_) => StatusCode::FORBIDDEN,
+        AuthError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// `GET /auth/challenge`
+///
+/// Issues a one-time challenge that the message signed for `POST /auth`
+/// must contain. Answers 404 when authorization is not enabled.
+pub async fn challenge(State(state): State<AppState>) -> Result<Json<Challenge>, StatusCode> {
+    let auth = state.auth.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(auth.issue_challenge()))
+}
+
+/// `POST /auth`
+///
+/// Verifies the signed request. When authorization is enabled, also opens a
+/// session whose token must be sent as `Authorization: Bearer <token>`.
+pub async fn authenticate(
+    State(state): State<AppState>,
+    Json(request): Json<AuthRequest>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    let Some(auth) = &state.auth else {
+        request.verify().map_err(|e| status_for(&e))?;
+        return Ok(Json(AuthResponse {
+            public_key: request.public_key,
+            role: None,
+            token: None,
+            expires_at: None,
+        }));
+    };
+
+    let session = auth.login(&request).await.map_err(|e| status_for(&e))?;
+    Ok(Json(AuthResponse {
+        public_key: session.public_key,
+        role: Some(session.role),
+        token: Some(session.token),
+        expires_at: Some(session.expires_at),
+    }))
+}
+
+/// Middleware requiring a session whose role is sufficient for the request
+/// method (see [`Role::required_for`]). Rejects with 401 when there is no
+/// valid session and 403 when the role is insufficient.
+pub async fn authorize(
+    State(auth): State<AuthManager>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let session = match token.map(|t| auth.session(t)) {
+        Some(Ok(session)) => session,
+        _ => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+
+    let required = Role::required_for(request.method());
+    if session.role < required {
+        tracing::warn!(
+            "Key {} with role {} denied {} {} (requires {})",
+            session.public_key,
+            session.role,
+            request.method(),
+            request.uri().path(),
+            required
+        );
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    request
+        .extensions_mut()
+        .insert(Actor(session.public_key.clone()));
+    request.extensions_mut().insert(session);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use crate::api::rate_limit::RateLimitConfig;
+    use crate::api::test_support::{module_body, send, send_with_headers};
+    use crate::api::{create_router, AppState};
+    use crate::auth::{AuthManager, Role};
+    use crate::registry::SqliteRegistry;
+
+    async fn post_auth(app: &Router, from: &str, body: serde_json::Value) -> StatusCode {
+        let addr: SocketAddr = from.parse().unwrap();
+        let mut request = Request::builder()
+            .method("POST")
+            .uri("/auth")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(addr));
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_flood_is_throttled_per_ip() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        let state =
+            AppState::new(std::sync::Arc::new(registry)).with_auth_rate_limit(RateLimitConfig {
+                max_requests: 3,
+                window: Duration::from_secs(60),
+                max_reads: None,
+            });
+        let app = create_router(state);
+        let bogus = json!({
+            "public_key": "00".repeat(32),
+            "message": "hello",
+            "signature": "11".repeat(64),
+        });
+
+        let mut statuses = Vec::new();
+        for _ in 0..5 {
+            statuses.push(post_auth(&app, "10.0.0.1:1000", bogus.clone()).await);
+        }
+        assert!(statuses[..3]
+            .iter()
+            .all(|s| *s != StatusCode::TOO_MANY_REQUESTS));
+        assert!(statuses[3..]
+            .iter()
+            .all(|s| *s == StatusCode::TOO_MANY_REQUESTS));
+
+        let other = post_auth(&app, "10.0.0.2:1000", bogus).await;
+        assert_ne!(other, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    /// Signs a login for `key` over a freshly issued challenge.
+    async fn signed_login(app: &Router, key: &SigningKey) -> serde_json::Value {
+        let (status, body) = send(app, "GET", "/auth/challenge", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let message = format!("login:{}", body["challenge"].as_str().unwrap());
+        json!({
+            "public_key": hex::encode(key.verifying_key().to_bytes()),
+            "message": message,
+            "signature": hex::encode(key.sign(message.as_bytes()).to_bytes()),
+        })
+    }
+
+    /// Logs in with `key` and returns its `Authorization` header value.
+    async fn login(app: &Router, key: &SigningKey) -> String {
+        let request = signed_login(app, key).await;
+        let (status, body) = send(app, "POST", "/auth", Some(request)).await;
+        assert_eq!(status, StatusCode::OK);
+        format!("Bearer {}", body["token"].as_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_readonly_key_denied_mutation_but_allowed_read() {
+        let registry = std::sync::Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let auth = AuthManager::new(registry.pool().clone());
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = hex::encode(key.verifying_key().to_bytes());
+        auth.set_role(&public_key, Role::ReadOnly).await.unwrap();
+        let app = create_router(AppState::new(registry).with_auth(auth));
+
+        let (status, _) = send(&app, "GET", "/modules", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let request = signed_login(&app, &key).await;
+        let (status, body) = send(&app, "POST", "/auth", Some(request)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["role"], "readonly");
+        let bearer = format!("Bearer {}", body["token"].as_str().unwrap());
+        let headers = [("authorization", bearer.as_str())];
+
+        let (status, _) = send_with_headers(&app, "GET", "/modules", None, &headers).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send_with_headers(
+            &app,
+            "POST",
+            "/modules",
+            Some(module_body("echo", "docker")),
+            &headers,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = send_with_headers(&app, "DELETE", "/modules/echo", None, &headers).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_replayed_login_rejected() {
+        let registry = std::sync::Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let auth = AuthManager::new(registry.pool().clone());
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = hex::encode(key.verifying_key().to_bytes());
+        auth.set_role(&public_key, Role::Operator).await.unwrap();
+        let app = create_router(AppState::new(registry).with_auth(auth));
+
+        let request = signed_login(&app, &key).await;
+        let (status, _) = send(&app, "POST", "/auth", Some(request.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(&app, "POST", "/auth", Some(request)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // A signature over a message without any challenge never logs in.
+        let message = "login";
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/auth",
+            Some(json!({
+                "public_key": public_key,
+                "message": message,
+                "signature": hex::encode(key.sign(message.as_bytes()).to_bytes()),
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_only_owner_or_admin_may_modify_module() {
+        let registry = std::sync::Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let auth = AuthManager::new(registry.pool().clone());
+        let [alice, bob, admin] = [1u8, 2, 3].map(|n| SigningKey::from_bytes(&[n; 32]));
+        for (key, role) in [
+            (&alice, Role::Operator),
+            (&bob, Role::Operator),
+            (&admin, Role::Admin),
+        ] {
+            let public_key = hex::encode(key.verifying_key().to_bytes());
+            auth.set_role(&public_key, role).await.unwrap();
+        }
+        let app = create_router(AppState::new(registry).with_auth(auth));
+        let [alice_auth, bob_auth, admin_auth] = [
+            login(&app, &alice).await,
+            login(&app, &bob).await,
+            login(&app, &admin).await,
+        ];
+        let as_alice = [("authorization", alice_auth.as_str())];
+        let as_bob = [("authorization", bob_auth.as_str())];
+        let as_admin = [("authorization", admin_auth.as_str())];
+
+        for name in ["echo", "relay"] {
+            let (status, body) = send_with_headers(
+                &app,
+                "POST",
+                "/modules",
+                Some(module_body(name, "local")),
+                &as_alice,
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+            assert_eq!(body["owner"], hex::encode(alice.verifying_key().to_bytes()));
+        }
+
+        let (status, body) =
+            send_with_headers(&app, "DELETE", "/modules/echo", None, &as_bob).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], 403);
+        let (status, _) =
+            send_with_headers(&app, "POST", "/modules/echo/stop", None, &as_bob).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = send_with_headers(&app, "DELETE", "/modules/echo", None, &as_alice).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) =
+            send_with_headers(&app, "DELETE", "/modules/relay", None, &as_admin).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_only_admin_may_manage_webhooks() {
+        let registry = std::sync::Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let auth = AuthManager::new(registry.pool().clone());
+        let [operator, admin] = [1u8, 2].map(|n| SigningKey::from_bytes(&[n; 32]));
+        for (key, role) in [(&operator, Role::Operator), (&admin, Role::Admin)] {
+            let public_key = hex::encode(key.verifying_key().to_bytes());
+            auth.set_role(&public_key, role).await.unwrap();
+        }
+        let app = create_router(AppState::new(registry).with_auth(auth));
+        let [operator_auth, admin_auth] = [login(&app, &operator).await, login(&app, &admin).await];
+        let as_operator = [("authorization", operator_auth.as_str())];
+        let as_admin = [("authorization", admin_auth.as_str())];
+        let hook = json!({"url": "https://example.com/hook"});
+
+        let (status, _) =
+            send_with_headers(&app, "POST", "/webhooks", Some(hook.clone()), &as_operator).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) =
+            send_with_headers(&app, "POST", "/webhooks", Some(hook), &as_admin).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let path = format!("/webhooks/{}", body["id"]);
+        let (status, _) = send_with_headers(&app, "GET", "/webhooks", None, &as_operator).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send_with_headers(&app, "DELETE", &path, None, &as_operator).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send_with_headers(&app, "DELETE", &path, None, &as_admin).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_signature_rejected_before_verification() {
+        let (app, _) = crate::api::test_support::test_app().await;
+        let status = post_auth(
+            &app,
+            "10.0.0.1:1000",
+            json!({
+                "public_key": "00".repeat(32),
+                "message": "hello",
+                "signature": "not-hex",
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,236 @@
+//! API documentation and version endpoints. These are public: they are
+//! served without authorization and allowed from any origin, so browsers
+//! can load them whatever the registrar's auth settings.
+
+use axum::extract::State;
+use axum::response::Html;
+use axum::Json;
+use schemars::schema_for;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::modules::CreateModuleRequest;
+use super::AppState;
+
+/// Routes described by `GET /openapi.json`, as (path, method, summary).
+const ROUTES: &[(&str, &str, &str)] = &[
+    (
+        "/health",
+        "get",
+        "Check that the registrar is serving requests",
+    ),
+    (
+        "/ready",
+        "get",
+        "Check that the registrar has finished starting up",
+    ),
+    ("/version", "get", "Registrar version"),
+    (
+        "/auth/challenge",
+        "get",
+        "Issue a one-time challenge to sign for login",
+    ),
+    ("/auth", "post", "Authenticate with a signed message"),
+    ("/modules", "get", "List modules"),
+    ("/modules", "post", "Register a module"),
+    (
+        "/modules",
+        "delete",
+        "Delete every module matching a filter",
+    ),
+    ("/modules/actions", "post", "Start or stop matching modules"),
+    (
+        "/modules/schema",
+        "get",
+        "JSON Schema of the module create request",
+    ),
+    ("/modules/validate", "post", "Check a module config"),
+    ("/modules/{name}", "get", "Get a module"),
+    ("/modules/{name}", "head", "Check that a module exists"),
+    ("/modules/{name}", "delete", "Unregister a module"),
+    (
+        "/modules/{name}/status",
+        "get",
+        "Module status, optionally probed",
+    ),
+    ("/modules/{name}/status", "put", "Set a module's status"),
+    (
+        "/modules/{name}/health",
+        "get",
+        "Container, probe and chain health combined",
+    ),
+    (
+        "/modules/{name}/effective-config",
+        "get",
+        "Config the module is started with, secrets masked",
+    ),
+    (
+        "/modules/{name}/config",
+        "get",
+        "Module config as YAML or JSON",
+    ),
+    (
+        "/modules/{name}/dependents",
+        "get",
+        "Modules depending on a module",
+    ),
+    (
+        "/modules/{name}/metadata",
+        "get",
+        "Registry bookkeeping about a module",
+    ),
+    ("/modules/{name}/package", "get", "Installation package"),
+    (
+        "/modules/{name}/package/archive",
+        "get",
+        "Installation package archive",
+    ),
+    (
+        "/modules/{name}/reload",
+        "post",
+        "Reload a module's config file",
+    ),
+    ("/modules/{name}/rename", "post", "Rename a module"),
+    ("/modules/{name}/start", "post", "Start a module"),
+    ("/modules/{name}/stop", "post", "Stop a module"),
+    ("/miners", "get", "List miners"),
+    ("/miners", "post", "Register a miner"),
+    ("/miners/batch", "post", "Register several miners"),
+    ("/audit", "get", "Page through the audit log"),
+    ("/events", "get", "Stream registry events"),
+    ("/resources", "get", "Resource usage of running modules"),
+    (
+        "/resources/usage",
+        "get",
+        "Resource usage per running module, busiest first",
+    ),
+    ("/uploads", "post", "Start a resumable package upload"),
+    ("/uploads/{id}", "get", "Bytes received by an upload"),
+    ("/uploads/{id}", "patch", "Append a chunk to an upload"),
+    (
+        "/uploads/{id}/complete",
+        "post",
+        "Verify an upload's digest",
+    ),
+    (
+        "/uploads/{id}/ingest",
+        "post",
+        "Register the module in a completed upload",
+    ),
+    ("/webhooks", "get", "List webhooks (admin)"),
+    (
+        "/webhooks",
+        "post",
+        "Register a webhook for module events and miner registrations (admin)",
+    ),
+    ("/webhooks/{id}", "delete", "Remove a webhook (admin)"),
+    ("/metrics", "get", "Prometheus metrics"),
+];
+
+/// Response body for `GET /version`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub name: String,
+    pub version: String,
+}
+
+/// `GET /version`
+pub async fn version() -> Json<VersionInfo> {
+    Json(VersionInfo {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+/// `GET /openapi.json`
+///
+/// OpenAPI description of the registrar's routes.
+pub async fn openapi(State(state): State<AppState>) -> Json<Value> {
+    let mut paths = Map::new();
+    for (path, method, summary) in ROUTES {
+        let operations = paths
+            .entry(path.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        operations[method] = json!({
+            "summary": summary,
+            "responses": {"default": {"description": "See the error body for failures"}},
+        });
+    }
+    let server = if state.base_path.is_empty() {
+        "/"
+    } else {
+        state.base_path.as_str()
+    };
+    Json(json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Synapse registrar",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{"url": server}],
+        "paths": paths,
+        "components": {
+            "schemas": {
+                "CreateModuleRequest": schema_for!(CreateModuleRequest),
+            },
+        },
+    }))
+}
+
+/// `GET /docs`
+///
+/// Swagger UI for `GET /openapi.json`.
+pub async fn docs(State(state): State<AppState>) -> Html<String> {
+    Html(format!(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>Synapse registrar API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({{ url: "{}/openapi.json", dom_id: "#swagger-ui" }});</script>
+</body>
+</html>
+"##,
+        state.base_path
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::http::StatusCode;
+
+    use crate::api::test_support::send_raw;
+    use crate::api::{create_router, AppState};
+    use crate::auth::AuthManager;
+    use crate::registry::SqliteRegistry;
+
+    #[tokio::test]
+    async fn test_docs_are_public_while_modules_require_auth() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let auth = AuthManager::new(registry.pool().clone());
+        let app = create_router(AppState::new(registry).with_auth(auth));
+        let origin = [("origin", "https://docs.example")];
+
+        let (status, headers, body) = send_raw(&app, "GET", "/openapi.json", &origin).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["access-control-allow-origin"], "*");
+        let spec: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(spec["openapi"], "3.0.3");
+        assert!(spec["paths"]["/modules/{name}"]["delete"].is_object());
+
+        for path in ["/docs", "/health", "/ready", "/version"] {
+            let (status, headers, _) = send_raw(&app, "GET", path, &origin).await;
+            assert_eq!(status, StatusCode::OK, "{}", path);
+            assert_eq!(headers["access-control-allow-origin"], "*", "{}", path);
+        }
+
+        let (status, _, _) = send_raw(&app, "GET", "/modules", &origin).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,109 @@
+//! The JSON body sent with API errors.
+
+use axum::extract::Request;
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::status_for;
+use crate::error::RegistryError;
+
+/// Header carrying the id of a request, echoed on every response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id accepted from a client; longer ids are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Body of an error response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// HTTP status code.
+    pub code: u16,
+    /// Description of the error.
+    pub error: String,
+    /// Id of the request that failed, as sent in `x-request-id`.
+    pub request_id: Option<String>,
+}
+
+/// An error answered with a status code and an [`ErrorBody`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status, status.canonical_reason().unwrap_or("Error"))
+    }
+}
+
+impl From<RegistryError> for ApiError {
+    fn from(err: RegistryError) -> Self {
+        let status = status_for(&err);
+        if status.is_server_error() {
+            // Database details stay in the log.
+            tracing::error!("Registry error: {}", err);
+            status.into()
+        } else {
+            Self::new(status, err.to_string())
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.status.as_u16(),
+            error: self.message,
+            request_id: REQUEST_ID.try_with(Clone::clone).ok(),
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+fn new_request_id() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 8]>())
+}
+
+/// Middleware giving each request an id, taken from its `x-request-id`
+/// header or generated, which is echoed on the response and included in
+/// any [`ErrorBody`].
+pub async fn assign_request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id);
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,66 @@
+//! Server-sent stream of registry events.
+
+use std::convert::Infallible;
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::stream::{self, Stream, StreamExt};
+use tokio::sync::broadcast;
+
+use super::AppState;
+use crate::events::{EventCursor, RegistryEvent};
+
+/// Header a reconnecting client sends with the id of the last event it saw.
+pub const LAST_EVENT_ID: &str = "last-event-id";
+
+fn to_sse(event: &RegistryEvent) -> Option<Event> {
+    match Event::default()
+        .id(event.cursor().to_string())
+        .event("registry")
+        .json_data(event)
+    {
+        Ok(sse) => Some(sse),
+        Err(e) => {
+            tracing::warn!("Failed to encode registry event {}: {}", event.id, e);
+            None
+        }
+    }
+}
+
+/// `GET /events`
+///
+/// Streams [`RegistryEvent`]s as server-sent events with `<epoch>-<id>`
+/// ids. A client sending `Last-Event-ID` first receives the retained
+/// events it missed, or all of them if the registrar restarted since.
+pub async fn stream_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last = headers
+        .get(LAST_EVENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(EventCursor::parse);
+    let (replay, receiver) = state.events.subscribe_after(last);
+    let seen = replay.last().map(RegistryEvent::cursor).or(last);
+
+    let live = stream::unfold((receiver, seen), |(mut receiver, seen)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if seen.is_some_and(|seen| !seen.precedes(&event)) => continue,
+                Ok(event) => {
+                    let seen = Some(event.cursor());
+                    return Some((event, (receiver, seen)));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Event stream client lagged, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    let events = stream::iter(replay)
+        .chain(live)
+        .filter_map(|event| async move { to_sse(&event).map(Ok) });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,23 @@
+//! Prometheus metrics handler.
+
+use axum::extract::State;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use super::AppState;
+
+/// `GET /metrics`
+///
+/// Renders every recorded metric in the Prometheus text format. Answers 404
+/// when the registrar was started without a metrics recorder.
+pub async fn get_metrics(State(state): State<AppState>) -> Result<Response, StatusCode> {
+    let handle = state.metrics.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        handle.render(),
+    )
+        .into_response())
+}
@@ -0,0 +1,192 @@
+//! Miner registration handlers.
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Utc;
+
+use synapse_chain_api::ss58;
+
+use super::{status_for, AppState};
+use crate::error::RegistryError;
+use crate::miner::{Miner, RegisterOutcome, RegisterResult, RegisteredMiner, Registration};
+use crate::webhooks::{MinerStatusChange, WebhookEvent};
+
+/// Delivers a miner's registration to webhooks.
+fn notify(state: &AppState, miner: Miner, registration: Registration) {
+    let change = MinerStatusChange {
+        miner,
+        registration,
+        timestamp: Utc::now(),
+    };
+    state
+        .webhooks
+        .notify(&state.tasks, WebhookEvent::Miner(change));
+}
+
+/// `GET /miners`
+pub async fn list_miners(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RegisteredMiner>>, StatusCode> {
+    state
+        .registry
+        .list_miners()
+        .await
+        .map(Json)
+        .map_err(|e| status_for(&e))
+}
+
+/// `POST /miners`
+///
+/// Answers 201 for a new miner and 200 when an existing uid is
+/// re-registered with the same key.
+pub async fn register_miner(
+    State(state): State<AppState>,
+    Json(miner): Json<Miner>,
+) -> Result<StatusCode, StatusCode> {
+    if !ss58::is_valid(&miner.key) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let registration = state
+        .registry
+        .register_miner(&miner)
+        .await
+        .map_err(|e| status_for(&e))?;
+    notify(&state, miner, registration);
+    Ok(match registration {
+        Registration::Created => StatusCode::CREATED,
+        Registration::Updated => StatusCode::OK,
+    })
+}
+
+/// `POST /miners/batch`
+///
+/// Registers each miner in order and reports every outcome; a miner that
+/// cannot be registered does not stop the rest.
+pub async fn register_miners(
+    State(state): State<AppState>,
+    Json(miners): Json<Vec<Miner>>,
+) -> Json<Vec<RegisterResult>> {
+    let mut results = Vec::with_capacity(miners.len());
+    for miner in miners {
+        if let Err(e) = ss58::validate_address(&miner.key) {
+            results.push(RegisterResult {
+                uid: miner.uid,
+                outcome: RegisterOutcome::Invalid,
+                error: Some(format!("Invalid key: {}", e)),
+            });
+            continue;
+        }
+        let registered = state.registry.register_miner(&miner).await;
+        let uid = miner.uid;
+        if let Ok(registration) = &registered {
+            notify(&state, miner, *registration);
+        }
+        let (outcome, error) = match registered {
+            Ok(Registration::Created) => (RegisterOutcome::Registered, None),
+            Ok(Registration::Updated) => (RegisterOutcome::Updated, None),
+            Err(e @ RegistryError::MinerExists(_)) => (RegisterOutcome::Conflict, Some(e)),
+            Err(e) => (RegisterOutcome::Failed, Some(e)),
+        };
+        results.push(RegisterResult {
+            uid,
+            outcome,
+            error: error.map(|e| e.to_string()),
+        });
+    }
+    Json(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use synapse_chain_api::ss58;
+
+    use crate::api::test_support::{send, test_app};
+    use crate::registry::Registry;
+
+    #[tokio::test]
+    async fn test_registration_requires_ss58_key() {
+        let (app, _) = test_app().await;
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/miners",
+            Some(json!({"uid": 1, "key": "not-an-address", "name": "bad"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let key = ss58::encode(&[7; 32], ss58::DEFAULT_PREFIX);
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/miners",
+            Some(json!({"uid": 1, "key": key, "name": "good"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (_, body) = send(
+            &app,
+            "POST",
+            "/miners/batch",
+            Some(json!([{"uid": 2, "key": "5Bogus", "name": "bad"}])),
+        )
+        .await;
+        assert_eq!(body[0]["outcome"], "invalid");
+    }
+
+    #[tokio::test]
+    async fn test_reregistration_updates_name() {
+        let (app, registry) = test_app().await;
+        let key = ss58::encode(&[1; 32], ss58::DEFAULT_PREFIX);
+        let miner = |name: &str| json!({"uid": 1, "key": key, "name": name});
+
+        let (status, _) = send(&app, "POST", "/miners", Some(miner("old"))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = send(&app, "POST", "/miners", Some(miner("new"))).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let miners = registry.list_miners().await.unwrap();
+        assert_eq!(miners.len(), 1);
+        assert_eq!(miners[0].miner.name, "new");
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_key_rejected() {
+        let (app, registry) = test_app().await;
+        let [first, second] = [1, 2].map(|n| ss58::encode(&[n; 32], ss58::DEFAULT_PREFIX));
+        send(
+            &app,
+            "POST",
+            "/miners",
+            Some(json!({"uid": 1, "key": first, "name": "a"})),
+        )
+        .await;
+
+        // Same uid, different key.
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/miners",
+            Some(json!({"uid": 1, "key": second, "name": "b"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        // Same key, different uid.
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/miners",
+            Some(json!({"uid": 2, "key": first, "name": "b"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let miners = registry.list_miners().await.unwrap();
+        assert_eq!(miners[0].miner.key, first);
+        assert_eq!(miners[0].miner.name, "a");
+    }
+}
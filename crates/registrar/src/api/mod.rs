@@ -0,0 +1,603 @@
+//! HTTP API for the registrar.
+
+pub mod audit;
+pub mod auth;
+pub mod docs;
+pub mod error;
+pub mod events;
+pub mod metrics;
+pub mod miners;
+pub mod modules;
+pub mod packages;
+pub mod rate_limit;
+pub mod resources;
+pub mod uploads;
+pub mod webhooks;
+pub mod ws;
+
+#[cfg(test)]
+pub(crate) mod test_support;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::async_trait;
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::rejection::PathRejection;
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::request::Parts;
+use axum::http::{Method, StatusCode};
+use axum::middleware;
+use axum::routing::{delete, get, post};
+use axum::{BoxError, Router};
+use metrics_exporter_prometheus::PrometheusHandle;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::timeout::TimeoutLayer;
+use tower::ServiceBuilder;
+use tower_http::cors::{Any, CorsLayer};
+
+use crate::auth::AuthManager;
+use crate::error::{DbError, RegistryError};
+use crate::events::EventBus;
+use crate::health::ChainStatus;
+use crate::module::{normalize_name, HealthCheckDefaults, ModuleType};
+use crate::package_cache::PackageCache;
+use crate::readiness::Readiness;
+use crate::registry::Registry;
+use crate::resources::ResourceAggregator;
+use crate::runtime::DockerModuleRuntime;
+use crate::tasks::BackgroundTasks;
+use crate::uploads::UploadStore;
+use crate::verify::{ModuleVerifier, VerificationConfig};
+use crate::webhooks::Webhooks;
+use modules::StartOverrides;
+use rate_limit::{RateLimitConfig, RateLimiter};
+use ws::WsState;
+
+/// Header carrying the identity of the authenticated caller.
+pub const ACTOR_HEADER: &str = "x-actor";
+
+/// Actor recorded when a request carries no identity.
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// Bounds on the requests the registrar handles at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcurrencyConfig {
+    /// Requests handled at once; further requests wait for a slot.
+    pub max_in_flight: usize,
+    /// How long a request may take, including time spent waiting for a
+    /// slot, before it is answered with 503.
+    pub timeout: Duration,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 32,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Largest installation package served by default, 256 MiB.
+pub const DEFAULT_MAX_PACKAGE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Shared state for API handlers.
+#[derive(Clone)]
+pub struct AppState {
+    pub registry: Arc<dyn Registry>,
+    pub ws: WsState,
+    pub resources: Option<ResourceAggregator>,
+    pub auth_rate_limit: RateLimitConfig,
+    /// Per-IP rate limit applied to the module, miner and audit routes;
+    /// unlimited when `None`.
+    pub api_rate_limit: Option<RateLimitConfig>,
+    pub auth: Option<AuthManager>,
+    pub packages: Option<PackageCache>,
+    pub runtime: Option<DockerModuleRuntime>,
+    pub verifier: ModuleVerifier,
+    pub health_checks: HealthCheckDefaults,
+    pub concurrency: ConcurrencyConfig,
+    /// Type given to modules created without one.
+    pub default_module_type: ModuleType,
+    /// Largest installation package served, in bytes.
+    pub max_package_size: u64,
+    /// Renders recorded metrics for `GET /metrics`.
+    pub metrics: Option<PrometheusHandle>,
+    /// Background tasks stopped when the server shuts down.
+    pub tasks: BackgroundTasks,
+    /// Prefix every route is served under, e.g. `/api`; empty for the root.
+    pub base_path: String,
+    /// Registry events streamed at `GET /events`.
+    pub events: EventBus,
+    /// Whether registry events are also pushed to WebSocket clients.
+    pub ws_module_updates: bool,
+    /// Resumable package uploads at `/uploads`; disabled when `None`.
+    pub uploads: Option<UploadStore>,
+    /// Reported at `GET /ready`.
+    pub readiness: Readiness,
+    /// Checked by `GET /modules/:name/health`; the chain is left out of
+    /// the summary when `None`.
+    pub chain: Option<Arc<dyn ChainStatus>>,
+    /// Overrides each module's container was last created with, reported
+    /// by `GET /modules/:name/effective-config`.
+    pub start_overrides: Arc<Mutex<HashMap<String, StartOverrides>>>,
+    /// Directory holding a `<name>` directory per module, moved when the
+    /// module is renamed.
+    pub config_dir: Option<PathBuf>,
+    /// Receivers of registry and miner events, managed at `/webhooks`.
+    pub webhooks: Webhooks,
+}
+
+impl AppState {
+    /// Creates state backed by the given registry.
+    pub fn new(registry: Arc<dyn Registry>) -> Self {
+        Self {
+            registry,
+            ws: WsState::default(),
+            resources: None,
+            auth_rate_limit: RateLimitConfig::default(),
+            api_rate_limit: None,
+            auth: None,
+            packages: None,
+            runtime: None,
+            verifier: ModuleVerifier::default(),
+            health_checks: HealthCheckDefaults::default(),
+            concurrency: ConcurrencyConfig::default(),
+            default_module_type: ModuleType::Docker,
+            max_package_size: DEFAULT_MAX_PACKAGE_SIZE,
+            metrics: None,
+            tasks: BackgroundTasks::default(),
+            base_path: String::new(),
+            events: EventBus::default(),
+            ws_module_updates: true,
+            uploads: None,
+            readiness: Readiness::ready(),
+            chain: None,
+            start_overrides: Arc::default(),
+            config_dir: None,
+            webhooks: Webhooks::default(),
+        }
+    }
+
+    /// Requires callers to authenticate and enforces per-key roles.
+    pub fn with_auth(mut self, auth: AuthManager) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sets the per-IP rate limit applied to `POST /auth`.
+    pub fn with_auth_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.auth_rate_limit = config;
+        self
+    }
+
+    /// Sets the per-IP rate limit applied to the routes that require
+    /// authorization. Give reads their own budget with
+    /// [`RateLimitConfig::max_reads`] so polling does not starve writes.
+    pub fn with_api_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.api_rate_limit = Some(config);
+        self
+    }
+
+    /// Serves installation packages through an on-disk cache.
+    pub fn with_package_cache(mut self, cache: PackageCache) -> Self {
+        self.packages = Some(cache);
+        self
+    }
+
+    /// Runs Docker modules' containers when they are started or stopped,
+    /// rather than only recording their status.
+    pub fn with_runtime(mut self, runtime: DockerModuleRuntime) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Sets the verifier used by `POST /modules/validate`.
+    pub fn with_verifier(mut self, verifier: ModuleVerifier) -> Self {
+        self.verifier = verifier;
+        self
+    }
+
+    /// Verifies modules against `config`.
+    pub fn with_verification(self, config: VerificationConfig) -> Self {
+        self.with_verifier(ModuleVerifier::new(config))
+    }
+
+    /// Serves metrics recorded through `handle`'s recorder at
+    /// `GET /metrics`.
+    pub fn with_metrics(mut self, handle: PrometheusHandle) -> Self {
+        self.metrics = Some(handle);
+        self
+    }
+
+    /// Registers tasks spawned on behalf of the API with `tasks`, so they
+    /// are cancelled along with the server's other background tasks.
+    pub fn with_background_tasks(mut self, tasks: BackgroundTasks) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Serves every route under `base_path`, e.g. `/api`. Leading and
+    /// trailing slashes are optional; an empty path or `/` serves at the
+    /// root.
+    pub fn with_base_path(mut self, base_path: &str) -> Self {
+        let trimmed = base_path.trim_matches('/');
+        self.base_path = if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", trimmed)
+        };
+        self
+    }
+
+    /// Sets the health checks given to new modules that configure none.
+    pub fn with_health_check_defaults(mut self, defaults: HealthCheckDefaults) -> Self {
+        self.health_checks = defaults;
+        self
+    }
+
+    /// Sets how many requests are handled at once and how long a request
+    /// may wait and run before it is shed.
+    pub fn with_concurrency_limit(mut self, config: ConcurrencyConfig) -> Self {
+        self.concurrency = config;
+        self
+    }
+
+    /// Sets the type given to modules created without one.
+    pub fn with_default_module_type(mut self, module_type: ModuleType) -> Self {
+        self.default_module_type = module_type;
+        self
+    }
+
+    /// Sets the largest installation package served, in bytes.
+    pub fn with_max_package_size(mut self, bytes: u64) -> Self {
+        self.max_package_size = bytes;
+        self
+    }
+
+    /// Sets whether module changes are pushed to WebSocket clients as
+    /// [`WsMessage::Module`](ws::WsMessage::Module) messages. On by
+    /// default.
+    pub fn with_ws_module_updates(mut self, enabled: bool) -> Self {
+        self.ws_module_updates = enabled;
+        self
+    }
+
+    /// Delivers events through `webhooks`, e.g. to change how failed
+    /// deliveries are retried.
+    pub fn with_webhooks(mut self, webhooks: Webhooks) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// Moves modules' directories in `config_dir` when they are renamed.
+    pub fn with_config_dir(mut self, config_dir: impl Into<PathBuf>) -> Self {
+        self.config_dir = Some(config_dir.into());
+        self
+    }
+
+    /// Accepts resumable package uploads at `/uploads`.
+    pub fn with_uploads(mut self, store: UploadStore) -> Self {
+        self.uploads = Some(store);
+        self
+    }
+
+    /// Reports `readiness` at `GET /ready` instead of always being ready,
+    /// for servers that finish initializing after they start listening.
+    pub fn with_readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    /// Includes each module's registration on chain in its health summary.
+    pub fn with_chain_status(mut self, chain: Arc<dyn ChainStatus>) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Enables resource aggregation for `GET /resources`.
+    pub fn with_resources(mut self, aggregator: ResourceAggregator) -> Self {
+        self.resources = Some(aggregator);
+        self
+    }
+}
+
+/// Identity of the caller performing a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Actor(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Set by the authorization middleware when auth is enabled.
+        if let Some(actor) = parts.extensions.get::<Actor>() {
+            return Ok(actor.clone());
+        }
+        let actor = parts
+            .headers
+            .get(ACTOR_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .unwrap_or(ANONYMOUS_ACTOR);
+        Ok(Actor(actor.to_string()))
+    }
+}
+
+/// Name of the module a request addresses, taken from the `:name` path
+/// segment and normalized, so `default%2Fecho` and `echo` address the same
+/// module. A namespaced module is addressed with its separator
+/// percent-encoded, as in `/modules/team-a%2Fecho`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleName(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ModuleName {
+    type Rejection = PathRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(name) = Path::<String>::from_request_parts(parts, state).await?;
+        Ok(ModuleName(normalize_name(&name)))
+    }
+}
+
+/// Maps a registry error to an HTTP status code.
+pub(crate) fn status_for(err: &RegistryError) -> StatusCode {
+    match err {
+        RegistryError::ModuleNotFound(_) => StatusCode::NOT_FOUND,
+        RegistryError::ModuleExists(_) | RegistryError::MinerExists(_) => StatusCode::CONFLICT,
+        RegistryError::Database(DbError::UniqueViolation(_)) => StatusCode::CONFLICT,
+        RegistryError::Database(DbError::ConnectionFailed(_) | DbError::Busy(_)) => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        RegistryError::Database(DbError::Other(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Answers requests that timed out waiting for or running in a
+/// concurrency slot.
+async fn shed(_: BoxError) -> StatusCode {
+    StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// `GET /health`
+///
+/// Answers 200 while the registrar is serving requests.
+async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+/// `GET /ready`
+///
+/// Answers 200 once the registrar has finished starting up and 503 before.
+async fn ready(State(state): State<AppState>) -> StatusCode {
+    if state.readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Builds the registrar router.
+pub fn create_router(state: AppState) -> Router {
+    let auth_limiter = RateLimiter::new(state.auth_rate_limit.clone());
+    let base_path = state.base_path.clone();
+    // One limit shared by every route; `ConcurrencyLimitLayer` would give
+    // each route its own.
+    let limit = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(shed))
+        .layer(TimeoutLayer::new(state.concurrency.timeout))
+        .layer(GlobalConcurrencyLimitLayer::new(
+            state.concurrency.max_in_flight,
+        ));
+
+    let mut protected = Router::new()
+        .route(
+            "/modules",
+            get(modules::list_modules)
+                .post(modules::create_module)
+                .delete(modules::delete_modules),
+        )
+        .route("/modules/actions", post(modules::bulk_action))
+        .route("/modules/schema", get(modules::create_module_schema))
+        .route("/modules/validate", post(modules::validate_module))
+        .route(
+            "/modules/:name",
+            get(modules::get_module)
+                .head(modules::head_module)
+                .delete(modules::delete_module),
+        )
+        .route(
+            "/modules/:name/status",
+            get(modules::get_status).put(modules::update_status),
+        )
+        .route("/modules/:name/config", get(modules::get_config))
+        .route("/modules/:name/dependents", get(modules::list_dependents))
+        .route(
+            "/modules/:name/effective-config",
+            get(modules::get_effective_config),
+        )
+        .route("/modules/:name/health", get(modules::get_health))
+        .route("/modules/:name/metadata", get(packages::get_metadata))
+        .route("/modules/:name/package", get(packages::get_package))
+        .route(
+            "/modules/:name/package/archive",
+            get(packages::get_package_archive),
+        )
+        .route("/modules/:name/reload", post(modules::reload_module))
+        .route("/modules/:name/rename", post(modules::rename_module))
+        .route("/modules/:name/start", post(modules::start_module))
+        .route("/modules/:name/stop", post(modules::stop_module))
+        .route(
+            "/miners",
+            get(miners::list_miners).post(miners::register_miner),
+        )
+        .route("/miners/batch", post(miners::register_miners))
+        .route("/audit", get(audit::list_audit))
+        .route("/events", get(events::stream_events))
+        .route("/resources", get(resources::get_resources))
+        .route("/resources/usage", get(resources::get_usage))
+        .route("/uploads", post(uploads::create_upload))
+        .route(
+            "/uploads/:id",
+            get(uploads::get_upload).patch(uploads::append_chunk),
+        )
+        .route("/uploads/:id/complete", post(uploads::complete_upload))
+        .route("/uploads/:id/ingest", post(uploads::ingest_upload))
+        .route(
+            "/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route("/webhooks/:id", delete(webhooks::delete_webhook))
+        .route("/ws", get(ws::ws_handler));
+    if let Some(auth) = state.auth.clone() {
+        protected = protected.route_layer(middleware::from_fn_with_state(auth, auth::authorize));
+    }
+    if let Some(config) = state.api_rate_limit.clone() {
+        protected = protected.route_layer(middleware::from_fn_with_state(
+            RateLimiter::new(config),
+            rate_limit::rate_limit,
+        ));
+    }
+
+    // Reachable without authorization and from any origin, so browsers can
+    // load the docs whatever the auth settings.
+    let public = Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/version", get(docs::version))
+        .route("/openapi.json", get(docs::openapi))
+        .route("/docs", get(docs::docs))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods([Method::GET, Method::HEAD]),
+        );
+
+    let router = Router::new()
+        .merge(public)
+        .route("/metrics", get(metrics::get_metrics))
+        .route(
+            "/auth",
+            post(auth::authenticate).route_layer(middleware::from_fn_with_state(
+                auth_limiter.clone(),
+                rate_limit::rate_limit,
+            )),
+        )
+        .route(
+            "/auth/challenge",
+            get(auth::challenge).route_layer(middleware::from_fn_with_state(
+                auth_limiter,
+                rate_limit::rate_limit,
+            )),
+        )
+        .merge(protected)
+        .layer(limit)
+        .with_state(state);
+    let router = if base_path.is_empty() {
+        router
+    } else {
+        Router::new().nest(&base_path, router)
+    };
+    router.layer(middleware::from_fn(error::assign_request_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::SqliteRegistry;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use test_support::{module_body, send, send_raw};
+
+    #[tokio::test]
+    async fn test_requests_beyond_concurrency_limit_queue() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(AppState::new(registry).with_concurrency_limit(
+            ConcurrencyConfig {
+                max_in_flight: 2,
+                timeout: Duration::from_secs(10),
+            },
+        ));
+
+        let requests = (0..20).map(|i| {
+            let app = app.clone();
+            async move {
+                let body = module_body(&format!("m{}", i), "docker");
+                send(&app, "POST", "/modules", Some(body)).await.0
+            }
+        });
+        let statuses = futures::future::join_all(requests).await;
+
+        assert!(
+            statuses.iter().all(|s| *s == StatusCode::CREATED),
+            "{:?}",
+            statuses
+        );
+        let (status, modules) = send(&app, "GET", "/modules", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(modules.as_array().unwrap().len(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_router_from_fully_configured_state() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let state = AppState::new(registry)
+            .with_auth_rate_limit(RateLimitConfig {
+                max_requests: 1,
+                window: Duration::from_secs(60),
+                max_reads: None,
+            })
+            .with_verification(VerificationConfig {
+                required_env: vec!["API_KEY".into()],
+                ..Default::default()
+            })
+            .with_concurrency_limit(ConcurrencyConfig::default())
+            .with_metrics(recorder.handle());
+        let app = create_router(state);
+        ::metrics::with_local_recorder(&recorder, || {
+            ::metrics::counter!("synapse_test_requests_total").increment(3);
+        });
+
+        let body = module_body("echo", "local");
+        let (status, response) = send(&app, "POST", "/modules/validate", Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response["errors"],
+            serde_json::json!(["Required environment variable API_KEY is not set"])
+        );
+
+        let (status, _, body) = send_raw(&app, "GET", "/metrics", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("synapse_test_requests_total 3"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_ready_once_startup_completes() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let readiness = Readiness::new();
+        let app = create_router(AppState::new(registry).with_readiness(readiness.clone()));
+
+        assert_eq!(
+            send(&app, "GET", "/ready", None).await.0,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(send(&app, "GET", "/health", None).await.0, StatusCode::OK);
+
+        readiness.set_ready();
+        assert_eq!(send(&app, "GET", "/ready", None).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_absent_by_default() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(AppState::new(registry));
+        let (status, _) = send(&app, "GET", "/metrics", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
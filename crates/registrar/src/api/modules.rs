@@ -0,0 +1,2524 @@
+//! Module management handlers.
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::error::ApiError;
+use super::ws::WsMessage;
+use super::{status_for, Actor, AppState, ModuleName};
+use crate::audit::{AuditAction, NewAuditEntry};
+use crate::auth::{Role, Session};
+use crate::config::{find_module_config, load_module_config, ModuleDefinition};
+use crate::container::DockerError;
+use crate::dependencies::dependents;
+use crate::diff::{diff, ModuleConfigDiff};
+use crate::env::{SecretPatterns, REDACTED};
+use crate::error::RegistryError;
+use crate::health::{check_module, ModuleHealth};
+use crate::module::{self, Module, ModuleConfig, ModuleSource, ModuleStatus, ModuleType};
+use crate::registry::{ModuleQuery, ModuleSort, SortOrder};
+use crate::runtime::{resolve_env, DockerModuleRuntime, ModuleState, RuntimeError};
+use crate::verify::{
+    env_placeholders, valid_namespace, valid_port, verify_name, ModuleVerifier, VerificationError,
+};
+use crate::webhooks::WebhookEvent;
+
+/// Request body for `POST /modules`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CreateModuleRequest {
+    pub name: String,
+    /// Module type; the registrar's default type when omitted.
+    #[serde(rename = "type", default)]
+    #[schemars(with = "Option<ModuleType>")]
+    pub module_type: Option<String>,
+    #[serde(default)]
+    pub config: ModuleConfig,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Repository the module was ingested from, when registering a module
+    /// fetched outside the registrar.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ModuleSource>,
+}
+
+/// Query parameters for `GET /modules`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ListModulesParams {
+    /// Only list modules carrying this tag.
+    pub tag: Option<String>,
+    /// Only list modules declaring this capability.
+    pub capability: Option<String>,
+    /// Only list modules in this namespace; `default` lists the modules
+    /// registered without one.
+    pub namespace: Option<String>,
+    /// Field to sort by: `name` (the default), `created_at`, `downloads`
+    /// or `status`.
+    #[serde(default)]
+    pub sort: ModuleSort,
+    /// `asc` (the default) or `desc`.
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+/// Response body for `POST /modules/validate`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationResponse {
+    pub valid: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// Problems that do not prevent registration.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Query parameters for `DELETE /modules/:name`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeleteParams {
+    /// Delete the module even if other modules depend on it.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Query parameters for `DELETE /modules`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BulkDeleteParams {
+    #[serde(rename = "type", alias = "filter[type]")]
+    pub module_type: Option<ModuleType>,
+    #[serde(alias = "filter[status]")]
+    pub status: Option<ModuleStatus>,
+    /// Must be `true`; guards against deleting by accident.
+    #[serde(default)]
+    pub confirm: bool,
+    /// Delete modules even if modules that are kept depend on them.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Response body for `DELETE /modules`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkDeleteResponse {
+    /// Names of the deleted modules, in order.
+    pub deleted: Vec<String>,
+}
+
+/// Request body for `POST /modules/:name/rename`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameModuleRequest {
+    pub new_name: String,
+}
+
+/// Response body for `POST /modules/:name/reload`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadResponse {
+    pub module: Module,
+    pub diff: ModuleConfigDiff,
+    /// Human-readable lines describing `diff`.
+    pub summary: Vec<String>,
+}
+
+/// How long `POST /modules/:name/start?wait=true` waits for the module by
+/// default. Kept below the default request timeout so the wait, not the
+/// server, decides the answer.
+pub const DEFAULT_START_WAIT: Duration = Duration::from_secs(20);
+
+/// How often a module's container is checked while waiting for it to start.
+const START_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Query parameters for `POST /modules/:name/start`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StartParams {
+    /// Wait for the module to be running before answering.
+    #[serde(default)]
+    pub wait: bool,
+    /// Seconds to wait; [`DEFAULT_START_WAIT`] when omitted.
+    pub timeout_secs: Option<u64>,
+}
+
+/// Longest a readiness probe waits for a module to accept a connection.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Host module ports are published on.
+const PROBE_HOST: &str = "127.0.0.1";
+
+/// Query parameters for `GET /modules/:name/status`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StatusParams {
+    /// Also check whether the module accepts connections on its port.
+    #[serde(default)]
+    pub probe: bool,
+}
+
+/// Response body for `GET /modules/:name/status`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleStatusResponse {
+    /// Status stored in the registry.
+    pub status: ModuleStatus,
+    /// Whether the module accepted a connection on its first TCP port; only
+    /// set when probed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reachable: Option<bool>,
+    /// How long the successful probe took.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_latency_ms: Option<u64>,
+}
+
+/// Optional body of `POST /modules/:name/start`: config applied to this run
+/// only, leaving the stored config unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StartOverrides {
+    /// Environment variables set over the stored ones.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Ports exposed instead of the stored ones.
+    #[serde(default)]
+    pub ports: Option<Vec<String>>,
+}
+
+impl StartOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.env.is_empty() && self.ports.is_none()
+    }
+
+    /// Problems that prevent the overrides being applied.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .env
+            .keys()
+            .filter(|key| key.is_empty() || key.contains('='))
+            .map(|key| format!("Invalid environment variable name {:?}", key))
+            .collect();
+        problems.extend(
+            self.ports
+                .iter()
+                .flatten()
+                .filter(|port| !valid_port(port))
+                .map(|port| format!("Invalid port {:?}: expected <1-65535>[/tcp|/udp]", port)),
+        );
+        problems
+    }
+
+    /// Returns `config` with the overrides merged over it.
+    pub fn apply(&self, config: &ModuleConfig) -> ModuleConfig {
+        let mut config = config.clone();
+        config
+            .env
+            .extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if let Some(ports) = &self.ports {
+            config.ports = ports.clone();
+        }
+        config
+    }
+}
+
+/// Response body for `GET /modules/:name/effective-config`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    pub module: String,
+    /// Stored config with the last start's overrides applied, env
+    /// placeholders expanded and secret values masked.
+    pub config: ModuleConfig,
+    /// Overrides the module was last started with, secret values masked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<StartOverrides>,
+}
+
+/// Response body for `POST /modules/:name/start?wait=true`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartResponse {
+    pub module: String,
+    pub status: ModuleStatus,
+}
+
+/// Maximum number of modules acted on at once by `POST /modules/actions`.
+pub const BULK_ACTION_CONCURRENCY: usize = 8;
+
+/// Action applied by `POST /modules/actions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleAction {
+    Start,
+    Stop,
+}
+
+/// Selects modules by type and status. Empty fields match everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleFilter {
+    #[serde(rename = "type")]
+    pub module_type: Option<ModuleType>,
+    pub status: Option<ModuleStatus>,
+}
+
+impl ModuleFilter {
+    fn matches(&self, module: &Module) -> bool {
+        self.module_type.is_none_or(|t| t == module.module_type)
+            && self.status.is_none_or(|s| s == module.status)
+    }
+}
+
+/// Request body for `POST /modules/actions`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkActionRequest {
+    pub action: ModuleAction,
+    #[serde(default)]
+    pub filter: ModuleFilter,
+}
+
+/// Outcome of a bulk action for a single module.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionResult {
+    pub name: String,
+    pub success: bool,
+    /// Status of the module after a successful action.
+    pub status: Option<ModuleStatus>,
+    pub error: Option<String>,
+}
+
+/// Response body for `POST /modules/actions`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkActionResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Per-module results, ordered by name.
+    pub results: Vec<ActionResult>,
+}
+
+/// Request body for `PUT /modules/:name/status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateStatusRequest {
+    pub status: ModuleStatus,
+}
+
+/// With authorization enabled, only a module's owner or an admin may
+/// modify it.
+pub(super) fn ensure_can_modify(
+    session: Option<&Session>,
+    module: &Module,
+) -> Result<(), ApiError> {
+    let Some(session) = session else {
+        return Ok(());
+    };
+    if session.role >= Role::Admin || module.owner.as_deref() == Some(session.public_key.as_str()) {
+        return Ok(());
+    }
+    Err(ApiError::new(
+        StatusCode::FORBIDDEN,
+        format!("{} is owned by another key", module.name),
+    ))
+}
+
+/// Publishes a registry event, also pushed to WebSocket clients unless
+/// disabled and delivered to webhooks.
+fn publish(state: &AppState, module: &str, action: AuditAction, status: Option<ModuleStatus>) {
+    let event = state.events.publish(module, action, status);
+    if state.ws_module_updates {
+        state.ws.broadcast(WsMessage::Module(event.clone()));
+    }
+    state
+        .webhooks
+        .notify(&state.tasks, WebhookEvent::Module(event));
+}
+
+/// Records an operation in the audit log and publishes it as a registry
+/// event. Audit failures are logged rather than failing the operation that
+/// already succeeded.
+pub(super) async fn audit(
+    state: &AppState,
+    module: &str,
+    action: AuditAction,
+    actor: &Actor,
+    before_status: Option<ModuleStatus>,
+    after_status: Option<ModuleStatus>,
+) {
+    publish(state, module, action, after_status);
+    let entry = NewAuditEntry {
+        module: module.to_string(),
+        action,
+        actor: actor.0.clone(),
+        timestamp: Utc::now(),
+        before_status,
+        after_status,
+    };
+    if let Err(e) = state.registry.record_audit(entry).await {
+        tracing::warn!("Failed to record audit entry for {}: {}", module, e);
+    }
+}
+
+/// `GET /modules?tag=&capability=&namespace=&sort=&order=`
+///
+/// Answers 400 for a sort field or order that is not allowed, or an
+/// invalid namespace.
+pub async fn list_modules(
+    State(state): State<AppState>,
+    Query(params): Query<ListModulesParams>,
+) -> Result<Json<Vec<Module>>, ApiError> {
+    if let Some(namespace) = &params.namespace {
+        if !valid_namespace(namespace) {
+            let e = VerificationError::InvalidNamespace(namespace.clone());
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, e.to_string()));
+        }
+    }
+    let query = ModuleQuery {
+        capability: params.capability.clone(),
+        namespace: params.namespace.clone(),
+        sort: params.sort,
+        order: params.order,
+    };
+    let mut modules = state.registry.query_modules(&query).await?;
+    if let Some(tag) = &params.tag {
+        modules.retain(|m| m.tags.contains(tag));
+    }
+    Ok(Json(modules))
+}
+
+/// `GET /modules/:name`
+pub async fn get_module(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+) -> Result<Json<Module>, ApiError> {
+    state
+        .registry
+        .get_module(&name)
+        .await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+/// Representation of a module config a client may ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigRepresentation {
+    Yaml,
+    Json,
+}
+
+/// Picks the representation for an `Accept` header, preferring higher
+/// quality values and YAML when the client accepts anything. Returns `None`
+/// if no acceptable representation is supported.
+fn negotiate_config(accept: Option<&str>) -> Option<ConfigRepresentation> {
+    let Some(accept) = accept.filter(|a| !a.trim().is_empty()) else {
+        return Some(ConfigRepresentation::Yaml);
+    };
+    let mut ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .map(|range| {
+            let mut parts = range.split(';');
+            let media = parts.next().unwrap_or_default().trim();
+            let quality = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (media, quality)
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges
+        .into_iter()
+        .find_map(|(media, _)| match media.to_ascii_lowercase().as_str() {
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml"
+            | "application/*" | "text/*" | "*/*" => Some(ConfigRepresentation::Yaml),
+            "application/json" => Some(ConfigRepresentation::Json),
+            _ => None,
+        })
+}
+
+/// `GET /modules/:name/config`
+///
+/// Returns the module's config in the same shape as a module config file,
+/// as YAML by default or as JSON when the client asks for it.
+pub async fn get_config(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    let representation = negotiate_config(accept).ok_or(StatusCode::NOT_ACCEPTABLE)?;
+    let module = state.registry.get_module(&name).await?;
+    let definition = ModuleDefinition {
+        name: module.name,
+        module_type: module.module_type,
+        config: module.config,
+    };
+
+    let (content_type, body) = match representation {
+        ConfigRepresentation::Yaml => (
+            "application/yaml",
+            serde_yaml::to_string(&definition).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        ),
+        ConfigRepresentation::Json => (
+            "application/json",
+            serde_json::to_string(&definition).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        ),
+    };
+    Ok((
+        [(header::CONTENT_TYPE, HeaderValue::from_static(content_type))],
+        body,
+    )
+        .into_response())
+}
+
+/// Entity tag identifying the current representation of a module.
+fn module_etag(module: &Module) -> Result<String, ApiError> {
+    let json = serde_json::to_vec(module).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(format!("\"{}\"", &hex::encode(Sha256::digest(json))[..32]))
+}
+
+/// `HEAD /modules/:name`
+///
+/// Reports whether a module exists without sending it, along with `ETag`
+/// and `Last-Modified` validators.
+pub async fn head_module(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+) -> Result<Response, ApiError> {
+    let module = state.registry.get_module(&name).await?;
+    let metadata = state.registry.get_module_metadata(&name).await?;
+    let etag = HeaderValue::from_str(&module_etag(&module)?)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let last_modified = HeaderValue::from_str(
+        &metadata
+            .updated_at
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string(),
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag), (header::LAST_MODIFIED, last_modified)],
+    )
+        .into_response())
+}
+
+/// `GET /modules/schema`
+///
+/// JSON Schema of the `POST /modules` request body.
+pub async fn create_module_schema() -> Json<serde_json::Value> {
+    Json(serde_json::to_value(schema_for!(CreateModuleRequest)).unwrap_or_default())
+}
+
+/// Rejects `definition` with 422, listing every lint error, when it may
+/// not be registered. Lint warnings do not block registration.
+pub(super) fn ensure_registrable(
+    verifier: &ModuleVerifier,
+    definition: &ModuleDefinition,
+) -> Result<(), ApiError> {
+    let report = verifier.lint(definition);
+    if report.is_valid() {
+        return Ok(());
+    }
+    let errors: Vec<String> = report.errors.iter().map(ToString::to_string).collect();
+    Err(ApiError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        errors.join("; "),
+    ))
+}
+
+/// `POST /modules`
+///
+/// Answers 422 when the module fails verification.
+pub async fn create_module(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    Json(request): Json<CreateModuleRequest>,
+) -> Result<(StatusCode, Json<Module>), ApiError> {
+    let module_type = match &request.module_type {
+        Some(module_type) => module_type
+            .parse::<ModuleType>()
+            .map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => state.default_module_type,
+    };
+    if let Some(e) = verify_name(&request.name).into_iter().next() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let mut definition = ModuleDefinition {
+        name: module::normalize_name(&request.name),
+        module_type,
+        config: request.config,
+    };
+    state
+        .health_checks
+        .apply(module_type, &mut definition.config);
+    ensure_registrable(&state.verifier, &definition)?;
+    let module = definition
+        .into_module()
+        .with_tags(request.tags)
+        .with_owner(session.map(|session| session.public_key.clone()));
+    state
+        .registry
+        .create_module_with_source(&module, request.source.as_ref())
+        .await?;
+    audit(
+        &state,
+        &module.name,
+        AuditAction::Create,
+        &actor,
+        None,
+        Some(module.status),
+    )
+    .await;
+    Ok((StatusCode::CREATED, Json(module)))
+}
+
+/// Masks env values whose key is secret, or that were expanded from a
+/// secret variable.
+fn mask_secrets(env: &mut BTreeMap<String, String>, unexpanded: &BTreeMap<String, String>) {
+    let patterns = SecretPatterns::default();
+    for (key, value) in env.iter_mut() {
+        let from_secret = unexpanded.get(key).is_some_and(|raw| {
+            env_placeholders(raw)
+                .iter()
+                .any(|var| patterns.is_secret(var))
+        });
+        if patterns.is_secret(key) || from_secret {
+            *value = REDACTED.to_string();
+        }
+    }
+}
+
+/// `GET /modules/:name/effective-config`
+///
+/// Returns the config the module's container was last started with, or
+/// would be started with when it has not been: the stored config with the
+/// last start's overrides merged over it and env placeholders expanded.
+/// Secret values are masked.
+pub async fn get_effective_config(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+) -> Result<Json<EffectiveConfig>, ApiError> {
+    let module = state.registry.get_module(&name).await?;
+    let overrides = state.start_overrides.lock().unwrap().get(&name).cloned();
+    let merged = match &overrides {
+        Some(overrides) => overrides.apply(&module.config),
+        None => module.config,
+    };
+    let mut config = resolve_env(&merged);
+    mask_secrets(&mut config.env, &merged.env);
+    let overrides = overrides.map(|mut overrides| {
+        let unexpanded = overrides.env.clone();
+        mask_secrets(&mut overrides.env, &unexpanded);
+        overrides
+    });
+    Ok(Json(EffectiveConfig {
+        module: name,
+        config,
+        overrides,
+    }))
+}
+
+/// `GET /modules/:name/dependents`
+///
+/// Lists the modules that depend on this one.
+pub async fn list_dependents(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+) -> Result<Json<Vec<Module>>, ApiError> {
+    state.registry.get_module(&name).await?;
+    let modules = state.registry.list_modules().await?;
+    Ok(Json(
+        dependents(&modules, &name).into_iter().cloned().collect(),
+    ))
+}
+
+/// `DELETE /modules/:name?force=`
+///
+/// Refuses with 409 to delete a module other modules depend on unless
+/// `force` is set.
+pub async fn delete_module(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    ModuleName(name): ModuleName,
+    Query(params): Query<DeleteParams>,
+) -> Result<StatusCode, ApiError> {
+    let existing = state.registry.get_module(&name).await?;
+    ensure_can_modify(session.as_deref(), &existing)?;
+    if !params.force {
+        let modules = state.registry.list_modules().await?;
+        if !dependents(&modules, &name).is_empty() {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                format!("Other modules depend on {}", name),
+            ));
+        }
+    }
+    state.registry.delete_module(&name).await?;
+    if let Some(cache) = &state.packages {
+        cache.invalidate(&name);
+    }
+    audit(
+        &state,
+        &name,
+        AuditAction::Delete,
+        &actor,
+        Some(existing.status),
+        None,
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `DELETE /modules?status=<status>&type=<type>&confirm=true`
+///
+/// Deletes every module matching the filter in one transaction: if any
+/// matching module cannot be deleted, none are. At least one filter field
+/// and `confirm=true` are required.
+pub async fn delete_modules(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    Query(params): Query<BulkDeleteParams>,
+) -> Result<Json<BulkDeleteResponse>, ApiError> {
+    if !params.confirm {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Bulk deletion requires confirm=true",
+        ));
+    }
+    if params.module_type.is_none() && params.status.is_none() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Bulk deletion requires a status or type filter",
+        ));
+    }
+    let filter = ModuleFilter {
+        module_type: params.module_type,
+        status: params.status,
+    };
+    let modules = state.registry.list_modules().await?;
+    let (doomed, kept): (Vec<&Module>, Vec<&Module>) =
+        modules.iter().partition(|m| filter.matches(m));
+    for module in &doomed {
+        ensure_can_modify(session.as_deref(), module)?;
+    }
+    let names: Vec<String> = doomed.iter().map(|m| m.name.clone()).collect();
+    if !params.force {
+        if let Some(dependent) = kept
+            .iter()
+            .find(|m| m.config.depends_on.iter().any(|d| names.contains(d)))
+        {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                format!(
+                    "{} depends on a module that would be deleted",
+                    dependent.name
+                ),
+            ));
+        }
+    }
+
+    state.registry.delete_modules(&names).await?;
+    for module in doomed {
+        if let Some(cache) = &state.packages {
+            cache.invalidate(&module.name);
+        }
+        audit(
+            &state,
+            &module.name,
+            AuditAction::Delete,
+            &actor,
+            Some(module.status),
+            None,
+        )
+        .await;
+    }
+    Ok(Json(BulkDeleteResponse { deleted: names }))
+}
+
+/// Moves `<config_dir>/<old>` to `<config_dir>/<new>` if it exists, and
+/// re-points symlinks in `config_dir` that pointed at it.
+fn move_config_dir(config_dir: &std::path::Path, old: &str, new: &str) -> std::io::Result<()> {
+    let from = config_dir.join(old);
+    if !from.is_dir() {
+        return Ok(());
+    }
+    let to = config_dir.join(new);
+    if to.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(&from, &to)?;
+    #[cfg(unix)]
+    for entry in std::fs::read_dir(config_dir)? {
+        let link = entry?.path();
+        let Ok(target) = std::fs::read_link(&link) else {
+            continue;
+        };
+        if config_dir.join(&target) != from {
+            continue;
+        }
+        // Keep relative links relative, so the directory can be moved.
+        let target = if target.is_absolute() {
+            to.clone()
+        } else {
+            std::path::PathBuf::from(new)
+        };
+        std::fs::remove_file(&link)?;
+        std::os::unix::fs::symlink(target, &link)?;
+    }
+    Ok(())
+}
+
+/// `POST /modules/:name/rename`
+///
+/// Answers 400 for an invalid new name, and 409 when the name is taken or
+/// the module is not stopped: its container is named after the module, so
+/// a running module would be orphaned. A leftover container of a stopped
+/// module is removed. With a config directory set, the module's directory
+/// there moves with it.
+pub async fn rename_module(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    ModuleName(name): ModuleName,
+    Json(request): Json<RenameModuleRequest>,
+) -> Result<Json<Module>, ApiError> {
+    if let Some(e) = verify_name(&request.new_name).into_iter().next() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let new_name = module::normalize_name(&request.new_name);
+    let existing = state.registry.get_module(&name).await?;
+    ensure_can_modify(session.as_deref(), &existing)?;
+    if matches!(
+        existing.status,
+        ModuleStatus::Running | ModuleStatus::Starting | ModuleStatus::Stopping
+    ) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!(
+                "Module {} is {}; stop it before renaming",
+                name, existing.status
+            ),
+        ));
+    }
+    if let (Some(runtime), ModuleType::Docker) = (&state.runtime, existing.module_type) {
+        runtime.stop(&name).await.map_err(TransitionError::from)?;
+    }
+    state.registry.rename_module(&name, &new_name).await?;
+    if let Some(config_dir) = &state.config_dir {
+        if let Err(e) = move_config_dir(config_dir, &name, &new_name) {
+            // Undo, so the registry keeps matching the config directory.
+            if let Err(e) = state.registry.rename_module(&new_name, &name).await {
+                tracing::error!("Failed to restore the name of {}: {}", new_name, e);
+            }
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to move the config directory of {}: {}", name, e),
+            ));
+        }
+    }
+    if let Some(cache) = &state.packages {
+        cache.invalidate(&name);
+    }
+    {
+        let mut overrides = state.start_overrides.lock().unwrap();
+        if let Some(start) = overrides.remove(&name) {
+            overrides.insert(new_name.clone(), start);
+        }
+    }
+    let module = state.registry.get_module(&new_name).await?;
+    audit(
+        &state,
+        &module.name,
+        AuditAction::Rename,
+        &actor,
+        Some(module.status),
+        Some(module.status),
+    )
+    .await;
+    Ok(Json(module))
+}
+
+/// `POST /modules/:name/reload`
+///
+/// Re-reads the config file from the module's ingested checkout and stores
+/// it, reporting what changed.
+pub async fn reload_module(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    ModuleName(name): ModuleName,
+) -> Result<Json<ReloadResponse>, ApiError> {
+    let existing = state.registry.get_module(&name).await?;
+    ensure_can_modify(session.as_deref(), &existing)?;
+    let metadata = state.registry.get_module_metadata(&name).await?;
+    // Only ingested modules have a config file to reload from.
+    let source = metadata.source.ok_or(StatusCode::CONFLICT)?;
+    let path = find_module_config(std::path::Path::new(&source.path))
+        .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+    let definition = load_module_config(&path).map_err(|e| {
+        tracing::warn!("Failed to reload config for {}: {}", name, e);
+        StatusCode::UNPROCESSABLE_ENTITY
+    })?;
+
+    let changes = diff(&existing.config, &definition.config);
+    if !changes.is_empty() {
+        state
+            .registry
+            .update_module_config(&name, &definition.config)
+            .await?;
+        if let Some(cache) = &state.packages {
+            cache.invalidate(&name);
+        }
+        audit(
+            &state,
+            &name,
+            AuditAction::Update,
+            &actor,
+            Some(existing.status),
+            Some(existing.status),
+        )
+        .await;
+        tracing::info!("Reloaded config for {}:\n{}", name, changes);
+    }
+
+    Ok(Json(ReloadResponse {
+        module: Module {
+            config: definition.config,
+            ..existing
+        },
+        summary: changes.summary(),
+        diff: changes,
+    }))
+}
+
+/// Why a module could not be moved to a new status.
+#[derive(Debug, Error)]
+enum TransitionError {
+    /// The module is part way through another transition.
+    #[error("Module {name} is {from} and cannot become {to}")]
+    Invalid {
+        name: String,
+        from: ModuleStatus,
+        to: ModuleStatus,
+    },
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+impl TransitionError {
+    fn status(&self) -> StatusCode {
+        match self {
+            TransitionError::Invalid { .. } => StatusCode::CONFLICT,
+            TransitionError::Registry(e) => status_for(e),
+            TransitionError::Runtime(
+                RuntimeError::MissingImage(_) | RuntimeError::InvalidConfig(_),
+            ) => StatusCode::UNPROCESSABLE_ENTITY,
+            TransitionError::Runtime(RuntimeError::Docker(DockerError::Unavailable(_))) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            TransitionError::Runtime(RuntimeError::Docker(_)) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl From<TransitionError> for ApiError {
+    fn from(err: TransitionError) -> Self {
+        match err {
+            TransitionError::Registry(e) => e.into(),
+            e => ApiError::new(e.status(), e.to_string()),
+        }
+    }
+}
+
+/// Transitions `module` to `status`, recording `action` in the audit log.
+/// Starting or stopping a Docker module also starts or stops its container
+/// when a runtime is configured, and the module is `starting` or
+/// `stopping` meanwhile; a module whose container fails to start is marked
+/// failed.
+async fn transition(
+    state: &AppState,
+    actor: &Actor,
+    module: &Module,
+    action: AuditAction,
+    status: ModuleStatus,
+) -> Result<(), TransitionError> {
+    transition_with(state, state.runtime.as_ref(), actor, module, action, status).await
+}
+
+/// Like [`transition`], acting on containers through `runtime`.
+async fn transition_with(
+    state: &AppState,
+    runtime: Option<&DockerModuleRuntime>,
+    actor: &Actor,
+    module: &Module,
+    action: AuditAction,
+    status: ModuleStatus,
+) -> Result<(), TransitionError> {
+    let in_progress = match action {
+        AuditAction::Start => Some(ModuleStatus::Starting),
+        AuditAction::Stop => Some(ModuleStatus::Stopping),
+        _ => None,
+    };
+    let next = in_progress.unwrap_or(status);
+    if !module.status.can_transition_to(next) {
+        return Err(TransitionError::Invalid {
+            name: module.name.clone(),
+            from: module.status,
+            to: next,
+        });
+    }
+    if let (Some(runtime), ModuleType::Docker) = (runtime, module.module_type) {
+        if let Some(in_progress) = in_progress {
+            state
+                .registry
+                .update_module_status(&module.name, in_progress)
+                .await?;
+        }
+        let result = match action {
+            AuditAction::Start => runtime.start(module).await,
+            AuditAction::Stop => runtime.stop(&module.name).await,
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            // A failed start leaves the module failed; a failed stop, or any
+            // operation while Docker is unreachable, leaves it as it was.
+            let unavailable = matches!(e, RuntimeError::Docker(DockerError::Unavailable(_)));
+            let settled = if action == AuditAction::Start && !unavailable {
+                ModuleStatus::Failed
+            } else {
+                module.status
+            };
+            match state
+                .registry
+                .update_module_status(&module.name, settled)
+                .await
+            {
+                Ok(()) if settled == ModuleStatus::Failed => {
+                    publish(state, &module.name, action, Some(settled));
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to mark {} as {}: {}", module.name, settled, e)
+                }
+            }
+            return Err(e.into());
+        }
+    }
+    state
+        .registry
+        .update_module_status(&module.name, status)
+        .await?;
+    audit(
+        state,
+        &module.name,
+        action,
+        actor,
+        Some(module.status),
+        Some(status),
+    )
+    .await;
+    Ok(())
+}
+
+/// Looks up the module called `name` and, if the caller may modify it,
+/// transitions it to `status`.
+async fn transition_named(
+    state: &AppState,
+    actor: &Actor,
+    session: Option<&Session>,
+    name: &str,
+    action: AuditAction,
+    status: ModuleStatus,
+) -> Result<StatusCode, ApiError> {
+    let existing = state.registry.get_module(name).await?;
+    ensure_can_modify(session, &existing)?;
+    transition(state, actor, &existing, action, status).await?;
+    Ok(StatusCode::OK)
+}
+
+/// Connects to `port`, returning how long the connection took, or `None`
+/// if it was refused or took longer than `timeout`.
+async fn probe(port: u16, timeout: Duration) -> Option<Duration> {
+    let started = tokio::time::Instant::now();
+    match tokio::time::timeout(timeout, tokio::net::TcpStream::connect((PROBE_HOST, port))).await {
+        Ok(Ok(_)) => Some(started.elapsed()),
+        _ => None,
+    }
+}
+
+/// `GET /modules/:name/status`
+///
+/// Returns the stored status. With `probe=true`, also reports whether the
+/// module accepts TCP connections on its first port; a module without one
+/// is unreachable.
+pub async fn get_status(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+    Query(params): Query<StatusParams>,
+) -> Result<Json<ModuleStatusResponse>, ApiError> {
+    let module = state.registry.get_module(&name).await?;
+    let mut response = ModuleStatusResponse {
+        status: module.status,
+        reachable: None,
+        probe_latency_ms: None,
+    };
+    if params.probe {
+        let latency = match module.tcp_port() {
+            Some(port) => probe(port, PROBE_TIMEOUT).await,
+            None => None,
+        };
+        response.reachable = Some(latency.is_some());
+        response.probe_latency_ms = latency.map(|l| l.as_millis() as u64);
+    }
+    Ok(Json(response))
+}
+
+/// `GET /modules/:name/health`
+///
+/// Checks the module's container, readiness probe and chain registration,
+/// and reports each along with the worst of them.
+pub async fn get_health(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+) -> Result<Json<ModuleHealth>, ApiError> {
+    let module = state.registry.get_module(&name).await?;
+    let health = check_module(&module, state.runtime.as_ref(), state.chain.as_ref()).await;
+    Ok(Json(health))
+}
+
+/// `PUT /modules/:name/status`
+pub async fn update_status(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    ModuleName(name): ModuleName,
+    Json(request): Json<UpdateStatusRequest>,
+) -> Result<StatusCode, ApiError> {
+    transition_named(
+        &state,
+        &actor,
+        session.as_deref(),
+        &name,
+        AuditAction::Update,
+        request.status,
+    )
+    .await
+}
+
+/// `POST /modules/validate`
+///
+/// Checks a module config, in the same shape as a module config file,
+/// against the registrar's verifier without registering it. Every problem
+/// found is reported, including in any default health check the module
+/// would be given. Warnings are reported alongside errors but do not make
+/// the config invalid.
+pub async fn validate_module(
+    State(state): State<AppState>,
+    Json(mut definition): Json<ModuleDefinition>,
+) -> Json<ValidationResponse> {
+    state
+        .health_checks
+        .apply(definition.module_type, &mut definition.config);
+    let report = state.verifier.lint(&definition);
+    Json(ValidationResponse {
+        valid: report.is_valid(),
+        errors: report.errors.iter().map(ToString::to_string).collect(),
+        warnings: report.warnings.iter().map(ToString::to_string).collect(),
+    })
+}
+
+/// Waits until the container of a started Docker module is running and
+/// not unhealthy, or has failed. Modules without a container are done as
+/// soon as they are recorded as running. Returns `None` on timeout.
+async fn wait_until_started(
+    state: &AppState,
+    module: &Module,
+    timeout: Duration,
+) -> Result<Option<ModuleStatus>, ApiError> {
+    let runtime = match (&state.runtime, module.module_type) {
+        (Some(runtime), ModuleType::Docker) => runtime,
+        _ => return Ok(Some(ModuleStatus::Running)),
+    };
+    let poll = async {
+        loop {
+            match runtime.status(&module.name).await {
+                Ok(ModuleState::Running) => return Ok(ModuleStatus::Running),
+                Ok(ModuleState::Failed) => return Ok(ModuleStatus::Failed),
+                Ok(ModuleState::Unhealthy | ModuleState::Stopped) => {}
+                Err(e) => return Err(ApiError::from(TransitionError::from(e))),
+            }
+            tokio::time::sleep(START_POLL_INTERVAL).await;
+        }
+    };
+    match tokio::time::timeout(timeout, poll).await {
+        Ok(status) => status.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// `POST /modules/:name/start?wait=&timeout_secs=`
+///
+/// Answers 200 as soon as the module is started. With `wait=true` it
+/// instead waits for the module to come up and returns its resulting
+/// status, or 504 if it has not within the timeout.
+///
+/// Accepts an optional JSON [`StartOverrides`] body. Overrides are merged
+/// over the stored config for this run only, and the module's container is
+/// recreated so they take effect.
+pub async fn start_module(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    ModuleName(name): ModuleName,
+    Query(params): Query<StartParams>,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let overrides: StartOverrides = if body.iter().all(u8::is_ascii_whitespace) {
+        StartOverrides::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?
+    };
+    let problems = overrides.problems();
+    if !problems.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            problems.join("; "),
+        ));
+    }
+
+    let mut module = state.registry.get_module(&name).await?;
+    ensure_can_modify(session.as_deref(), &module)?;
+    let runtime = if overrides.is_empty() {
+        state.runtime.clone()
+    } else {
+        module.config = overrides.apply(&module.config);
+        state.runtime.clone().map(|r| r.with_recreate(true))
+    };
+    transition_with(
+        &state,
+        runtime.as_ref(),
+        &actor,
+        &module,
+        AuditAction::Start,
+        ModuleStatus::Running,
+    )
+    .await?;
+    // A start without overrides reuses the container, and with it the
+    // overrides it was created with.
+    if !overrides.is_empty() {
+        state
+            .start_overrides
+            .lock()
+            .unwrap()
+            .insert(name.clone(), overrides);
+    }
+    if !params.wait {
+        return Ok(StatusCode::OK.into_response());
+    }
+
+    let timeout = params
+        .timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_START_WAIT);
+    match wait_until_started(&state, &module, timeout).await? {
+        Some(status) => {
+            if status == ModuleStatus::Failed {
+                state
+                    .registry
+                    .update_module_status(&name, ModuleStatus::Failed)
+                    .await?;
+                publish(&state, &name, AuditAction::Start, Some(status));
+            }
+            Ok(Json(StartResponse {
+                module: name,
+                status,
+            })
+            .into_response())
+        }
+        None => Err(ApiError::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            format!("{} did not start within {}s", name, timeout.as_secs()),
+        )),
+    }
+}
+
+/// `POST /modules/:name/stop`
+pub async fn stop_module(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    ModuleName(name): ModuleName,
+) -> Result<StatusCode, ApiError> {
+    transition_named(
+        &state,
+        &actor,
+        session.as_deref(),
+        &name,
+        AuditAction::Stop,
+        ModuleStatus::Stopped,
+    )
+    .await
+}
+
+/// `POST /modules/actions`
+///
+/// Applies the action to every matching module, at most
+/// [`BULK_ACTION_CONCURRENCY`] at a time. A failure is reported in that
+/// module's result and does not stop the rest of the batch.
+pub async fn bulk_action(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    Json(request): Json<BulkActionRequest>,
+) -> Result<Json<BulkActionResponse>, ApiError> {
+    let (audit_action, status) = match request.action {
+        ModuleAction::Start => (AuditAction::Start, ModuleStatus::Running),
+        ModuleAction::Stop => (AuditAction::Stop, ModuleStatus::Stopped),
+    };
+    let modules: Vec<Module> = state
+        .registry
+        .list_modules()
+        .await?
+        .into_iter()
+        .filter(|m| request.filter.matches(m))
+        .collect();
+
+    let mut results: Vec<ActionResult> = stream::iter(modules)
+        .map(|module| {
+            let (state, actor, session) = (&state, &actor, session.as_deref());
+            async move {
+                if let Err(e) = ensure_can_modify(session, &module) {
+                    return ActionResult {
+                        name: module.name,
+                        success: false,
+                        status: None,
+                        error: Some(e.message().to_string()),
+                    };
+                }
+                match transition(state, actor, &module, audit_action, status).await {
+                    Ok(()) => ActionResult {
+                        name: module.name,
+                        success: true,
+                        status: Some(status),
+                        error: None,
+                    },
+                    Err(e) => ActionResult {
+                        name: module.name,
+                        success: false,
+                        status: None,
+                        error: Some(e.to_string()),
+                    },
+                }
+            }
+        })
+        .buffer_unordered(BULK_ACTION_CONCURRENCY)
+        .collect()
+        .await;
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let succeeded = results.iter().filter(|r| r.success).count();
+    Ok(Json(BulkActionResponse {
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use super::{negotiate_config, ConfigRepresentation};
+    use crate::api::test_support::{module_body, send, send_raw, test_app};
+    use std::sync::Arc;
+
+    use crate::api::{create_router, AppState};
+    use crate::container::fake::FakeContainers;
+    use crate::container::{ContainerState, DockerError};
+    use crate::env::REDACTED;
+    use crate::module::{Module, ModuleConfig, ModuleSource, ModuleStatus, ModuleType};
+    use crate::port::Port;
+    use crate::probe::ProbeConfig;
+    use crate::reconnect::ReconnectingContainers;
+    use crate::registry::Registry;
+    use crate::registry::SqliteRegistry;
+    use crate::runtime::DockerModuleRuntime;
+
+    #[tokio::test]
+    async fn test_create_duplicate_module_conflicts() {
+        let (app, _) = test_app().await;
+        let body = module_body("echo", "docker");
+
+        let (status, _) = send(&app, "POST", "/modules", Some(body.clone())).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_config_negotiates_json_and_yaml() {
+        let (app, _) = test_app().await;
+        let body = json!({
+            "name": "echo",
+            "type": "docker",
+            "config": {
+                "image": "synapse/echo:1.0",
+                "env": {"MODEL": "tiny", "MODULE_PORT": "8080"}
+            }
+        });
+        send(&app, "POST", "/modules", Some(body)).await;
+
+        let (status, headers, body) = send_raw(
+            &app,
+            "GET",
+            "/modules/echo/config",
+            &[("accept", "application/json")],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "application/json");
+        let config: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(config["name"], "echo");
+        assert_eq!(config["image"], "synapse/echo:1.0");
+        assert_eq!(config["env"]["MODEL"], "tiny");
+
+        let (status, headers, body) = send_raw(&app, "GET", "/modules/echo/config", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "application/yaml");
+        let yaml: serde_json::Value = serde_yaml::from_slice(&body).unwrap();
+        assert_eq!(yaml, config);
+
+        let (status, _, _) = send_raw(
+            &app,
+            "GET",
+            "/modules/echo/config",
+            &[("accept", "text/html")],
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn test_missing_module_config_returns_error_envelope() {
+        let (app, _) = test_app().await;
+
+        let (status, headers, body) = send_raw(
+            &app,
+            "GET",
+            "/modules/missing/config",
+            &[("x-request-id", "req-42")],
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(headers["x-request-id"], "req-42");
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "code": 404,
+                "error": "Module not found: missing",
+                "request_id": "req-42",
+            })
+        );
+
+        // Without one from the client, an id is generated.
+        let (_, headers, body) = send_raw(&app, "GET", "/modules/missing/config", &[]).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["request_id"],
+            headers["x-request-id"].to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_accept_quality_values_respected() {
+        assert_eq!(
+            negotiate_config(Some("application/yaml;q=0.5, application/json")),
+            Some(ConfigRepresentation::Json)
+        );
+        assert_eq!(
+            negotiate_config(Some("application/json;q=0, */*")),
+            Some(ConfigRepresentation::Yaml)
+        );
+        assert_eq!(negotiate_config(None), Some(ConfigRepresentation::Yaml));
+    }
+
+    #[tokio::test]
+    async fn test_head_reports_existence() {
+        let (app, _) = test_app().await;
+        send(
+            &app,
+            "POST",
+            "/modules",
+            Some(module_body("echo", "docker")),
+        )
+        .await;
+
+        let (status, headers, body) = send_raw(&app, "HEAD", "/modules/echo", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers["etag"].to_str().unwrap().starts_with('"'));
+        assert!(headers["last-modified"].to_str().unwrap().ends_with("GMT"));
+        assert!(body.is_empty());
+
+        let (status, _, body) = send_raw(&app, "HEAD", "/modules/missing", &[]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_schema_lists_module_types() {
+        let (app, _) = test_app().await;
+        let (status, schema) = send(&app, "GET", "/modules/schema", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&json!("name")));
+        assert!(!required.contains(&json!("type")));
+
+        let module_type = &schema["definitions"]["ModuleType"];
+        let mut values: Vec<&str> = module_type["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|variant| variant["enum"].as_array().unwrap())
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        values.sort();
+        assert_eq!(values, vec!["docker", "local", "observer"]);
+    }
+
+    #[tokio::test]
+    async fn test_rename_preserves_metadata() {
+        let (app, registry) = test_app().await;
+        for name in ["echo", "taken"] {
+            send(&app, "POST", "/modules", Some(module_body(name, "docker"))).await;
+        }
+        registry.increment_downloads("echo").await.unwrap();
+        let before = registry.get_module_metadata("echo").await.unwrap();
+
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/modules/echo/rename",
+            Some(json!({"new_name": "echo-v2"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "echo-v2");
+
+        let after = registry.get_module_metadata("echo-v2").await.unwrap();
+        assert_eq!(after.created_at, before.created_at);
+        assert_eq!(after.downloads, 1);
+        let (status, _) = send(&app, "GET", "/modules/echo", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // The create entry moved with the module.
+        let (_, audit) = send(&app, "GET", "/audit?module=echo-v2", None).await;
+        let actions: Vec<&str> = audit["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["action"].as_str().unwrap())
+            .collect();
+        assert_eq!(actions, vec!["rename", "create"]);
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/modules/echo-v2/rename",
+            Some(json!({"new_name": "taken"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        for new_name in ["Echo V3", "team/", "a/b/c"] {
+            let (status, _) = send(
+                &app,
+                "POST",
+                "/modules/echo-v2/rename",
+                Some(json!({"new_name": new_name})),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", new_name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rename_moves_references_and_config_dir() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let config_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(config_dir.path().join("echo")).unwrap();
+        std::os::unix::fs::symlink("echo", config_dir.path().join("current")).unwrap();
+        let app = create_router(AppState::new(registry.clone()).with_config_dir(config_dir.path()));
+        registry
+            .create_module(&Module::new("echo", ModuleType::Local))
+            .await
+            .unwrap();
+        let relay = ModuleConfig {
+            depends_on: vec!["echo".into()],
+            ..Default::default()
+        };
+        registry
+            .create_module(&Module::new("relay", ModuleType::Local).with_config(relay))
+            .await
+            .unwrap();
+        registry
+            .create_module(&Module::new("busy", ModuleType::Local))
+            .await
+            .unwrap();
+        registry
+            .update_module_status("busy", ModuleStatus::Running)
+            .await
+            .unwrap();
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/modules/echo/rename",
+            Some(json!({"new_name": "team-a/echo"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let relay = registry.get_module("relay").await.unwrap();
+        assert_eq!(relay.config.depends_on, vec!["team-a/echo"]);
+        assert!(!config_dir.path().join("echo").exists());
+        assert!(config_dir.path().join("team-a/echo").is_dir());
+        assert_eq!(
+            std::fs::read_link(config_dir.path().join("current")).unwrap(),
+            std::path::PathBuf::from("team-a/echo")
+        );
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/modules/busy/rename",
+            Some(json!({"new_name": "idle"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(registry.get_module("busy").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reload_reports_config_diff() {
+        let (app, registry) = test_app().await;
+        send(
+            &app,
+            "POST",
+            "/modules",
+            Some(json!({
+                "name": "echo",
+                "type": "docker",
+                "config": {
+                "image": "synapse/echo:1.0",
+                "env": {"MODEL": "tiny", "MODULE_PORT": "8080"}
+            }
+            })),
+        )
+        .await;
+
+        let (status, _) = send(&app, "POST", "/modules/echo/reload", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let checkout = tempfile::tempdir().unwrap();
+        std::fs::write(
+            checkout.path().join("config.yaml"),
+            "name: echo\ntype: docker\nimage: synapse/echo:1.1\nenv:\n  MODEL: large\n  MODULE_PORT: \"8080\"\n",
+        )
+        .unwrap();
+        registry
+            .set_module_source(
+                "echo",
+                &ModuleSource {
+                    repo_url: "https://example.com/echo.git".into(),
+                    git_ref: "main".into(),
+                    commit: "abc123".into(),
+                    path: checkout.path().display().to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let (status, body) = send(&app, "POST", "/modules/echo/reload", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["summary"],
+            json!(["image synapse/echo: tag 1.0 -> 1.1", "env MODEL: changed"])
+        );
+        assert_eq!(body["diff"]["image"]["new"], "synapse/echo:1.1");
+        let stored = registry.get_module("echo").await.unwrap();
+        assert_eq!(stored.config.image.as_deref(), Some("synapse/echo:1.1"));
+
+        let (_, body) = send(&app, "POST", "/modules/echo/reload", None).await;
+        assert_eq!(body["summary"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_module_is_starting_while_its_container_is_created() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let containers = Arc::new(FakeContainers::default());
+        let app = create_router(
+            AppState::new(registry.clone())
+                .with_runtime(DockerModuleRuntime::new(containers.clone())),
+        );
+        let config = ModuleConfig {
+            image: Some("echo:1".into()),
+            ..Default::default()
+        };
+        registry
+            .create_module(&Module::new("echo", ModuleType::Docker).with_config(config))
+            .await
+            .unwrap();
+        let gate = containers.gate_creates();
+
+        let start = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, "POST", "/modules/echo/start", None).await.0 }
+        });
+        while !containers.calls().contains(&"create echo".to_string()) {
+            tokio::task::yield_now().await;
+        }
+        let (_, body) = send(&app, "GET", "/modules/echo/status", None).await;
+        assert_eq!(body["status"], "starting");
+        // Starting again while the first start is in progress is refused.
+        let (status, _) = send(&app, "POST", "/modules/echo/start", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        gate.add_permits(1);
+        assert_eq!(start.await.unwrap(), StatusCode::OK);
+        let (_, body) = send(&app, "GET", "/modules/echo/status", None).await;
+        assert_eq!(body["status"], "running");
+    }
+
+    #[tokio::test]
+    async fn test_reads_work_while_docker_is_unavailable() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let disconnected = ReconnectingContainers::new(|| {
+            Box::pin(async { Err(DockerError::Api("connection refused".into())) })
+        })
+        .with_reconnect_interval(std::time::Duration::ZERO);
+        let app = create_router(
+            AppState::new(registry.clone())
+                .with_runtime(DockerModuleRuntime::new(Arc::new(disconnected))),
+        );
+        let config = ModuleConfig {
+            image: Some("echo:1".into()),
+            ..Default::default()
+        };
+        registry
+            .create_module(&Module::new("echo", ModuleType::Docker).with_config(config))
+            .await
+            .unwrap();
+
+        let (status, body) = send(&app, "GET", "/modules", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["name"], "echo");
+        assert_eq!(
+            send(&app, "GET", "/modules/echo", None).await.0,
+            StatusCode::OK
+        );
+
+        for action in ["start", "stop"] {
+            let uri = format!("/modules/echo/{}", action);
+            let (status, _) = send(&app, "POST", &uri, None).await;
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", action);
+        }
+        // The module is left as it was rather than marked failed.
+        let (_, body) = send(&app, "GET", "/modules/echo/status", None).await;
+        assert_eq!(body["status"], "stopped");
+    }
+
+    #[tokio::test]
+    async fn test_bulk_start_reports_each_module() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let containers = Arc::new(FakeContainers::default());
+        let app = create_router(
+            AppState::new(registry.clone())
+                .with_runtime(DockerModuleRuntime::new(containers.clone())),
+        );
+        for (name, image) in [
+            ("a", Some("echo:1")),
+            ("b", Some("echo:1")),
+            ("broken", None),
+        ] {
+            let config = ModuleConfig {
+                image: image.map(String::from),
+                ..Default::default()
+            };
+            registry
+                .create_module(&Module::new(name, ModuleType::Docker).with_config(config))
+                .await
+                .unwrap();
+        }
+        registry
+            .create_module(&Module::new("already", ModuleType::Docker))
+            .await
+            .unwrap();
+        registry
+            .update_module_status("already", ModuleStatus::Running)
+            .await
+            .unwrap();
+
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/modules/actions",
+            Some(json!({"action": "start", "filter": {"status": "stopped"}})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["succeeded"], 2);
+        assert_eq!(body["failed"], 1);
+        let results = body["results"].as_array().unwrap();
+        let names: Vec<&str> = results
+            .iter()
+            .map(|r| r["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a", "b", "broken"]);
+        assert_eq!(results[0]["status"], "running");
+        assert_eq!(results[2]["success"], false);
+        assert_eq!(results[2]["error"], "Module broken has no image configured");
+
+        let broken = registry.get_module("broken").await.unwrap();
+        assert_eq!(broken.status, ModuleStatus::Failed);
+        assert!(!containers.calls().contains(&"start already".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_failed_start_sends_signed_webhook() {
+        use crate::retry::RetryConfig;
+        use crate::webhooks::{sign, Webhooks, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+        use axum::http::HeaderMap;
+        use axum::routing::post;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        // The receiver fails its first delivery, which must be retried.
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let receiver = axum::Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: axum::body::Bytes| {
+                let sender = sender.clone();
+                let hit = hits.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if hit == 0 {
+                        return StatusCode::INTERNAL_SERVER_ERROR;
+                    }
+                    let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+                    let timestamp: i64 =
+                        headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+                    sender.send((signature, timestamp, body)).unwrap();
+                    StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, receiver).await.unwrap() });
+
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let retry = RetryConfig {
+            initial_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let app = create_router(
+            AppState::new(registry.clone())
+                .with_runtime(DockerModuleRuntime::new(
+                    Arc::new(FakeContainers::default()),
+                ))
+                .with_webhooks(Webhooks::default().with_retry(retry)),
+        );
+        let body = json!({"url": format!("http://{}/hook", addr), "secret": "s3cret"});
+        let (status, webhook) = send(&app, "POST", "/webhooks", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(webhook["signed"], true);
+        assert!(webhook.get("secret").is_none());
+        registry
+            .create_module(&Module::new("broken", ModuleType::Docker))
+            .await
+            .unwrap();
+
+        let (status, _) = send(&app, "POST", "/modules/broken/start", None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (signature, timestamp, body) =
+            tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(signature, sign("s3cret", timestamp, &body));
+        assert!((chrono::Utc::now().timestamp() - timestamp).abs() < 60);
+        let event: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(event["type"], "Module");
+        assert_eq!(event["data"]["module"], "broken");
+        assert_eq!(event["data"]["status"], "failed");
+    }
+
+    #[tokio::test]
+    async fn test_validate_accepts_valid_config() {
+        let (app, registry) = test_app().await;
+        let body = json!({
+            "name": "echo",
+            "type": "docker",
+            "image": "synapse/echo:1.0",
+            "ports": ["8080/tcp"],
+            "env": {"MODULE_PORT": "8080"}
+        });
+
+        let (status, response) = send(&app, "POST", "/modules/validate", Some(body)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!({"valid": true}));
+        assert!(registry.list_modules().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_every_violation() {
+        let (app, registry) = test_app().await;
+        let body = json!({
+            "name": "Echo",
+            "type": "docker",
+            "ports": ["8080/tcp", "99999", "80/sctp"]
+        });
+
+        let (status, response) = send(&app, "POST", "/modules/validate", Some(body)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["valid"], json!(false));
+        let errors: Vec<&str> = response["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e.as_str().unwrap())
+            .collect();
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(errors[0].starts_with("Invalid module name \"Echo\""));
+        assert_eq!(errors[1], "Docker module Echo has no image");
+        assert!(errors[2].starts_with("Invalid port \"99999\""));
+        assert!(errors[3].starts_with("Invalid port \"80/sctp\""));
+        assert_eq!(
+            errors[4],
+            "Required environment variable MODULE_PORT is not set"
+        );
+        assert!(registry.list_modules().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_latest_image_warns_but_still_registers() {
+        let (app, registry) = test_app().await;
+        let body = json!({
+            "name": "echo",
+            "type": "docker",
+            "image": "synapse/echo:latest",
+            "ports": ["8080/tcp"],
+            "env": {"MODULE_PORT": "8080"}
+        });
+
+        let (status, response) = send(&app, "POST", "/modules/validate", Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response,
+            json!({
+                "valid": true,
+                "warnings": ["Image synapse/echo:latest is not pinned to a version; avoid :latest"]
+            })
+        );
+
+        let body = json!({
+            "name": "echo",
+            "type": "docker",
+            "config": {
+                "image": "synapse/echo:latest",
+                "ports": ["8080/tcp"],
+                "env": {"MODULE_PORT": "8080"}
+            }
+        });
+        let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(registry.get_module("echo").await.is_ok());
+
+        let body = json!({
+            "name": "relay",
+            "type": "docker",
+            "config": {"image": "synapse/relay:latest", "ports": ["0"]}
+        });
+        let (status, response) = send(&app, "POST", "/modules", Some(body)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response["error"],
+            "Invalid port \"0\": expected <1-65535>[/tcp|/udp]; \
+             Required environment variable MODULE_PORT is not set"
+        );
+        assert!(registry.get_module("relay").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_removes_only_failed_modules() {
+        let (app, registry) = test_app().await;
+        for name in ["a", "b", "c", "d"] {
+            registry
+                .create_module(&Module::new(name, ModuleType::Docker))
+                .await
+                .unwrap();
+        }
+        for (name, status) in [
+            ("a", ModuleStatus::Failed),
+            ("b", ModuleStatus::Running),
+            ("c", ModuleStatus::Failed),
+        ] {
+            registry.update_module_status(name, status).await.unwrap();
+        }
+
+        let (status, _) = send(&app, "DELETE", "/modules?status=failed", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(registry.list_modules().await.unwrap().len(), 4);
+
+        let (status, body) = send(
+            &app,
+            "DELETE",
+            "/modules?filter%5Bstatus%5D=failed&confirm=true",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"deleted": ["a", "c"]}));
+        let names: Vec<String> = registry
+            .list_modules()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(names, vec!["b", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_of_depended_on_module_is_blocked() {
+        let (app, _) = test_app().await;
+        let depending = |name: &str, depends_on: serde_json::Value| {
+            let mut body = module_body(name, "docker");
+            body["config"]["depends_on"] = depends_on;
+            body
+        };
+        for body in [
+            module_body("db", "docker"),
+            depending("api", json!(["db"])),
+            depending("web", json!(["api", "db"])),
+        ] {
+            let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let (status, dependents) = send(&app, "GET", "/modules/db/dependents", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let names: Vec<_> = dependents
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["api", "web"]);
+
+        let (status, _) = send(&app, "DELETE", "/modules/db", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(&app, "GET", "/modules/db", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&app, "DELETE", "/modules/web", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "DELETE", "/modules/db?force=true", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_docker_module_gets_default_health_check() {
+        let (app, registry) = test_app().await;
+        let body = json!({
+            "name": "echo",
+            "type": "docker",
+            "config": {
+                "image": "synapse/echo:1.0",
+                "ports": ["8080/tcp"],
+                "env": {"MODULE_PORT": "8080"}
+            }
+        });
+
+        let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let check = registry
+            .get_module("echo")
+            .await
+            .unwrap()
+            .config
+            .health_check
+            .unwrap();
+        assert_eq!(
+            check.test,
+            vec!["CMD", "curl", "-f", "http://localhost:8080/health"]
+        );
+        assert_eq!(check.interval_secs, 30);
+
+        let body = json!({
+            "name": "local",
+            "type": "local",
+            "config": {"ports": ["9000"], "env": {"MODULE_PORT": "9000"}}
+        });
+        let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let local = registry.get_module("local").await.unwrap();
+        assert_eq!(local.config.health_check, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_modules_filters_by_tag() {
+        let (app, _) = test_app().await;
+        let tagged = |name: &str, module_type: &str, tags: serde_json::Value| {
+            let mut body = module_body(name, module_type);
+            body["tags"] = tags;
+            body
+        };
+        for body in [
+            tagged("echo", "docker", json!(["team-a", "prod"])),
+            tagged("relay", "docker", json!(["team-b"])),
+            tagged("scorer", "local", json!(["team-a"])),
+            module_body("watcher", "observer"),
+        ] {
+            let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let names = |modules: serde_json::Value| -> Vec<String> {
+            modules
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let (status, modules) = send(&app, "GET", "/modules?tag=team-a", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(names(modules), vec!["echo", "scorer"]);
+
+        let (_, modules) = send(&app, "GET", "/modules?tag=missing", None).await;
+        assert!(names(modules).is_empty());
+
+        let (_, modules) = send(&app, "GET", "/modules", None).await;
+        assert_eq!(names(modules.clone()).len(), 4);
+        assert_eq!(modules[0]["tags"], json!(["team-a", "prod"]));
+        assert_eq!(modules[3]["tags"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_list_modules_filters_by_capability() {
+        let (app, registry) = test_app().await;
+        for (name, capabilities) in [
+            ("echo", json!(["streaming", "batch"])),
+            ("relay", json!(["batch"])),
+            ("scorer", json!(["streaming"])),
+            ("watcher", json!([])),
+        ] {
+            let mut body = module_body(name, "docker");
+            body["config"]["capabilities"] = capabilities;
+            let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+        let names = |modules: serde_json::Value| -> Vec<String> {
+            modules
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let (status, modules) = send(&app, "GET", "/modules?capability=streaming", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(names(modules), vec!["echo", "scorer"]);
+
+        // The index follows config changes and deletions.
+        let config = ModuleConfig {
+            capabilities: vec!["streaming".into()],
+            ..Default::default()
+        };
+        registry
+            .update_module_config("relay", &config)
+            .await
+            .unwrap();
+        registry.delete_module("echo").await.unwrap();
+        let (_, modules) = send(&app, "GET", "/modules?capability=streaming", None).await;
+        assert_eq!(names(modules), vec!["relay", "scorer"]);
+        let (_, modules) = send(&app, "GET", "/modules?capability=batch", None).await;
+        assert!(names(modules).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_same_name_in_two_namespaces() {
+        let (app, _registry) = test_app().await;
+        for name in ["team-a/echo", "team-b/echo", "default/echo"] {
+            let body = module_body(name, "docker");
+            let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+            assert_eq!(status, StatusCode::CREATED, "{}", name);
+        }
+        // `default/echo` is the unnamespaced `echo`.
+        let body = module_body("echo", "docker");
+        let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        for name in ["Team A/echo", "team/", "a/b/c", "team/UPPER"] {
+            let body = json!({"name": name, "type": "docker"});
+            let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", name);
+        }
+
+        let names = |modules: serde_json::Value| -> Vec<String> {
+            modules
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let (status, modules) = send(&app, "GET", "/modules?namespace=team-a", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(names(modules), vec!["team-a/echo"]);
+        let (_, modules) = send(&app, "GET", "/modules?namespace=default", None).await;
+        assert_eq!(names(modules), vec!["echo"]);
+        let (_, modules) = send(&app, "GET", "/modules", None).await;
+        assert_eq!(names(modules).len(), 3);
+
+        let (status, module) = send(&app, "GET", "/modules/team-b%2Fecho", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(module["name"], "team-b/echo");
+        let (status, module) = send(&app, "GET", "/modules/default%2Fecho", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(module["name"], "echo");
+        let (status, _) = send(&app, "GET", "/modules?namespace=Bad%20Ns", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(&app, "DELETE", "/modules/default%2Fecho", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "GET", "/modules/team-a%2Fecho/status", None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_list_modules_sorts_by_allowed_fields() {
+        let (app, registry) = test_app().await;
+        for (name, downloads) in [("a", 1), ("b", 3), ("c", 0), ("d", 3)] {
+            let body = module_body(name, "docker");
+            send(&app, "POST", "/modules", Some(body)).await;
+            for _ in 0..downloads {
+                registry.increment_downloads(name).await.unwrap();
+            }
+        }
+        let names = |modules: serde_json::Value| -> Vec<String> {
+            modules
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let (status, modules) = send(&app, "GET", "/modules?sort=downloads&order=desc", None).await;
+        assert_eq!(status, StatusCode::OK);
+        // Ties are broken by name.
+        assert_eq!(names(modules), vec!["b", "d", "a", "c"]);
+        let (_, modules) = send(&app, "GET", "/modules?order=desc", None).await;
+        assert_eq!(names(modules), vec!["d", "c", "b", "a"]);
+
+        for uri in ["/modules?sort=config", "/modules?sort=name&order=sideways"] {
+            let (status, _) = send(&app, "GET", uri, None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_without_type_uses_default() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(
+            AppState::new(registry.clone()).with_default_module_type(ModuleType::Local),
+        );
+
+        let (status, module) = send(
+            &app,
+            "POST",
+            "/modules",
+            Some(json!({"name": "echo", "config": {"env": {"MODULE_PORT": "8080"}}})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(module["module_type"], "local");
+        assert_eq!(
+            registry.get_module("echo").await.unwrap().module_type,
+            ModuleType::Local
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_with_unknown_type_is_rejected() {
+        let (app, registry) = test_app().await;
+
+        let body = json!({"name": "echo", "type": "dokcer"});
+        let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(registry.list_modules().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_status_probe_reports_reachability() {
+        let (app, registry) = test_app().await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        for (name, port) in [("up", open), ("down", closed)] {
+            let config = ModuleConfig {
+                ports: vec![format!("{}/tcp", port)],
+                ..Default::default()
+            };
+            registry
+                .create_module(&Module::new(name, ModuleType::Docker).with_config(config))
+                .await
+                .unwrap();
+        }
+        registry
+            .update_module_status("up", ModuleStatus::Running)
+            .await
+            .unwrap();
+
+        let (status, body) = send(&app, "GET", "/modules/down/status", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"status": "stopped"}));
+
+        let (_, body) = send(&app, "GET", "/modules/down/status?probe=true", None).await;
+        assert_eq!(body, json!({"status": "stopped", "reachable": false}));
+
+        let (_, body) = send(&app, "GET", "/modules/up/status?probe=true", None).await;
+        assert_eq!(body["status"], "running");
+        assert_eq!(body["reachable"], true);
+        assert!(body["probe_latency_ms"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_start_overrides_apply_to_one_run_only() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let containers = Arc::new(FakeContainers::default());
+        let app = create_router(
+            AppState::new(registry.clone())
+                .with_runtime(DockerModuleRuntime::new(containers.clone())),
+        );
+        let mut config = ModuleConfig {
+            image: Some("echo:1".into()),
+            ports: vec!["8080".into()],
+            ..Default::default()
+        };
+        config.env.insert("MODEL".into(), "tiny".into());
+        config.env.insert("MODULE_PORT".into(), "8080".into());
+        registry
+            .create_module(&Module::new("echo", ModuleType::Docker).with_config(config.clone()))
+            .await
+            .unwrap();
+
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/modules/echo/start",
+            Some(json!({"ports": ["0"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], 422);
+        assert!(containers.containers.lock().unwrap().is_empty());
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/modules/echo/start",
+            Some(json!({"env": {"MODEL": "large"}, "ports": ["9090/tcp"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let started = containers.containers.lock().unwrap()["echo"].config.clone();
+        assert_eq!(started.env["MODEL"], "large");
+        assert_eq!(started.env["MODULE_PORT"], "8080");
+        assert_eq!(started.ports, vec![Port::tcp(9090)]);
+
+        let stored = registry.get_module("echo").await.unwrap();
+        assert_eq!(stored.config, config);
+        assert_eq!(stored.status, ModuleStatus::Running);
+    }
+
+    #[tokio::test]
+    async fn test_effective_config_expands_placeholders_and_masks_secrets() {
+        std::env::set_var("SYNAPSE_TEST_EFFECTIVE_MODEL", "tiny");
+        std::env::set_var("SYNAPSE_TEST_EFFECTIVE_TOKEN", "tok-123");
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let containers = Arc::new(FakeContainers::default());
+        let app = create_router(
+            AppState::new(registry.clone())
+                .with_runtime(DockerModuleRuntime::new(containers.clone())),
+        );
+        let mut config = ModuleConfig {
+            image: Some("echo:1".into()),
+            ports: vec!["8080".into()],
+            ..Default::default()
+        };
+        config.env.insert(
+            "MODEL".into(),
+            "models/${SYNAPSE_TEST_EFFECTIVE_MODEL}".into(),
+        );
+        config
+            .env
+            .insert("UPSTREAM".into(), "${SYNAPSE_TEST_EFFECTIVE_TOKEN}".into());
+        config.env.insert("API_KEY".into(), "sk-stored".into());
+        registry
+            .create_module(&Module::new("echo", ModuleType::Docker).with_config(config))
+            .await
+            .unwrap();
+
+        let (status, body) = send(&app, "GET", "/modules/echo/effective-config", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["config"]["env"]["MODEL"], "models/tiny");
+        assert_eq!(body["config"]["env"]["UPSTREAM"], REDACTED);
+        assert_eq!(body["config"]["env"]["API_KEY"], REDACTED);
+        assert!(body.get("overrides").is_none());
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/modules/echo/start",
+            Some(json!({"env": {"DB_PASSWORD": "hunter2"}, "ports": ["9090/tcp"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let started = containers.containers.lock().unwrap()["echo"].config.clone();
+        assert_eq!(started.env["MODEL"], "models/tiny");
+
+        let (_, body) = send(&app, "GET", "/modules/echo/effective-config", None).await;
+        assert_eq!(body["config"]["ports"], json!(["9090/tcp"]));
+        assert_eq!(body["config"]["env"]["DB_PASSWORD"], REDACTED);
+        assert_eq!(body["overrides"]["env"]["DB_PASSWORD"], REDACTED);
+        assert!(!body.to_string().contains("hunter2"));
+        assert!(!body.to_string().contains("tok-123"));
+    }
+
+    #[tokio::test]
+    async fn test_start_with_wait_returns_running_status() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let containers = Arc::new(
+            FakeContainers::default()
+                .with_container("sick", ContainerState::Exited)
+                .with_health("sick", "unhealthy"),
+        );
+        let app = create_router(
+            AppState::new(registry.clone())
+                .with_runtime(DockerModuleRuntime::new(containers.clone())),
+        );
+        for name in ["echo", "sick"] {
+            let config = ModuleConfig {
+                image: Some("echo:1".into()),
+                ..Default::default()
+            };
+            registry
+                .create_module(&Module::new(name, ModuleType::Docker).with_config(config))
+                .await
+                .unwrap();
+        }
+
+        let (status, body) = send(&app, "POST", "/modules/echo/start?wait=true", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"module": "echo", "status": "running"}));
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/modules/sick/start?wait=true&timeout_secs=1",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+
+        let (status, body) = send(&app, "POST", "/modules/sick/start", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_health_degraded_when_running_module_fails_probe() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let containers =
+            Arc::new(FakeContainers::default().with_container("echo", ContainerState::Running));
+        let app = create_router(
+            AppState::new(registry.clone()).with_runtime(DockerModuleRuntime::new(containers)),
+        );
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let config = ModuleConfig {
+            ports: vec![format!("{}/tcp", closed)],
+            probe: Some(ProbeConfig::Tcp { timeout_secs: 1 }),
+            ..Default::default()
+        };
+        registry
+            .create_module(&Module::new("echo", ModuleType::Docker).with_config(config))
+            .await
+            .unwrap();
+
+        let (status, body) = send(&app, "GET", "/modules/echo/health", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["container"],
+            json!({"status": "healthy", "detail": "running"})
+        );
+        assert_eq!(body["probe"]["status"], "degraded");
+        assert_eq!(body["chain"], serde_json::Value::Null);
+        assert_eq!(body["overall"], "degraded");
+    }
+
+    #[tokio::test]
+    async fn test_quarantined_module_cannot_start() {
+        let (app, registry) = test_app().await;
+        registry
+            .create_module(&Module::new("echo", ModuleType::Local))
+            .await
+            .unwrap();
+        registry
+            .update_module_status("echo", ModuleStatus::Quarantined)
+            .await
+            .unwrap();
+
+        let (status, _) = send(&app, "POST", "/modules/echo/start", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        // Only re-verification releases a quarantined module.
+        let (status, _) = send(
+            &app,
+            "PUT",
+            "/modules/echo/status",
+            Some(json!({ "status": "stopped" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, body) = send(&app, "GET", "/modules", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["status"], "quarantined");
+    }
+}
@@ -0,0 +1,287 @@
+//! Module metadata and installation package handlers.
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use super::{status_for, AppState, ModuleName};
+use crate::module::ModuleMetadata;
+use crate::package::{build_package, InstallationPackage, PackageManifest};
+
+/// Header carrying the SHA-256 of a served archive.
+pub const SHA256_HEADER: &str = "x-package-sha256";
+
+/// Header carrying the commit a served archive was built from.
+pub const COMMIT_HEADER: &str = "x-package-commit";
+
+/// Response body for `GET /modules/:name/package`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageResponse {
+    pub manifest: PackageManifest,
+    /// Base64-encoded gzipped tarball.
+    pub archive: String,
+}
+
+/// Why an installation package could not be served.
+#[derive(Debug)]
+pub enum PackageServeError {
+    Status(StatusCode),
+    /// The package exceeds the configured size limit.
+    TooLarge(String),
+}
+
+impl From<StatusCode> for PackageServeError {
+    fn from(status: StatusCode) -> Self {
+        PackageServeError::Status(status)
+    }
+}
+
+impl IntoResponse for PackageServeError {
+    fn into_response(self) -> Response {
+        match self {
+            PackageServeError::Status(status) => status.into_response(),
+            PackageServeError::TooLarge(message) => {
+                (StatusCode::PAYLOAD_TOO_LARGE, message).into_response()
+            }
+        }
+    }
+}
+
+/// `GET /modules/:name/metadata`
+pub async fn get_metadata(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+) -> Result<Json<ModuleMetadata>, StatusCode> {
+    state
+        .registry
+        .get_module_metadata(&name)
+        .await
+        .map(Json)
+        .map_err(|e| status_for(&e))
+}
+
+/// Builds the package of an ingested module, or takes it from the package
+/// cache when one is configured. Modules without an ingested source have no
+/// package, and packages over the configured size limit are refused with
+/// 413 rather than held in memory and encoded.
+pub(crate) async fn get_installation_package(
+    state: &AppState,
+    name: &str,
+) -> Result<InstallationPackage, PackageServeError> {
+    let package = build_installation_package(state, name).await?;
+    let size = package.archive.len() as u64;
+    if size > state.max_package_size {
+        tracing::error!(
+            "Package for {} is {} bytes, over the {} byte limit",
+            name,
+            size,
+            state.max_package_size
+        );
+        let message = format!(
+            "Package for {} is {} bytes, which exceeds the limit of {} bytes",
+            name, size, state.max_package_size
+        );
+        return Err(PackageServeError::TooLarge(message));
+    }
+    Ok(package)
+}
+
+async fn build_installation_package(
+    state: &AppState,
+    name: &str,
+) -> Result<InstallationPackage, StatusCode> {
+    let module = state
+        .registry
+        .get_module(name)
+        .await
+        .map_err(|e| status_for(&e))?;
+    let source = state
+        .registry
+        .get_module_metadata(name)
+        .await
+        .map_err(|e| status_for(&e))?
+        .source
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let cache = state.packages.clone();
+    tokio::task::spawn_blocking(move || match cache {
+        Some(cache) => cache.get_or_build(&module, &source),
+        None => build_package(&module, &source),
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|e| {
+        tracing::error!("Failed to build package for {}: {}", name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Whether a request fetches the package from its start. Requests for a
+/// later byte range continue an earlier download and are not counted again.
+fn is_new_download(headers: &HeaderMap) -> bool {
+    match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) => range
+            .trim()
+            .strip_prefix("bytes=")
+            .is_some_and(|ranges| ranges.trim_start().starts_with("0-")),
+        None => true,
+    }
+}
+
+/// Counts a served package. Failing to count does not fail the download.
+async fn record_download(state: &AppState, name: &str, headers: &HeaderMap) {
+    if !is_new_download(headers) {
+        return;
+    }
+    if let Err(e) = state.registry.increment_downloads(name).await {
+        tracing::warn!("Failed to count download of {}: {}", name, e);
+    }
+}
+
+/// `GET /modules/:name/package`
+pub async fn get_package(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+    headers: HeaderMap,
+) -> Result<Json<PackageResponse>, PackageServeError> {
+    let package = get_installation_package(&state, &name).await?;
+    record_download(&state, &name, &headers).await;
+    Ok(Json(PackageResponse {
+        manifest: package.manifest,
+        archive: STANDARD.encode(&package.archive),
+    }))
+}
+
+/// `GET /modules/:name/package/archive`
+pub async fn get_package_archive(
+    State(state): State<AppState>,
+    ModuleName(name): ModuleName,
+    headers: HeaderMap,
+) -> Result<Response, PackageServeError> {
+    let package = get_installation_package(&state, &name).await?;
+    record_download(&state, &name, &headers).await;
+    let header_value =
+        |value: &str| HeaderValue::from_str(value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    let mut response = package.archive.into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/gzip"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        header_value(&format!(
+            "attachment; filename=\"{}-{}.tar.gz\"",
+            package.manifest.name, package.manifest.version
+        ))?,
+    );
+    headers.insert(SHA256_HEADER, header_value(&package.manifest.sha256)?);
+    headers.insert(COMMIT_HEADER, header_value(&package.manifest.commit)?);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_support::{send, send_raw, send_with_headers, test_app};
+    use crate::module::{Module, ModuleSource, ModuleType};
+    use crate::registry::Registry;
+    use crate::registry::SqliteRegistry;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_package_fetch_counts_one_download() {
+        let (app, registry) = test_app().await;
+        let checkout = tempfile::tempdir().unwrap();
+        std::fs::write(checkout.path().join("main.py"), "print()").unwrap();
+        registry
+            .create_module(&Module::new("echo", ModuleType::Local))
+            .await
+            .unwrap();
+        registry
+            .set_module_source(
+                "echo",
+                &ModuleSource {
+                    repo_url: "https://example.com/echo.git".into(),
+                    git_ref: "main".into(),
+                    commit: "abc123".into(),
+                    path: checkout.path().display().to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let (status, body) = send(&app, "GET", "/modules/echo/package", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["manifest"]["commit"], "abc123");
+
+        let (_, metadata) = send(&app, "GET", "/modules/echo/metadata", None).await;
+        assert_eq!(metadata["downloads"], 1);
+
+        // Resuming a download from a later offset is not a new download.
+        let (status, _) = send_with_headers(
+            &app,
+            "GET",
+            "/modules/echo/package/archive",
+            None,
+            &[("range", "bytes=100-")],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, metadata) = send(&app, "GET", "/modules/echo/metadata", None).await;
+        assert_eq!(metadata["downloads"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_module_without_source_has_no_package() {
+        let (app, registry) = test_app().await;
+        registry
+            .create_module(&Module::new("echo", ModuleType::Local))
+            .await
+            .unwrap();
+
+        let (status, _) = send(&app, "GET", "/modules/echo/package", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, metadata) = send(&app, "GET", "/modules/echo/metadata", None).await;
+        assert_eq!(metadata["downloads"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_package_is_refused() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app =
+            crate::api::create_router(AppState::new(registry.clone()).with_max_package_size(1024));
+        let checkout = tempfile::tempdir().unwrap();
+        // Random bytes do not compress, so the archive stays over the limit.
+        let blob: Vec<u8> = (0..8192).map(|_| rand::random::<u8>()).collect();
+        std::fs::write(checkout.path().join("weights.bin"), blob).unwrap();
+        registry
+            .create_module(&Module::new("echo", ModuleType::Local))
+            .await
+            .unwrap();
+        registry
+            .set_module_source(
+                "echo",
+                &ModuleSource {
+                    repo_url: "https://example.com/echo.git".into(),
+                    git_ref: "main".into(),
+                    commit: "abc123".into(),
+                    path: checkout.path().display().to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        for uri in ["/modules/echo/package", "/modules/echo/package/archive"] {
+            let (status, _, body) = send_raw(&app, "GET", uri, &[]).await;
+            assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+            let message = String::from_utf8(body).unwrap();
+            assert!(message.starts_with("Package for echo is "), "{}", message);
+            assert!(message.ends_with("exceeds the limit of 1024 bytes"));
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Per-client rate limiting middleware.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Rate limit settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// Requests allowed per client within one window. Reads count against
+    /// it too unless `max_reads` is set.
+    pub max_requests: u32,
+    /// Length of the counting window.
+    pub window: Duration,
+    /// Separate per-client budget for reads (`GET`, `HEAD`, `OPTIONS`)
+    /// within one window, so polling does not use up the write budget.
+    pub max_reads: Option<u32>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_requests: 10,
+            window: Duration::from_secs(60),
+            max_reads: None,
+        }
+    }
+}
+
+/// Which budget a request is counted against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Budget {
+    Read,
+    Write,
+}
+
+/// Start and request count of each client's current window.
+type Windows = HashMap<(IpAddr, Budget), (Instant, u32)>;
+
+/// Fixed-window request counter keyed by client IP and, when reads have a
+/// budget of their own, by whether the request reads or writes.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    windows: Arc<Mutex<Windows>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Counts a `method` request from `ip`, returning `false` if it exceeds
+    /// the limit.
+    pub fn check(&self, ip: IpAddr, method: &Method) -> bool {
+        let read = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+        let (budget, max) = match self.config.max_reads {
+            Some(max_reads) if read => (Budget::Read, max_reads),
+            _ => (Budget::Write, self.config.max_requests),
+        };
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, (start, _)| now.duration_since(*start) < self.config.window);
+
+        let (_, count) = windows.entry((ip, budget)).or_insert((now, 0));
+        if *count >= max {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+/// Middleware rejecting requests over the limit with `429 Too Many Requests`.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    if !limiter.check(ip, request.method()) {
+        tracing::warn!("Rate limit exceeded for {}", ip);
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_support::{module_body, send};
+    use crate::api::{create_router, AppState};
+    use crate::registry::SqliteRegistry;
+
+    #[tokio::test]
+    async fn test_exhausted_write_budget_still_allows_reads() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(
+            AppState::new(registry).with_api_rate_limit(RateLimitConfig {
+                max_requests: 2,
+                window: Duration::from_secs(60),
+                max_reads: Some(100),
+            }),
+        );
+
+        for (i, expected) in [
+            StatusCode::CREATED,
+            StatusCode::CREATED,
+            StatusCode::TOO_MANY_REQUESTS,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let body = module_body(&format!("m{}", i), "docker");
+            let (status, _) = send(&app, "POST", "/modules", Some(body)).await;
+            assert_eq!(status, expected, "write {}", i);
+        }
+
+        for _ in 0..5 {
+            let (status, modules) = send(&app, "GET", "/modules", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(modules.as_array().unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_reads_share_the_budget_without_max_reads() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            max_requests: 1,
+            ..Default::default()
+        });
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert!(limiter.check(ip, &Method::POST));
+        assert!(!limiter.check(ip, &Method::GET));
+    }
+}
@@ -0,0 +1,40 @@
+//! Resource usage handlers.
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+
+use super::{status_for, AppState};
+use crate::resources::{ModuleUsage, ResourceMetrics};
+
+/// `GET /resources`
+pub async fn get_resources(
+    State(state): State<AppState>,
+) -> Result<Json<ResourceMetrics>, StatusCode> {
+    let aggregator = state
+        .resources
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    aggregator
+        .collect()
+        .await
+        .map(Json)
+        .map_err(|e| status_for(&e))
+}
+
+/// `GET /resources/usage`
+///
+/// Usage of each running module, busiest CPU first.
+pub async fn get_usage(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ModuleUsage>>, StatusCode> {
+    let aggregator = state
+        .resources
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    aggregator
+        .usage()
+        .await
+        .map(Json)
+        .map_err(|e| status_for(&e))
+}
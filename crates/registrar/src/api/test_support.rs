@@ -0,0 +1,90 @@
+//! Helpers for exercising the router in tests.
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::Router;
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use super::{create_router, AppState};
+use crate::registry::SqliteRegistry;
+
+/// Builds a router over a fresh in-memory registry.
+pub async fn test_app() -> (Router, Arc<SqliteRegistry>) {
+    let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+    let router = create_router(AppState::new(registry.clone()));
+    (router, registry)
+}
+
+/// A `POST /modules` body registering a module that passes the default
+/// verifier.
+pub fn module_body(name: &str, module_type: &str) -> Value {
+    let mut config = json!({"env": {"MODULE_PORT": "8080"}});
+    if module_type == "docker" {
+        config["image"] = json!(format!("synapse/{}:1.0", name));
+    }
+    json!({"name": name, "type": module_type, "config": config})
+}
+
+/// Sends a request with an optional JSON body and returns the status and
+/// parsed JSON body (`Value::Null` when the body is empty or not JSON).
+pub async fn send(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    send_with_headers(router, method, uri, body, &[]).await
+}
+
+/// Like [`send`], with extra request headers.
+pub async fn send_with_headers(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+    headers: &[(&str, &str)],
+) -> (StatusCode, Value) {
+    let mut builder = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let request = match body {
+        Some(json) => builder
+            .header("content-type", "application/json")
+            .body(Body::from(json.to_string()))
+            .unwrap(),
+        None => builder.body(Body::empty()).unwrap(),
+    };
+
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+/// Sends a bodiless request and returns the status, headers and raw body.
+pub async fn send_raw(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let mut builder = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let response = router
+        .clone()
+        .oneshot(builder.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, headers, bytes.to_vec())
+}
@@ -0,0 +1,471 @@
+//! Resumable package upload handlers.
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use super::error::ApiError;
+use super::modules::{audit, ensure_can_modify, ensure_registrable};
+use super::{Actor, AppState};
+use crate::audit::AuditAction;
+use crate::auth::Session;
+use crate::config::{find_module_config, load_module_config};
+use crate::error::RegistryError;
+use crate::module::{normalize_name, Module, ModuleSource};
+use crate::uploads::{CompletedUpload, ExtractedUpload, UploadError, UploadStore};
+use crate::verify::verify_name;
+
+/// Progress of an upload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadStatus {
+    pub id: String,
+    /// Bytes received; the next chunk starts here.
+    pub offset: u64,
+}
+
+/// Request body for `POST /uploads/:id/complete`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompleteUploadRequest {
+    /// Hex-encoded SHA-256 digest of the whole upload.
+    pub sha256: String,
+}
+
+impl From<UploadError> for ApiError {
+    fn from(err: UploadError) -> Self {
+        let status = match &err {
+            UploadError::NotFound(_) => StatusCode::NOT_FOUND,
+            UploadError::OffsetMismatch { .. } => StatusCode::CONFLICT,
+            UploadError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadError::DigestMismatch { .. } | UploadError::Extract { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            UploadError::Io(e) => {
+                tracing::error!("Upload I/O error: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into();
+            }
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+fn store(state: &AppState) -> Result<&UploadStore, ApiError> {
+    state
+        .uploads
+        .as_ref()
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Uploads are not enabled"))
+}
+
+/// Parses `bytes <start>-<end>/<total or *>`, returning the start and the
+/// chunk length it describes.
+fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let (start, end) = span.split_once('-')?;
+    let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+    if end < start {
+        return None;
+    }
+    if total != "*" && total.parse::<u64>().ok()? <= end {
+        return None;
+    }
+    Some((start, end - start + 1))
+}
+
+/// `POST /uploads`
+///
+/// Starts an upload.
+pub async fn create_upload(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<UploadStatus>), ApiError> {
+    let id = store(&state)?.create().await?;
+    Ok((StatusCode::CREATED, Json(UploadStatus { id, offset: 0 })))
+}
+
+/// `GET /uploads/:id`
+///
+/// Reports where the next chunk must start, for resuming after a dropped
+/// connection.
+pub async fn get_upload(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<UploadStatus>, ApiError> {
+    let offset = store(&state)?.offset(&id).await?;
+    Ok(Json(UploadStatus { id, offset }))
+}
+
+/// `PATCH /uploads/:id`
+///
+/// Appends the body, placed by its `Content-Range` header. A chunk must
+/// start where the upload currently ends; otherwise 409 is returned and the
+/// client should resume from the offset given by `GET /uploads/:id`.
+pub async fn append_chunk(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<UploadStatus>, ApiError> {
+    let store = store(&state)?;
+    let (start, len) = headers
+        .get(header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_content_range)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "Expected Content-Range: bytes <start>-<end>/<total or *>",
+            )
+        })?;
+    if len != body.len() as u64 {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Content-Range covers {} bytes but the body has {}",
+                len,
+                body.len()
+            ),
+        ));
+    }
+    let offset = store.append(&id, start, &body).await?;
+    Ok(Json(UploadStatus { id, offset }))
+}
+
+/// `POST /uploads/:id/complete`
+///
+/// Verifies the assembled upload against the given digest.
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<CompleteUploadRequest>,
+) -> Result<Json<CompletedUpload>, ApiError> {
+    let completed = store(&state)?.complete(&id, &request.sha256).await?;
+    Ok(Json(completed))
+}
+
+/// `POST /uploads/:id/ingest`
+///
+/// Registers the module in a completed upload: a `.tar.gz` of the module's
+/// source with its config file at the root. A module already registered
+/// under that name has its config and source replaced. The upload is
+/// removed once ingested; after a failure it is kept so the ingest can be
+/// retried.
+pub async fn ingest_upload(
+    State(state): State<AppState>,
+    actor: Actor,
+    session: Option<Extension<Session>>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<Module>), ApiError> {
+    let store = store(&state)?;
+    let extracted = store.extract(&id).await?;
+    match register_upload(&state, &actor, session.as_deref(), &extracted).await {
+        Ok(registered) => {
+            store.remove(&id).await?;
+            Ok(registered)
+        }
+        Err(e) => {
+            if let Err(e) = tokio::fs::remove_dir_all(&extracted.path).await {
+                tracing::warn!("Failed to remove unpacked upload {}: {}", id, e);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Registers the module unpacked from an upload, like an ingest from git
+/// with the archive's digest standing in for the commit.
+async fn register_upload(
+    state: &AppState,
+    actor: &Actor,
+    session: Option<&Session>,
+    extracted: &ExtractedUpload,
+) -> Result<(StatusCode, Json<Module>), ApiError> {
+    let unprocessable = |message: String| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, message);
+    let config_path = find_module_config(&extracted.path)
+        .ok_or_else(|| unprocessable("No module config found at the root of the upload".into()))?;
+    let mut definition =
+        load_module_config(config_path).map_err(|e| unprocessable(e.to_string()))?;
+    if let Some(e) = verify_name(&definition.name).into_iter().next() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    definition.name = normalize_name(&definition.name);
+    state
+        .health_checks
+        .apply(definition.module_type, &mut definition.config);
+    ensure_registrable(&state.verifier, &definition)?;
+    let module = definition.into_module();
+    let source = ModuleSource {
+        repo_url: format!("upload:{}", extracted.upload.id),
+        git_ref: String::new(),
+        commit: extracted.upload.sha256.clone(),
+        path: extracted.path.display().to_string(),
+    };
+
+    match state.registry.get_module(&module.name).await {
+        Ok(existing) => {
+            ensure_can_modify(session, &existing)?;
+            state
+                .registry
+                .update_module_config(&module.name, &module.config)
+                .await?;
+            state
+                .registry
+                .set_module_source(&module.name, &source)
+                .await?;
+            if let Some(cache) = &state.packages {
+                cache.invalidate(&module.name);
+            }
+            audit(
+                state,
+                &module.name,
+                AuditAction::Update,
+                actor,
+                Some(existing.status),
+                Some(existing.status),
+            )
+            .await;
+            let module = state.registry.get_module(&module.name).await?;
+            Ok((StatusCode::OK, Json(module)))
+        }
+        Err(RegistryError::ModuleNotFound(_)) => {
+            let module = module.with_owner(session.map(|session| session.public_key.clone()));
+            state
+                .registry
+                .create_module_with_source(&module, Some(&source))
+                .await?;
+            audit(
+                state,
+                &module.name,
+                AuditAction::Create,
+                actor,
+                None,
+                Some(module.status),
+            )
+            .await;
+            Ok((StatusCode::CREATED, Json(module)))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::api::create_router;
+    use crate::api::test_support::send;
+    use crate::registry::{Registry, SqliteRegistry};
+
+    async fn patch(app: &axum::Router, id: &str, range: &str, chunk: &[u8]) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method("PATCH")
+            .uri(format!("/uploads/{}", id))
+            .header("content-range", range)
+            .body(Body::from(chunk.to_vec()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_upload_in_two_chunks_and_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(
+            AppState::new(registry).with_uploads(UploadStore::new(dir.path()).unwrap()),
+        );
+        let package: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let (first, second) = package.split_at(6_000);
+
+        let (status, body) = send(&app, "POST", "/uploads", None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let id = body["id"].as_str().unwrap().to_string();
+
+        let (status, body) = patch(&app, &id, "bytes 0-5999/10000", first).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["offset"], 6_000);
+
+        // A resent first chunk is refused, and the client resumes from the
+        // reported offset.
+        let (status, _) = patch(&app, &id, "bytes 0-5999/10000", first).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, body) = send(&app, "GET", &format!("/uploads/{}", id), None).await;
+        assert_eq!(body["offset"], 6_000);
+
+        let (status, body) = patch(&app, &id, "bytes 6000-9999/10000", second).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["offset"], 10_000);
+
+        let sha256 = hex::encode(Sha256::digest(&package));
+        let (status, body) = send(
+            &app,
+            "POST",
+            &format!("/uploads/{}/complete", id),
+            Some(json!({"sha256": sha256})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["size"], 10_000);
+        assert_eq!(body["sha256"], sha256);
+        // Server paths are not reported.
+        assert!(body.get("path").is_none());
+        assert_eq!(
+            std::fs::read(dir.path().join(format!("{}.upload", id))).unwrap(),
+            package
+        );
+    }
+
+    /// A `.tar.gz` of a module source with `files` at its root.
+    fn module_archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let source = tempfile::tempdir().unwrap();
+        for (name, contents) in files {
+            std::fs::write(source.path().join(name), contents).unwrap();
+        }
+        let archive = source.path().join("module.tar.gz");
+        let status = std::process::Command::new("tar")
+            .arg("czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(source.path())
+            .args(files.iter().map(|(name, _)| name))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        std::fs::read(archive).unwrap()
+    }
+
+    async fn upload(app: &axum::Router, contents: &[u8]) -> String {
+        let (_, body) = send(app, "POST", "/uploads", None).await;
+        let id = body["id"].as_str().unwrap().to_string();
+        let range = format!("bytes 0-{}/{}", contents.len() - 1, contents.len());
+        let (status, _) = patch(app, &id, &range, contents).await;
+        assert_eq!(status, StatusCode::OK);
+        let sha256 = hex::encode(Sha256::digest(contents));
+        let (status, _) = send(
+            app,
+            "POST",
+            &format!("/uploads/{}/complete", id),
+            Some(json!({"sha256": sha256})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        id
+    }
+
+    #[tokio::test]
+    async fn test_completed_upload_is_ingested_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(
+            AppState::new(registry.clone()).with_uploads(UploadStore::new(dir.path()).unwrap()),
+        );
+
+        // An upload without a module config is refused and kept.
+        let id = upload(&app, &module_archive(&[("main.py", "print()")])).await;
+        let (status, _) = send(&app, "POST", &format!("/uploads/{}/ingest", id), None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(dir.path().join(format!("{}.upload", id)).exists());
+
+        // So is one whose config fails verification.
+        let id = upload(
+            &app,
+            &module_archive(&[("config.yaml", "name: echo\ntype: local\n")]),
+        )
+        .await;
+        let (status, body) = send(&app, "POST", &format!("/uploads/{}/ingest", id), None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body["error"],
+            "Required environment variable MODULE_PORT is not set"
+        );
+        assert!(registry.get_module("echo").await.is_err());
+
+        let archive = module_archive(&[
+            (
+                "config.yaml",
+                "name: echo\ntype: local\nenv:\n  MODULE_PORT: \"8080\"\n",
+            ),
+            ("main.py", "print()"),
+        ]);
+        let id = upload(&app, &archive).await;
+        let ingest = format!("/uploads/{}/ingest", id);
+        let (status, body) = send(&app, "POST", &ingest, None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["name"], "echo");
+
+        let source = registry
+            .get_module_metadata("echo")
+            .await
+            .unwrap()
+            .source
+            .unwrap();
+        assert_eq!(source.commit, hex::encode(Sha256::digest(&archive)));
+        assert!(std::path::Path::new(&source.path).join("main.py").is_file());
+
+        let (status, _) = send(&app, "POST", &ingest, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_expired_uploads_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UploadStore::new(dir.path()).unwrap();
+        let partial = store.create().await.unwrap();
+        let completed = store.create().await.unwrap();
+        store.append(&completed, 0, b"hello").await.unwrap();
+        let sha256 = hex::encode(Sha256::digest(b"hello"));
+        store.complete(&completed, &sha256).await.unwrap();
+
+        let ttl = std::time::Duration::from_secs(3600);
+        assert_eq!(store.remove_expired(ttl).await.unwrap(), 0);
+        assert_eq!(store.offset(&partial).await.unwrap(), 0);
+
+        assert_eq!(
+            store
+                .remove_expired(std::time::Duration::ZERO)
+                .await
+                .unwrap(),
+            2
+        );
+        assert!(matches!(
+            store.offset(&partial).await,
+            Err(UploadError::NotFound(_))
+        ));
+        assert!(matches!(
+            store.extract(&completed).await,
+            Err(UploadError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_complete_rejects_wrong_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UploadStore::new(dir.path()).unwrap();
+        let id = store.create().await.unwrap();
+        store.append(&id, 0, b"hello").await.unwrap();
+
+        assert!(matches!(
+            store.complete(&id, &"00".repeat(32)).await,
+            Err(UploadError::DigestMismatch { .. })
+        ));
+        assert!(matches!(
+            store.offset(&id).await,
+            Err(UploadError::NotFound(_))
+        ));
+        assert_eq!(parse_content_range("bytes 0-4/5"), Some((0, 5)));
+        assert_eq!(parse_content_range("bytes 0-4/4"), None);
+    }
+}
@@ -0,0 +1,69 @@
+//! Webhook management handlers.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+
+use super::error::ApiError;
+use super::AppState;
+use crate::auth::{Role, Session};
+use crate::webhooks::{NewWebhook, Webhook, WebhookError};
+
+impl From<WebhookError> for ApiError {
+    fn from(err: WebhookError) -> Self {
+        let status = match &err {
+            WebhookError::InvalidUrl { .. } => StatusCode::BAD_REQUEST,
+            WebhookError::NotFound(_) => StatusCode::NOT_FOUND,
+            WebhookError::Delivery(_) | WebhookError::Status(_) => StatusCode::BAD_GATEWAY,
+            WebhookError::TimedOut(_) => StatusCode::GATEWAY_TIMEOUT,
+            WebhookError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+/// With authorization enabled, webhooks are managed by admins only: they
+/// receive every event, and their URLs may carry credentials.
+fn ensure_admin(session: Option<&Session>) -> Result<(), ApiError> {
+    match session {
+        Some(session) if session.role < Role::Admin => Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Managing webhooks requires the admin role",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// `GET /webhooks`
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    session: Option<Extension<Session>>,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    ensure_admin(session.as_deref())?;
+    Ok(Json(state.webhooks.list()))
+}
+
+/// `POST /webhooks`
+///
+/// Registers a URL that module events and miner registrations are POSTed
+/// to.
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    session: Option<Extension<Session>>,
+    Json(request): Json<NewWebhook>,
+) -> Result<(StatusCode, Json<Webhook>), ApiError> {
+    ensure_admin(session.as_deref())?;
+    let webhook = state.webhooks.add(request).await?;
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+/// `DELETE /webhooks/:id`
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    session: Option<Extension<Session>>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, ApiError> {
+    ensure_admin(session.as_deref())?;
+    state.webhooks.remove(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
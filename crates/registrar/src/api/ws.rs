@@ -0,0 +1,137 @@
+//! WebSocket push channel for GUI clients.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::AppState;
+use crate::events::RegistryEvent;
+use crate::resources::ResourceMetrics;
+
+/// Number of messages buffered for slow WebSocket clients.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Messages pushed to WebSocket clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum WsMessage {
+    /// Cluster-level resource usage.
+    ResourceMetrics(ResourceMetrics),
+    /// A module was created, changed or deleted.
+    Module(RegistryEvent),
+}
+
+/// Fan-out of [`WsMessage`]s to connected clients.
+#[derive(Clone)]
+pub struct WsState {
+    sender: broadcast::Sender<WsMessage>,
+}
+
+impl Default for WsState {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl WsState {
+    /// Sends a message to every connected client. Messages sent while no
+    /// client is connected are dropped.
+    pub fn broadcast(&self, message: WsMessage) {
+        let _ = self.sender.send(message);
+    }
+
+    /// Subscribes to broadcast messages.
+    pub fn subscribe(&self) -> broadcast::Receiver<WsMessage> {
+        self.sender.subscribe()
+    }
+}
+
+/// `GET /ws`
+pub async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    let receiver = state.ws.subscribe();
+    ws.on_upgrade(move |socket| forward(socket, receiver))
+}
+
+async fn forward(mut socket: WebSocket, mut receiver: broadcast::Receiver<WsMessage>) {
+    loop {
+        let message = match receiver.recv().await {
+            Ok(message) => message,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("WebSocket client lagged, skipped {} messages", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let text = match serde_json::to_string(&message) {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::warn!("Failed to encode WebSocket message: {}", e);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite;
+
+    use super::*;
+    use crate::api::create_router;
+    use crate::api::test_support::module_body;
+    use crate::audit::AuditAction;
+    use crate::module::ModuleStatus;
+    use crate::registry::SqliteRegistry;
+
+    #[tokio::test]
+    async fn test_module_created_over_rest_is_pushed_to_ws_clients() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(AppState::new(registry));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap()
+        });
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+            .await
+            .unwrap();
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/modules", addr))
+            .json(&module_body("a", "docker"))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let tungstenite::Message::Text(text) = message else {
+            panic!("expected a text message, got {:?}", message);
+        };
+        let WsMessage::Module(event) = serde_json::from_str(&text).unwrap() else {
+            panic!("expected a module message, got {}", text);
+        };
+        assert_eq!(event.module, "a");
+        assert_eq!(event.action, AuditAction::Create);
+        assert_eq!(event.status, Some(ModuleStatus::Stopped));
+    }
+}
@@ -0,0 +1,103 @@
+//! Audit trail of registry operations.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::module::ModuleStatus;
+use crate::page::Page;
+
+/// Default number of audit entries returned by a query.
+pub const DEFAULT_AUDIT_LIMIT: u32 = 100;
+
+/// An operation recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Start,
+    Stop,
+    Rename,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+            AuditAction::Start => "start",
+            AuditAction::Stop => "stop",
+            AuditAction::Rename => "rename",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(AuditAction::Create),
+            "update" => Ok(AuditAction::Update),
+            "delete" => Ok(AuditAction::Delete),
+            "start" => Ok(AuditAction::Start),
+            "stop" => Ok(AuditAction::Stop),
+            "rename" => Ok(AuditAction::Rename),
+            other => Err(format!("unknown audit action: {}", other)),
+        }
+    }
+}
+
+/// A single row of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Row id, assigned by the store.
+    pub id: i64,
+    /// Name of the module the operation applied to.
+    pub module: String,
+    /// Operation performed.
+    pub action: AuditAction,
+    /// Identity of the caller that performed the operation.
+    pub actor: String,
+    /// When the operation happened.
+    pub timestamp: DateTime<Utc>,
+    /// Module status before the operation, if the module existed.
+    pub before_status: Option<ModuleStatus>,
+    /// Module status after the operation, if the module still exists.
+    pub after_status: Option<ModuleStatus>,
+}
+
+/// An audit entry to be recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAuditEntry {
+    pub module: String,
+    pub action: AuditAction,
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    pub before_status: Option<ModuleStatus>,
+    pub after_status: Option<ModuleStatus>,
+}
+
+/// Filter for reading the audit log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    /// Only return entries for this module.
+    pub module: Option<String>,
+    /// Maximum number of entries to return, newest first.
+    pub limit: Option<u32>,
+    /// Only return entries at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only return entries at or before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only return entries older than the entry with this id.
+    pub before_id: Option<i64>,
+}
+
+/// A page of audit entries, newest first.
+pub type AuditPage = Page<AuditEntry>;
@@ -0,0 +1,312 @@
+//! Signature-based authentication of API callers.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use axum::http::Method;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use thiserror::Error;
+
+use synapse_chain_api::keystore;
+
+/// Hex length of an ed25519 public key.
+const PUBLIC_KEY_HEX_LEN: usize = 64;
+/// Hex length of an ed25519 signature.
+const SIGNATURE_HEX_LEN: usize = 128;
+
+/// Errors produced while authenticating a caller.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The request was rejected by the cheap format checks, before any
+    /// cryptography was performed.
+    #[error("Malformed credentials: {0}")]
+    Malformed(String),
+
+    /// The signature does not match the message and public key.
+    #[error("Invalid signature")]
+    InvalidSignature,
+
+    /// The key verified but has no role assigned.
+    #[error("Key is not authorized: {0}")]
+    UnknownKey(String),
+
+    /// The signed message does not contain a live challenge from
+    /// [`AuthManager::issue_challenge`], or the challenge was already used.
+    #[error("Unknown, expired or already used challenge")]
+    InvalidChallenge,
+
+    /// The session token is missing, unknown, or expired.
+    #[error("Invalid or expired session")]
+    InvalidSession,
+
+    /// The key store failed.
+    #[error("Database error: {0}")]
+    Database(String),
+}
+
+impl From<sqlx::Error> for AuthError {
+    fn from(err: sqlx::Error) -> Self {
+        AuthError::Database(err.to_string())
+    }
+}
+
+/// Default lifetime of a session issued by [`AuthManager::login`].
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(3600);
+
+/// Default time a challenge from [`AuthManager::issue_challenge`] may be
+/// signed and used to log in.
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(300);
+
+/// Authorization level of a key. Roles are ordered: every role may do
+/// everything a lower role may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// May only read.
+    ReadOnly,
+    /// May read, create modules, and modify or delete the modules it owns.
+    Operator,
+    /// May do anything, including modifying modules owned by other keys.
+    Admin,
+}
+
+impl Role {
+    /// The minimum role required to call an endpoint with `method`.
+    pub fn required_for(method: &Method) -> Role {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => Role::ReadOnly,
+            _ => Role::Operator,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Role::ReadOnly => "readonly",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "readonly" => Ok(Role::ReadOnly),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("unknown role: {}", other)),
+        }
+    }
+}
+
+/// An authenticated session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub token: String,
+    pub public_key: String,
+    pub role: Role,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A one-time value a caller must include in the message it signs to log
+/// in, so a captured login cannot be replayed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Challenge {
+    pub challenge: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A signed authentication request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthRequest {
+    /// Hex-encoded ed25519 public key.
+    pub public_key: String,
+    /// The signed message. To log in it must contain a challenge issued by
+    /// `GET /auth/challenge`.
+    pub message: String,
+    /// Hex-encoded signature of `message`.
+    pub signature: String,
+}
+
+fn is_hex_of_len(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl AuthRequest {
+    /// Rejects requests whose encodings cannot possibly verify. This is cheap
+    /// and runs before signature verification.
+    pub fn precheck(&self) -> Result<(), AuthError> {
+        if !is_hex_of_len(&self.public_key, PUBLIC_KEY_HEX_LEN) {
+            return Err(AuthError::Malformed(format!(
+                "public_key must be {} hex characters",
+                PUBLIC_KEY_HEX_LEN
+            )));
+        }
+        if !is_hex_of_len(&self.signature, SIGNATURE_HEX_LEN) {
+            return Err(AuthError::Malformed(format!(
+                "signature must be {} hex characters",
+                SIGNATURE_HEX_LEN
+            )));
+        }
+        if self.message.is_empty() {
+            return Err(AuthError::Malformed("message must not be empty".into()));
+        }
+        Ok(())
+    }
+
+    /// Prechecks the request and verifies its signature.
+    pub fn verify(&self) -> Result<(), AuthError> {
+        self.precheck()?;
+        match keystore::verify(&self.public_key, self.message.as_bytes(), &self.signature) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(AuthError::InvalidSignature),
+            Err(e) => Err(AuthError::Malformed(e.to_string())),
+        }
+    }
+}
+
+/// Verifies callers and tracks the role assigned to each key.
+#[derive(Clone)]
+pub struct AuthManager {
+    pool: SqlitePool,
+    sessions: Arc<RwLock<HashMap<String, Session>>>,
+    session_ttl: Duration,
+    /// Outstanding challenges and when they expire.
+    challenges: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+    challenge_ttl: Duration,
+}
+
+impl AuthManager {
+    /// Creates a manager over the `api_keys` table in `pool`.
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            session_ttl: DEFAULT_SESSION_TTL,
+            challenges: Arc::new(Mutex::new(HashMap::new())),
+            challenge_ttl: DEFAULT_CHALLENGE_TTL,
+        }
+    }
+
+    /// Sets how long issued sessions remain valid.
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Sets how long issued challenges may be used to log in.
+    pub fn with_challenge_ttl(mut self, ttl: Duration) -> Self {
+        self.challenge_ttl = ttl;
+        self
+    }
+
+    /// Issues a challenge to be included in the next signed login message.
+    pub fn issue_challenge(&self) -> Challenge {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let ttl = chrono::Duration::from_std(self.challenge_ttl).unwrap_or(chrono::Duration::MAX);
+        let now = Utc::now();
+        let challenge = Challenge {
+            challenge: hex::encode(bytes),
+            expires_at: now + ttl,
+        };
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, expires_at| *expires_at > now);
+        challenges.insert(challenge.challenge.clone(), challenge.expires_at);
+        challenge
+    }
+
+    /// Removes the live challenge contained in `message`, so it cannot be
+    /// used again.
+    fn consume_challenge(&self, message: &str) -> Result<(), AuthError> {
+        let mut challenges = self.challenges.lock().unwrap();
+        let now = Utc::now();
+        challenges.retain(|_, expires_at| *expires_at > now);
+        let found = challenges
+            .keys()
+            .find(|challenge| message.contains(challenge.as_str()))
+            .cloned()
+            .ok_or(AuthError::InvalidChallenge)?;
+        challenges.remove(&found);
+        Ok(())
+    }
+
+    /// Assigns `role` to the key, replacing any previous role.
+    pub async fn set_role(&self, public_key: &str, role: Role) -> Result<(), AuthError> {
+        sqlx::query(
+            "INSERT INTO api_keys (public_key, role, created_at) VALUES (?, ?, ?)
+             ON CONFLICT(public_key) DO UPDATE SET role = excluded.role",
+        )
+        .bind(public_key.to_lowercase())
+        .bind(role.to_string())
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the role assigned to the key, if any.
+    pub async fn role(&self, public_key: &str) -> Result<Option<Role>, AuthError> {
+        let row = sqlx::query("SELECT role FROM api_keys WHERE public_key = ?")
+            .bind(public_key.to_lowercase())
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => {
+                let role: String = row.try_get("role")?;
+                role.parse().map(Some).map_err(AuthError::Database)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Verifies a signed request and opens a session for its key. The
+    /// message must contain a challenge from
+    /// [`issue_challenge`](Self::issue_challenge), which is used up.
+    pub async fn login(&self, request: &AuthRequest) -> Result<Session, AuthError> {
+        request.verify()?;
+        self.consume_challenge(&request.message)?;
+        let role = self
+            .role(&request.public_key)
+            .await?
+            .ok_or_else(|| AuthError::UnknownKey(request.public_key.clone()))?;
+
+        let mut token = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut token);
+        let ttl = chrono::Duration::from_std(self.session_ttl).unwrap_or(chrono::Duration::MAX);
+        let session = Session {
+            token: hex::encode(token),
+            public_key: request.public_key.to_lowercase(),
+            role,
+            expires_at: Utc::now() + ttl,
+        };
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.token.clone(), session.clone());
+        Ok(session)
+    }
+
+    /// Looks up a live session by token.
+    pub fn session(&self, token: &str) -> Result<Session, AuthError> {
+        let mut sessions = self.sessions.write().unwrap();
+        let now = Utc::now();
+        sessions.retain(|_, s| s.expires_at > now);
+        sessions
+            .get(token)
+            .cloned()
+            .ok_or(AuthError::InvalidSession)
+    }
+}
@@ -0,0 +1,292 @@
+//! Exporting the registry to a file and importing it back.
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::error::RegistryError;
+use crate::module::{Module, ModuleSource};
+use crate::registry::Registry;
+
+/// Version of the export format written by [`export`].
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Errors from importing an export.
+#[derive(Debug, Error)]
+pub enum BackupError {
+    /// The export was written in a format this registrar does not read.
+    #[error("Unsupported export version {0} (expected {EXPORT_VERSION})")]
+    UnsupportedVersion(u32),
+
+    /// Reading or writing the registry failed.
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+}
+
+/// A module as exported, with the source it was ingested from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedModule {
+    #[serde(flatten)]
+    pub module: Module,
+    /// Source the module was ingested from, pinning its version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ModuleSource>,
+}
+
+/// Snapshot of every registered module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistryExport {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub modules: Vec<ExportedModule>,
+}
+
+/// What to do with an imported module whose name is already registered
+/// with different contents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Keep the registered module.
+    #[default]
+    Skip,
+    /// Replace the registered module with the imported one.
+    Overwrite,
+}
+
+/// What [`import`] did, or would do on a dry run, per module name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    /// Conflicting modules left as registered.
+    pub skipped: Vec<String>,
+    /// Modules already registered exactly as imported.
+    pub unchanged: Vec<String>,
+}
+
+/// Exports every registered module along with its source.
+pub async fn export(registry: &dyn Registry) -> Result<RegistryExport, RegistryError> {
+    let mut modules = Vec::new();
+    for module in registry.list_modules().await? {
+        let source = registry.get_module_metadata(&module.name).await?.source;
+        modules.push(ExportedModule { module, source });
+    }
+    Ok(RegistryExport {
+        version: EXPORT_VERSION,
+        exported_at: Utc::now(),
+        modules,
+    })
+}
+
+async fn write_module(
+    registry: &dyn Registry,
+    exported: &ExportedModule,
+) -> Result<(), RegistryError> {
+    registry
+        .create_module_with_source(&exported.module, exported.source.as_ref())
+        .await?;
+    Ok(())
+}
+
+/// Restores the modules in `export`. Modules already registered exactly as
+/// exported are left alone, so importing the same file twice changes
+/// nothing; other name clashes are resolved by `policy`. Overwritten
+/// modules are updated in place, keeping their download count and audit
+/// history. With `dry_run` the registry is not modified and the report
+/// describes what would happen.
+pub async fn import(
+    registry: &dyn Registry,
+    export: &RegistryExport,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> Result<ImportReport, BackupError> {
+    if export.version != EXPORT_VERSION {
+        return Err(BackupError::UnsupportedVersion(export.version));
+    }
+    let mut report = ImportReport::default();
+    for exported in &export.modules {
+        let name = exported.module.name.clone();
+        let existing = match registry.get_module(&name).await {
+            Ok(module) => {
+                let source = registry.get_module_metadata(&name).await?.source;
+                Some(ExportedModule { module, source })
+            }
+            Err(RegistryError::ModuleNotFound(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+        match (existing, policy) {
+            (None, _) => {
+                if !dry_run {
+                    write_module(registry, exported).await?;
+                }
+                report.created.push(name);
+            }
+            (Some(existing), _) if existing == *exported => report.unchanged.push(name),
+            (Some(_), ConflictPolicy::Skip) => report.skipped.push(name),
+            (Some(_), ConflictPolicy::Overwrite) => {
+                if !dry_run {
+                    registry
+                        .replace_module(&exported.module, exported.source.as_ref())
+                        .await?;
+                }
+                report.overwritten.push(name);
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, AuditQuery, NewAuditEntry};
+    use crate::module::{ModuleConfig, ModuleStatus, ModuleType};
+    use crate::registry::SqliteRegistry;
+
+    async fn populated() -> SqliteRegistry {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        let echo = Module::new("echo", ModuleType::Docker)
+            .with_config(ModuleConfig {
+                image: Some("synapse/echo:1.0".into()),
+                ports: vec!["8080/tcp".into()],
+                ..Default::default()
+            })
+            .with_tags(vec!["team-a".into()]);
+        registry.create_module(&echo).await.unwrap();
+        registry
+            .update_module_status("echo", ModuleStatus::Running)
+            .await
+            .unwrap();
+        registry
+            .set_module_source(
+                "echo",
+                &ModuleSource {
+                    repo_url: "https://example.com/echo.git".into(),
+                    git_ref: "v1.0".into(),
+                    commit: "0123456789abcdef0123456789abcdef01234567".into(),
+                    path: "/var/lib/synapse/repos/echo".into(),
+                },
+            )
+            .await
+            .unwrap();
+        registry
+            .create_module(&Module::new("watcher", ModuleType::Observer))
+            .await
+            .unwrap();
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let original = populated().await;
+        let exported = export(&original).await.unwrap();
+        let json = serde_json::to_string_pretty(&exported).unwrap();
+
+        let restored = SqliteRegistry::in_memory().await.unwrap();
+        let parsed: RegistryExport = serde_json::from_str(&json).unwrap();
+        let report = import(&restored, &parsed, ConflictPolicy::Skip, false)
+            .await
+            .unwrap();
+        assert_eq!(report.created, vec!["echo", "watcher"]);
+
+        let reexported = export(&restored).await.unwrap();
+        assert_eq!(reexported.modules, exported.modules);
+
+        // Importing again is a no-op.
+        let report = import(&restored, &parsed, ConflictPolicy::Overwrite, false)
+            .await
+            .unwrap();
+        assert_eq!(report.unchanged, vec!["echo", "watcher"]);
+        assert!(report.created.is_empty() && report.overwritten.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_follow_policy_and_dry_run_writes_nothing() {
+        let exported = export(&populated().await).await.unwrap();
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        registry
+            .create_module(&Module::new("echo", ModuleType::Local))
+            .await
+            .unwrap();
+
+        let report = import(&registry, &exported, ConflictPolicy::Overwrite, true)
+            .await
+            .unwrap();
+        assert_eq!(report.overwritten, vec!["echo"]);
+        assert_eq!(report.created, vec!["watcher"]);
+        assert_eq!(registry.list_modules().await.unwrap().len(), 1);
+
+        let report = import(&registry, &exported, ConflictPolicy::Skip, false)
+            .await
+            .unwrap();
+        assert_eq!(report.skipped, vec!["echo"]);
+        assert_eq!(
+            registry.get_module("echo").await.unwrap().module_type,
+            ModuleType::Local
+        );
+
+        import(&registry, &exported, ConflictPolicy::Overwrite, false)
+            .await
+            .unwrap();
+        assert_eq!(export(&registry).await.unwrap().modules, exported.modules);
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_keeps_history_and_owner() {
+        let exported = export(&populated().await).await.unwrap();
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        registry
+            .create_module(
+                &Module::new("echo", ModuleType::Local).with_owner(Some("5Grwva".into())),
+            )
+            .await
+            .unwrap();
+        registry.increment_downloads("echo").await.unwrap();
+        registry
+            .record_audit(NewAuditEntry {
+                module: "echo".into(),
+                action: AuditAction::Create,
+                actor: "5Grwva".into(),
+                timestamp: Utc::now(),
+                before_status: None,
+                after_status: Some(ModuleStatus::Stopped),
+            })
+            .await
+            .unwrap();
+
+        import(&registry, &exported, ConflictPolicy::Overwrite, false)
+            .await
+            .unwrap();
+
+        let echo = registry.get_module("echo").await.unwrap();
+        assert_eq!(echo.module_type, ModuleType::Docker);
+        assert_eq!(echo.owner.as_deref(), Some("5Grwva"));
+        assert_eq!(
+            registry
+                .get_module_metadata("echo")
+                .await
+                .unwrap()
+                .downloads,
+            1
+        );
+        let audit = registry
+            .list_audit(&AuditQuery {
+                module: Some("echo".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(audit.items.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_version_rejected() {
+        let mut exported = export(&populated().await).await.unwrap();
+        exported.version = EXPORT_VERSION + 1;
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        let err = import(&registry, &exported, ConflictPolicy::Skip, true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BackupError::UnsupportedVersion(v) if v == EXPORT_VERSION + 1));
+    }
+}
@@ -0,0 +1,57 @@
+//! Asking before destructive commands.
+
+use std::io::IsTerminal;
+
+use dialoguer::Confirm;
+use thiserror::Error;
+
+/// Errors produced while asking for confirmation.
+#[derive(Debug, Error)]
+pub enum ConfirmError {
+    /// Confirmation is required but there is no terminal to ask on.
+    #[error("{0}: refusing without a terminal to confirm on; pass --yes to proceed")]
+    NotInteractive(String),
+
+    #[error(transparent)]
+    Prompt(#[from] dialoguer::Error),
+}
+
+/// Asks the user to confirm destructive operations, unless they agreed to
+/// everything up front with `--yes`.
+#[derive(Debug, Clone, Copy)]
+pub struct Confirmer {
+    assume_yes: bool,
+}
+
+impl Confirmer {
+    pub fn new(assume_yes: bool) -> Self {
+        Self { assume_yes }
+    }
+
+    /// Asks `prompt`, with `default` chosen on an empty answer. Returns
+    /// `true` without asking when `--yes` was passed, and fails when stdin
+    /// is not a terminal rather than proceeding unconfirmed.
+    pub fn confirm(&self, prompt: &str, default: bool) -> Result<bool, ConfirmError> {
+        if self.assume_yes {
+            return Ok(true);
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(ConfirmError::NotInteractive(prompt.to_string()));
+        }
+        Ok(Confirm::new()
+            .with_prompt(prompt)
+            .default(default)
+            .interact()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yes_bypasses_prompt() {
+        // Would block on, or fail for lack of, a terminal if it asked.
+        assert!(Confirmer::new(true).confirm("Delete echo?", false).unwrap());
+    }
+}
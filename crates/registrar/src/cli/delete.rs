@@ -0,0 +1,54 @@
+//! `registrar delete`.
+
+use synapse_registrar::dependencies::dependents;
+use synapse_registrar::registry::Registry;
+
+use super::confirm::Confirmer;
+
+/// Unregisters `name` once the user confirms, warning about modules that
+/// depend on it. Returns whether the module was deleted.
+pub async fn run(
+    registry: &dyn Registry,
+    name: &str,
+    confirmer: Confirmer,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    registry.get_module(name).await?;
+    let modules = registry.list_modules().await?;
+    let dependents: Vec<&str> = dependents(&modules, name)
+        .into_iter()
+        .map(|module| module.name.as_str())
+        .collect();
+    let prompt = if dependents.is_empty() {
+        format!("Delete module {}?", name)
+    } else {
+        format!(
+            "Delete module {}? It is a dependency of {}",
+            name,
+            dependents.join(", ")
+        )
+    };
+    if !confirmer.confirm(&prompt, false)? {
+        return Ok(false);
+    }
+    registry.delete_module(name).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_registrar::module::{Module, ModuleType};
+    use synapse_registrar::registry::SqliteRegistry;
+
+    #[tokio::test]
+    async fn test_yes_deletes_without_prompting() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        registry
+            .create_module(&Module::new("echo", ModuleType::Docker))
+            .await
+            .unwrap();
+
+        assert!(run(&registry, "echo", Confirmer::new(true)).await.unwrap());
+        assert!(registry.list_modules().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+//! `registrar doctor`.
+
+use std::path::Path;
+
+use synapse_registrar::data_dir::DataDir;
+use synapse_registrar::doctor::{
+    check_dir_present, check_docker, check_python, check_writable_dir, DoctorReport,
+};
+use synapse_registrar::reconnect::ReconnectingContainers;
+
+/// Checks what the registrar depends on and prints the report. Returns
+/// whether every check passed.
+pub async fn run(data_dir: &DataDir, config_dir: &Path) -> bool {
+    let mut report = DoctorReport::default();
+    report.push(check_docker(&ReconnectingContainers::docker()).await);
+    report.push(check_writable_dir(
+        "data dir",
+        data_dir.root(),
+        "create it (mkdir -p) or point --data-dir / SYNAPSE_DATA_DIR at a writable directory",
+    ));
+    report.push(check_dir_present(
+        "config dir",
+        config_dir,
+        "create it, or pass --config-dir; `registrar new` scaffolds modules into it",
+    ));
+    report.push(check_python().await);
+    println!("{}", report);
+    report.passed()
+}
@@ -0,0 +1,76 @@
+//! `registrar env` subcommands.
+
+use std::collections::BTreeMap;
+
+use clap::Subcommand;
+use synapse_registrar::env_store::{ActiveEnv, EnvStore};
+
+use super::confirm::Confirmer;
+
+#[derive(Subcommand)]
+pub enum EnvCommand {
+    /// List the envs in the profile, marking the active one
+    List {
+        /// Clear a dangling active env link without asking
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Create an env in the profile
+    Create {
+        name: String,
+        /// Variable to set, as KEY=VALUE; may be repeated
+        #[arg(long = "set", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    /// Make an env the profile's active env
+    Activate { name: String },
+}
+
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {:?}", s)),
+    }
+}
+
+pub fn run(
+    store: &EnvStore,
+    command: EnvCommand,
+    confirmer: &Confirmer,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        EnvCommand::List { fix } => {
+            let mut active = store.active()?;
+            if let ActiveEnv::Dangling(target) = &active {
+                eprintln!(
+                    "Warning: the active env of profile {} points at {}, which no longer exists",
+                    store.profile(),
+                    target.display()
+                );
+                if fix || confirmer.confirm("Clear the active env?", true)? {
+                    store.clear_active()?;
+                    eprintln!("Cleared the active env");
+                    active = ActiveEnv::None;
+                }
+            }
+            for name in store.list()? {
+                let marker = if active == ActiveEnv::Env(name.clone()) {
+                    "*"
+                } else {
+                    " "
+                };
+                println!("{} {}", marker, name);
+            }
+        }
+        EnvCommand::Create { name, vars } => {
+            let vars: BTreeMap<String, String> = vars.into_iter().collect();
+            let path = store.create(&name, &vars)?;
+            println!("Created {} in profile {}", path.display(), store.profile());
+        }
+        EnvCommand::Activate { name } => {
+            store.activate(&name)?;
+            println!("Activated {} in profile {}", name, store.profile());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! `registrar keys` subcommands.
+
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use dialoguer::Password;
+use synapse_chain_api::keystore::{self, Keystore};
+
+/// Environment variable consulted for the keystore password before prompting.
+pub const PASSWORD_ENV: &str = "SYNAPSE_KEY_PASSWORD";
+
+#[derive(Subcommand)]
+pub enum KeysCommand {
+    /// Generate a new key
+    Generate {
+        #[arg(long)]
+        name: String,
+    },
+    /// List stored keys
+    List,
+    /// Export an encrypted key file
+    Export {
+        #[arg(long)]
+        key: String,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Import an encrypted key file
+    Import {
+        #[arg(long)]
+        file: PathBuf,
+        /// Store the key under a different name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Sign a message with a stored key
+    Sign {
+        #[arg(long)]
+        key: String,
+        #[arg(long)]
+        message: String,
+    },
+    /// Verify a signature against a public key
+    Verify {
+        #[arg(long)]
+        public_key: String,
+        #[arg(long)]
+        message: String,
+        #[arg(long)]
+        signature: String,
+    },
+}
+
+fn password(confirm: bool) -> Result<String, Box<dyn std::error::Error>> {
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+    let mut prompt = Password::new().with_prompt("Keystore password");
+    if confirm {
+        prompt = prompt.with_confirmation("Confirm password", "Passwords do not match");
+    }
+    Ok(prompt.interact()?)
+}
+
+pub fn run(keystore: &Keystore, command: KeysCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        KeysCommand::Generate { name } => {
+            let public_key = keystore.generate(&name, &password(true)?)?;
+            println!("{}", hex::encode(public_key.to_bytes()));
+        }
+        KeysCommand::List => {
+            for name in keystore.list()? {
+                let file = keystore.load(&name)?;
+                println!("{}\t{}", name, file.public_key);
+            }
+        }
+        KeysCommand::Export { key, out } => {
+            keystore.export(&key, &out)?;
+            println!("Exported {} to {}", key, out.display());
+        }
+        KeysCommand::Import { file, name } => {
+            let name = keystore.import(&file, name.as_deref())?;
+            println!("Imported {}", name);
+        }
+        KeysCommand::Sign { key, message } => {
+            let signature = keystore.sign(&key, &password(false)?, message.as_bytes())?;
+            println!("{}", hex::encode(signature.to_bytes()));
+        }
+        KeysCommand::Verify {
+            public_key,
+            message,
+            signature,
+        } => {
+            if keystore::verify(&public_key, message.as_bytes(), &signature)? {
+                println!("Signature is valid");
+            } else {
+                return Err("Signature is invalid".into());
+            }
+        }
+    }
+    Ok(())
+}
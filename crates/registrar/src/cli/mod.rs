@@ -0,0 +1,9 @@
+//! Command implementations for the `registrar` binary.
+
+pub mod confirm;
+pub mod delete;
+pub mod doctor;
+pub mod env;
+pub mod keys;
+pub mod validate;
+pub mod verify_all;
@@ -0,0 +1,37 @@
+//! `registrar validate`.
+
+use std::path::Path;
+
+use synapse_registrar::verify::{validate_config_file, ModuleVerifier, Severity};
+
+/// Validates the module config at `path`, printing every issue found.
+/// Returns whether the config is valid, i.e. has no errors; warnings are
+/// printed but do not fail validation.
+pub fn run(path: &Path) -> bool {
+    let issues = validate_config_file(path, &ModuleVerifier::default(), |name| {
+        std::env::var(name).ok()
+    });
+    for issue in &issues {
+        eprintln!("{}: {}", issue.severity, issue);
+    }
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    let warnings = issues.len() - errors;
+    if errors == 0 {
+        if warnings == 0 {
+            println!("{}: OK", path.display());
+        } else {
+            println!("{}: OK with {} warning(s)", path.display(), warnings);
+        }
+        return true;
+    }
+    eprintln!(
+        "{}: {} error(s), {} warning(s) found",
+        path.display(),
+        errors,
+        warnings
+    );
+    false
+}
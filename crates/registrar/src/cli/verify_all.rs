@@ -0,0 +1,32 @@
+//! `registrar verify-all`.
+
+use synapse_registrar::quarantine::verify_registered;
+use synapse_registrar::registry::Registry;
+use synapse_registrar::verify::ModuleVerifier;
+
+/// Re-verifies every registered module, quarantining those that fail and
+/// releasing those that pass again. Returns whether every module passed.
+pub async fn run(
+    registry: &dyn Registry,
+    verifier: &ModuleVerifier,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let report = verify_registered(registry, verifier).await?;
+    for (name, errors) in &report.quarantined {
+        eprintln!("Quarantined {}:", name);
+        for e in errors {
+            eprintln!("  {}", e);
+        }
+    }
+    for name in &report.still_quarantined {
+        eprintln!("Still quarantined: {}", name);
+    }
+    for name in &report.released {
+        println!("Released {} from quarantine", name);
+    }
+    println!(
+        "{} compliant, {} quarantined",
+        report.compliant,
+        report.quarantined.len() + report.still_quarantined.len()
+    );
+    Ok(report.quarantined.is_empty() && report.still_quarantined.is_empty())
+}
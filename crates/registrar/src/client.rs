@@ -0,0 +1,595 @@
+//! HTTP client for the registrar API.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::{self, Stream};
+use reqwest::{StatusCode, Url};
+use thiserror::Error;
+
+use crate::api::events::LAST_EVENT_ID;
+use crate::dependencies::ModuleStarter;
+use crate::events::{EventCursor, RegistryEvent};
+use crate::miner::{Miner, RegisterResult};
+use crate::module::{Module, ModuleStatus};
+use crate::resources::ModuleUsage;
+use crate::retry::{retry, RetryConfig, Retryable};
+
+/// Errors produced by [`RegistrarClient`].
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The registrar URL could not be parsed.
+    #[error("Invalid registrar URL: {0}")]
+    InvalidUrl(String),
+
+    /// The request could not be sent or the response could not be read.
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The registrar answered with an unexpected status.
+    #[error("Unexpected status {0}")]
+    Status(StatusCode),
+
+    /// A request was cut off by its retry budget.
+    #[error("Request timed out: {0}")]
+    TimedOut(#[from] tokio::time::error::Elapsed),
+
+    /// The registrar did not become healthy in time.
+    #[error("Registrar at {url} not reachable after {timeout:?}: {reason}")]
+    Unavailable {
+        url: String,
+        timeout: Duration,
+        reason: String,
+    },
+}
+
+impl Retryable for ClientError {
+    /// Whether the request may succeed if sent again: connection failures,
+    /// timeouts, rate limiting and server errors.
+    fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::InvalidUrl(_)
+            | ClientError::TimedOut(_)
+            | ClientError::Unavailable { .. } => false,
+            ClientError::Http(e) => e.is_connect() || e.is_timeout(),
+            ClientError::Status(status) => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+        }
+    }
+}
+
+/// Parses one server-sent event frame into a registry event, ignoring
+/// comments and frames without data.
+fn parse_event_frame(frame: &[u8]) -> Option<RegistryEvent> {
+    let frame = String::from_utf8_lossy(frame);
+    let mut data = Vec::new();
+    for line in frame.lines() {
+        if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    if data.is_empty() {
+        return None;
+    }
+    match serde_json::from_str(&data.join("\n")) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            tracing::warn!("Ignoring malformed registry event: {}", e);
+            None
+        }
+    }
+}
+
+/// State of a [`RegistrarClient::watch_events`] stream across reconnects.
+struct EventWatch {
+    client: RegistrarClient,
+    backoff: RetryConfig,
+    response: Option<reqwest::Response>,
+    buffer: Vec<u8>,
+    pending: VecDeque<RegistryEvent>,
+    last: Option<EventCursor>,
+    /// Connection failures since the last event arrived.
+    failures: u32,
+}
+
+impl EventWatch {
+    async fn connect(&self) -> Result<reqwest::Response, ClientError> {
+        let mut request = self
+            .client
+            .http
+            .get(self.client.url("events")?)
+            .header(reqwest::header::ACCEPT, "text/event-stream");
+        if let Some(last) = self.last {
+            request = request.header(LAST_EVENT_ID, last.to_string());
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(response)
+    }
+
+    /// Moves complete frames out of the buffer, queueing events not seen
+    /// before. Events from a new epoch are always new, since ids start over
+    /// when the registrar restarts.
+    fn drain_frames(&mut self) {
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let frame: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let Some(event) = parse_event_frame(&frame) else {
+                continue;
+            };
+            if self.last.is_some_and(|last| !last.precedes(&event)) {
+                continue;
+            }
+            self.last = Some(event.cursor());
+            self.failures = 0;
+            self.pending.push_back(event);
+        }
+    }
+
+    async fn next(&mut self) -> RegistryEvent {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return event;
+            }
+            let Some(response) = self.response.as_mut() else {
+                if self.failures > 0 {
+                    tokio::time::sleep(self.backoff.backoff(self.failures - 1)).await;
+                }
+                match self.connect().await {
+                    Ok(response) => {
+                        self.buffer.clear();
+                        self.response = Some(response);
+                    }
+                    Err(e) => {
+                        self.failures += 1;
+                        tracing::warn!("Failed to connect to registry events: {}", e);
+                    }
+                }
+                continue;
+            };
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    // Normalize line endings so frames split on a blank line.
+                    self.buffer
+                        .extend(chunk.iter().copied().filter(|&byte| byte != b'\r'));
+                    self.drain_frames();
+                }
+                Ok(None) | Err(_) => {
+                    tracing::warn!("Registry event stream dropped, reconnecting");
+                    self.response = None;
+                    self.failures += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Client for a running registrar.
+#[derive(Debug, Clone)]
+pub struct RegistrarClient {
+    base_url: Url,
+    http: reqwest::Client,
+}
+
+impl RegistrarClient {
+    /// Creates a client for the registrar at `base_url`, which may include
+    /// the path prefix the registrar serves under, e.g.
+    /// `http://host:3000/api`.
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        let mut base_url =
+            Url::parse(base_url).map_err(|e| ClientError::InvalidUrl(e.to_string()))?;
+        // Without a trailing slash, joining would replace the last segment.
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        Ok(Self {
+            base_url,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url, ClientError> {
+        self.base_url
+            .join(path)
+            .map_err(|e| ClientError::InvalidUrl(e.to_string()))
+    }
+
+    /// URL of `modules/<name>/<rest...>`. The name is percent-encoded as a
+    /// single segment, so a namespaced name like `team-a/echo` stays one.
+    fn module_url(&self, name: &str, rest: &[&str]) -> Result<Url, ClientError> {
+        let mut url = self.url("modules/")?;
+        url.path_segments_mut()
+            .map_err(|_| ClientError::InvalidUrl(self.base_url.to_string()))?
+            .pop_if_empty()
+            .push(name)
+            .extend(rest);
+        Ok(url)
+    }
+
+    /// Checks that the registrar is up and serving requests.
+    pub async fn health(&self) -> Result<(), ClientError> {
+        let response = self.http.get(self.url("health")?).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(())
+    }
+
+    /// Polls the registrar's health with exponential backoff until it
+    /// answers, for at most `timeout`. Useful at startup, when the
+    /// registrar may still be coming up.
+    pub async fn wait_until_healthy(&self, timeout: Duration) -> Result<(), ClientError> {
+        let config = RetryConfig {
+            max_retries: u32::MAX,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            ..Default::default()
+        }
+        .with_max_elapsed(timeout);
+        retry("registrar_health", &config, || self.health())
+            .await
+            .map_err(|e| ClientError::Unavailable {
+                url: self.base_url.to_string(),
+                timeout,
+                reason: e.to_string(),
+            })
+    }
+
+    /// Streams registry events. The stream never ends: when the connection
+    /// drops it reconnects with exponential backoff, resuming after the
+    /// last event received, and events already yielded are not repeated.
+    pub fn watch_events(&self) -> impl Stream<Item = RegistryEvent> {
+        self.watch_events_with(RetryConfig {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            ..Default::default()
+        })
+    }
+
+    /// Like [`watch_events`](Self::watch_events), spacing reconnects by
+    /// `backoff`.
+    pub fn watch_events_with(&self, backoff: RetryConfig) -> impl Stream<Item = RegistryEvent> {
+        let watch = EventWatch {
+            client: self.clone(),
+            backoff,
+            response: None,
+            buffer: Vec::new(),
+            pending: VecDeque::new(),
+            last: None,
+            failures: 0,
+        };
+        stream::unfold(watch, |mut watch| async move {
+            let event = watch.next().await;
+            Some((event, watch))
+        })
+    }
+
+    /// Lists all registered modules.
+    pub async fn list_modules(&self) -> Result<Vec<Module>, ClientError> {
+        let response = self.http.get(self.url("modules")?).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Fetches a single module.
+    pub async fn get_module(&self, name: &str) -> Result<Module, ClientError> {
+        let response = self.http.get(self.module_url(name, &[])?).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Fetches the resource usage of each running module, busiest CPU
+    /// first.
+    pub async fn resource_usage(&self) -> Result<Vec<ModuleUsage>, ClientError> {
+        let response = self.http.get(self.url("resources/usage")?).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Asks the registrar to start a module.
+    pub async fn start_module(&self, name: &str) -> Result<(), ClientError> {
+        let url = self.module_url(name, &["start"])?;
+        let response = self.http.post(url).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(())
+    }
+
+    /// Sets a module's status, e.g. to mark it failed.
+    pub async fn update_module_status(
+        &self,
+        name: &str,
+        status: ModuleStatus,
+    ) -> Result<(), ClientError> {
+        let url = self.module_url(name, &["status"])?;
+        let response = self
+            .http
+            .put(url)
+            .json(&serde_json::json!({ "status": status }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(())
+    }
+
+    /// Registers a single miner.
+    pub async fn register_miner(&self, uid: u16, key: &str, name: &str) -> Result<(), ClientError> {
+        let miner = Miner {
+            uid,
+            key: key.to_string(),
+            name: name.to_string(),
+        };
+        let response = self
+            .http
+            .post(self.url("miners")?)
+            .json(&miner)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(())
+    }
+
+    /// Registers `(uid, key, name)` miners in one request, returning each
+    /// miner's outcome in order.
+    pub async fn register_miners(
+        &self,
+        miners: &[(u16, &str, &str)],
+    ) -> Result<Vec<RegisterResult>, ClientError> {
+        let miners: Vec<Miner> = miners
+            .iter()
+            .map(|&(uid, key, name)| Miner {
+                uid,
+                key: key.to_string(),
+                name: name.to_string(),
+            })
+            .collect();
+        let response = self
+            .http
+            .post(self.url("miners/batch")?)
+            .json(&miners)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status()));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl ModuleStarter for RegistrarClient {
+    type Error = ClientError;
+
+    async fn start_module(&self, name: &str) -> Result<(), ClientError> {
+        RegistrarClient::start_module(self, name).await
+    }
+
+    async fn is_healthy(&self, name: &str) -> Result<bool, ClientError> {
+        Ok(self.get_module(name).await?.status == ModuleStatus::Running)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use synapse_chain_api::ss58;
+
+    use super::*;
+    use crate::api::{create_router, AppState};
+    use crate::miner::RegisterOutcome;
+    use crate::module::ModuleType;
+    use crate::registry::{Registry, SqliteRegistry};
+
+    /// Serves a fresh registrar on a local port and returns its URL.
+    async fn serve() -> String {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        let app = create_router(AppState::new(registry));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_routes_resolve_under_base_path() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        registry
+            .create_module(&Module::new("team-a/echo", ModuleType::Local))
+            .await
+            .unwrap();
+        let app = create_router(AppState::new(registry).with_base_path("api/"));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        for url in [
+            format!("http://{}/api", addr),
+            format!("http://{}/api/", addr),
+        ] {
+            let client = RegistrarClient::new(&url).unwrap();
+            client.health().await.unwrap();
+            assert_eq!(client.list_modules().await.unwrap().len(), 1);
+            let module = client.get_module("team-a/echo").await.unwrap();
+            assert_eq!(module.name, "team-a/echo");
+        }
+        let client = RegistrarClient::new(&format!("http://{}/api", addr)).unwrap();
+        client
+            .update_module_status("team-a/echo", ModuleStatus::Failed)
+            .await
+            .unwrap();
+        let module = client.get_module("team-a/echo").await.unwrap();
+        assert_eq!(module.status, ModuleStatus::Failed);
+        let root = RegistrarClient::new(&format!("http://{}", addr)).unwrap();
+        assert!(matches!(
+            root.list_modules().await,
+            Err(ClientError::Status(StatusCode::NOT_FOUND))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_healthy_gives_up_after_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let client = RegistrarClient::new(&format!("http://{}/", addr)).unwrap();
+
+        let result = client.wait_until_healthy(Duration::from_millis(300)).await;
+        assert!(matches!(result, Err(ClientError::Unavailable { .. })));
+    }
+
+    fn sse_frame(epoch: u64, id: u64, module: &str) -> String {
+        let event = RegistryEvent {
+            epoch,
+            id,
+            module: module.to_string(),
+            action: crate::audit::AuditAction::Create,
+            status: Some(ModuleStatus::Stopped),
+            timestamp: chrono::Utc::now(),
+        };
+        format!(
+            "id: {}\nevent: registry\ndata: {}\n\n",
+            event.cursor(),
+            serde_json::to_string(&event).unwrap()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_watch_events_reconnects_without_repeating_events() {
+        use axum::body::Body;
+        use axum::http::HeaderMap;
+        use futures::StreamExt;
+        use std::sync::Mutex;
+
+        let seen_headers = Arc::new(Mutex::new(Vec::new()));
+        let headers_in_handler = seen_headers.clone();
+        let app = axum::Router::new().route(
+            "/events",
+            axum::routing::get(move |headers: HeaderMap| {
+                let seen = headers_in_handler.clone();
+                async move {
+                    let last_id = headers
+                        .get(LAST_EVENT_ID)
+                        .map(|v| v.to_str().unwrap().to_string());
+                    let mut seen = seen.lock().unwrap();
+                    seen.push(last_id);
+                    let frames: Vec<Result<String, std::convert::Infallible>> = match seen.len() {
+                        // The server drops the connection after two events.
+                        1 => vec![Ok(sse_frame(1, 1, "a")), Ok(sse_frame(1, 2, "b"))],
+                        // Resending event 2 must not produce a duplicate.
+                        2 => vec![Ok(sse_frame(1, 2, "b")), Ok(sse_frame(1, 3, "c"))],
+                        // After a restart, ids start over in a new epoch.
+                        _ => vec![Ok(sse_frame(2, 1, "d"))],
+                    };
+                    let body = stream::iter(frames);
+                    if seen.len() < 3 {
+                        Body::from_stream(body)
+                    } else {
+                        Body::from_stream(body.chain(stream::pending()))
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = RegistrarClient::new(&format!("http://{}/", addr)).unwrap();
+        let backoff = RetryConfig {
+            initial_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let events: Vec<_> = tokio::time::timeout(
+            Duration::from_secs(5),
+            client
+                .watch_events_with(backoff)
+                .take(4)
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+
+        let ids: Vec<_> = events
+            .iter()
+            .map(|e| (e.epoch, e.id, e.module.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![(1, 1, "a"), (1, 2, "b"), (1, 3, "c"), (2, 1, "d")]
+        );
+        assert_eq!(
+            *seen_headers.lock().unwrap(),
+            vec![None, Some("1-2".to_string()), Some("1-3".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_events_receives_registry_changes() {
+        use futures::StreamExt;
+
+        let url = serve().await;
+        let client = RegistrarClient::new(&url).unwrap();
+        let mut events = Box::pin(client.watch_events());
+        // Give the subscription time to connect before the change.
+        let next = tokio::spawn(async move { events.next().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        reqwest::Client::new()
+            .post(format!("{}modules", url))
+            .json(&crate::api::test_support::module_body("echo", "docker"))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), next)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.module, "echo");
+        assert_eq!(event.action, crate::audit::AuditAction::Create);
+    }
+
+    #[tokio::test]
+    async fn test_register_miners_in_one_call() {
+        let client = RegistrarClient::new(&serve().await).unwrap();
+        let [alice, bob, carol, dave] =
+            [1, 2, 3, 4].map(|n| ss58::encode(&[n; 32], ss58::DEFAULT_PREFIX));
+        let results = client
+            .register_miners(&[(1, &alice, "alice"), (2, &bob, "bob"), (1, &carol, "carol")])
+            .await
+            .unwrap();
+
+        let outcomes: Vec<_> = results.iter().map(|r| (r.uid, r.outcome)).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (1, RegisterOutcome::Registered),
+                (2, RegisterOutcome::Registered),
+                (1, RegisterOutcome::Conflict),
+            ]
+        );
+        assert!(results[2].error.is_some());
+
+        assert!(matches!(
+            client.register_miner(2, &dave, "dave").await,
+            Err(ClientError::Status(StatusCode::CONFLICT))
+        ));
+        client.register_miner(3, &dave, "dave").await.unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! Loading module definitions from config files.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::module::{Module, ModuleConfig, ModuleType};
+
+/// Errors produced while loading a module config file.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The file extension does not map to a known format.
+    #[error("Unsupported config format for {path}: expected .yaml, .yml, .toml or .json")]
+    UnsupportedFormat { path: String },
+
+    /// The file could not be read.
+    #[error("Failed to read {path}: {reason}")]
+    Io { path: String, reason: String },
+
+    /// The file contents are not a valid module config.
+    #[error("Invalid {format} module config: {reason}")]
+    Parse {
+        format: ConfigFormat,
+        reason: String,
+    },
+}
+
+/// File names, in order of preference, under which a module repository
+/// keeps its config.
+pub const CONFIG_FILE_NAMES: [&str; 4] =
+    ["config.yaml", "config.yml", "config.toml", "config.json"];
+
+/// Returns the module config in `dir`, if there is one.
+pub fn find_module_config(dir: &Path) -> Option<PathBuf> {
+    CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Serialization format of a module config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Picks the format from the file extension, case-insensitively.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "toml" => Some(ConfigFormat::Toml),
+            "json" => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ConfigFormat::Yaml => "YAML",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Json => "JSON",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A module as described in a config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleDefinition {
+    /// Unique module name.
+    pub name: String,
+    /// Module type.
+    #[serde(rename = "type")]
+    pub module_type: ModuleType,
+    /// Module configuration.
+    #[serde(flatten)]
+    pub config: ModuleConfig,
+}
+
+impl ModuleDefinition {
+    /// Converts the definition into a new, stopped module.
+    pub fn into_module(self) -> Module {
+        Module::new(self.name, self.module_type).with_config(self.config)
+    }
+}
+
+/// Parses a module definition in the given format.
+pub fn parse_module_config(
+    contents: &str,
+    format: ConfigFormat,
+) -> Result<ModuleDefinition, ConfigError> {
+    let parsed = match format {
+        ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        ConfigFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+        ConfigFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+    };
+    parsed.map_err(|reason| ConfigError::Parse { format, reason })
+}
+
+/// Loads a module definition from `path`, choosing the parser from the file
+/// extension.
+pub fn load_module_config(path: impl AsRef<Path>) -> Result<ModuleDefinition, ConfigError> {
+    let path = path.as_ref();
+    let format = ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnsupportedFormat {
+        path: path.display().to_string(),
+    })?;
+    let contents = std::fs::read_to_string(path).map_err(|e| ConfigError::Io {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    parse_module_config(&contents, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const YAML: &str = r#"
+name: echo
+type: docker
+image: synapse/echo:1.0
+ports: ["8080/tcp"]
+depends_on: [chain-bridge]
+env:
+  MODEL: tiny
+"#;
+
+    const TOML: &str = r#"
+name = "echo"
+type = "docker"
+image = "synapse/echo:1.0"
+ports = ["8080/tcp"]
+depends_on = ["chain-bridge"]
+
+[env]
+MODEL = "tiny"
+"#;
+
+    const JSON: &str = r#"{
+  "name": "echo",
+  "type": "docker",
+  "image": "synapse/echo:1.0",
+  "ports": ["8080/tcp"],
+  "depends_on": ["chain-bridge"],
+  "env": {"MODEL": "tiny"}
+}"#;
+
+    fn expected() -> ModuleDefinition {
+        ModuleDefinition {
+            name: "echo".into(),
+            module_type: ModuleType::Docker,
+            config: ModuleConfig {
+                depends_on: vec!["chain-bridge".into()],
+                image: Some("synapse/echo:1.0".into()),
+                env: BTreeMap::from([("MODEL".into(), "tiny".into())]),
+                ports: vec!["8080/tcp".into()],
+                health_check: None,
+                probe: None,
+                capabilities: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_same_config_loads_from_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        for (file, contents) in [
+            ("echo.yaml", YAML),
+            ("echo.yml", YAML),
+            ("echo.toml", TOML),
+            ("echo.json", JSON),
+        ] {
+            let path = dir.path().join(file);
+            std::fs::write(&path, contents).unwrap();
+            assert_eq!(load_module_config(&path).unwrap(), expected(), "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_unknown_extension_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("echo.ini");
+        std::fs::write(&path, "name = echo").unwrap();
+        assert!(matches!(
+            load_module_config(&path),
+            Err(ConfigError::UnsupportedFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_error_names_format() {
+        let err = parse_module_config("name: [", ConfigFormat::Yaml).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid YAML module config"));
+    }
+}
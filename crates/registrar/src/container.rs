@@ -0,0 +1,657 @@
+//! Container management abstraction used by the registrar.
+
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::module::HealthCheck;
+use crate::port::Port;
+
+/// Errors produced by container operations.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DockerError {
+    /// No container with the given name exists.
+    #[error("Container not found: {0}")]
+    ContainerNotFound(String),
+
+    /// A container with the given name already exists.
+    #[error("Container already exists: {0}")]
+    ContainerExists(String),
+
+    /// The Docker daemon returned an error.
+    #[error("Docker API error: {0}")]
+    Api(String),
+
+    /// The Docker daemon cannot be reached.
+    #[error("Docker unavailable: {0}")]
+    Unavailable(String),
+
+    /// The daemon and the client share no API version.
+    #[error("Docker API {daemon} is not supported, {required} or newer is required")]
+    UnsupportedApiVersion { daemon: String, required: String },
+}
+
+/// Settings used to create a container.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerConfig {
+    /// Image to run.
+    pub image: String,
+    /// Environment variables.
+    pub env: BTreeMap<String, String>,
+    /// Exposed ports. Each is published on the same host port.
+    pub ports: Vec<Port>,
+    /// Health check run inside the container.
+    pub health_check: Option<HealthCheck>,
+    /// Bind mounts, as `host:container` or `host:container:ro`.
+    pub volumes: Vec<String>,
+    /// Labels attached to the container, in addition to the module label.
+    pub labels: BTreeMap<String, String>,
+    pub resources: ResourceLimits,
+    pub restart_policy: RestartPolicy,
+    /// Whether Docker removes the container once it exits.
+    #[serde(default)]
+    pub auto_remove: bool,
+}
+
+/// Resource limits applied to a container. `None` leaves a resource
+/// unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Memory limit in bytes.
+    pub memory_bytes: Option<u64>,
+    /// CPU limit in thousandths of a CPU; `1500` is one and a half CPUs.
+    pub cpu_millis: Option<u64>,
+}
+
+/// What Docker does when a container exits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Never restart.
+    #[default]
+    No,
+    /// Restart after a non-zero exit, at most `max_retries` times.
+    OnFailure { max_retries: u32 },
+    /// Always restart unless stopped explicitly.
+    UnlessStopped,
+    /// Always restart.
+    Always,
+}
+
+/// Reasons a [`ContainerConfigBuilder`] rejects its settings.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ContainerConfigError {
+    #[error("No image set")]
+    MissingImage,
+
+    /// Ports must look like `8080/tcp` or `53/udp`.
+    #[error("Invalid port: {0}")]
+    InvalidPort(String),
+
+    /// Volumes must look like `host:container` or `host:container:ro`.
+    #[error("Invalid volume: {0}")]
+    InvalidVolume(String),
+
+    #[error("Invalid environment variable name: {0}")]
+    InvalidEnv(String),
+
+    /// Docker cannot both remove a container on exit and restart it.
+    #[error("auto_remove cannot be combined with a restart policy")]
+    AutoRemoveWithRestart,
+}
+
+/// Builds a validated [`ContainerConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct ContainerConfigBuilder {
+    config: ContainerConfig,
+    /// Ports as given, parsed by `build`.
+    ports: Vec<String>,
+}
+
+impl ContainerConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.config.image = image.into();
+        self
+    }
+
+    /// Exposes and publishes a port such as `"8080/tcp"`; a bare number
+    /// means TCP.
+    pub fn port(mut self, port: impl Into<String>) -> Self {
+        self.ports.push(port.into());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Adds a bind mount, e.g. `"/data/models:/models:ro"`.
+    pub fn volume(mut self, volume: impl Into<String>) -> Self {
+        self.config.volumes.push(volume.into());
+        self
+    }
+
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.labels.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn resources(mut self, resources: ResourceLimits) -> Self {
+        self.config.resources = resources;
+        self
+    }
+
+    pub fn health_check(mut self, health_check: HealthCheck) -> Self {
+        self.config.health_check = Some(health_check);
+        self
+    }
+
+    pub fn restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.config.restart_policy = restart_policy;
+        self
+    }
+
+    /// Has Docker remove the container as soon as it exits.
+    pub fn auto_remove(mut self, auto_remove: bool) -> Self {
+        self.config.auto_remove = auto_remove;
+        self
+    }
+
+    /// Validates the settings and returns the config.
+    pub fn build(self) -> Result<ContainerConfig, ContainerConfigError> {
+        let mut config = self.config;
+        if config.image.trim().is_empty() {
+            return Err(ContainerConfigError::MissingImage);
+        }
+        config.ports = self
+            .ports
+            .iter()
+            .map(|port| {
+                port.parse()
+                    .map_err(|_| ContainerConfigError::InvalidPort(port.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+        for volume in &config.volumes {
+            let parts: Vec<&str> = volume.split(':').collect();
+            let valid = match parts.as_slice() {
+                [host, container] => !host.is_empty() && container.starts_with('/'),
+                [host, container, mode] => {
+                    !host.is_empty() && container.starts_with('/') && matches!(*mode, "ro" | "rw")
+                }
+                _ => false,
+            };
+            if !valid {
+                return Err(ContainerConfigError::InvalidVolume(volume.clone()));
+            }
+        }
+        if let Some(key) = config
+            .env
+            .keys()
+            .find(|key| key.is_empty() || key.contains('='))
+        {
+            return Err(ContainerConfigError::InvalidEnv(key.clone()));
+        }
+        if config.auto_remove && config.restart_policy != RestartPolicy::No {
+            return Err(ContainerConfigError::AutoRemoveWithRestart);
+        }
+        Ok(config)
+    }
+}
+
+/// Lifecycle state of a container as reported by Docker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerState {
+    Created,
+    Running,
+    Paused,
+    Restarting,
+    Removing,
+    Exited,
+    Dead,
+    Unknown,
+}
+
+/// Current state of a container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerStatus {
+    /// Lifecycle state.
+    pub state: ContainerState,
+    /// Health check result (`"starting"`, `"healthy"`, `"unhealthy"`), if
+    /// the container defines a health check.
+    pub health: Option<String>,
+}
+
+/// Point-in-time resource usage of a container.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContainerStats {
+    /// CPU usage as a percentage of one core.
+    pub cpu_percent: f64,
+    /// Memory currently in use, in bytes.
+    pub memory_usage_bytes: u64,
+    /// Memory limit, in bytes.
+    pub memory_limit_bytes: u64,
+    /// Total bytes received over the network.
+    pub network_rx_bytes: u64,
+    /// Total bytes sent over the network.
+    pub network_tx_bytes: u64,
+}
+
+/// What happened to a container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerEventKind {
+    Start,
+    Stop,
+    /// The container's main process exited.
+    Die,
+    /// The health check result changed, e.g. to `"unhealthy"`.
+    HealthStatus(String),
+}
+
+/// A lifecycle event of a module's container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerEvent {
+    /// Name of the module the container runs.
+    pub name: String,
+    pub kind: ContainerEventKind,
+}
+
+/// Operations on the containers backing modules.
+#[async_trait]
+pub trait ContainerManager: Send + Sync {
+    /// Creates a stopped container. Fails with
+    /// [`DockerError::ContainerExists`] if the name is taken.
+    async fn create_container(
+        &self,
+        name: &str,
+        config: &ContainerConfig,
+    ) -> Result<(), DockerError>;
+
+    /// Starts an existing container.
+    async fn start_container(&self, name: &str) -> Result<(), DockerError>;
+
+    /// Stops a running container using the daemon's default grace period.
+    async fn stop_container(&self, name: &str) -> Result<(), DockerError>;
+
+    /// Stops a running container, sending SIGTERM and escalating to SIGKILL
+    /// if it has not exited after `timeout_secs` seconds.
+    async fn stop_container_with_timeout(
+        &self,
+        name: &str,
+        timeout_secs: u64,
+    ) -> Result<(), DockerError>;
+
+    /// Removes a container, killing it first if it is still running.
+    async fn remove_container(&self, name: &str) -> Result<(), DockerError>;
+
+    /// Returns the current state of the named container.
+    async fn get_container_status(&self, name: &str) -> Result<ContainerStatus, DockerError>;
+
+    /// Returns current resource usage of the named container.
+    async fn get_container_stats(&self, name: &str) -> Result<ContainerStats, DockerError>;
+
+    /// Streams start, stop, die and health events of module containers as
+    /// they happen.
+    fn events(&self) -> BoxStream<'static, Result<ContainerEvent, DockerError>>;
+
+    /// Streams the named container's output, stdout and stderr, one line
+    /// at a time: the last `tail` lines printed so far, then each new line
+    /// as it is printed. The stream ends when the container stops.
+    fn logs(&self, name: &str, tail: usize) -> BoxStream<'static, Result<String, DockerError>>;
+
+    /// Returns the status of each named container, querying at most
+    /// [`STATUS_CONCURRENCY`] at once. A failure for one name is reported
+    /// under that name and does not affect the others.
+    async fn get_statuses(
+        &self,
+        names: &[&str],
+    ) -> HashMap<String, Result<ContainerStatus, DockerError>> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        stream::iter(names)
+            .map(|name| async move {
+                let status = self.get_container_status(&name).await;
+                (name, status)
+            })
+            .buffer_unordered(STATUS_CONCURRENCY)
+            .collect()
+            .await
+    }
+}
+
+/// Number of containers [`ContainerManager::get_statuses`] inspects
+/// concurrently.
+pub const STATUS_CONCURRENCY: usize = 8;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod fake {
+    //! In-memory [`ContainerManager`] for tests.
+
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use futures::channel::mpsc::{unbounded, UnboundedSender};
+    use tokio::sync::Semaphore;
+
+    use super::*;
+
+    /// A container tracked by [`FakeContainers`].
+    #[derive(Debug, Clone)]
+    pub struct FakeContainer {
+        pub config: ContainerConfig,
+        pub status: ContainerStatus,
+        pub stats: ContainerStats,
+        /// Whether the container keeps running after SIGTERM.
+        pub ignores_sigterm: bool,
+        /// Lines the container has printed.
+        pub logs: Vec<String>,
+    }
+
+    /// Containers held in memory, with a log of the calls made.
+    #[derive(Default)]
+    pub struct FakeContainers {
+        pub containers: Mutex<HashMap<String, FakeContainer>>,
+        pub calls: Mutex<Vec<String>>,
+        subscribers: Mutex<Vec<UnboundedSender<Result<ContainerEvent, DockerError>>>>,
+        /// When set, each create waits for a permit from it.
+        create_gate: Mutex<Option<Arc<Semaphore>>>,
+    }
+
+    impl FakeContainers {
+        /// Adds a container in the given state.
+        pub fn with_container(self, name: &str, state: ContainerState) -> Self {
+            self.containers.lock().unwrap().insert(
+                name.to_string(),
+                FakeContainer {
+                    config: ContainerConfig::default(),
+                    status: ContainerStatus {
+                        state,
+                        health: None,
+                    },
+                    stats: ContainerStats::default(),
+                    ignores_sigterm: false,
+                    logs: Vec::new(),
+                },
+            );
+            self
+        }
+
+        /// Changes the state of an existing container.
+        pub fn set_state(&self, name: &str, state: ContainerState) {
+            self.containers
+                .lock()
+                .unwrap()
+                .get_mut(name)
+                .unwrap()
+                .status
+                .state = state;
+        }
+
+        /// Sets the health check result reported for a container.
+        pub fn with_health(self, name: &str, health: &str) -> Self {
+            self.containers
+                .lock()
+                .unwrap()
+                .get_mut(name)
+                .unwrap()
+                .status
+                .health = Some(health.to_string());
+            self
+        }
+
+        /// Makes a container ignore SIGTERM, so stopping it only succeeds by
+        /// killing it once the timeout elapses.
+        pub fn ignoring_sigterm(self, name: &str) -> Self {
+            self.containers
+                .lock()
+                .unwrap()
+                .get_mut(name)
+                .unwrap()
+                .ignores_sigterm = true;
+            self
+        }
+
+        /// Adds a running container reporting the given stats.
+        pub fn with_stats(self, name: &str, stats: ContainerStats) -> Self {
+            let this = self.with_container(name, ContainerState::Running);
+            this.containers.lock().unwrap().get_mut(name).unwrap().stats = stats;
+            this
+        }
+
+        /// Sets the lines a container has printed.
+        pub fn with_logs(self, name: &str, lines: &[&str]) -> Self {
+            self.containers.lock().unwrap().get_mut(name).unwrap().logs =
+                lines.iter().map(|line| line.to_string()).collect();
+            self
+        }
+
+        /// Sends an event to every stream returned by `events`.
+        pub fn emit(&self, name: &str, kind: ContainerEventKind) {
+            let event = ContainerEvent {
+                name: name.to_string(),
+                kind,
+            };
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|tx| tx.unbounded_send(Ok(event.clone())).is_ok());
+        }
+
+        /// Holds every later create until a permit is added to the returned
+        /// semaphore, one permit per create.
+        pub fn gate_creates(&self) -> Arc<Semaphore> {
+            let gate = Arc::new(Semaphore::new(0));
+            *self.create_gate.lock().unwrap() = Some(gate.clone());
+            gate
+        }
+
+        /// Returns the calls made so far, e.g. `"create a"`.
+        pub fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        fn record(&self, call: &str, name: &str) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", call, name));
+        }
+
+        fn update<T>(
+            &self,
+            name: &str,
+            f: impl FnOnce(&mut FakeContainer) -> T,
+        ) -> Result<T, DockerError> {
+            self.containers
+                .lock()
+                .unwrap()
+                .get_mut(name)
+                .map(f)
+                .ok_or_else(|| DockerError::ContainerNotFound(name.to_string()))
+        }
+    }
+
+    #[async_trait]
+    impl ContainerManager for FakeContainers {
+        async fn create_container(
+            &self,
+            name: &str,
+            config: &ContainerConfig,
+        ) -> Result<(), DockerError> {
+            self.record("create", name);
+            let gate = self.create_gate.lock().unwrap().clone();
+            if let Some(gate) = gate {
+                gate.acquire().await.unwrap().forget();
+            }
+            let mut containers = self.containers.lock().unwrap();
+            if containers.contains_key(name) {
+                return Err(DockerError::ContainerExists(name.to_string()));
+            }
+            containers.insert(
+                name.to_string(),
+                FakeContainer {
+                    config: config.clone(),
+                    status: ContainerStatus {
+                        state: ContainerState::Created,
+                        health: None,
+                    },
+                    stats: ContainerStats::default(),
+                    ignores_sigterm: false,
+                    logs: Vec::new(),
+                },
+            );
+            Ok(())
+        }
+
+        async fn start_container(&self, name: &str) -> Result<(), DockerError> {
+            self.record("start", name);
+            self.update(name, |c| c.status.state = ContainerState::Running)
+        }
+
+        async fn stop_container(&self, name: &str) -> Result<(), DockerError> {
+            self.stop_container_with_timeout(name, 10).await
+        }
+
+        async fn stop_container_with_timeout(
+            &self,
+            name: &str,
+            timeout_secs: u64,
+        ) -> Result<(), DockerError> {
+            self.record("stop", name);
+            let ignores_sigterm = self.update(name, |c| c.ignores_sigterm)?;
+            if ignores_sigterm {
+                tokio::time::sleep(std::time::Duration::from_secs(timeout_secs)).await;
+                self.record("kill", name);
+            }
+            self.update(name, |c| c.status.state = ContainerState::Exited)
+        }
+
+        async fn remove_container(&self, name: &str) -> Result<(), DockerError> {
+            self.record("remove", name);
+            self.containers
+                .lock()
+                .unwrap()
+                .remove(name)
+                .map(|_| ())
+                .ok_or_else(|| DockerError::ContainerNotFound(name.to_string()))
+        }
+
+        async fn get_container_status(&self, name: &str) -> Result<ContainerStatus, DockerError> {
+            self.update(name, |c| c.status.clone())
+        }
+
+        async fn get_container_stats(&self, name: &str) -> Result<ContainerStats, DockerError> {
+            self.update(name, |c| c.stats.clone())
+        }
+
+        fn events(&self) -> BoxStream<'static, Result<ContainerEvent, DockerError>> {
+            let (tx, rx) = unbounded();
+            self.subscribers.lock().unwrap().push(tx);
+            rx.boxed()
+        }
+
+        /// The last `tail` lines set with `with_logs`, as if the container
+        /// stopped after printing them.
+        fn logs(&self, name: &str, tail: usize) -> BoxStream<'static, Result<String, DockerError>> {
+            let lines = self.update(name, |c| {
+                let skip = c.logs.len().saturating_sub(tail);
+                c.logs[skip..].to_vec()
+            });
+            match lines {
+                Ok(lines) => stream::iter(lines.into_iter().map(Ok)).boxed(),
+                Err(e) => stream::once(async { Err(e) }).boxed(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::FakeContainers;
+    use super::*;
+
+    #[test]
+    fn test_builder_matches_hand_built_config() {
+        let health_check = HealthCheck {
+            test: vec!["CMD".into(), "true".into()],
+            interval_secs: 30,
+            timeout_secs: 5,
+            retries: 3,
+        };
+        let built = ContainerConfigBuilder::new()
+            .image("synapse/echo:latest")
+            .env("MODULE_PORT", "8080")
+            .port("8080")
+            .health_check(health_check.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            built,
+            ContainerConfig {
+                image: "synapse/echo:latest".into(),
+                env: BTreeMap::from([("MODULE_PORT".into(), "8080".into())]),
+                ports: vec![Port::tcp(8080)],
+                health_check: Some(health_check),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_settings() {
+        let base = || ContainerConfigBuilder::new().image("echo");
+        assert_eq!(
+            ContainerConfigBuilder::new().build(),
+            Err(ContainerConfigError::MissingImage)
+        );
+        assert_eq!(
+            base().port("http/tcp").build(),
+            Err(ContainerConfigError::InvalidPort("http/tcp".into()))
+        );
+        assert_eq!(
+            base().volume("/data").build(),
+            Err(ContainerConfigError::InvalidVolume("/data".into()))
+        );
+        assert_eq!(
+            base().env("A=B", "c").build(),
+            Err(ContainerConfigError::InvalidEnv("A=B".into()))
+        );
+        let config = base()
+            .volume("/data/models:/models:ro")
+            .port("9000/udp")
+            .restart_policy(RestartPolicy::OnFailure { max_retries: 3 })
+            .build()
+            .unwrap();
+        assert_eq!(config.volumes, vec!["/data/models:/models:ro"]);
+    }
+
+    #[tokio::test]
+    async fn test_statuses_report_missing_per_name() {
+        let containers = FakeContainers::default()
+            .with_container("a", ContainerState::Running)
+            .with_container("b", ContainerState::Exited);
+
+        let statuses = containers.get_statuses(&["a", "b", "missing"]).await;
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(
+            statuses["a"].as_ref().unwrap().state,
+            ContainerState::Running
+        );
+        assert_eq!(
+            statuses["b"].as_ref().unwrap().state,
+            ContainerState::Exited
+        );
+        assert_eq!(
+            statuses["missing"],
+            Err(DockerError::ContainerNotFound("missing".into()))
+        );
+    }
+}
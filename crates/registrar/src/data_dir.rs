@@ -0,0 +1,85 @@
+//! Where the registrar keeps its persistent state.
+
+use std::path::{Path, PathBuf};
+
+/// Directory rooting the registry database, package cache, uploads and
+/// env files, so none of them depend on the working directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDir {
+    root: PathBuf,
+}
+
+impl DataDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Returns the default location, `$XDG_DATA_HOME/synapse`, falling
+    /// back to `~/.local/share/synapse`.
+    pub fn default_dir() -> PathBuf {
+        match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir).join("synapse"),
+            None => std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_default()
+                .join(".local")
+                .join("share")
+                .join("synapse"),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of the registry database.
+    pub fn registry_db(&self) -> PathBuf {
+        self.root.join("registrar.db")
+    }
+
+    /// Directory for cached installation packages.
+    pub fn package_cache(&self) -> PathBuf {
+        self.root.join("packages")
+    }
+
+    /// Directory holding resumable package uploads.
+    pub fn uploads(&self) -> PathBuf {
+        self.root.join("uploads")
+    }
+
+    /// Base directory of the env store.
+    pub fn env(&self) -> PathBuf {
+        self.root.join("env")
+    }
+
+    /// Creates the directory if it does not exist yet.
+    pub fn create(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_cache::PackageCache;
+    use crate::registry::SqliteRegistry;
+
+    #[tokio::test]
+    async fn test_state_lands_under_data_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = DataDir::new(dir.path().join("synapse"));
+        data_dir.create().unwrap();
+
+        let db = data_dir.registry_db();
+        SqliteRegistry::connect(&format!("sqlite://{}", db.display()))
+            .await
+            .unwrap();
+        PackageCache::new(data_dir.package_cache(), 1024).unwrap();
+
+        assert!(db.is_file());
+        assert!(data_dir.package_cache().is_dir());
+        for path in [db, data_dir.package_cache()] {
+            assert!(path.starts_with(dir.path().join("synapse")), "{:?}", path);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Module dependency resolution and ordered startup.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::module::Module;
+
+/// Errors produced while resolving module dependencies.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DependencyError {
+    /// A module depends on a module that is not registered.
+    #[error("Module {module} depends on unknown module {dependency}")]
+    MissingDependency { module: String, dependency: String },
+
+    /// The dependency graph contains a cycle between the listed modules.
+    #[error("Dependency cycle detected between modules: {}", .0.join(", "))]
+    Cycle(Vec<String>),
+}
+
+/// Errors produced by [`start_all`].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum StartAllError {
+    #[error(transparent)]
+    Dependency(#[from] DependencyError),
+
+    /// A module failed to start; modules after it were not started.
+    #[error("Failed to start module {module}: {reason}")]
+    StartFailed { module: String, reason: String },
+
+    /// A dependency did not become healthy in time, so the dependent module
+    /// and everything after it were not started.
+    #[error("Module {module} not started: dependency {dependency} did not become healthy within {timeout:?}")]
+    DependencyUnhealthy {
+        module: String,
+        dependency: String,
+        timeout: Duration,
+    },
+}
+
+/// Options controlling [`start_all`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartAllOptions {
+    /// Wait for each dependency to report healthy before starting a
+    /// dependent module, rather than only waiting for it to be started.
+    pub wait_for_dependency_health: bool,
+    /// How long to wait for a single dependency to become healthy.
+    pub dependency_timeout: Duration,
+    /// How often to poll a dependency's health while waiting.
+    pub poll_interval: Duration,
+}
+
+impl Default for StartAllOptions {
+    fn default() -> Self {
+        Self {
+            wait_for_dependency_health: false,
+            dependency_timeout: Duration::from_secs(60),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Something that can start a module by name.
+#[async_trait]
+pub trait ModuleStarter: Send + Sync {
+    type Error: fmt::Display + Send;
+
+    /// Starts the named module.
+    async fn start_module(&self, name: &str) -> Result<(), Self::Error>;
+
+    /// Reports whether the named module is currently healthy.
+    async fn is_healthy(&self, name: &str) -> Result<bool, Self::Error>;
+
+    /// Polls [`is_healthy`](Self::is_healthy) until the module is healthy or
+    /// `timeout` elapses. Returns `Ok(false)` on timeout.
+    async fn wait_until_healthy(
+        &self,
+        name: &str,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<bool, Self::Error> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if self.is_healthy(name).await? {
+                return Ok(true);
+            }
+            if tokio::time::Instant::now() + poll_interval > deadline {
+                return Ok(false);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+}
+
+/// Resolves the order in which modules must be started so that every module
+/// starts after all of its dependencies. Modules with no ordering constraint
+/// between them are ordered by name.
+pub fn startup_order(modules: &[Module]) -> Result<Vec<&Module>, DependencyError> {
+    let by_name: BTreeMap<&str, &Module> = modules.iter().map(|m| (m.name.as_str(), m)).collect();
+
+    let mut pending: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for module in modules {
+        let mut deps = BTreeSet::new();
+        for dependency in &module.config.depends_on {
+            if !by_name.contains_key(dependency.as_str()) {
+                return Err(DependencyError::MissingDependency {
+                    module: module.name.clone(),
+                    dependency: dependency.clone(),
+                });
+            }
+            deps.insert(dependency.as_str());
+        }
+        pending.insert(module.name.as_str(), deps);
+    }
+
+    let mut order = Vec::with_capacity(modules.len());
+    while !pending.is_empty() {
+        let ready: Vec<&str> = pending
+            .iter()
+            .filter(|(_, deps)| deps.is_empty())
+            .map(|(name, _)| *name)
+            .collect();
+
+        if ready.is_empty() {
+            return Err(DependencyError::Cycle(
+                pending.keys().map(|name| name.to_string()).collect(),
+            ));
+        }
+
+        for name in ready {
+            pending.remove(name);
+            for deps in pending.values_mut() {
+                deps.remove(name);
+            }
+            order.push(by_name[name]);
+        }
+    }
+
+    Ok(order)
+}
+
+/// Modules that list `name` in their `depends_on`, ordered by name.
+pub fn dependents<'a>(modules: &'a [Module], name: &str) -> Vec<&'a Module> {
+    let mut dependents: Vec<&Module> = modules
+        .iter()
+        .filter(|m| m.config.depends_on.iter().any(|d| d == name))
+        .collect();
+    dependents.sort_by(|a, b| a.name.cmp(&b.name));
+    dependents
+}
+
+/// Starts every module in dependency order, stopping at the first failure.
+/// Returns the names of the modules in the order they were started.
+pub async fn start_all<S: ModuleStarter + ?Sized>(
+    modules: &[Module],
+    starter: &S,
+    options: &StartAllOptions,
+) -> Result<Vec<String>, StartAllError> {
+    let order = startup_order(modules)?;
+
+    let mut started = Vec::with_capacity(order.len());
+    for module in order {
+        if options.wait_for_dependency_health {
+            for dependency in &module.config.depends_on {
+                let healthy = starter
+                    .wait_until_healthy(
+                        dependency,
+                        options.dependency_timeout,
+                        options.poll_interval,
+                    )
+                    .await
+                    .map_err(|e| StartAllError::StartFailed {
+                        module: module.name.clone(),
+                        reason: format!("health check of {} failed: {}", dependency, e),
+                    })?;
+                if !healthy {
+                    return Err(StartAllError::DependencyUnhealthy {
+                        module: module.name.clone(),
+                        dependency: dependency.clone(),
+                        timeout: options.dependency_timeout,
+                    });
+                }
+            }
+        }
+
+        tracing::info!("Starting module {}", module.name);
+        starter
+            .start_module(&module.name)
+            .await
+            .map_err(|e| StartAllError::StartFailed {
+                module: module.name.clone(),
+                reason: e.to_string(),
+            })?;
+        started.push(module.name.clone());
+    }
+
+    Ok(started)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::{ModuleConfig, ModuleType};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingStarter {
+        started: Mutex<Vec<String>>,
+        never_healthy: Vec<String>,
+    }
+
+    #[async_trait]
+    impl ModuleStarter for RecordingStarter {
+        type Error = String;
+
+        async fn start_module(&self, name: &str) -> Result<(), String> {
+            self.started.lock().unwrap().push(name.to_string());
+            Ok(())
+        }
+
+        async fn is_healthy(&self, name: &str) -> Result<bool, String> {
+            Ok(!self.never_healthy.iter().any(|n| n == name))
+        }
+    }
+
+    fn module(name: &str, depends_on: &[&str]) -> Module {
+        Module::new(name, ModuleType::Docker).with_config(ModuleConfig {
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_dependency_starts_first() {
+        let modules = vec![module("a", &["b"]), module("b", &[])];
+        let starter = RecordingStarter::default();
+
+        let started = start_all(&modules, &starter, &StartAllOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(started, vec!["b", "a"]);
+        assert_eq!(*starter.started.lock().unwrap(), vec!["b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_cycle_rejected() {
+        let modules = vec![module("a", &["b"]), module("b", &["a"]), module("c", &[])];
+        let starter = RecordingStarter::default();
+
+        let result = start_all(&modules, &starter, &StartAllOptions::default()).await;
+        assert_eq!(
+            result,
+            Err(StartAllError::Dependency(DependencyError::Cycle(vec![
+                "a".into(),
+                "b".into()
+            ])))
+        );
+        assert!(starter.started.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_dependency_blocks_dependent() {
+        let modules = vec![module("a", &["b"]), module("b", &[])];
+        let starter = RecordingStarter {
+            never_healthy: vec!["b".into()],
+            ..Default::default()
+        };
+        let options = StartAllOptions {
+            wait_for_dependency_health: true,
+            dependency_timeout: Duration::from_millis(50),
+            poll_interval: Duration::from_millis(10),
+        };
+
+        let result = start_all(&modules, &starter, &options).await;
+        assert!(matches!(
+            result,
+            Err(StartAllError::DependencyUnhealthy { ref module, ref dependency, .. })
+                if module == "a" && dependency == "b"
+        ));
+        assert_eq!(*starter.started.lock().unwrap(), vec!["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_healthy_dependency_allows_dependent() {
+        let modules = vec![module("a", &["b"]), module("b", &[])];
+        let starter = RecordingStarter::default();
+        let options = StartAllOptions {
+            wait_for_dependency_health: true,
+            ..Default::default()
+        };
+
+        let started = start_all(&modules, &starter, &options).await.unwrap();
+        assert_eq!(started, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_missing_dependency_rejected() {
+        let modules = vec![module("a", &["ghost"])];
+        assert!(matches!(
+            startup_order(&modules),
+            Err(DependencyError::MissingDependency { .. })
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+//! Differences between two module configurations.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::module::{HealthCheck, ModuleConfig};
+use crate::probe::ProbeConfig;
+
+/// A value that changed from `old` to `new`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+/// How an environment variable changed. Values are not recorded, since
+/// they often hold secrets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvChange {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Everything that differs between two module configurations.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleConfigDiff {
+    /// Image change, including a change of tag only.
+    pub image: Option<Change<Option<String>>>,
+    /// Changed environment variables, by name.
+    pub env: Vec<(String, EnvChange)>,
+    pub ports_added: Vec<String>,
+    pub ports_removed: Vec<String>,
+    pub health_check: Option<Change<Option<HealthCheck>>>,
+    pub probe: Option<Change<Option<ProbeConfig>>>,
+    pub dependencies_added: Vec<String>,
+    pub dependencies_removed: Vec<String>,
+    pub capabilities_added: Vec<String>,
+    pub capabilities_removed: Vec<String>,
+}
+
+fn set_diff(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let old: BTreeSet<&String> = old.iter().collect();
+    let new: BTreeSet<&String> = new.iter().collect();
+    (
+        new.difference(&old).map(|s| s.to_string()).collect(),
+        old.difference(&new).map(|s| s.to_string()).collect(),
+    )
+}
+
+/// Compares two module configurations.
+pub fn diff(old: &ModuleConfig, new: &ModuleConfig) -> ModuleConfigDiff {
+    let image = (old.image != new.image).then(|| Change {
+        old: old.image.clone(),
+        new: new.image.clone(),
+    });
+
+    let names: BTreeSet<&String> = old.env.keys().chain(new.env.keys()).collect();
+    let env = names
+        .into_iter()
+        .filter_map(|name| {
+            let change = match (old.env.get(name), new.env.get(name)) {
+                (None, Some(_)) => EnvChange::Added,
+                (Some(_), None) => EnvChange::Removed,
+                (Some(a), Some(b)) if a != b => EnvChange::Changed,
+                _ => return None,
+            };
+            Some((name.clone(), change))
+        })
+        .collect();
+
+    let (ports_added, ports_removed) = set_diff(&old.ports, &new.ports);
+    let (dependencies_added, dependencies_removed) = set_diff(&old.depends_on, &new.depends_on);
+    let (capabilities_added, capabilities_removed) = set_diff(&old.capabilities, &new.capabilities);
+    let health_check = (old.health_check != new.health_check).then(|| Change {
+        old: old.health_check.clone(),
+        new: new.health_check.clone(),
+    });
+    let probe = (old.probe != new.probe).then(|| Change {
+        old: old.probe.clone(),
+        new: new.probe.clone(),
+    });
+
+    ModuleConfigDiff {
+        image,
+        env,
+        ports_added,
+        ports_removed,
+        health_check,
+        probe,
+        dependencies_added,
+        dependencies_removed,
+        capabilities_added,
+        capabilities_removed,
+    }
+}
+
+/// Splits an image reference into repository and tag.
+fn split_tag(image: &str) -> (&str, Option<&str>) {
+    match image.rsplit_once(':') {
+        // A colon followed by a slash belongs to a registry port.
+        Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag)),
+        _ => (image, None),
+    }
+}
+
+impl ModuleConfigDiff {
+    /// Whether the configurations are identical.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// One line per change, suitable for showing to an operator.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(change) = &self.image {
+            let line = match (change.old.as_deref(), change.new.as_deref()) {
+                (Some(old), Some(new)) => match (split_tag(old), split_tag(new)) {
+                    ((old_repo, old_tag), (new_repo, new_tag)) if old_repo == new_repo => {
+                        format!(
+                            "image {}: tag {} -> {}",
+                            new_repo,
+                            old_tag.unwrap_or("latest"),
+                            new_tag.unwrap_or("latest")
+                        )
+                    }
+                    _ => format!("image: {} -> {}", old, new),
+                },
+                (None, Some(new)) => format!("image: set to {}", new),
+                (Some(old), None) => format!("image: {} removed", old),
+                (None, None) => unreachable!("unchanged image recorded as a change"),
+            };
+            lines.push(line);
+        }
+        for (name, change) in &self.env {
+            let verb = match change {
+                EnvChange::Added => "added",
+                EnvChange::Removed => "removed",
+                EnvChange::Changed => "changed",
+            };
+            lines.push(format!("env {}: {}", name, verb));
+        }
+        for port in &self.ports_added {
+            lines.push(format!("port {}: added", port));
+        }
+        for port in &self.ports_removed {
+            lines.push(format!("port {}: removed", port));
+        }
+        if let Some(change) = &self.health_check {
+            let verb = match (&change.old, &change.new) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                _ => "changed",
+            };
+            lines.push(format!("health check: {}", verb));
+        }
+        if let Some(change) = &self.probe {
+            let verb = match (&change.old, &change.new) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                _ => "changed",
+            };
+            lines.push(format!("probe: {}", verb));
+        }
+        for dependency in &self.dependencies_added {
+            lines.push(format!("dependency {}: added", dependency));
+        }
+        for dependency in &self.dependencies_removed {
+            lines.push(format!("dependency {}: removed", dependency));
+        }
+        for capability in &self.capabilities_added {
+            lines.push(format!("capability {}: added", capability));
+        }
+        for capability in &self.capabilities_removed {
+            lines.push(format!("capability {}: removed", capability));
+        }
+        lines
+    }
+}
+
+impl fmt::Display for ModuleConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no changes");
+        }
+        f.write_str(&self.summary().join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_image_and_env_change() {
+        let old = ModuleConfig {
+            image: Some("synapse/echo:1.0".into()),
+            env: BTreeMap::from([
+                ("MODEL".into(), "tiny".into()),
+                ("OLD".into(), "x".into()),
+                ("SAME".into(), "y".into()),
+            ]),
+            ports: vec!["8080/tcp".into()],
+            ..Default::default()
+        };
+        let new = ModuleConfig {
+            image: Some("synapse/echo:1.1".into()),
+            env: BTreeMap::from([
+                ("MODEL".into(), "large".into()),
+                ("NEW".into(), "z".into()),
+                ("SAME".into(), "y".into()),
+            ]),
+            ports: vec!["8080/tcp".into()],
+            ..Default::default()
+        };
+
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff.image,
+            Some(Change {
+                old: Some("synapse/echo:1.0".into()),
+                new: Some("synapse/echo:1.1".into()),
+            })
+        );
+        assert_eq!(
+            diff.env,
+            vec![
+                ("MODEL".to_string(), EnvChange::Changed),
+                ("NEW".to_string(), EnvChange::Added),
+                ("OLD".to_string(), EnvChange::Removed),
+            ]
+        );
+        assert!(diff.ports_added.is_empty() && diff.ports_removed.is_empty());
+        assert_eq!(diff.health_check, None);
+        assert_eq!(
+            diff.to_string(),
+            "image synapse/echo: tag 1.0 -> 1.1\n\
+             env MODEL: changed\n\
+             env NEW: added\n\
+             env OLD: removed"
+        );
+    }
+
+    #[test]
+    fn test_identical_configs_have_empty_diff() {
+        let config = ModuleConfig {
+            image: Some("registry.local:5000/echo".into()),
+            ..Default::default()
+        };
+        let diff = diff(&config, &config.clone());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no changes");
+    }
+}
@@ -0,0 +1,578 @@
+//! [`ContainerManager`] backed by a Docker daemon.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use bollard::container::{
+    Config, CreateContainerOptions, LogsOptions, RemoveContainerOptions, StartContainerOptions,
+    Stats, StatsOptions, StopContainerOptions,
+};
+use bollard::errors::Error as BollardError;
+use bollard::models::{
+    ContainerStateStatusEnum, EventMessage, HealthConfig, HostConfig, PortBinding,
+    RestartPolicyNameEnum,
+};
+use bollard::system::EventsOptions;
+use bollard::{ClientVersion, Docker};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use crate::container::{
+    ContainerConfig, ContainerEvent, ContainerEventKind, ContainerManager, ContainerState,
+    ContainerStats, ContainerStatus, DockerError, RestartPolicy,
+};
+use crate::env::MaskedEnv;
+
+/// Label attached to every container created by the registrar, holding the
+/// module name.
+pub const MODULE_LABEL: &str = "synapse.module";
+
+/// Oldest Docker API version the registrar works with (Docker 20.10).
+pub const MIN_API_VERSION: ClientVersion = ClientVersion {
+    major_version: 1,
+    minor_version: 41,
+};
+
+/// Seconds to wait for a response from the daemon.
+const TIMEOUT_SECS: u64 = 120;
+
+fn parse_api_version(version: &str) -> Option<ClientVersion> {
+    let (major, minor) = version.trim().split_once('.')?;
+    Some(ClientVersion {
+        major_version: major.parse().ok()?,
+        minor_version: minor.parse().ok()?,
+    })
+}
+
+fn version_key(version: &ClientVersion) -> (usize, usize) {
+    (version.major_version, version.minor_version)
+}
+
+/// Picks the API version to talk to a daemon with: the newer of what the
+/// client and daemon support, as long as it is not older than
+/// [`MIN_API_VERSION`] or the daemon's own minimum.
+pub fn negotiate_api_version(
+    client: &ClientVersion,
+    daemon: &str,
+    daemon_min: Option<&str>,
+) -> Result<ClientVersion, DockerError> {
+    let unsupported = |required: &ClientVersion| DockerError::UnsupportedApiVersion {
+        daemon: daemon.to_string(),
+        required: required.to_string(),
+    };
+    let daemon_version = parse_api_version(daemon)
+        .ok_or_else(|| DockerError::Api(format!("Invalid daemon API version: {}", daemon)))?;
+    let chosen = if version_key(client) <= version_key(&daemon_version) {
+        *client
+    } else {
+        daemon_version
+    };
+    if version_key(&chosen) < version_key(&MIN_API_VERSION) {
+        return Err(unsupported(&MIN_API_VERSION));
+    }
+    if let Some(daemon_min) = daemon_min.and_then(parse_api_version) {
+        if version_key(&chosen) < version_key(&daemon_min) {
+            return Err(unsupported(&daemon_min));
+        }
+    }
+    Ok(chosen)
+}
+
+/// Manages module containers through the Docker API.
+#[derive(Debug, Clone)]
+pub struct DockerManager {
+    docker: Docker,
+}
+
+impl DockerManager {
+    /// Connects to the local Docker daemon using the platform defaults
+    /// (`DOCKER_HOST` or the local socket).
+    pub fn new() -> Result<Self, DockerError> {
+        let docker =
+            Docker::connect_with_local_defaults().map_err(|e| DockerError::Api(e.to_string()))?;
+        Ok(Self { docker })
+    }
+
+    /// Connects to the local Docker daemon and negotiates the API version
+    /// with it.
+    pub async fn connect() -> Result<Self, DockerError> {
+        Self::negotiated(
+            Docker::connect_with_local_defaults().map_err(|e| DockerError::Api(e.to_string()))?,
+        )
+        .await
+    }
+
+    /// Connects to the daemon at `endpoint` (`unix://`, `tcp://` or
+    /// `http://`), using at most `api_version`, e.g. `"1.43"`, and
+    /// negotiating down if the daemon is older.
+    pub async fn connect_with_version(
+        endpoint: &str,
+        api_version: &str,
+    ) -> Result<Self, DockerError> {
+        let version = parse_api_version(api_version)
+            .ok_or_else(|| DockerError::Api(format!("Invalid API version: {}", api_version)))?;
+        let docker = if endpoint.starts_with("unix://") || endpoint.starts_with('/') {
+            Docker::connect_with_unix(endpoint, TIMEOUT_SECS, &version)
+        } else if endpoint.starts_with("tcp://") || endpoint.starts_with("http://") {
+            Docker::connect_with_http(endpoint, TIMEOUT_SECS, &version)
+        } else {
+            return Err(DockerError::Api(format!(
+                "Unsupported Docker endpoint: {}",
+                endpoint
+            )));
+        }
+        .map_err(|e| DockerError::Api(e.to_string()))?;
+        Self::negotiated(docker).await
+    }
+
+    /// Checks the daemon's API version and downgrades the client to it if
+    /// needed.
+    async fn negotiated(docker: Docker) -> Result<Self, DockerError> {
+        let version = docker
+            .version()
+            .await
+            .map_err(|e| DockerError::Api(e.to_string()))?;
+        let daemon = version.api_version.unwrap_or_default();
+        negotiate_api_version(
+            &docker.client_version(),
+            &daemon,
+            version.min_api_version.as_deref(),
+        )?;
+        let docker = docker
+            .negotiate_version()
+            .await
+            .map_err(|e| DockerError::Api(e.to_string()))?;
+        tracing::info!(
+            "Connected to Docker {} using API {}",
+            version.version.unwrap_or_default(),
+            docker.client_version()
+        );
+        Ok(Self { docker })
+    }
+
+    /// Wraps an existing Docker client.
+    pub fn with_client(docker: Docker) -> Self {
+        Self { docker }
+    }
+}
+
+fn map_error(name: &str, err: BollardError) -> DockerError {
+    match err {
+        BollardError::DockerResponseServerError {
+            status_code: 404, ..
+        } => DockerError::ContainerNotFound(name.to_string()),
+        BollardError::DockerResponseServerError {
+            status_code: 409, ..
+        } => DockerError::ContainerExists(name.to_string()),
+        other => DockerError::Api(other.to_string()),
+    }
+}
+
+/// Converts a daemon event into a [`ContainerEvent`], skipping events of
+/// other kinds and of containers the registrar did not create.
+fn container_event(message: EventMessage) -> Option<ContainerEvent> {
+    let action = message.action?;
+    let name = message.actor?.attributes?.remove(MODULE_LABEL)?;
+    let kind = match action.as_str() {
+        "start" => ContainerEventKind::Start,
+        "stop" => ContainerEventKind::Stop,
+        "die" => ContainerEventKind::Die,
+        other => {
+            ContainerEventKind::HealthStatus(other.strip_prefix("health_status: ")?.to_string())
+        }
+    };
+    Some(ContainerEvent { name, kind })
+}
+
+fn container_state(status: Option<ContainerStateStatusEnum>) -> ContainerState {
+    match status {
+        Some(ContainerStateStatusEnum::CREATED) => ContainerState::Created,
+        Some(ContainerStateStatusEnum::RUNNING) => ContainerState::Running,
+        Some(ContainerStateStatusEnum::PAUSED) => ContainerState::Paused,
+        Some(ContainerStateStatusEnum::RESTARTING) => ContainerState::Restarting,
+        Some(ContainerStateStatusEnum::REMOVING) => ContainerState::Removing,
+        Some(ContainerStateStatusEnum::EXITED) => ContainerState::Exited,
+        Some(ContainerStateStatusEnum::DEAD) => ContainerState::Dead,
+        _ => ContainerState::Unknown,
+    }
+}
+
+fn container_stats(stats: &Stats) -> ContainerStats {
+    let cpu_delta = stats
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+    let system_delta = stats
+        .cpu_stats
+        .system_cpu_usage
+        .unwrap_or(0)
+        .saturating_sub(stats.precpu_stats.system_cpu_usage.unwrap_or(0));
+    let cpus = stats.cpu_stats.online_cpus.unwrap_or(1);
+    let cpu_percent = if system_delta > 0 {
+        cpu_delta as f64 / system_delta as f64 * cpus as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    let (rx, tx) = stats
+        .networks
+        .iter()
+        .flat_map(|networks| networks.values())
+        .fold((0, 0), |(rx, tx), n| (rx + n.rx_bytes, tx + n.tx_bytes));
+
+    ContainerStats {
+        cpu_percent,
+        memory_usage_bytes: stats.memory_stats.usage.unwrap_or(0),
+        memory_limit_bytes: stats.memory_stats.limit.unwrap_or(0),
+        network_rx_bytes: rx,
+        network_tx_bytes: tx,
+    }
+}
+
+fn create_config(name: &str, config: &ContainerConfig) -> Config<String> {
+    let env = config
+        .env
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let exposed_ports = config
+        .ports
+        .iter()
+        .map(|port| (port.to_string(), HashMap::new()))
+        .collect();
+    let port_bindings = config
+        .ports
+        .iter()
+        .map(|port| {
+            (
+                port.to_string(),
+                Some(vec![PortBinding {
+                    host_ip: None,
+                    host_port: Some(port.number.to_string()),
+                }]),
+            )
+        })
+        .collect();
+
+    const NANOS_PER_SEC: i64 = 1_000_000_000;
+    let secs = |secs: u64| i64::try_from(secs).unwrap_or(i64::MAX / NANOS_PER_SEC) * NANOS_PER_SEC;
+    let healthcheck = config.health_check.as_ref().map(|check| HealthConfig {
+        test: Some(check.test.clone()),
+        interval: Some(secs(check.interval_secs)),
+        timeout: Some(secs(check.timeout_secs)),
+        retries: Some(i64::from(check.retries)),
+        ..Default::default()
+    });
+
+    let mut labels: HashMap<String, String> = config
+        .labels
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    labels.insert(MODULE_LABEL.to_string(), name.to_string());
+
+    let (restart_name, maximum_retry_count) = match config.restart_policy {
+        RestartPolicy::No => (RestartPolicyNameEnum::NO, None),
+        RestartPolicy::OnFailure { max_retries } => (
+            RestartPolicyNameEnum::ON_FAILURE,
+            Some(i64::from(max_retries)),
+        ),
+        RestartPolicy::UnlessStopped => (RestartPolicyNameEnum::UNLESS_STOPPED, None),
+        RestartPolicy::Always => (RestartPolicyNameEnum::ALWAYS, None),
+    };
+
+    Config {
+        image: Some(config.image.clone()),
+        healthcheck,
+        env: Some(env),
+        exposed_ports: Some(exposed_ports),
+        labels: Some(labels),
+        host_config: Some(HostConfig {
+            port_bindings: Some(port_bindings),
+            binds: (!config.volumes.is_empty()).then(|| config.volumes.clone()),
+            memory: config
+                .resources
+                .memory_bytes
+                .map(|bytes| i64::try_from(bytes).unwrap_or(i64::MAX)),
+            nano_cpus: config
+                .resources
+                .cpu_millis
+                .map(|millis| i64::try_from(millis.saturating_mul(1_000_000)).unwrap_or(i64::MAX)),
+            restart_policy: Some(bollard::models::RestartPolicy {
+                name: Some(restart_name),
+                maximum_retry_count,
+            }),
+            auto_remove: Some(config.auto_remove),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl ContainerManager for DockerManager {
+    async fn create_container(
+        &self,
+        name: &str,
+        config: &ContainerConfig,
+    ) -> Result<(), DockerError> {
+        tracing::debug!(
+            "Creating container {} from {} with env {}",
+            name,
+            config.image,
+            MaskedEnv::new(&config.env)
+        );
+        let options = CreateContainerOptions {
+            name,
+            platform: None,
+        };
+        self.docker
+            .create_container(Some(options), create_config(name, config))
+            .await
+            .map_err(|e| map_error(name, e))?;
+        Ok(())
+    }
+
+    async fn start_container(&self, name: &str) -> Result<(), DockerError> {
+        self.docker
+            .start_container(name, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| map_error(name, e))
+    }
+
+    async fn stop_container(&self, name: &str) -> Result<(), DockerError> {
+        self.docker
+            .stop_container(name, None::<StopContainerOptions>)
+            .await
+            .map_err(|e| map_error(name, e))
+    }
+
+    async fn stop_container_with_timeout(
+        &self,
+        name: &str,
+        timeout_secs: u64,
+    ) -> Result<(), DockerError> {
+        let options = StopContainerOptions {
+            t: i64::try_from(timeout_secs).unwrap_or(i64::MAX),
+        };
+        self.docker
+            .stop_container(name, Some(options))
+            .await
+            .map_err(|e| map_error(name, e))
+    }
+
+    async fn remove_container(&self, name: &str) -> Result<(), DockerError> {
+        let options = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+        self.docker
+            .remove_container(name, Some(options))
+            .await
+            .map_err(|e| map_error(name, e))
+    }
+
+    async fn get_container_status(&self, name: &str) -> Result<ContainerStatus, DockerError> {
+        let inspect = self
+            .docker
+            .inspect_container(name, None)
+            .await
+            .map_err(|e| map_error(name, e))?;
+        let state = inspect.state.unwrap_or_default();
+        Ok(ContainerStatus {
+            state: container_state(state.status),
+            health: state
+                .health
+                .and_then(|health| health.status)
+                .map(|status| status.to_string())
+                .filter(|status| !status.is_empty() && status != "none"),
+        })
+    }
+
+    async fn get_container_stats(&self, name: &str) -> Result<ContainerStats, DockerError> {
+        let options = StatsOptions {
+            stream: false,
+            one_shot: false,
+        };
+        let stats = self
+            .docker
+            .stats(name, Some(options))
+            .next()
+            .await
+            .ok_or_else(|| DockerError::Api(format!("no stats returned for {}", name)))?
+            .map_err(|e| map_error(name, e))?;
+        Ok(container_stats(&stats))
+    }
+
+    fn events(&self) -> BoxStream<'static, Result<ContainerEvent, DockerError>> {
+        let filters = HashMap::from([
+            ("type".to_string(), vec!["container".to_string()]),
+            ("label".to_string(), vec![MODULE_LABEL.to_string()]),
+            (
+                "event".to_string(),
+                ["start", "stop", "die", "health_status"]
+                    .map(String::from)
+                    .to_vec(),
+            ),
+        ]);
+        let options = EventsOptions {
+            filters,
+            ..Default::default()
+        };
+        self.docker
+            .events(Some(options))
+            .filter_map(|message| async move {
+                match message {
+                    Ok(message) => container_event(message).map(Ok),
+                    Err(e) => Some(Err(DockerError::Api(e.to_string()))),
+                }
+            })
+            .boxed()
+    }
+
+    fn logs(&self, name: &str, tail: usize) -> BoxStream<'static, Result<String, DockerError>> {
+        let options = LogsOptions {
+            follow: true,
+            stdout: true,
+            stderr: true,
+            tail: tail.to_string(),
+            ..Default::default()
+        };
+        let name = name.to_string();
+        self.docker
+            .logs(&name, Some(options))
+            .flat_map(move |output| {
+                // One write may carry several lines.
+                let lines: Vec<_> = match output {
+                    Ok(output) => output
+                        .to_string()
+                        .lines()
+                        .map(|l| Ok(l.to_string()))
+                        .collect(),
+                    Err(e) => vec![Err(map_error(&name, e))],
+                };
+                futures::stream::iter(lines)
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{ContainerConfigBuilder, ContainerConfigError, ResourceLimits};
+    use bollard::API_DEFAULT_VERSION;
+
+    fn version(major_version: usize, minor_version: usize) -> ClientVersion {
+        ClientVersion {
+            major_version,
+            minor_version,
+        }
+    }
+
+    #[test]
+    fn test_negotiation_picks_compatible_version() {
+        let chosen = negotiate_api_version(API_DEFAULT_VERSION, "1.43", Some("1.12")).unwrap();
+        assert_eq!(version_key(&chosen), (1, 43));
+        let chosen = negotiate_api_version(&version(1, 42), "1.47", None).unwrap();
+        assert_eq!(version_key(&chosen), (1, 42));
+
+        assert_eq!(
+            negotiate_api_version(API_DEFAULT_VERSION, "1.40", None).unwrap_err(),
+            DockerError::UnsupportedApiVersion {
+                daemon: "1.40".into(),
+                required: "1.41".into(),
+            }
+        );
+        assert!(matches!(
+            negotiate_api_version(&version(1, 41), "1.47", Some("1.44")),
+            Err(DockerError::UnsupportedApiVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_create_config_maps_builder_settings() {
+        let config = ContainerConfigBuilder::new()
+            .image("synapse/echo:latest")
+            .port("8080")
+            .volume("/data:/data:ro")
+            .label("team", "inference")
+            .resources(ResourceLimits {
+                memory_bytes: Some(512 * 1024 * 1024),
+                cpu_millis: Some(1500),
+            })
+            .restart_policy(RestartPolicy::OnFailure { max_retries: 5 })
+            .build()
+            .unwrap();
+        let created = create_config("echo", &config);
+
+        let labels = created.labels.unwrap();
+        assert_eq!(labels[MODULE_LABEL], "echo");
+        assert_eq!(labels["team"], "inference");
+        let host = created.host_config.unwrap();
+        assert_eq!(host.binds, Some(vec!["/data:/data:ro".to_string()]));
+        assert_eq!(host.memory, Some(512 * 1024 * 1024));
+        assert_eq!(host.nano_cpus, Some(1_500_000_000));
+        let restart = host.restart_policy.unwrap();
+        assert_eq!(restart.name, Some(RestartPolicyNameEnum::ON_FAILURE));
+        assert_eq!(restart.maximum_retry_count, Some(5));
+        assert!(host.port_bindings.unwrap().contains_key("8080/tcp"));
+        assert_eq!(host.auto_remove, Some(false));
+    }
+
+    #[test]
+    fn test_auto_remove_set_in_host_config() {
+        let builder = ContainerConfigBuilder::new().image("synapse/validator:1.0");
+        let config = builder.clone().auto_remove(true).build().unwrap();
+        let host = create_config("validator", &config).host_config.unwrap();
+        assert_eq!(host.auto_remove, Some(true));
+
+        assert_eq!(
+            builder
+                .auto_remove(true)
+                .restart_policy(RestartPolicy::Always)
+                .build(),
+            Err(ContainerConfigError::AutoRemoveWithRestart)
+        );
+    }
+
+    #[test]
+    fn test_events_converted_for_module_containers() {
+        let message = |action: &str, label: Option<&str>| EventMessage {
+            action: Some(action.to_string()),
+            actor: Some(bollard::models::EventActor {
+                id: Some("abc".into()),
+                attributes: Some(
+                    label
+                        .map(|name| HashMap::from([(MODULE_LABEL.to_string(), name.to_string())]))
+                        .unwrap_or_default(),
+                ),
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            container_event(message("die", Some("echo"))),
+            Some(ContainerEvent {
+                name: "echo".into(),
+                kind: ContainerEventKind::Die,
+            })
+        );
+        assert_eq!(
+            container_event(message("health_status: unhealthy", Some("echo"))).map(|e| e.kind),
+            Some(ContainerEventKind::HealthStatus("unhealthy".into()))
+        );
+        assert_eq!(container_event(message("die", None)), None);
+        assert_eq!(container_event(message("attach", Some("echo"))), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Docker daemon"]
+    async fn test_connect_negotiates_with_daemon() {
+        let manager = DockerManager::connect_with_version("unix:///var/run/docker.sock", "1.45")
+            .await
+            .unwrap();
+        assert!(version_key(&manager.docker.client_version()) >= version_key(&MIN_API_VERSION));
+    }
+}
@@ -0,0 +1,177 @@
+//! Checking that the environment has what the registrar and validator need
+//! before they are started.
+
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::reconnect::ReconnectingContainers;
+
+/// How long the Docker daemon is given to answer.
+pub const DOCKER_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interpreter the Commune chain scripts are run with.
+pub const PYTHON: &str = "python3";
+
+/// Result of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    /// What was found.
+    pub detail: String,
+    /// How to fix a failed check.
+    pub hint: Option<String>,
+}
+
+impl Check {
+    pub fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: true,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    pub fn fail(
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            passed: false,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Checks run by a `doctor` command, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    pub fn push(&mut self, check: Check) {
+        self.checks.push(check);
+    }
+
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = if check.passed { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {}: {}", status, check.name, check.detail)?;
+            if let Some(hint) = &check.hint {
+                writeln!(f, "       hint: {}", hint)?;
+            }
+        }
+        let failed = self.checks.iter().filter(|check| !check.passed).count();
+        write!(
+            f,
+            "{} of {} checks passed",
+            self.checks.len() - failed,
+            self.checks.len()
+        )
+    }
+}
+
+/// Checks that the Docker daemon answers.
+pub async fn check_docker(containers: &ReconnectingContainers) -> Check {
+    const NAME: &str = "docker";
+    const HINT: &str = "start the Docker daemon and check that this user may \
+                        use its socket (e.g. is in the docker group)";
+    match tokio::time::timeout(DOCKER_CHECK_TIMEOUT, containers.connected()).await {
+        Ok(Ok(_)) => Check::pass(NAME, "daemon reachable"),
+        Ok(Err(e)) => Check::fail(NAME, e.to_string(), HINT),
+        Err(_) => Check::fail(
+            NAME,
+            format!("no answer within {:?}", DOCKER_CHECK_TIMEOUT),
+            HINT,
+        ),
+    }
+}
+
+/// Checks that `dir` exists and files can be created in it.
+pub fn check_writable_dir(name: &str, dir: &Path, hint: &str) -> Check {
+    if !dir.is_dir() {
+        return Check::fail(name, format!("{} does not exist", dir.display()), hint);
+    }
+    match tempfile::tempfile_in(dir) {
+        Ok(_) => Check::pass(name, format!("{} is writable", dir.display())),
+        Err(e) => Check::fail(
+            name,
+            format!("cannot write to {}: {}", dir.display(), e),
+            hint,
+        ),
+    }
+}
+
+/// Checks that the directory `dir` exists.
+pub fn check_dir_present(name: &str, dir: &Path, hint: &str) -> Check {
+    if dir.is_dir() {
+        Check::pass(name, format!("{} found", dir.display()))
+    } else {
+        Check::fail(name, format!("{} does not exist", dir.display()), hint)
+    }
+}
+
+/// Checks that [`PYTHON`] runs, reporting its version.
+pub async fn check_python() -> Check {
+    const NAME: &str = "python";
+    let hint = format!(
+        "install {} and put it on PATH; the Commune chain scripts run with it",
+        PYTHON
+    );
+    match Command::new(PYTHON).arg("--version").output().await {
+        Ok(output) if output.status.success() => {
+            // Older pythons print their version to stderr.
+            let version = [output.stdout, output.stderr].concat();
+            Check::pass(NAME, String::from_utf8_lossy(&version).trim().to_string())
+        }
+        Ok(output) => Check::fail(
+            NAME,
+            format!("{} --version exited with {}", PYTHON, output.status),
+            hint,
+        ),
+        Err(e) => Check::fail(NAME, format!("cannot run {}: {}", PYTHON, e), hint),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::DockerError;
+
+    #[tokio::test]
+    async fn test_missing_docker_reported_as_failed_check() {
+        let containers = ReconnectingContainers::new(|| {
+            Box::pin(async { Err(DockerError::Api("connection refused".into())) })
+        });
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut report = DoctorReport::default();
+        report.push(check_docker(&containers).await);
+        report.push(check_writable_dir("data dir", dir.path(), "create it"));
+
+        assert!(!report.passed());
+        let docker = &report.checks[0];
+        assert!(!docker.passed);
+        assert!(docker.detail.contains("connection refused"));
+        assert!(docker.hint.is_some());
+        assert!(report.checks[1].passed);
+        let shown = report.to_string();
+        assert!(shown.starts_with("[FAIL] docker: "));
+        assert!(shown.ends_with("1 of 2 checks passed"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Redacting secrets from environment variables before they are logged.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Key patterns treated as secret by default. `*` matches any run of
+/// characters; matching ignores case.
+pub const DEFAULT_SECRET_PATTERNS: &[&str] = &[
+    "*_KEY",
+    "*_TOKEN",
+    "*SECRET*",
+    "*PASSWORD*",
+    "*CREDENTIALS*",
+];
+
+/// Shown in place of a secret value.
+pub const REDACTED: &str = "***";
+
+/// Whether `key` matches the wildcard `pattern`, ignoring case.
+fn matches(pattern: &str, key: &str) -> bool {
+    let pattern = pattern.to_ascii_uppercase();
+    let key = key.to_ascii_uppercase();
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one part.
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: the whole key must match.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Key patterns whose values are redacted by [`MaskedEnv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretPatterns(Vec<String>);
+
+impl SecretPatterns {
+    pub fn new(patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self(patterns.into_iter().map(Into::into).collect())
+    }
+
+    /// Whether the value of `key` should be redacted.
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.0.iter().any(|pattern| matches(pattern, key))
+    }
+}
+
+impl Default for SecretPatterns {
+    fn default() -> Self {
+        Self::new(DEFAULT_SECRET_PATTERNS.iter().copied())
+    }
+}
+
+/// Environment variables formatted with secret values redacted. Use it
+/// whenever an env map is logged.
+pub struct MaskedEnv<'a> {
+    env: &'a BTreeMap<String, String>,
+    patterns: SecretPatterns,
+}
+
+impl<'a> MaskedEnv<'a> {
+    /// Masks values matching [`DEFAULT_SECRET_PATTERNS`].
+    pub fn new(env: &'a BTreeMap<String, String>) -> Self {
+        Self::with_patterns(env, SecretPatterns::default())
+    }
+
+    pub fn with_patterns(env: &'a BTreeMap<String, String>, patterns: SecretPatterns) -> Self {
+        Self { env, patterns }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.env.iter().map(|(key, value)| {
+            let value = if self.patterns.is_secret(key) {
+                REDACTED
+            } else {
+                value.as_str()
+            };
+            (key.as_str(), value)
+        })
+    }
+}
+
+impl fmt::Debug for MaskedEnv<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.entries()).finish()
+    }
+}
+
+impl fmt::Display for MaskedEnv<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.entries().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_values_redacted() {
+        let env = BTreeMap::from([
+            ("API_KEY".to_string(), "sk-12345".to_string()),
+            ("db_password".to_string(), "hunter2".to_string()),
+            ("MODEL".to_string(), "tiny".to_string()),
+            ("KEYBOARD".to_string(), "us".to_string()),
+        ]);
+        let masked = MaskedEnv::new(&env);
+
+        assert_eq!(
+            masked.to_string(),
+            "API_KEY=***, KEYBOARD=us, MODEL=tiny, db_password=***"
+        );
+        let debug = format!("{:?}", masked);
+        assert!(!debug.contains("sk-12345") && !debug.contains("hunter2"));
+        assert!(debug.contains(r#""MODEL": "tiny""#));
+
+        let custom = MaskedEnv::with_patterns(&env, SecretPatterns::new(["MODEL"]));
+        assert!(custom.to_string().contains("MODEL=***"));
+        assert!(custom.to_string().contains("API_KEY=sk-12345"));
+    }
+}
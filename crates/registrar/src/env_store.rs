@@ -0,0 +1,427 @@
+//! Named env files grouped into profiles, such as `dev` and `prod`.
+//!
+//! Each profile is a directory under the store's root holding `<name>.env`
+//! files of `KEY=VALUE` lines. Activating an env points the profile's
+//! `active.env` symlink at it, so each profile has its own active env.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+/// Profile used when none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Symlink in a profile directory pointing at the active env.
+pub const ACTIVE_ENV_FILE: &str = "active.env";
+
+/// File in a profile directory locked while the profile is modified.
+const LOCK_FILE: &str = ".lock";
+
+/// How long a write waits for another process to release the profile.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Errors produced by an [`EnvStore`].
+#[derive(Debug, Error)]
+pub enum EnvStoreError {
+    #[error("Invalid name {0:?}: use letters, digits, '-' or '_'")]
+    InvalidName(String),
+
+    #[error("Env {name} not found in profile {profile}")]
+    NotFound { profile: String, name: String },
+
+    #[error("Env {name} already exists in profile {profile}")]
+    Exists { profile: String, name: String },
+
+    #[error("Profile {profile} is locked by another process; gave up after {timeout:?}")]
+    LockTimeout { profile: String, timeout: Duration },
+
+    #[error("Invalid line {line} in {path}: expected KEY=VALUE")]
+    InvalidLine { path: PathBuf, line: usize },
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// What a profile's [`ACTIVE_ENV_FILE`] link points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActiveEnv {
+    /// No env has been activated.
+    None,
+    /// The named env is active.
+    Env(String),
+    /// The link's target no longer exists, e.g. because the env file was
+    /// deleted.
+    Dangling(PathBuf),
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+fn validate_name(name: &str) -> Result<(), EnvStoreError> {
+    // `active` would collide with the active env link.
+    let valid = !name.is_empty()
+        && name != "active"
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(EnvStoreError::InvalidName(name.to_string()))
+    }
+}
+
+/// Parses `KEY=VALUE` lines, skipping blank lines and `#` comments.
+fn parse_env(path: &Path, contents: &str) -> Result<BTreeMap<String, String>, EnvStoreError> {
+    let mut vars = BTreeMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                vars.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            _ => {
+                return Err(EnvStoreError::InvalidLine {
+                    path: path.to_path_buf(),
+                    line: i + 1,
+                })
+            }
+        }
+    }
+    Ok(vars)
+}
+
+/// Env files for one profile under a base directory.
+#[derive(Debug, Clone)]
+pub struct EnvStore {
+    root: PathBuf,
+    profile: String,
+    lock_timeout: Duration,
+}
+
+impl EnvStore {
+    /// Opens the store rooted at `root`, selecting [`DEFAULT_PROFILE`].
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            profile: DEFAULT_PROFILE.to_string(),
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+        }
+    }
+
+    /// Returns the default store location, `~/.synapse/env`.
+    pub fn default_dir() -> PathBuf {
+        let home = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        home.join(".synapse").join("env")
+    }
+
+    /// Selects the profile the store operates within.
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Result<Self, EnvStoreError> {
+        let profile = profile.into();
+        validate_name(&profile)?;
+        self.profile = profile;
+        Ok(self)
+    }
+
+    /// Sets how long writes wait for other processes to release the
+    /// profile.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// Directory holding the selected profile's env files.
+    pub fn dir(&self) -> PathBuf {
+        self.root.join(&self.profile)
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, EnvStoreError> {
+        validate_name(name)?;
+        Ok(self.dir().join(format!("{}.env", name)))
+    }
+
+    fn not_found(&self, name: &str) -> EnvStoreError {
+        EnvStoreError::NotFound {
+            profile: self.profile.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    /// Takes an exclusive advisory lock on the selected profile, waiting up
+    /// to the lock timeout. The lock is released when the file is dropped.
+    fn lock(&self) -> Result<File, EnvStoreError> {
+        fs::create_dir_all(self.dir())?;
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir().join(LOCK_FILE))?;
+        let started = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(file),
+                Err(TryLockError::WouldBlock) if started.elapsed() < self.lock_timeout => {
+                    std::thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(EnvStoreError::LockTimeout {
+                        profile: self.profile.clone(),
+                        timeout: self.lock_timeout,
+                    })
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Writes a new env to the selected profile, returning its path.
+    pub fn create(
+        &self,
+        name: &str,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<PathBuf, EnvStoreError> {
+        let path = self.path(name)?;
+        let _lock = self.lock()?;
+        if path.exists() {
+            return Err(EnvStoreError::Exists {
+                profile: self.profile.clone(),
+                name: name.to_string(),
+            });
+        }
+        let contents: String = vars
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, value))
+            .collect();
+        // Written aside and renamed, so readers never see a partial file.
+        let partial = self.dir().join(format!(".{}.env.tmp", name));
+        fs::write(&partial, contents)?;
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Reads an env from the selected profile.
+    pub fn load(&self, name: &str) -> Result<BTreeMap<String, String>, EnvStoreError> {
+        let path = self.path(name)?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(self.not_found(name)),
+            Err(e) => return Err(e.into()),
+        };
+        parse_env(&path, &contents)
+    }
+
+    /// Lists the names of the envs in the selected profile.
+    pub fn list(&self) -> Result<Vec<String>, EnvStoreError> {
+        let entries = match fs::read_dir(self.dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.file_name().and_then(|n| n.to_str()) == Some(ACTIVE_ENV_FILE) {
+                continue;
+            }
+            if path.extension().and_then(|e| e.to_str()) == Some("env") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(stem.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Makes `name` the selected profile's active env by pointing
+    /// [`ACTIVE_ENV_FILE`] at it. Other profiles are unaffected. An env that
+    /// does not exist or does not parse is refused.
+    pub fn activate(&self, name: &str) -> Result<PathBuf, EnvStoreError> {
+        let _lock = self.lock()?;
+        self.load(name)?;
+        let link = self.dir().join(ACTIVE_ENV_FILE);
+        self.remove_active_link()?;
+        // Relative, so the profile directory can be moved.
+        symlink(Path::new(&format!("{}.env", name)), &link)?;
+        Ok(link)
+    }
+
+    /// Reports what the selected profile's active env link points at.
+    pub fn active(&self) -> Result<ActiveEnv, EnvStoreError> {
+        let link = self.dir().join(ACTIVE_ENV_FILE);
+        let target = match fs::read_link(&link) {
+            Ok(target) => target,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ActiveEnv::None),
+            Err(e) => return Err(e.into()),
+        };
+        // Following the link fails when its target is gone.
+        if fs::metadata(&link).is_err() {
+            return Ok(ActiveEnv::Dangling(target));
+        }
+        let name = target
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string();
+        Ok(ActiveEnv::Env(name))
+    }
+
+    /// Removes the selected profile's active env link, returning whether
+    /// there was one.
+    pub fn clear_active(&self) -> Result<bool, EnvStoreError> {
+        let _lock = self.lock()?;
+        self.remove_active_link()
+    }
+
+    fn remove_active_link(&self) -> Result<bool, EnvStoreError> {
+        match fs::remove_file(self.dir().join(ACTIVE_ENV_FILE)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_profiles_activate_same_named_env_independently() {
+        let root = tempfile::tempdir().unwrap();
+        let dev = EnvStore::new(root.path()).with_profile("dev").unwrap();
+        let prod = EnvStore::new(root.path()).with_profile("prod").unwrap();
+
+        dev.create("node", &vars(&[("CHAIN_URL", "ws://localhost:9944")]))
+            .unwrap();
+        prod.create("node", &vars(&[("CHAIN_URL", "wss://chain.example")]))
+            .unwrap();
+        prod.create("backup", &vars(&[("CHAIN_URL", "wss://backup.example")]))
+            .unwrap();
+        assert_eq!(dev.list().unwrap(), vec!["node"]);
+        assert_eq!(prod.list().unwrap(), vec!["backup", "node"]);
+
+        dev.activate("node").unwrap();
+        assert_eq!(dev.active().unwrap(), ActiveEnv::Env("node".into()));
+        assert_eq!(prod.active().unwrap(), ActiveEnv::None);
+
+        prod.activate("node").unwrap();
+        prod.activate("backup").unwrap();
+        let read = |store: &EnvStore| {
+            let path = store.dir().join(ACTIVE_ENV_FILE);
+            parse_env(&path, &fs::read_to_string(&path).unwrap()).unwrap()
+        };
+        assert_eq!(read(&dev)["CHAIN_URL"], "ws://localhost:9944");
+        assert_eq!(read(&prod)["CHAIN_URL"], "wss://backup.example");
+        assert_eq!(dev.active().unwrap(), ActiveEnv::Env("node".into()));
+        // The active link is not listed as an env.
+        assert_eq!(dev.list().unwrap(), vec!["node"]);
+
+        assert!(matches!(
+            dev.activate("backup"),
+            Err(EnvStoreError::NotFound { .. })
+        ));
+        assert!(matches!(
+            dev.create("node", &BTreeMap::new()),
+            Err(EnvStoreError::Exists { .. })
+        ));
+        assert!(EnvStore::new(root.path()).with_profile("../etc").is_err());
+    }
+
+    #[test]
+    fn test_concurrent_creates_leave_one_valid_env() {
+        let root = tempfile::tempdir().unwrap();
+        let barrier = std::sync::Barrier::new(2);
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = ["first", "second"]
+                .map(|value| {
+                    let store = EnvStore::new(root.path());
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        let vars: BTreeMap<_, _> = (0..500)
+                            .map(|i| (format!("KEY_{}", i), value.to_string()))
+                            .collect();
+                        barrier.wait();
+                        store.create("node", &vars)
+                    })
+                })
+                .into_iter()
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(EnvStoreError::Exists { .. }))));
+        let env = EnvStore::new(root.path()).load("node").unwrap();
+        assert_eq!(env.len(), 500);
+        let values: std::collections::BTreeSet<_> = env.values().collect();
+        assert_eq!(values.len(), 1, "env mixes both writes");
+    }
+
+    #[test]
+    fn test_write_times_out_while_profile_is_locked() {
+        let root = tempfile::tempdir().unwrap();
+        let store = EnvStore::new(root.path()).with_lock_timeout(Duration::from_millis(100));
+        let _held = store.lock().unwrap();
+
+        // A separate handle on the lock file, as another process would have.
+        let other = EnvStore::new(root.path()).with_lock_timeout(Duration::from_millis(100));
+        assert!(matches!(
+            other.create("node", &BTreeMap::new()),
+            Err(EnvStoreError::LockTimeout { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dangling_active_link_is_reported_and_cleared() {
+        let root = tempfile::tempdir().unwrap();
+        let store = EnvStore::new(root.path());
+        store.create("node", &vars(&[("A", "1")])).unwrap();
+        store.activate("node").unwrap();
+
+        fs::remove_file(store.dir().join("node.env")).unwrap();
+        assert_eq!(
+            store.active().unwrap(),
+            ActiveEnv::Dangling(PathBuf::from("node.env"))
+        );
+        assert!(matches!(
+            store.activate("node"),
+            Err(EnvStoreError::NotFound { .. })
+        ));
+
+        assert!(store.clear_active().unwrap());
+        assert_eq!(store.active().unwrap(), ActiveEnv::None);
+        assert!(!store.clear_active().unwrap());
+    }
+}
@@ -0,0 +1,95 @@
+//! Error types for registry operations.
+
+use thiserror::Error;
+
+/// Errors produced by the registry.
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    /// No module with the given name is registered.
+    #[error("Module not found: {0}")]
+    ModuleNotFound(String),
+
+    /// A module with the given name is already registered.
+    #[error("Module already exists: {0}")]
+    ModuleExists(String),
+
+    /// A miner with the given uid or key is already registered.
+    #[error("Miner already registered: {0}")]
+    MinerExists(u16),
+
+    /// The underlying database failed.
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+}
+
+/// Database failures, classified from the underlying driver error.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DbError {
+    /// A write would have duplicated a unique key.
+    #[error("unique constraint violated: {0}")]
+    UniqueViolation(String),
+
+    /// The database could not be reached or the pool is exhausted.
+    #[error("connection failed: {0}")]
+    ConnectionFailed(String),
+
+    /// The database was busy or locked by another writer.
+    #[error("database busy: {0}")]
+    Busy(String),
+
+    /// Any other failure, including rows that cannot be decoded.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                DbError::UniqueViolation(db.message().to_string())
+            }
+            // SQLITE_BUSY and SQLITE_LOCKED, including their extended codes.
+            sqlx::Error::Database(db)
+                if db
+                    .code()
+                    .and_then(|code| code.parse::<i32>().ok())
+                    .is_some_and(|code| matches!(code & 0xff, 5 | 6)) =>
+            {
+                DbError::Busy(db.message().to_string())
+            }
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => DbError::ConnectionFailed(err.to_string()),
+            _ => DbError::Other(err.to_string()),
+        }
+    }
+}
+
+/// A write cut off by its retry budget is reported like a pool timeout.
+impl From<tokio::time::error::Elapsed> for RegistryError {
+    fn from(err: tokio::time::error::Elapsed) -> Self {
+        RegistryError::Database(DbError::ConnectionFailed(err.to_string()))
+    }
+}
+
+impl RegistryError {
+    /// Whether the operation failed only because the database was busy,
+    /// and may succeed if tried again.
+    pub fn is_busy(&self) -> bool {
+        matches!(self, RegistryError::Database(DbError::Busy(_)))
+    }
+}
+
+impl From<sqlx::Error> for RegistryError {
+    fn from(err: sqlx::Error) -> Self {
+        RegistryError::Database(err.into())
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for RegistryError {
+    fn from(err: sqlx::migrate::MigrateError) -> Self {
+        RegistryError::Database(DbError::Other(err.to_string()))
+    }
+}
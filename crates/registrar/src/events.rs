@@ -0,0 +1,202 @@
+//! Events describing changes to the registry.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::audit::AuditAction;
+use crate::module::ModuleStatus;
+
+/// Number of recent events kept for clients resuming after a disconnect.
+pub const DEFAULT_REPLAY_CAPACITY: usize = 256;
+
+/// A change to a registered module.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEvent {
+    /// Identifies the registrar run that published the event. Ids start
+    /// over when the registrar restarts, so they only order events within
+    /// one epoch.
+    #[serde(default)]
+    pub epoch: u64,
+    /// Increases by one with each event, so clients can tell which events
+    /// they have seen.
+    pub id: u64,
+    pub module: String,
+    pub action: AuditAction,
+    /// Module status after the change, if the module still exists.
+    pub status: Option<ModuleStatus>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl RegistryEvent {
+    /// The stream position just after this event.
+    pub fn cursor(&self) -> EventCursor {
+        EventCursor {
+            epoch: self.epoch,
+            id: self.id,
+        }
+    }
+}
+
+/// A position in the event stream, sent as the server-sent event id
+/// `<epoch>-<id>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventCursor {
+    pub epoch: u64,
+    pub id: u64,
+}
+
+impl EventCursor {
+    /// Parses an `<epoch>-<id>` event id.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (epoch, id) = value.trim().split_once('-')?;
+        Some(Self {
+            epoch: epoch.parse().ok()?,
+            id: id.parse().ok()?,
+        })
+    }
+
+    /// Whether `event` comes after this position. Events from another
+    /// epoch always do: the registrar restarted and its ids started over.
+    pub fn precedes(&self, event: &RegistryEvent) -> bool {
+        event.epoch != self.epoch || event.id > self.id
+    }
+}
+
+impl fmt::Display for EventCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.epoch, self.id)
+    }
+}
+
+struct Inner {
+    next_id: u64,
+    recent: VecDeque<RegistryEvent>,
+}
+
+/// Publishes [`RegistryEvent`]s to subscribers and keeps the most recent
+/// ones for replay.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<RegistryEvent>,
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+    epoch: u64,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLAY_CAPACITY)
+    }
+}
+
+impl EventBus {
+    /// Creates a bus keeping up to `capacity` events for replay. Its epoch
+    /// is the creation time in milliseconds.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self {
+            sender,
+            inner: Arc::new(Mutex::new(Inner {
+                next_id: 1,
+                recent: VecDeque::new(),
+            })),
+            capacity,
+            epoch: Utc::now().timestamp_millis().max(0) as u64,
+        }
+    }
+
+    /// Sets the epoch stamped on published events.
+    pub fn with_epoch(mut self, epoch: u64) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Assigns the next id to an event and sends it to subscribers.
+    pub fn publish(
+        &self,
+        module: &str,
+        action: AuditAction,
+        status: Option<ModuleStatus>,
+    ) -> RegistryEvent {
+        let mut inner = self.inner.lock().unwrap();
+        let event = RegistryEvent {
+            epoch: self.epoch,
+            id: inner.next_id,
+            module: module.to_string(),
+            action,
+            status,
+            timestamp: Utc::now(),
+        };
+        inner.next_id += 1;
+        inner.recent.push_back(event.clone());
+        while inner.recent.len() > self.capacity {
+            inner.recent.pop_front();
+        }
+        // Sending under the lock keeps ids in order for subscribers.
+        let _ = self.sender.send(event.clone());
+        event
+    }
+
+    /// Subscribes to new events, returning along with the receiver the
+    /// retained events after `last`, so none are missed or repeated
+    /// between the two. A `last` from an earlier epoch replays every
+    /// retained event.
+    pub fn subscribe_after(
+        &self,
+        last: Option<EventCursor>,
+    ) -> (Vec<RegistryEvent>, broadcast::Receiver<RegistryEvent>) {
+        let inner = self.inner.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let replay = match last {
+            Some(last) => inner
+                .recent
+                .iter()
+                .filter(|event| last.precedes(event))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        (replay, receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribe_after_replays_missed_events() {
+        let bus = EventBus::new(2).with_epoch(7);
+        for name in ["a", "b", "c"] {
+            bus.publish(name, AuditAction::Create, Some(ModuleStatus::Stopped));
+        }
+
+        let (replay, mut receiver) = bus.subscribe_after(EventCursor::parse("7-1"));
+        // Event 1 is seen and only two are retained anyway.
+        assert_eq!(replay.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(bus.subscribe_after(None).0.is_empty());
+
+        bus.publish("a", AuditAction::Delete, None);
+        assert_eq!(receiver.recv().await.unwrap().id, 4);
+    }
+
+    #[test]
+    fn test_cursor_from_earlier_epoch_replays_everything() {
+        let bus = EventBus::new(8).with_epoch(9);
+        bus.publish("a", AuditAction::Create, Some(ModuleStatus::Stopped));
+        bus.publish("b", AuditAction::Create, Some(ModuleStatus::Stopped));
+
+        // A client that saw event 5 before the registrar restarted.
+        let (replay, _) = bus.subscribe_after(Some(EventCursor { epoch: 3, id: 5 }));
+        assert_eq!(replay.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        assert_eq!(replay[1].cursor().to_string(), "9-2");
+        assert_eq!(EventCursor::parse("9-2"), Some(replay[1].cursor()));
+        assert_eq!(EventCursor::parse("12"), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Overall module health, combined from the container, the module's
+//! readiness probe and its registration on chain.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use synapse_chain_api::commune::CommuneRpc;
+
+use crate::module::Module;
+use crate::runtime::{DockerModuleRuntime, ModuleState};
+
+/// Health of one component, or of a module as a whole. Variants are
+/// ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthLevel {
+    Healthy,
+    /// The component could not be checked.
+    Unknown,
+    Degraded,
+    Unhealthy,
+}
+
+/// Result of checking one component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub status: HealthLevel,
+    /// What was observed, e.g. the container state or the probe error.
+    pub detail: String,
+}
+
+impl ComponentHealth {
+    fn new(status: HealthLevel, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Health of a module. A component is `None` when it does not apply: the
+/// module declares no probe, or no chain is configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleHealth {
+    pub container: ComponentHealth,
+    pub probe: Option<ComponentHealth>,
+    pub chain: Option<ComponentHealth>,
+    /// The worst of the components.
+    pub overall: HealthLevel,
+}
+
+/// Reports whether modules are registered and active on chain.
+#[async_trait]
+pub trait ChainStatus: Send + Sync {
+    async fn is_active(&self, module: &Module) -> Result<bool, String>;
+}
+
+/// A module is active when a module of the same name is registered on
+/// the subnet.
+pub struct SubnetMembership {
+    rpc: CommuneRpc,
+    netuid: u16,
+}
+
+impl SubnetMembership {
+    pub fn new(rpc: CommuneRpc, netuid: u16) -> Self {
+        Self { rpc, netuid }
+    }
+}
+
+#[async_trait]
+impl ChainStatus for SubnetMembership {
+    async fn is_active(&self, module: &Module) -> Result<bool, String> {
+        let registered = self
+            .rpc
+            .modules(self.netuid)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(registered.iter().any(|m| m.name == module.name))
+    }
+}
+
+async fn container_health(runtime: Option<&DockerModuleRuntime>, module: &str) -> ComponentHealth {
+    let Some(runtime) = runtime else {
+        return ComponentHealth::new(HealthLevel::Unknown, "no container runtime configured");
+    };
+    match runtime.status(module).await {
+        Ok(state) => {
+            let status = match state {
+                ModuleState::Running => HealthLevel::Healthy,
+                ModuleState::Unhealthy => HealthLevel::Degraded,
+                ModuleState::Stopped | ModuleState::Failed => HealthLevel::Unhealthy,
+            };
+            ComponentHealth::new(status, state.to_string())
+        }
+        Err(e) => ComponentHealth::new(HealthLevel::Unknown, e.to_string()),
+    }
+}
+
+async fn probe_health(module: &Module) -> Option<ComponentHealth> {
+    let probe = module.config.probe.as_ref()?;
+    let Some(port) = module.tcp_port() else {
+        return Some(ComponentHealth::new(
+            HealthLevel::Unknown,
+            "module publishes no TCP port to probe",
+        ));
+    };
+    Some(match probe.build().probe("127.0.0.1", port).await {
+        Ok(()) => ComponentHealth::new(HealthLevel::Healthy, "ready"),
+        Err(e) => ComponentHealth::new(HealthLevel::Degraded, e.to_string()),
+    })
+}
+
+async fn chain_health(
+    chain: Option<&Arc<dyn ChainStatus>>,
+    module: &Module,
+) -> Option<ComponentHealth> {
+    Some(match chain?.is_active(module).await {
+        Ok(true) => ComponentHealth::new(HealthLevel::Healthy, "active"),
+        Ok(false) => ComponentHealth::new(HealthLevel::Unhealthy, "not registered on chain"),
+        Err(e) => ComponentHealth::new(HealthLevel::Unknown, e),
+    })
+}
+
+/// Checks every component of `module` at once.
+pub async fn check_module(
+    module: &Module,
+    runtime: Option<&DockerModuleRuntime>,
+    chain: Option<&Arc<dyn ChainStatus>>,
+) -> ModuleHealth {
+    let (container, probe, chain) = tokio::join!(
+        container_health(runtime, &module.name),
+        probe_health(module),
+        chain_health(chain, module),
+    );
+    let overall = [Some(&container), probe.as_ref(), chain.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|component| component.status)
+        .max()
+        .unwrap_or(HealthLevel::Unknown);
+    ModuleHealth {
+        container,
+        probe,
+        chain,
+        overall,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::fake::FakeContainers;
+    use crate::container::ContainerState;
+    use crate::module::ModuleType;
+
+    struct Chain(bool);
+
+    #[async_trait]
+    impl ChainStatus for Chain {
+        async fn is_active(&self, _module: &Module) -> Result<bool, String> {
+            Ok(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overall_is_worst_component() {
+        let runtime = DockerModuleRuntime::new(Arc::new(
+            FakeContainers::default().with_container("echo", ContainerState::Running),
+        ));
+        let module = Module::new("echo", ModuleType::Docker);
+
+        let active: Arc<dyn ChainStatus> = Arc::new(Chain(true));
+        let health = check_module(&module, Some(&runtime), Some(&active)).await;
+        assert_eq!(health.container.status, HealthLevel::Healthy);
+        assert_eq!(health.probe, None);
+        assert_eq!(health.overall, HealthLevel::Healthy);
+
+        let inactive: Arc<dyn ChainStatus> = Arc::new(Chain(false));
+        let health = check_module(&module, Some(&runtime), Some(&inactive)).await;
+        assert_eq!(
+            health.chain,
+            Some(ComponentHealth::new(
+                HealthLevel::Unhealthy,
+                "not registered on chain"
+            ))
+        );
+        assert_eq!(health.overall, HealthLevel::Unhealthy);
+    }
+}
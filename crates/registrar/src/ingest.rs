@@ -0,0 +1,534 @@
+//! Fetching module sources from git repositories.
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+use tokio::process::Command;
+
+use crate::config::{self, ConfigError};
+use crate::error::RegistryError;
+use crate::module::{Module, ModuleSource};
+use crate::registry::Registry;
+use crate::verify::{ModuleVerifier, VerificationError};
+
+/// Branch checked out when an ingest names no ref.
+pub const DEFAULT_BRANCH: &str = "main";
+
+/// URL schemes repositories may be fetched over by default.
+pub const DEFAULT_REPO_SCHEMES: [&str; 2] = ["https", "ssh"];
+
+/// Errors produced while ingesting a repository.
+#[derive(Debug, Error)]
+pub enum IngestError {
+    /// A git command exited unsuccessfully.
+    #[error("git {command} failed: {stderr}")]
+    Git { command: String, stderr: String },
+
+    /// The repository URL is malformed or uses a scheme that is not
+    /// allowed.
+    #[error("Invalid repository URL {url}: {reason}")]
+    InvalidRepoUrl { url: String, reason: String },
+
+    /// The ref starts with '-', so git would read it as an option.
+    #[error("Invalid git ref {0:?}: refs may not start with '-'")]
+    InvalidRef(String),
+
+    /// The module config fails verification.
+    #[error("Module {module} failed verification: {}", join_errors(.errors))]
+    Verification {
+        module: String,
+        errors: Vec<VerificationError>,
+    },
+
+    /// The repository has no module config at its root.
+    #[error("No module config found in {0}")]
+    MissingConfig(PathBuf),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+fn join_errors(errors: &[VerificationError]) -> String {
+    let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    errors.join("; ")
+}
+
+/// Result of ingesting a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestOutcome {
+    /// Checkout of the requested ref.
+    pub path: PathBuf,
+    /// Ref checked out; the default branch when none was requested.
+    pub git_ref: String,
+    /// Commit the requested ref resolved to.
+    pub commit: String,
+    /// Whether an existing cached clone was updated instead of cloning.
+    pub reused_cache: bool,
+    /// Whether the checked out commit differs from the previous ingest.
+    pub changed: bool,
+}
+
+/// Keeps one clone per repository URL and updates it in place on later
+/// ingests.
+#[derive(Debug, Clone)]
+pub struct RepoCache {
+    root: PathBuf,
+    default_branch: String,
+    allowed_schemes: Vec<String>,
+}
+
+/// Whether `repo_url` has git's scp-like ssh form, e.g.
+/// `git@github.com:org/repo.git`.
+fn is_scp_like(repo_url: &str) -> bool {
+    match repo_url.split_once(':') {
+        Some((host, path)) => {
+            !host.is_empty() && !host.contains('/') && !path.is_empty() && !path.starts_with("//")
+        }
+        None => false,
+    }
+}
+
+async fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, IngestError> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command.args(args).output().await?;
+    if !output.status.success() {
+        return Err(IngestError::Git {
+            command: args.first().copied().unwrap_or_default().to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+impl RepoCache {
+    /// Creates a cache storing clones under `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            default_branch: DEFAULT_BRANCH.to_string(),
+            allowed_schemes: DEFAULT_REPO_SCHEMES.map(String::from).to_vec(),
+        }
+    }
+
+    /// Sets the branch checked out when an ingest names no ref.
+    pub fn with_default_branch(mut self, branch: impl Into<String>) -> Self {
+        self.default_branch = branch.into();
+        self
+    }
+
+    /// Sets the URL schemes repositories may be fetched over.
+    pub fn with_allowed_schemes(mut self, schemes: Vec<String>) -> Self {
+        self.allowed_schemes = schemes;
+        self
+    }
+
+    /// Checks that `repo_url` is a URL with an allowed scheme. The scp-like
+    /// `user@host:path` form counts as `ssh`.
+    pub fn validate_repo_url(&self, repo_url: &str) -> Result<(), IngestError> {
+        let invalid = |reason: String| IngestError::InvalidRepoUrl {
+            url: repo_url.to_string(),
+            reason,
+        };
+        let scheme = match url::Url::parse(repo_url) {
+            Ok(url) => {
+                if url.scheme() != "file" && url.host_str().is_none_or(str::is_empty) {
+                    return Err(invalid("missing host".into()));
+                }
+                url.scheme().to_string()
+            }
+            Err(_) if is_scp_like(repo_url) => "ssh".to_string(),
+            Err(e) => return Err(invalid(e.to_string())),
+        };
+        if !self.allowed_schemes.contains(&scheme) {
+            return Err(invalid(format!(
+                "scheme {} is not one of {}",
+                scheme,
+                self.allowed_schemes.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Default cache location, `~/.synapse/repos`.
+    pub fn default_dir() -> PathBuf {
+        let home = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        home.join(".synapse").join("repos")
+    }
+
+    /// Directory holding the clone of `repo_url`.
+    pub fn clone_dir(&self, repo_url: &str) -> PathBuf {
+        let name: String = repo_url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        self.root.join(name)
+    }
+
+    /// Checks out `git_ref` (a branch, tag or commit sha) of `repo_url`,
+    /// or the default branch when `git_ref` is empty. A cached clone is
+    /// fetched into and updated; otherwise, or with `use_cache` unset, the
+    /// repository is cloned from scratch first.
+    pub async fn ingest(
+        &self,
+        repo_url: &str,
+        git_ref: &str,
+        use_cache: bool,
+    ) -> Result<IngestOutcome, IngestError> {
+        self.validate_repo_url(repo_url)?;
+        let git_ref = match git_ref.trim() {
+            "" => self.default_branch.as_str(),
+            git_ref => git_ref,
+        };
+        if git_ref.starts_with('-') {
+            return Err(IngestError::InvalidRef(git_ref.to_string()));
+        }
+        let dir = self.clone_dir(repo_url);
+        let cached = dir.join(".git").is_dir();
+        let previous = if cached {
+            git(Some(&dir), &["rev-parse", "HEAD"]).await.ok()
+        } else {
+            None
+        };
+
+        let reused_cache = cached && use_cache;
+        if reused_cache {
+            tracing::debug!("Updating cached clone of {} in {}", repo_url, dir.display());
+        } else {
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir)?;
+            }
+            std::fs::create_dir_all(&self.root)?;
+            tracing::debug!("Cloning {} into {}", repo_url, dir.display());
+            let target = dir.to_string_lossy();
+            git(
+                None,
+                &["clone", "--quiet", "--no-checkout", "--", repo_url, &target],
+            )
+            .await?;
+        }
+        git(Some(&dir), &["fetch", "--quiet", "origin", git_ref]).await?;
+        git(
+            Some(&dir),
+            &["checkout", "--quiet", "--detach", "FETCH_HEAD"],
+        )
+        .await?;
+
+        let commit = git(Some(&dir), &["rev-parse", "HEAD"]).await?;
+        Ok(IngestOutcome {
+            changed: previous.as_deref() != Some(commit.as_str()),
+            path: dir,
+            git_ref: git_ref.to_string(),
+            commit,
+            reused_cache,
+        })
+    }
+}
+
+/// A module registered or updated by [`ingest_module`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct IngestedModule {
+    pub module: Module,
+    pub source: ModuleSource,
+    pub outcome: IngestOutcome,
+}
+
+/// Fetches `git_ref` of `repo_url`, registers the module described by the
+/// config at the repository root (updating its config if it is already
+/// registered) and records the resolved commit as the module's source.
+/// A config with lint errors is rejected before anything is registered.
+pub async fn ingest_module(
+    registry: &dyn Registry,
+    cache: &RepoCache,
+    verifier: &ModuleVerifier,
+    repo_url: &str,
+    git_ref: &str,
+    use_cache: bool,
+) -> Result<IngestedModule, IngestError> {
+    let outcome = cache.ingest(repo_url, git_ref, use_cache).await?;
+    let config_path = config::find_module_config(&outcome.path)
+        .ok_or_else(|| IngestError::MissingConfig(outcome.path.clone()))?;
+    let definition = config::load_module_config(config_path)?;
+    let report = verifier.lint(&definition);
+    if !report.is_valid() {
+        return Err(IngestError::Verification {
+            module: definition.name,
+            errors: report.errors,
+        });
+    }
+    let module = definition.into_module();
+    let source = ModuleSource {
+        repo_url: repo_url.to_string(),
+        git_ref: outcome.git_ref.clone(),
+        commit: outcome.commit.clone(),
+        path: outcome.path.display().to_string(),
+    };
+
+    match registry
+        .create_module_with_source(&module, Some(&source))
+        .await
+    {
+        Ok(_) => {}
+        Err(RegistryError::ModuleExists(_)) => {
+            registry
+                .update_module_config(&module.name, &module.config)
+                .await?;
+            registry.set_module_source(&module.name, &source).await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(IngestedModule {
+        module: registry.get_module(&module.name).await?,
+        source,
+        outcome,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn commit(repo: &Path, file: &str) {
+        std::fs::write(repo.join(file), file).unwrap();
+        git(Some(repo), &["add", "."]).await.unwrap();
+        git(
+            Some(repo),
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "--quiet",
+                "-m",
+                file,
+            ],
+        )
+        .await
+        .unwrap();
+    }
+
+    /// A cache that accepts the `file://` URLs of test repositories.
+    fn cache(root: &Path) -> RepoCache {
+        RepoCache::new(root).with_allowed_schemes(vec!["file".into()])
+    }
+
+    fn file_url(path: &Path) -> String {
+        url::Url::from_file_path(path).unwrap().to_string()
+    }
+
+    async fn upstream() -> tempfile::TempDir {
+        let upstream = tempfile::tempdir().unwrap();
+        git(Some(upstream.path()), &["init", "--quiet", "-b", "main"])
+            .await
+            .unwrap();
+        upstream
+    }
+
+    #[tokio::test]
+    async fn test_second_ingest_reuses_cache() {
+        let upstream = upstream().await;
+        commit(upstream.path(), "config.yaml").await;
+        let url = file_url(upstream.path());
+
+        let cache_root = tempfile::tempdir().unwrap();
+        let cache = cache(cache_root.path());
+
+        let first = cache.ingest(&url, "main", true).await.unwrap();
+        assert!(!first.reused_cache);
+        assert!(first.changed);
+        assert!(first.path.join("config.yaml").is_file());
+
+        // A marker left in the clone survives only if it is not re-cloned.
+        std::fs::write(first.path.join(".marker"), "").unwrap();
+        let second = cache.ingest(&url, "main", true).await.unwrap();
+        assert!(second.reused_cache);
+        assert!(!second.changed);
+        assert_eq!(second.commit, first.commit);
+        assert!(second.path.join(".marker").exists());
+
+        commit(upstream.path(), "main.py").await;
+        let third = cache.ingest(&url, "main", true).await.unwrap();
+        assert!(third.reused_cache);
+        assert!(third.changed);
+        assert!(third.path.join("main.py").is_file());
+
+        let fresh = cache.ingest(&url, "main", false).await.unwrap();
+        assert!(!fresh.reused_cache);
+        assert!(!fresh.changed);
+        assert!(!fresh.path.join(".marker").exists());
+    }
+
+    #[tokio::test]
+    async fn test_ingest_at_sha_records_commit() {
+        let upstream = upstream().await;
+        std::fs::write(
+            upstream.path().join("config.yaml"),
+            "name: echo\ntype: local\nenv:\n  MODULE_PORT: \"8080\"\n",
+        )
+        .unwrap();
+        commit(upstream.path(), "v1").await;
+        let pinned = git(Some(upstream.path()), &["rev-parse", "HEAD"])
+            .await
+            .unwrap();
+        commit(upstream.path(), "v2").await;
+        let url = file_url(upstream.path());
+
+        let cache_root = tempfile::tempdir().unwrap();
+        let cache = cache(cache_root.path());
+        let registry = crate::registry::SqliteRegistry::in_memory().await.unwrap();
+        let verifier = ModuleVerifier::default();
+
+        let ingested = ingest_module(&registry, &cache, &verifier, &url, &pinned, true)
+            .await
+            .unwrap();
+        assert_eq!(ingested.module.name, "echo");
+        assert_eq!(ingested.outcome.commit, pinned);
+        assert!(ingested.outcome.path.join("v1").exists());
+        assert!(!ingested.outcome.path.join("v2").exists());
+
+        let source = registry
+            .get_module_metadata("echo")
+            .await
+            .unwrap()
+            .source
+            .unwrap();
+        assert_eq!(source.commit, pinned);
+        assert_eq!(source.git_ref, pinned);
+        assert_eq!(source.repo_url, url);
+
+        // Moving to the branch updates the recorded commit.
+        let ingested = ingest_module(&registry, &cache, &verifier, &url, "main", true)
+            .await
+            .unwrap();
+        assert_ne!(ingested.source.commit, pinned);
+        assert_eq!(
+            registry
+                .get_module_metadata("echo")
+                .await
+                .unwrap()
+                .source
+                .unwrap()
+                .commit,
+            ingested.source.commit
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ingest_with_lint_errors_rejected() {
+        let upstream = upstream().await;
+        std::fs::write(
+            upstream.path().join("config.yaml"),
+            "name: echo\ntype: local\nports: [\"0\"]\n",
+        )
+        .unwrap();
+        commit(upstream.path(), "v1").await;
+        let url = file_url(upstream.path());
+
+        let cache_root = tempfile::tempdir().unwrap();
+        let registry = crate::registry::SqliteRegistry::in_memory().await.unwrap();
+        let err = ingest_module(
+            &registry,
+            &cache(cache_root.path()),
+            &ModuleVerifier::default(),
+            &url,
+            "main",
+            true,
+        )
+        .await
+        .unwrap_err();
+
+        match err {
+            IngestError::Verification { module, errors } => {
+                assert_eq!(module, "echo");
+                assert_eq!(
+                    errors,
+                    vec![
+                        VerificationError::InvalidPort("0".into()),
+                        VerificationError::MissingEnv("MODULE_PORT".into()),
+                    ]
+                );
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+        assert!(registry.list_modules().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_repo_url_validation() {
+        let cache = RepoCache::new("/tmp/repos");
+        cache
+            .validate_repo_url("https://github.com/synapse/echo.git")
+            .unwrap();
+        cache
+            .validate_repo_url("git@github.com:synapse/echo.git")
+            .unwrap();
+        cache
+            .validate_repo_url("ssh://git@github.com/synapse/echo.git")
+            .unwrap();
+
+        for url in [
+            "http://github.com/synapse/echo.git",
+            "file:///srv/echo",
+            "/srv/echo",
+            "not a url",
+            "https://",
+        ] {
+            assert!(
+                matches!(
+                    cache.validate_repo_url(url),
+                    Err(IngestError::InvalidRepoUrl { .. })
+                ),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_option_like_ref_rejected() {
+        let upstream = upstream().await;
+        commit(upstream.path(), "config.yaml").await;
+
+        let cache_root = tempfile::tempdir().unwrap();
+        let result = cache(cache_root.path())
+            .ingest(
+                &file_url(upstream.path()),
+                "--upload-pack=touch pwned",
+                true,
+            )
+            .await;
+        assert!(matches!(result, Err(IngestError::InvalidRef(_))));
+    }
+
+    #[tokio::test]
+    async fn test_empty_ref_checks_out_default_branch() {
+        let upstream = tempfile::tempdir().unwrap();
+        git(Some(upstream.path()), &["init", "--quiet", "-b", "trunk"])
+            .await
+            .unwrap();
+        commit(upstream.path(), "config.yaml").await;
+
+        let cache_root = tempfile::tempdir().unwrap();
+        let cache = cache(cache_root.path()).with_default_branch("trunk");
+        let outcome = cache
+            .ingest(&file_url(upstream.path()), " ", true)
+            .await
+            .unwrap();
+        assert_eq!(outcome.git_ref, "trunk");
+        assert!(outcome.path.join("config.yaml").is_file());
+    }
+}
@@ -1,8 +1,53 @@
 //! Registrar implementation for the Synapse Subnet project.
-//! 
+//!
 //! This crate provides the module registry and build system for managing
 //! inference modules.
 
+pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod client;
+pub mod config;
+pub mod container;
+pub mod data_dir;
+pub mod dependencies;
+pub mod diff;
+pub mod docker;
+pub mod doctor;
+pub mod env;
+pub mod env_store;
+pub mod error;
+pub mod events;
+pub mod health;
+pub mod ingest;
+pub mod logging;
+pub mod miner;
+pub mod module;
+pub mod package;
+pub mod package_cache;
+pub mod page;
+pub mod port;
+pub mod probe;
+pub mod quarantine;
+pub mod readiness;
+pub mod reconnect;
+pub mod registry;
+pub mod resources;
+pub mod retry;
+pub mod runtime;
+pub mod scaffold;
+pub mod status_poller;
+pub mod tasks;
+pub mod unix_socket;
+pub mod uploads;
+pub mod verify;
+pub mod webhooks;
+
+pub use error::{DbError, RegistryError};
+pub use module::{Module, ModuleStatus, ModuleType};
+pub use registry::{Registry, SqliteRegistry};
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,140 @@
+//! Tracing subscriber setup shared by the binaries.
+
+use clap::{Args, ValueEnum};
+use thiserror::Error;
+use tracing::subscriber::SetGlobalDefaultError;
+use tracing::Subscriber;
+use tracing_subscriber::filter::ParseError;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Errors produced while configuring logging.
+#[derive(Debug, Error)]
+pub enum LoggingError {
+    /// The log level is not a valid filter directive.
+    #[error("Invalid log level: {0}")]
+    InvalidLevel(#[from] ParseError),
+
+    #[error(transparent)]
+    Init(#[from] SetGlobalDefaultError),
+}
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Pretty,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Command-line logging options.
+#[derive(Debug, Clone, Default, Args)]
+pub struct LogArgs {
+    /// Log filter such as `info` or `synapse_registrar=debug`; overrides RUST_LOG
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Pretty, global = true)]
+    pub log_format: LogFormat,
+}
+
+impl LogArgs {
+    /// The filter to log with: `--log-level` when given, `RUST_LOG`
+    /// otherwise.
+    pub fn filter(&self) -> Result<EnvFilter, LoggingError> {
+        match &self.log_level {
+            Some(level) => Ok(EnvFilter::try_new(level)?),
+            None => Ok(EnvFilter::from_default_env()),
+        }
+    }
+
+    /// Builds a subscriber writing to `writer`.
+    pub fn subscriber<W>(
+        &self,
+        writer: W,
+    ) -> Result<Box<dyn Subscriber + Send + Sync>, LoggingError>
+    where
+        W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    {
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(self.filter()?)
+            .with_writer(writer);
+        Ok(match self.log_format {
+            LogFormat::Pretty => Box::new(builder.finish()),
+            LogFormat::Json => Box::new(builder.json().finish()),
+        })
+    }
+
+    /// Installs the subscriber as the global default, writing to stdout.
+    pub fn init(&self) -> Result<(), LoggingError> {
+        tracing::subscriber::set_global_default(self.subscriber(std::io::stdout)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_format_emits_parseable_lines() {
+        let args = LogArgs {
+            log_level: Some("debug".into()),
+            log_format: LogFormat::Json,
+        };
+        let buffer = Buffer::default();
+        let subscriber = args.subscriber(buffer.clone()).unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(module = "echo", "module started");
+            tracing::debug!("health check passed");
+            tracing::trace!("filtered out");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2, "{}", output);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["fields"]["message"], "module started");
+        assert_eq!(lines[0]["fields"]["module"], "echo");
+        assert_eq!(lines[1]["level"], "DEBUG");
+    }
+
+    #[test]
+    fn test_invalid_level_is_rejected() {
+        let args = LogArgs {
+            log_level: Some("=nonsense=".into()),
+            log_format: LogFormat::Pretty,
+        };
+        assert!(matches!(args.filter(), Err(LoggingError::InvalidLevel(_))));
+    }
+}
@@ -0,0 +1,638 @@
+mod cli;
+
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use metrics_exporter_prometheus::PrometheusBuilder;
+
+use synapse_chain_api::keystore::Keystore;
+use synapse_registrar::api::rate_limit::RateLimitConfig;
+use synapse_registrar::api::{create_router, AppState, ConcurrencyConfig};
+use synapse_registrar::auth::{AuthManager, Role};
+use synapse_registrar::backup::{export, import, ConflictPolicy, RegistryExport};
+use synapse_registrar::client::RegistrarClient;
+use synapse_registrar::data_dir::DataDir;
+use synapse_registrar::dependencies::{start_all, StartAllOptions};
+use synapse_registrar::env_store::{EnvStore, DEFAULT_PROFILE};
+use synapse_registrar::ingest::{ingest_module, RepoCache, DEFAULT_BRANCH};
+use synapse_registrar::logging::LogArgs;
+use synapse_registrar::module::ModuleType;
+use synapse_registrar::package_cache::PackageCache;
+use synapse_registrar::readiness::Readiness;
+use synapse_registrar::reconnect::ReconnectingContainers;
+use synapse_registrar::registry::SqliteRegistry;
+use synapse_registrar::runtime::DockerModuleRuntime;
+use synapse_registrar::scaffold::scaffold_module;
+use synapse_registrar::tasks::{BackgroundTasks, DEFAULT_SHUTDOWN_TIMEOUT};
+use synapse_registrar::unix_socket;
+use synapse_registrar::uploads::UploadStore;
+use synapse_registrar::verify::{ModuleVerifier, VerificationConfig};
+use synapse_registrar::webhooks::Webhooks;
+
+use cli::confirm::Confirmer;
+use cli::env::EnvCommand;
+use cli::keys::KeysCommand;
+
+#[derive(Parser)]
+#[command(name = "registrar", about = "Synapse module registrar")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    log: LogArgs,
+    /// Proceed with destructive operations without asking for confirmation
+    #[arg(long, global = true, visible_alias = "no-prompt")]
+    yes: bool,
+    /// Directory holding the registry database, package cache, uploads and
+    /// env files (defaults to $XDG_DATA_HOME/synapse)
+    #[arg(long, global = true, env = "SYNAPSE_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the registrar API server
+    Serve {
+        /// Path to the registry database (defaults to registrar.db in the
+        /// data directory)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Public key granted the admin role; enables authorization
+        #[arg(long = "admin-key")]
+        admin_keys: Vec<String>,
+        /// Directory for cached installation packages (defaults to
+        /// packages in the data directory)
+        #[arg(long)]
+        package_cache_dir: Option<PathBuf>,
+        /// Size cap of the package cache in MiB
+        #[arg(long, default_value_t = 512)]
+        package_cache_mb: u64,
+        /// Largest installation package served, in MiB
+        #[arg(long, default_value_t = 256)]
+        max_package_mb: u64,
+        /// Directory holding resumable package uploads (defaults to uploads
+        /// in the data directory)
+        #[arg(long)]
+        upload_dir: Option<PathBuf>,
+        /// Largest package accepted through an upload, in MiB
+        #[arg(long, default_value_t = 1024)]
+        max_upload_mb: u64,
+        /// Hours an upload may sit untouched, incomplete or not ingested,
+        /// before it is removed
+        #[arg(long, default_value_t = 24)]
+        upload_ttl_hours: u64,
+        /// Address to listen on, as host:port
+        #[arg(long, env = "BIND_ADDR", default_value = "127.0.0.1:3000")]
+        bind: SocketAddr,
+        /// Unix socket to serve the API on instead of TCP, e.g.
+        /// /run/synapse.sock; only its owner and group may connect
+        #[arg(long, env = "REGISTRAR_SOCKET")]
+        socket: Option<PathBuf>,
+        /// Writes a client may make per minute; unlimited when unset
+        #[arg(long)]
+        max_writes_per_minute: Option<u32>,
+        /// Reads a client may make per minute, counted apart from writes;
+        /// reads share the write budget when unset
+        #[arg(long, requires = "max_writes_per_minute")]
+        max_reads_per_minute: Option<u32>,
+        /// Requests handled at once; further requests queue
+        #[arg(long, default_value_t = 32)]
+        max_concurrent_requests: usize,
+        /// Seconds a request may queue and run before it is answered with 503
+        #[arg(long, default_value_t = 30)]
+        request_timeout: u64,
+        /// Type given to modules created without one
+        #[arg(long, default_value = "docker", value_parser = ["docker", "local", "observer"])]
+        default_module_type: String,
+        /// Path prefix every route is served under, e.g. /api
+        #[arg(long, env = "REGISTRAR_BASE_PATH", default_value = "")]
+        base_path: String,
+        /// Directory module configs are kept in; a renamed module's
+        /// directory there is moved with it
+        #[arg(long)]
+        config_dir: Option<PathBuf>,
+    },
+    /// Start all registered modules in dependency order
+    StartAll {
+        /// URL of the running registrar
+        #[arg(long, default_value = "http://127.0.0.1:3000")]
+        url: String,
+        /// Wait for each dependency to be healthy before starting dependents
+        #[arg(long)]
+        wait_for_dependency_health: bool,
+        /// Seconds to wait for a single dependency to become healthy
+        #[arg(long, default_value_t = 60)]
+        dependency_timeout: u64,
+    },
+    /// Re-verify every registered module, quarantining those that no
+    /// longer pass so they cannot be started
+    VerifyAll {
+        /// Path to the registry database (defaults to registrar.db in the
+        /// data directory)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Environment variable every module must define
+        #[arg(long = "required-env", default_value = "MODULE_PORT")]
+        required_env: Vec<String>,
+        /// Registry images may be pulled from; any when none is given
+        #[arg(long = "allowed-registry")]
+        allowed_registries: Vec<String>,
+    },
+    /// Check that Docker, the data directory, the module config directory
+    /// and python are usable, with hints for fixing what is not
+    Doctor {
+        /// Directory module configs are kept in
+        #[arg(long, default_value = "modules")]
+        config_dir: PathBuf,
+    },
+    /// Check a module config without registering or starting it
+    Validate {
+        /// Path to the module config (.yaml, .yml, .toml or .json)
+        #[arg(long)]
+        config: PathBuf,
+    },
+    /// Scaffold a new module directory
+    New {
+        /// Module name
+        name: String,
+        /// Module type
+        #[arg(long = "type", default_value = "docker", value_parser = ["docker", "local"])]
+        module_type: String,
+        /// Directory the module directory is created in
+        #[arg(long, default_value = "modules")]
+        config_dir: PathBuf,
+        /// Overwrite an existing module directory
+        #[arg(long)]
+        force: bool,
+    },
+    /// Fetch a module repository and register the module it contains
+    Ingest {
+        /// Git URL of the module repository
+        #[arg(long)]
+        repo_url: String,
+        /// Branch, tag or commit sha to check out (defaults to
+        /// --default-branch)
+        #[arg(long = "ref", alias = "branch")]
+        git_ref: Option<String>,
+        /// Branch checked out when no ref is given
+        #[arg(long, env = "SYNAPSE_DEFAULT_BRANCH", default_value = DEFAULT_BRANCH)]
+        default_branch: String,
+        /// Path to the registry database (defaults to registrar.db in the
+        /// data directory)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Clone cache directory (defaults to ~/.synapse/repos)
+        #[arg(long)]
+        cache_dir: Option<PathBuf>,
+        /// Clone from scratch instead of updating a cached clone
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Write every registered module to a JSON file
+    Export {
+        /// Path to the registry database (defaults to registrar.db in the
+        /// data directory)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// File the export is written to
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Unregister a module
+    Delete {
+        /// Module name
+        name: String,
+        /// Path to the registry database (defaults to registrar.db in the
+        /// data directory)
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Restore modules from a file written by `export`
+    Import {
+        /// Path to the registry database (defaults to registrar.db in the
+        /// data directory)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// File written by `export`
+        #[arg(long = "in")]
+        input: PathBuf,
+        /// What to do with modules already registered with other contents
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Skip)]
+        on_conflict: ConflictPolicy,
+        /// Report what would change without modifying the registry
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage signing keys
+    Keys {
+        /// Keystore directory (defaults to ~/.synapse/keys)
+        #[arg(long)]
+        keystore: Option<PathBuf>,
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+    /// Manage env files, grouped into profiles
+    Env {
+        /// Base directory holding one directory per profile (defaults to env
+        /// in the data directory)
+        #[arg(long, env = "SYNAPSE_ENV_DIR")]
+        env_dir: Option<PathBuf>,
+        /// Profile to operate within, e.g. dev or prod
+        #[arg(long, env = "SYNAPSE_PROFILE", default_value = DEFAULT_PROFILE)]
+        profile: String,
+        #[command(subcommand)]
+        command: EnvCommand,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    cli.log.init()?;
+    let confirmer = Confirmer::new(cli.yes);
+    let data_dir = DataDir::new(cli.data_dir.unwrap_or_else(DataDir::default_dir));
+
+    match cli.command {
+        Command::Serve {
+            db,
+            admin_keys,
+            package_cache_dir,
+            package_cache_mb,
+            max_package_mb,
+            upload_dir,
+            max_upload_mb,
+            upload_ttl_hours,
+            bind,
+            socket,
+            max_writes_per_minute,
+            max_reads_per_minute,
+            max_concurrent_requests,
+            request_timeout,
+            default_module_type,
+            base_path,
+            config_dir,
+        } => {
+            let default_module_type = default_module_type.parse::<ModuleType>()?;
+            let registry = open_registry(db, &data_dir).await?;
+            let packages = PackageCache::new(
+                package_cache_dir.unwrap_or_else(|| data_dir.package_cache()),
+                package_cache_mb * 1024 * 1024,
+            )?;
+            let uploads = UploadStore::new(upload_dir.unwrap_or_else(|| data_dir.uploads()))?
+                .with_max_size(max_upload_mb * 1024 * 1024);
+            let tasks = BackgroundTasks::new();
+            tasks.spawn(
+                uploads
+                    .clone()
+                    .expire_periodically(Duration::from_secs(upload_ttl_hours * 60 * 60)),
+            );
+            let mut state = AppState::new(Arc::new(registry.clone()))
+                .with_background_tasks(tasks.clone())
+                .with_package_cache(packages)
+                .with_max_package_size(max_package_mb * 1024 * 1024)
+                .with_uploads(uploads)
+                .with_metrics(PrometheusBuilder::new().install_recorder()?)
+                .with_concurrency_limit(ConcurrencyConfig {
+                    max_in_flight: max_concurrent_requests,
+                    timeout: Duration::from_secs(request_timeout),
+                })
+                .with_default_module_type(default_module_type)
+                .with_base_path(&base_path);
+            if let Some(config_dir) = config_dir {
+                state = state.with_config_dir(config_dir);
+            }
+            // Without Docker the registrar still serves reads; operations on
+            // containers answer 503 until the daemon can be reached.
+            let docker = ReconnectingContainers::docker();
+            if let Err(e) = docker.connected().await {
+                tracing::warn!("Docker unavailable, running degraded: {}", e);
+            }
+            state = state.with_runtime(DockerModuleRuntime::new(Arc::new(docker)));
+            if let Some(max_requests) = max_writes_per_minute {
+                state = state.with_api_rate_limit(RateLimitConfig {
+                    max_requests,
+                    window: Duration::from_secs(60),
+                    max_reads: max_reads_per_minute,
+                });
+            }
+            state = state.with_webhooks(Webhooks::load(registry.pool().clone()).await?);
+            let auth = (!admin_keys.is_empty()).then(|| AuthManager::new(registry.pool().clone()));
+            if let Some(auth) = &auth {
+                state = state.with_auth(auth.clone());
+            }
+            let readiness = Readiness::new();
+            let app = create_router(state.with_readiness(readiness.clone()));
+
+            let server = match &socket {
+                Some(path) => {
+                    tracing::info!("Registrar listening on {}{}", path.display(), base_path);
+                    let listener = unix_socket::bind(path)?;
+                    tokio::spawn(unix_socket::serve(listener, app, shutdown_signal()))
+                }
+                None => {
+                    tracing::info!("Registrar listening on {}{}", bind, base_path);
+                    let listener = tokio::net::TcpListener::bind(bind).await?;
+                    tokio::spawn(
+                        axum::serve(
+                            listener,
+                            app.into_make_service_with_connect_info::<SocketAddr>(),
+                        )
+                        .with_graceful_shutdown(shutdown_signal())
+                        .into_future(),
+                    )
+                }
+            };
+            // Migrations ran when the registry was opened; seeding admin
+            // keys is all that is left before traffic may be routed here.
+            if let Some(auth) = &auth {
+                for key in &admin_keys {
+                    auth.set_role(key, Role::Admin).await?;
+                }
+            }
+            readiness.set_ready();
+            tracing::info!("Registrar ready");
+            server.await??;
+            if let Some(path) = &socket {
+                let _ = std::fs::remove_file(path);
+            }
+            tracing::info!("Stopping {} background task(s)", tasks.len());
+            if !tasks.shutdown(DEFAULT_SHUTDOWN_TIMEOUT).await {
+                tracing::warn!(
+                    "Background tasks did not stop within {:?}",
+                    DEFAULT_SHUTDOWN_TIMEOUT
+                );
+            }
+        }
+        Command::StartAll {
+            url,
+            wait_for_dependency_health,
+            dependency_timeout,
+        } => {
+            let options = StartAllOptions {
+                wait_for_dependency_health,
+                dependency_timeout: Duration::from_secs(dependency_timeout),
+                ..Default::default()
+            };
+            let client = RegistrarClient::new(&url)?;
+            let modules = client.list_modules().await?;
+            let started = start_all(&modules, &client, &options).await?;
+            println!(
+                "Started {} module(s): {}",
+                started.len(),
+                started.join(", ")
+            );
+        }
+        Command::VerifyAll {
+            db,
+            required_env,
+            allowed_registries,
+        } => {
+            let registry = open_registry(db, &data_dir).await?;
+            let verifier = ModuleVerifier::new(VerificationConfig {
+                required_env,
+                allowed_registries,
+            });
+            if !cli::verify_all::run(&registry, &verifier).await? {
+                std::process::exit(1);
+            }
+        }
+        Command::Doctor { config_dir } => {
+            if !cli::doctor::run(&data_dir, &config_dir).await {
+                std::process::exit(1);
+            }
+        }
+        Command::Validate { config } => {
+            if !cli::validate::run(&config) {
+                std::process::exit(1);
+            }
+        }
+        Command::New {
+            name,
+            module_type,
+            config_dir,
+            force,
+        } => {
+            let module_type = module_type.parse::<ModuleType>()?;
+            let target = config_dir.join(&name);
+            if force
+                && target.exists()
+                && !confirmer.confirm(&format!("Overwrite {}?", target.display()), false)?
+            {
+                println!("Aborted");
+                return Ok(());
+            }
+            let dir = scaffold_module(
+                &config_dir,
+                &name,
+                module_type,
+                &VerificationConfig::default(),
+                force,
+            )?;
+            println!("Created {} module in {}", module_type, dir.display());
+        }
+        Command::Ingest {
+            repo_url,
+            git_ref,
+            default_branch,
+            db,
+            cache_dir,
+            no_cache,
+        } => {
+            let registry = open_registry(db, &data_dir).await?;
+            let cache = RepoCache::new(cache_dir.unwrap_or_else(RepoCache::default_dir))
+                .with_default_branch(default_branch);
+            let git_ref = git_ref.unwrap_or_default();
+            let ingested = ingest_module(
+                &registry,
+                &cache,
+                &ModuleVerifier::default(),
+                &repo_url,
+                &git_ref,
+                !no_cache,
+            )
+            .await?;
+            println!(
+                "{} module {} from {} at {} ({}){}",
+                if ingested.outcome.reused_cache {
+                    "Updated"
+                } else {
+                    "Cloned"
+                },
+                ingested.module.name,
+                repo_url,
+                ingested.source.git_ref,
+                ingested.source.commit,
+                if ingested.outcome.changed {
+                    ""
+                } else {
+                    ", unchanged"
+                }
+            );
+        }
+        Command::Export { db, out } => {
+            let registry = open_registry(db, &data_dir).await?;
+            let export = export(&registry).await?;
+            std::fs::write(&out, serde_json::to_string_pretty(&export)?)?;
+            println!(
+                "Exported {} module(s) to {}",
+                export.modules.len(),
+                out.display()
+            );
+        }
+        Command::Delete { name, db } => {
+            let registry = open_registry(db, &data_dir).await?;
+            if cli::delete::run(&registry, &name, confirmer).await? {
+                println!("Deleted module {}", name);
+            } else {
+                println!("Aborted");
+            }
+        }
+        Command::Import {
+            db,
+            input,
+            on_conflict,
+            dry_run,
+        } => {
+            let export: RegistryExport = serde_json::from_str(&std::fs::read_to_string(&input)?)?;
+            let registry = open_registry(db, &data_dir).await?;
+            if on_conflict == ConflictPolicy::Overwrite && !dry_run {
+                let planned = import(&registry, &export, on_conflict, true).await?;
+                if !planned.overwritten.is_empty()
+                    && !confirmer.confirm(
+                        &format!("Overwrite {}?", planned.overwritten.join(", ")),
+                        false,
+                    )?
+                {
+                    println!("Aborted");
+                    return Ok(());
+                }
+            }
+            let report = import(&registry, &export, on_conflict, dry_run).await?;
+            let prefix = if dry_run { "Would import" } else { "Imported" };
+            println!(
+                "{}: {} created, {} overwritten, {} skipped, {} unchanged",
+                prefix,
+                report.created.len(),
+                report.overwritten.len(),
+                report.skipped.len(),
+                report.unchanged.len()
+            );
+            for name in &report.skipped {
+                println!(
+                    "  skipped {} (already registered with different contents)",
+                    name
+                );
+            }
+        }
+        Command::Keys { keystore, command } => {
+            let keystore = Keystore::new(keystore.unwrap_or_else(Keystore::default_dir));
+            cli::keys::run(&keystore, command)?;
+        }
+        Command::Env {
+            env_dir,
+            profile,
+            command,
+        } => {
+            let store =
+                EnvStore::new(env_dir.unwrap_or_else(|| data_dir.env())).with_profile(profile)?;
+            cli::env::run(&store, command, &confirmer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the registry at `db`, or in the data directory when unset,
+/// creating the database and its directory if needed.
+async fn open_registry(
+    db: Option<PathBuf>,
+    data_dir: &DataDir,
+) -> Result<SqliteRegistry, Box<dyn std::error::Error>> {
+    let db = db.unwrap_or_else(|| data_dir.registry_db());
+    if let Some(parent) = db.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(SqliteRegistry::connect(&format!("sqlite://{}", db.display())).await?)
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("Shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_address_from_env() {
+        let bind = |args: &[&str]| match Cli::try_parse_from(args).map(|cli| cli.command) {
+            Ok(Command::Serve { bind, .. }) => Ok(bind),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        };
+
+        std::env::set_var("BIND_ADDR", "0.0.0.0:8080");
+        assert_eq!(
+            bind(&["registrar", "serve"]).unwrap(),
+            "0.0.0.0:8080".parse().unwrap()
+        );
+        // A flag takes precedence over the environment.
+        assert_eq!(
+            bind(&["registrar", "serve", "--bind", "[::1]:9000"]).unwrap(),
+            "[::1]:9000".parse().unwrap()
+        );
+
+        std::env::set_var("BIND_ADDR", "localhost");
+        assert!(bind(&["registrar", "serve"]).is_err());
+        std::env::remove_var("BIND_ADDR");
+        assert_eq!(
+            bind(&["registrar", "serve"]).unwrap(),
+            "127.0.0.1:3000".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_yes_flag_is_global() {
+        for args in [
+            ["registrar", "--yes", "delete", "echo"],
+            ["registrar", "delete", "echo", "--no-prompt"],
+        ] {
+            assert!(Cli::try_parse_from(args).unwrap().yes);
+        }
+        assert!(
+            !Cli::try_parse_from(["registrar", "delete", "echo"])
+                .unwrap()
+                .yes
+        );
+    }
+}
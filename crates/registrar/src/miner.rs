@@ -0,0 +1,58 @@
+//! Miners registered with the registrar.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A miner registered on the subnet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Miner {
+    /// Subnet uid of the miner.
+    pub uid: u16,
+    /// SS58 address of the miner's key.
+    pub key: String,
+    /// Display name.
+    pub name: String,
+}
+
+/// A miner as stored by the registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredMiner {
+    #[serde(flatten)]
+    pub miner: Miner,
+    pub registered_at: DateTime<Utc>,
+}
+
+/// What registering a miner did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Registration {
+    /// The uid was not registered before.
+    Created,
+    /// The uid was already registered with the same key; its name was
+    /// updated.
+    Updated,
+}
+
+/// How registering one miner of a batch turned out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterOutcome {
+    /// The miner was registered.
+    Registered,
+    /// The miner was already registered with the same key and was updated.
+    Updated,
+    /// The uid or key is already registered.
+    Conflict,
+    /// The key is not a valid SS58 address.
+    Invalid,
+    /// The registry failed.
+    Failed,
+}
+
+/// Result of registering one miner of a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterResult {
+    pub uid: u16,
+    pub outcome: RegisterOutcome,
+    pub error: Option<String>,
+}
@@ -0,0 +1,507 @@
+//! Module model types.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::port::{Port, Protocol};
+use crate::probe::ProbeConfig;
+
+/// A string that names no variant of the enum it was parsed as.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown {kind}: {value}")]
+pub struct UnknownVariant {
+    /// What was being parsed, e.g. `module type`.
+    pub kind: &'static str,
+    pub value: String,
+}
+
+/// The kind of module managed by the registrar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleType {
+    /// Module that runs as a Docker container.
+    Docker,
+    /// Module that runs as a local process.
+    Local,
+    /// Module that only observes the subnet and runs no workload.
+    Observer,
+}
+
+impl ModuleType {
+    /// Every module type.
+    pub const ALL: [ModuleType; 3] = [ModuleType::Docker, ModuleType::Local, ModuleType::Observer];
+}
+
+impl fmt::Display for ModuleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ModuleType::Docker => "docker",
+            ModuleType::Local => "local",
+            ModuleType::Observer => "observer",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for ModuleType {
+    type Err = UnknownVariant;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "docker" => Ok(ModuleType::Docker),
+            "local" => Ok(ModuleType::Local),
+            "observer" => Ok(ModuleType::Observer),
+            other => Err(UnknownVariant {
+                kind: "module type",
+                value: other.to_string(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<String> for ModuleType {
+    type Error = UnknownVariant;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Lifecycle status of a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleStatus {
+    /// The module is running.
+    Running,
+    /// The module is registered but not running.
+    Stopped,
+    /// The module failed to start or crashed.
+    Failed,
+    /// The module's container is being created or started.
+    Starting,
+    /// The module's container is being stopped.
+    Stopping,
+    /// The module no longer passes verification and may not be started
+    /// until it does.
+    Quarantined,
+}
+
+impl ModuleStatus {
+    /// Every module status.
+    pub const ALL: [ModuleStatus; 6] = [
+        ModuleStatus::Running,
+        ModuleStatus::Stopped,
+        ModuleStatus::Failed,
+        ModuleStatus::Starting,
+        ModuleStatus::Stopping,
+        ModuleStatus::Quarantined,
+    ];
+
+    /// Whether the module is part way through starting or stopping.
+    pub fn is_transitional(self) -> bool {
+        matches!(self, ModuleStatus::Starting | ModuleStatus::Stopping)
+    }
+
+    /// Whether a module may move from this status to `next`. A module
+    /// that is starting or stopping may only finish, fail, or, when
+    /// starting, be stopped; a quarantined module stays quarantined until
+    /// re-verification releases it; other settled modules may move
+    /// anywhere.
+    pub fn can_transition_to(self, next: ModuleStatus) -> bool {
+        match self {
+            ModuleStatus::Starting => matches!(
+                next,
+                ModuleStatus::Running | ModuleStatus::Failed | ModuleStatus::Stopping
+            ),
+            ModuleStatus::Stopping => {
+                matches!(next, ModuleStatus::Stopped | ModuleStatus::Failed)
+            }
+            ModuleStatus::Quarantined => next == ModuleStatus::Quarantined,
+            ModuleStatus::Running | ModuleStatus::Stopped | ModuleStatus::Failed => true,
+        }
+    }
+}
+
+impl fmt::Display for ModuleStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ModuleStatus::Running => "running",
+            ModuleStatus::Stopped => "stopped",
+            ModuleStatus::Failed => "failed",
+            ModuleStatus::Starting => "starting",
+            ModuleStatus::Stopping => "stopping",
+            ModuleStatus::Quarantined => "quarantined",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for ModuleStatus {
+    type Err = UnknownVariant;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "running" => Ok(ModuleStatus::Running),
+            "stopped" => Ok(ModuleStatus::Stopped),
+            "failed" => Ok(ModuleStatus::Failed),
+            "starting" => Ok(ModuleStatus::Starting),
+            "stopping" => Ok(ModuleStatus::Stopping),
+            "quarantined" => Ok(ModuleStatus::Quarantined),
+            other => Err(UnknownVariant {
+                kind: "module status",
+                value: other.to_string(),
+            }),
+        }
+    }
+}
+
+/// Container health check of a module, mirroring Docker's `HEALTHCHECK`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct HealthCheck {
+    /// Command to run, in Docker form, e.g.
+    /// `["CMD", "curl", "-f", "http://localhost:8080/health"]`.
+    pub test: Vec<String>,
+    /// Seconds between checks.
+    #[serde(default = "HealthCheck::default_interval_secs")]
+    pub interval_secs: u64,
+    /// Seconds before a single check is considered failed.
+    #[serde(default = "HealthCheck::default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Consecutive failures before the container is unhealthy.
+    #[serde(default = "HealthCheck::default_retries")]
+    pub retries: u32,
+}
+
+impl HealthCheck {
+    fn default_interval_secs() -> u64 {
+        30
+    }
+
+    fn default_timeout_secs() -> u64 {
+        5
+    }
+
+    fn default_retries() -> u32 {
+        3
+    }
+}
+
+/// Template for the health check given to modules that configure none:
+/// an HTTP GET of `path` on the module's first port.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefaultHealthCheck {
+    /// Path probed on the module, e.g. `/health`.
+    pub path: String,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+    pub retries: u32,
+}
+
+impl Default for DefaultHealthCheck {
+    fn default() -> Self {
+        Self {
+            path: "/health".to_string(),
+            interval_secs: HealthCheck::default_interval_secs(),
+            timeout_secs: HealthCheck::default_timeout_secs(),
+            retries: HealthCheck::default_retries(),
+        }
+    }
+}
+
+impl DefaultHealthCheck {
+    /// The health check probing `port`, given as `<number>[/protocol]`.
+    /// Returns `None` when the port number cannot be parsed.
+    pub fn for_port(&self, port: &str) -> Option<HealthCheck> {
+        let number = port.parse::<Port>().ok()?.number;
+        Some(HealthCheck {
+            test: vec![
+                "CMD".to_string(),
+                "curl".to_string(),
+                "-f".to_string(),
+                format!("http://localhost:{}{}", number, self.path),
+            ],
+            interval_secs: self.interval_secs,
+            timeout_secs: self.timeout_secs,
+            retries: self.retries,
+        })
+    }
+}
+
+/// Health checks injected per module type into modules whose config has
+/// none. By default only Docker modules get one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheckDefaults {
+    by_type: HashMap<ModuleType, DefaultHealthCheck>,
+}
+
+impl Default for HealthCheckDefaults {
+    fn default() -> Self {
+        Self::none().with_default(ModuleType::Docker, DefaultHealthCheck::default())
+    }
+}
+
+impl HealthCheckDefaults {
+    /// Defaults that inject nothing.
+    pub fn none() -> Self {
+        Self {
+            by_type: HashMap::new(),
+        }
+    }
+
+    /// Sets the health check injected into modules of `module_type`.
+    pub fn with_default(mut self, module_type: ModuleType, check: DefaultHealthCheck) -> Self {
+        self.by_type.insert(module_type, check);
+        self
+    }
+
+    /// Stops injecting a health check into modules of `module_type`.
+    pub fn without_default(mut self, module_type: ModuleType) -> Self {
+        self.by_type.remove(&module_type);
+        self
+    }
+
+    /// Gives `config` the default health check for `module_type` if it has
+    /// no health check and exposes a port. Returns whether one was added.
+    pub fn apply(&self, module_type: ModuleType, config: &mut ModuleConfig) -> bool {
+        if config.health_check.is_some() {
+            return false;
+        }
+        let check = self
+            .by_type
+            .get(&module_type)
+            .and_then(|default| default.for_port(config.ports.first()?));
+        let added = check.is_some();
+        config.health_check = check;
+        added
+    }
+}
+
+/// Configuration of a module.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ModuleConfig {
+    /// Names of modules that must be started before this one.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Container image for Docker modules.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Environment variables passed to the module.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Ports the module exposes, e.g. `"8080/tcp"`.
+    #[serde(default)]
+    pub ports: Vec<String>,
+    /// Container health check.
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    /// Application-level readiness probe, run by validators against the
+    /// module itself rather than through Docker.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe: Option<ProbeConfig>,
+    /// What the module can do, e.g. `"streaming"`, so schedulers can find
+    /// compatible modules with `GET /modules?capability=`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+}
+
+/// Separates a module's namespace from its base name, as in `team-a/echo`.
+pub const NAMESPACE_SEPARATOR: char = '/';
+
+/// Namespace of modules whose name has no namespace prefix.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Splits a module name into its namespace and base name. Names without a
+/// prefix are in [`DEFAULT_NAMESPACE`].
+pub fn split_name(name: &str) -> (&str, &str) {
+    name.split_once(NAMESPACE_SEPARATOR)
+        .unwrap_or((DEFAULT_NAMESPACE, name))
+}
+
+/// Joins a namespace and base name into a module name. Names in
+/// [`DEFAULT_NAMESPACE`] are stored without a prefix, so `default/echo`
+/// and `echo` name the same module.
+pub fn qualified_name(namespace: &str, name: &str) -> String {
+    if namespace == DEFAULT_NAMESPACE {
+        name.to_string()
+    } else {
+        format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name)
+    }
+}
+
+/// Writes a module name the way the registry stores it, dropping an
+/// explicit `default/` prefix.
+pub fn normalize_name(name: &str) -> String {
+    let (namespace, base) = split_name(name);
+    qualified_name(namespace, base)
+}
+
+/// Module name without '/', for use as a file name or a Docker container
+/// name. The namespace separator becomes '.', which module names never
+/// contain, so distinct modules keep distinct names.
+pub fn dotted_name(name: &str) -> String {
+    name.replace(NAMESPACE_SEPARATOR, ".")
+}
+
+/// A module registered with the registrar.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Module {
+    /// Unique module name.
+    pub name: String,
+    /// Module type.
+    pub module_type: ModuleType,
+    /// Current status.
+    pub status: ModuleStatus,
+    /// Module configuration.
+    #[serde(default)]
+    pub config: ModuleConfig,
+    /// Free-form labels used to group modules, e.g. `team-a`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Public key that created the module, when authorization was enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl Module {
+    /// Creates a new, stopped module.
+    pub fn new(name: impl Into<String>, module_type: ModuleType) -> Self {
+        Self {
+            name: name.into(),
+            module_type,
+            status: ModuleStatus::Stopped,
+            config: ModuleConfig::default(),
+            tags: Vec::new(),
+            owner: None,
+        }
+    }
+
+    /// Sets the module configuration.
+    pub fn with_config(mut self, config: ModuleConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the module's tags.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Sets the key that owns the module.
+    pub fn with_owner(mut self, owner: Option<String>) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    /// Namespace the module is registered in.
+    pub fn namespace(&self) -> &str {
+        split_name(&self.name).0
+    }
+
+    /// Module name without its namespace prefix.
+    pub fn base_name(&self) -> &str {
+        split_name(&self.name).1
+    }
+
+    /// The module's first TCP port, e.g. `8080` for `"8080/tcp"`.
+    pub fn tcp_port(&self) -> Option<u16> {
+        self.config
+            .ports
+            .iter()
+            .filter_map(|port| port.parse::<Port>().ok())
+            .find(|port| port.protocol == Protocol::Tcp)
+            .map(|port| port.number)
+    }
+}
+
+/// Where a module's code was ingested from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ModuleSource {
+    /// Git URL of the module repository.
+    pub repo_url: String,
+    /// Branch, tag or commit requested at ingest.
+    pub git_ref: String,
+    /// Commit sha the requested ref resolved to, or the SHA-256 of the
+    /// archive for a module ingested from an upload.
+    pub commit: String,
+    /// Local checkout of the resolved commit.
+    pub path: String,
+}
+
+/// Registry bookkeeping about a module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleMetadata {
+    pub name: String,
+    pub module_type: ModuleType,
+    /// Source the module was ingested from, if it was ingested.
+    pub source: Option<ModuleSource>,
+    /// Number of times the module's package has been served.
+    pub downloads: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that every variant parses back from its display form, and
+    /// that the display form matches the serde name.
+    fn assert_round_trip<T>(variants: &[T])
+    where
+        T: FromStr + fmt::Display + Serialize + PartialEq + fmt::Debug,
+        T::Err: fmt::Debug,
+    {
+        for variant in variants {
+            let shown = variant.to_string();
+            assert_eq!(&shown.parse::<T>().unwrap(), variant, "{}", shown);
+            assert_eq!(
+                serde_json::to_value(variant).unwrap(),
+                serde_json::Value::String(shown)
+            );
+        }
+    }
+
+    #[test]
+    fn test_enums_round_trip_through_display() {
+        // Adding a variant breaks these matches until it is listed in ALL.
+        for module_type in ModuleType::ALL {
+            match module_type {
+                ModuleType::Docker | ModuleType::Local | ModuleType::Observer => {}
+            }
+        }
+        for status in ModuleStatus::ALL {
+            match status {
+                ModuleStatus::Running
+                | ModuleStatus::Stopped
+                | ModuleStatus::Failed
+                | ModuleStatus::Starting
+                | ModuleStatus::Stopping
+                | ModuleStatus::Quarantined => {}
+            }
+        }
+        assert_round_trip(&ModuleType::ALL);
+        assert_round_trip(&ModuleStatus::ALL);
+    }
+
+    #[test]
+    fn test_unknown_module_type_is_an_error() {
+        assert_eq!(
+            ModuleType::try_from("dokcer".to_string()),
+            Err(UnknownVariant {
+                kind: "module type",
+                value: "dokcer".into(),
+            })
+        );
+        assert_eq!(
+            "Paused".parse::<ModuleStatus>().unwrap_err().to_string(),
+            "unknown module status: paused"
+        );
+    }
+}
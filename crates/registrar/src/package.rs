@@ -0,0 +1,222 @@
+//! Installation packages built from ingested module sources.
+
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::module::{Module, ModuleSource, ModuleType};
+
+/// Name of the installer script included in every package.
+pub const INSTALLER: &str = "install.sh";
+
+/// Environment variable that overrides where an installer installs to.
+pub const MODULES_DIR_ENV: &str = "SYNAPSE_MODULES_DIR";
+
+/// Directory modules are installed into when [`MODULES_DIR_ENV`] is unset.
+pub const DEFAULT_MODULES_DIR: &str = "$HOME/.synapsis/modules";
+
+/// Errors produced while building a package.
+#[derive(Debug, Error)]
+pub enum PackageError {
+    /// The archive could not be created.
+    #[error("Failed to archive {name}: {reason}")]
+    Archive { name: String, reason: String },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Description of a package, sufficient to reproduce it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageManifest {
+    pub name: String,
+    pub module_type: ModuleType,
+    /// Short form of the commit the package was built from.
+    pub version: String,
+    pub repo_url: String,
+    /// Ref requested when the module was ingested.
+    pub git_ref: String,
+    /// Commit the package was built from.
+    pub commit: String,
+    /// Hex-encoded SHA-256 of the archive.
+    pub sha256: String,
+    /// Archive size in bytes.
+    pub size: u64,
+}
+
+/// A gzipped tarball of a module's source plus its manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallationPackage {
+    pub manifest: PackageManifest,
+    pub archive: Vec<u8>,
+}
+
+/// Copies a module checkout, leaving out `.git` and symlinks. Following a
+/// symlink such as `secrets -> /etc/shadow` would put host files in the
+/// served package.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            tracing::warn!(
+                "Leaving symlink {} out of the package",
+                entry.path().display()
+            );
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Generates the installer for `module`. The script works in
+/// `<modules_dir>/<name>`, where `$SYNAPSE_MODULES_DIR` takes precedence
+/// over `modules_dir` when set at install time.
+pub fn generate_installer_script(module: &Module, modules_dir: &str) -> String {
+    let body = match module.module_type {
+        ModuleType::Docker => format!(
+            "docker build -t {} .\n",
+            module.config.image.as_deref().unwrap_or(&module.name)
+        ),
+        _ => "if [ -f requirements.txt ]; then\n    pip install -r requirements.txt\nfi\n"
+            .to_string(),
+    };
+    format!(
+        "#!/usr/bin/env bash\nset -euo pipefail\ncd \"${{{}:-{}}}/{}\"\n{}",
+        MODULES_DIR_ENV, modules_dir, module.name, body
+    )
+}
+
+/// Packages the checkout at `source.path` together with a generated
+/// installer. Blocks while copying and archiving.
+pub fn build_package(
+    module: &Module,
+    source: &ModuleSource,
+) -> Result<InstallationPackage, PackageError> {
+    let staging = tempfile::tempdir()?;
+    let root = staging.path().join("module");
+    copy_dir(Path::new(&source.path), &root)?;
+    std::fs::write(
+        root.join(INSTALLER),
+        generate_installer_script(module, DEFAULT_MODULES_DIR),
+    )?;
+
+    let archive_path = staging.path().join("package.tar.gz");
+    let output = Command::new("tar")
+        .arg("czf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(&root)
+        .arg(".")
+        .output()?;
+    if !output.status.success() {
+        return Err(PackageError::Archive {
+            name: module.name.clone(),
+            reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    let archive = std::fs::read(&archive_path)?;
+
+    let manifest = PackageManifest {
+        name: module.name.clone(),
+        module_type: module.module_type,
+        version: source.commit.chars().take(12).collect(),
+        repo_url: source.repo_url.clone(),
+        git_ref: source.git_ref.clone(),
+        commit: source.commit.clone(),
+        sha256: hex::encode(Sha256::digest(&archive)),
+        size: archive.len() as u64,
+    };
+    Ok(InstallationPackage { manifest, archive })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_pins_commit_and_hashes_archive() {
+        let checkout = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(checkout.path().join(".git")).unwrap();
+        std::fs::create_dir_all(checkout.path().join("src")).unwrap();
+        std::fs::write(checkout.path().join("src/main.py"), "print()").unwrap();
+
+        let module = Module::new("echo", ModuleType::Local);
+        let source = ModuleSource {
+            repo_url: "https://example.com/echo.git".into(),
+            git_ref: "v1.0".into(),
+            commit: "0123456789abcdef0123456789abcdef01234567".into(),
+            path: checkout.path().display().to_string(),
+        };
+
+        let package = build_package(&module, &source).unwrap();
+        assert_eq!(package.manifest.commit, source.commit);
+        assert_eq!(package.manifest.version, "0123456789ab");
+        assert_eq!(package.manifest.git_ref, "v1.0");
+        assert_eq!(
+            package.manifest.sha256,
+            hex::encode(Sha256::digest(&package.archive))
+        );
+
+        let unpacked = tempfile::tempdir().unwrap();
+        let archive = unpacked.path().join("p.tar.gz");
+        std::fs::write(&archive, &package.archive).unwrap();
+        let status = Command::new("tar")
+            .arg("xzf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(unpacked.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(unpacked.path().join("src/main.py").is_file());
+        assert!(unpacked.path().join(INSTALLER).is_file());
+        assert!(!unpacked.path().join(".git").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_left_out_of_package() {
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret"), "hunter2").unwrap();
+        let checkout = tempfile::tempdir().unwrap();
+        std::fs::write(checkout.path().join("main.py"), "print()").unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("secret"),
+            checkout.path().join("secret"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(outside.path(), checkout.path().join("host")).unwrap();
+
+        let packaged = tempfile::tempdir().unwrap();
+        copy_dir(checkout.path(), packaged.path()).unwrap();
+        assert!(packaged.path().join("main.py").is_file());
+        assert!(!packaged.path().join("secret").exists());
+        assert!(!packaged.path().join("host").exists());
+    }
+
+    #[test]
+    fn test_installer_uses_overridden_modules_dir() {
+        let module = Module::new("echo", ModuleType::Local);
+
+        let default = generate_installer_script(&module, DEFAULT_MODULES_DIR);
+        assert!(default.contains("cd \"${SYNAPSE_MODULES_DIR:-$HOME/.synapsis/modules}/echo\""));
+
+        let script = generate_installer_script(&module, "/srv/tenant-a/modules");
+        assert!(script.contains("cd \"${SYNAPSE_MODULES_DIR:-/srv/tenant-a/modules}/echo\""));
+        assert!(!script.contains(".synapsis"));
+    }
+}
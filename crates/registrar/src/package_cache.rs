@@ -0,0 +1,369 @@
+//! On-disk cache of built installation packages.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::module::{dotted_name, Module, ModuleSource};
+use crate::package::{build_package, InstallationPackage, PackageError, PackageManifest};
+
+/// Default size cap of the package cache, 512 MiB.
+pub const DEFAULT_PACKAGE_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Counters describing cache effectiveness.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageCacheStats {
+    /// Fetches served from the cache.
+    pub hits: u64,
+    /// Fetches that had to build the package.
+    pub misses: u64,
+    /// Packages currently cached.
+    pub entries: u64,
+    /// Total size of cached archives.
+    pub bytes: u64,
+}
+
+struct Entry {
+    name: String,
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, Entry>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl CacheState {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn bytes(&self) -> u64 {
+        self.entries.values().map(|e| e.size).sum()
+    }
+}
+
+/// Caches package archives on disk keyed by module name, version and a hash
+/// of the source contents, so unchanged modules are not re-archived. The
+/// least recently used packages are evicted once the cache exceeds its size
+/// cap. Operations block on disk I/O.
+#[derive(Clone)]
+pub struct PackageCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    state: Arc<Mutex<CacheState>>,
+}
+
+/// Hashes the files a package would be built from, skipping `.git`, along
+/// with the module settings that shape the generated installer.
+pub fn content_hash(module: &Module, source: &Path) -> Result<String, PackageError> {
+    fn visit(root: &Path, dir: &Path, hasher: &mut Sha256) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            // Symlinks are left out of packages, so they do not change them.
+            let file_type = entry.file_type()?;
+            if entry.file_name() == ".git" || file_type.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            if file_type.is_dir() {
+                visit(root, &path, hasher)?;
+            } else {
+                let mut file = std::fs::File::open(&path)?;
+                let mut buffer = Vec::new();
+                file.read_to_end(&mut buffer)?;
+                hasher.update((buffer.len() as u64).to_le_bytes());
+                hasher.update(&buffer);
+            }
+        }
+        Ok(())
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(module.module_type.to_string().as_bytes());
+    hasher.update(
+        module
+            .config
+            .image
+            .as_deref()
+            .unwrap_or_default()
+            .as_bytes(),
+    );
+    hasher.update([0]);
+    visit(source, source, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+impl PackageCache {
+    /// Opens the cache in `dir`, picking up packages cached by earlier runs.
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Result<Self, PackageError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut state = CacheState::default();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(key) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let manifest: Option<PackageManifest> = std::fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+            let archive = dir.join(format!("{}.tar.gz", key));
+            match manifest {
+                Some(manifest) if archive.is_file() => {
+                    let last_used = state.tick();
+                    state.entries.insert(
+                        key.to_string(),
+                        Entry {
+                            name: manifest.name,
+                            size: manifest.size,
+                            last_used,
+                        },
+                    );
+                }
+                _ => {
+                    let _ = std::fs::remove_file(&path);
+                    let _ = std::fs::remove_file(&archive);
+                }
+            }
+        }
+
+        Ok(Self {
+            dir,
+            max_bytes,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    fn manifest_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    fn archive_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.tar.gz", key))
+    }
+
+    fn remove_files(&self, key: &str) {
+        let _ = std::fs::remove_file(self.manifest_path(key));
+        let _ = std::fs::remove_file(self.archive_path(key));
+    }
+
+    fn read(&self, key: &str) -> Option<InstallationPackage> {
+        let manifest =
+            serde_json::from_slice(&std::fs::read(self.manifest_path(key)).ok()?).ok()?;
+        let archive = std::fs::read(self.archive_path(key)).ok()?;
+        Some(InstallationPackage { manifest, archive })
+    }
+
+    /// Returns the cached package for the module's current source, building
+    /// and caching it if the source changed or was never packaged.
+    pub fn get_or_build(
+        &self,
+        module: &Module,
+        source: &ModuleSource,
+    ) -> Result<InstallationPackage, PackageError> {
+        let hash = content_hash(module, Path::new(&source.path))?;
+        let version: String = source.commit.chars().take(12).collect();
+        let key = format!("{}-{}-{}", dotted_name(&module.name), version, &hash[..16]);
+
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.entries.contains_key(&key) {
+                if let Some(package) = self.read(&key) {
+                    let now = state.tick();
+                    state.entries.get_mut(&key).unwrap().last_used = now;
+                    state.hits += 1;
+                    return Ok(package);
+                }
+                state.entries.remove(&key);
+            }
+            state.misses += 1;
+        }
+
+        let package = build_package(module, source)?;
+        let manifest = serde_json::to_vec(&package.manifest)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(self.archive_path(&key), &package.archive)?;
+        std::fs::write(self.manifest_path(&key), manifest)?;
+
+        let mut state = self.state.lock().unwrap();
+        // Older builds of the same module are stale once its source changes.
+        let stale: Vec<String> = state
+            .entries
+            .iter()
+            .filter(|(k, e)| e.name == module.name && **k != key)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for stale_key in stale {
+            state.entries.remove(&stale_key);
+            self.remove_files(&stale_key);
+        }
+        let last_used = state.tick();
+        state.entries.insert(
+            key.clone(),
+            Entry {
+                name: module.name.clone(),
+                size: package.manifest.size,
+                last_used,
+            },
+        );
+        while state.bytes() > self.max_bytes && state.entries.len() > 1 {
+            let oldest = state
+                .entries
+                .iter()
+                .filter(|(k, _)| **k != key)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(oldest) => {
+                    tracing::debug!("Evicting cached package {}", oldest);
+                    state.entries.remove(&oldest);
+                    self.remove_files(&oldest);
+                }
+                None => break,
+            }
+        }
+        Ok(package)
+    }
+
+    /// Drops every cached package of the named module.
+    pub fn invalidate(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        let keys: Vec<String> = state
+            .entries
+            .iter()
+            .filter(|(_, e)| e.name == name)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in keys {
+            state.entries.remove(&key);
+            self.remove_files(&key);
+        }
+    }
+
+    /// Returns hit, miss and size counters.
+    pub fn stats(&self) -> PackageCacheStats {
+        let state = self.state.lock().unwrap();
+        PackageCacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.entries.len() as u64,
+            bytes: state.bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::ModuleType;
+
+    fn checkout(files: &[(&str, &str)]) -> (tempfile::TempDir, ModuleSource) {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        }
+        let source = ModuleSource {
+            repo_url: "https://example.com/m.git".into(),
+            git_ref: "main".into(),
+            commit: "0123456789abcdef".into(),
+            path: dir.path().display().to_string(),
+        };
+        (dir, source)
+    }
+
+    #[test]
+    fn test_unchanged_source_builds_once() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = PackageCache::new(cache_dir.path(), DEFAULT_PACKAGE_CACHE_BYTES).unwrap();
+        let module = Module::new("echo", ModuleType::Local);
+        let (_checkout, source) = checkout(&[("main.py", "print()")]);
+
+        let first = cache.get_or_build(&module, &source).unwrap();
+        let second = cache.get_or_build(&module, &source).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.stats().hits, 1);
+
+        // Changing the source rebuilds and replaces the stale entry.
+        std::fs::write(Path::new(&source.path).join("main.py"), "print(1)").unwrap();
+        cache.get_or_build(&module, &source).unwrap();
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(cache.stats().entries, 1);
+
+        // A reopened cache still serves the package.
+        let reopened = PackageCache::new(cache_dir.path(), DEFAULT_PACKAGE_CACHE_BYTES).unwrap();
+        reopened.get_or_build(&module, &source).unwrap();
+        assert_eq!(reopened.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_namespaced_module_is_cached() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = PackageCache::new(cache_dir.path(), DEFAULT_PACKAGE_CACHE_BYTES).unwrap();
+        let module = Module::new("team-a/echo", ModuleType::Local);
+        let (_checkout, source) = checkout(&[("main.py", "print()")]);
+
+        cache.get_or_build(&module, &source).unwrap();
+        cache.get_or_build(&module, &source).unwrap();
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted_over_cap() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (_a_dir, a_source) = checkout(&[("a.py", "a")]);
+        let (_b_dir, b_source) = checkout(&[("b.py", "b")]);
+        let (_c_dir, c_source) = checkout(&[("c.py", "c")]);
+        let a = Module::new("a", ModuleType::Local);
+        let b = Module::new("b", ModuleType::Local);
+        let c = Module::new("c", ModuleType::Local);
+
+        let probe_dir = tempfile::tempdir().unwrap();
+        let probe = PackageCache::new(probe_dir.path(), u64::MAX).unwrap();
+        let size = probe.get_or_build(&a, &a_source).unwrap().manifest.size;
+
+        // Room for roughly two packages.
+        let cache = PackageCache::new(cache_dir.path(), size * 2 + size / 2).unwrap();
+        cache.get_or_build(&a, &a_source).unwrap();
+        cache.get_or_build(&b, &b_source).unwrap();
+        cache.get_or_build(&a, &a_source).unwrap();
+        cache.get_or_build(&c, &c_source).unwrap();
+
+        assert_eq!(cache.stats().entries, 2);
+        cache.get_or_build(&a, &a_source).unwrap();
+        assert_eq!(cache.stats().hits, 2, "a should have survived eviction");
+        cache.get_or_build(&b, &b_source).unwrap();
+        assert_eq!(cache.stats().misses, 4, "b should have been evicted");
+    }
+
+    #[test]
+    fn test_invalidate_drops_module_entries() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = PackageCache::new(cache_dir.path(), DEFAULT_PACKAGE_CACHE_BYTES).unwrap();
+        let module = Module::new("echo", ModuleType::Local);
+        let (_checkout, source) = checkout(&[("main.py", "print()")]);
+
+        cache.get_or_build(&module, &source).unwrap();
+        cache.invalidate("echo");
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(std::fs::read_dir(cache_dir.path()).unwrap().count(), 0);
+    }
+}
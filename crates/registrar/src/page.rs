@@ -0,0 +1,73 @@
+//! The envelope shared by paginated API responses.
+
+use serde::{Deserialize, Serialize};
+
+/// One page of a longer list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of items matching the request across all pages.
+    pub total: u64,
+    /// One-based index of this page.
+    pub page: u32,
+    /// Largest number of items a page holds.
+    pub per_page: u32,
+    /// Cursor for the next page, if more items match.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: u64, page: u32, per_page: u32) -> Self {
+        Self {
+            items,
+            total,
+            page,
+            per_page,
+            next_cursor: None,
+        }
+    }
+
+    /// Sets the cursor for the next page.
+    pub fn with_next_cursor(mut self, cursor: Option<String>) -> Self {
+        self.next_cursor = cursor;
+        self
+    }
+
+    /// Converts the items, keeping the page position.
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            page: self.page,
+            per_page: self.per_page,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::{Module, ModuleType};
+    use serde_json::json;
+
+    #[test]
+    fn test_page_of_modules_serializes_to_envelope() {
+        let module = Module::new("echo", ModuleType::Docker);
+        let page = Page::new(vec![module.clone()], 3, 1, 1).with_next_cursor(Some("echo".into()));
+
+        assert_eq!(
+            serde_json::to_value(&page).unwrap(),
+            json!({
+                "items": [serde_json::to_value(&module).unwrap()],
+                "total": 3,
+                "page": 1,
+                "per_page": 1,
+                "next_cursor": "echo",
+            })
+        );
+        let parsed: Page<Module> =
+            serde_json::from_value(serde_json::to_value(&page).unwrap()).unwrap();
+        assert_eq!(parsed, page);
+    }
+}
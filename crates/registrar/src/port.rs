@@ -0,0 +1,129 @@
+//! Container ports such as `8080/tcp`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Transport protocol of a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        })
+    }
+}
+
+/// A port that is not of the form `<1-65535>[/tcp|/udp]`.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("Invalid port {0:?}: expected <1-65535>[/tcp|/udp]")]
+pub struct PortParseError(pub String);
+
+/// A port number and its protocol, written `80/tcp`. Serialized as that
+/// string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Port {
+    pub number: u16,
+    pub protocol: Protocol,
+}
+
+impl Port {
+    pub fn tcp(number: u16) -> Self {
+        Self {
+            number,
+            protocol: Protocol::Tcp,
+        }
+    }
+
+    pub fn udp(number: u16) -> Self {
+        Self {
+            number,
+            protocol: Protocol::Udp,
+        }
+    }
+
+    /// Whether binding the port needs elevated privileges on most hosts.
+    pub fn is_privileged(&self) -> bool {
+        self.number < 1024
+    }
+}
+
+impl FromStr for Port {
+    type Err = PortParseError;
+
+    /// Parses `<number>[/tcp|/udp]`; a bare number means TCP.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PortParseError(s.to_string());
+        let (number, protocol) = match s.split_once('/') {
+            Some((number, "tcp")) => (number, Protocol::Tcp),
+            Some((number, "udp")) => (number, Protocol::Udp),
+            Some(_) => return Err(invalid()),
+            None => (s, Protocol::Tcp),
+        };
+        match number.parse::<u16>() {
+            Ok(number) if number > 0 => Ok(Self { number, protocol }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.number, self.protocol)
+    }
+}
+
+impl TryFrom<String> for Port {
+    type Error = PortParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Port> for String {
+    fn from(port: Port) -> Self {
+        port.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ports_round_trip() {
+        for (s, port) in [("80/tcp", Port::tcp(80)), ("53/udp", Port::udp(53))] {
+            assert_eq!(s.parse::<Port>(), Ok(port));
+            assert_eq!(port.to_string(), s);
+            assert_eq!(serde_json::to_string(&port).unwrap(), format!("\"{}\"", s));
+            assert_eq!(
+                serde_json::from_str::<Port>(&format!("\"{}\"", s)).unwrap(),
+                port
+            );
+        }
+        assert_eq!("8080".parse::<Port>(), Ok(Port::tcp(8080)));
+    }
+
+    #[test]
+    fn test_invalid_ports_rejected() {
+        for s in ["0", "65536/tcp", "80/sctp", "http", "80/"] {
+            assert_eq!(
+                s.parse::<Port>(),
+                Err(PortParseError(s.to_string())),
+                "{}",
+                s
+            );
+        }
+    }
+}
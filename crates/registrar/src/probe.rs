@@ -0,0 +1,203 @@
+//! Application-level readiness probes.
+//!
+//! A container can be running, and even pass its Docker health check,
+//! before the module inside is ready to serve. A probe asks the module
+//! itself, over HTTP or by connecting to its port.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::net::TcpStream;
+
+/// Default time a probe may take.
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Why a probe failed.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ProbeError {
+    #[error("Probe timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// The module refused the connection or the request failed.
+    #[error("Module unreachable: {0}")]
+    Unreachable(String),
+
+    #[error("Expected HTTP status {expected}, got {actual}")]
+    UnexpectedStatus { expected: u16, actual: u16 },
+}
+
+/// Checks whether a module is ready to serve.
+#[async_trait]
+pub trait HealthProbe: Send + Sync {
+    /// Probes the module listening at `host:port`.
+    async fn probe(&self, host: &str, port: u16) -> Result<(), ProbeError>;
+}
+
+/// Ready when `GET <path>` answers with `expected_status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpProbe {
+    pub path: String,
+    pub expected_status: u16,
+    pub timeout: Duration,
+}
+
+#[async_trait]
+impl HealthProbe for HttpProbe {
+    async fn probe(&self, host: &str, port: u16) -> Result<(), ProbeError> {
+        let url = format!("http://{}:{}{}", host, port, self.path);
+        let response = reqwest::Client::new()
+            .get(&url)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    ProbeError::Timeout(self.timeout)
+                } else {
+                    ProbeError::Unreachable(e.to_string())
+                }
+            })?;
+        let actual = response.status().as_u16();
+        if actual != self.expected_status {
+            return Err(ProbeError::UnexpectedStatus {
+                expected: self.expected_status,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Ready when a TCP connection to the module's port succeeds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpProbe {
+    pub timeout: Duration,
+}
+
+#[async_trait]
+impl HealthProbe for TcpProbe {
+    async fn probe(&self, host: &str, port: u16) -> Result<(), ProbeError> {
+        match tokio::time::timeout(self.timeout, TcpStream::connect((host, port))).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(ProbeError::Unreachable(e.to_string())),
+            Err(_) => Err(ProbeError::Timeout(self.timeout)),
+        }
+    }
+}
+
+/// Probe declared in a module config, separate from the container's
+/// Docker health check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ProbeConfig {
+    /// `GET` a path on the module's first port.
+    Http {
+        path: String,
+        /// Status a ready module answers with.
+        #[serde(default = "ProbeConfig::default_expected_status")]
+        expected_status: u16,
+        #[serde(default = "ProbeConfig::default_timeout_secs")]
+        timeout_secs: u64,
+    },
+    /// Connect to the module's first port.
+    Tcp {
+        #[serde(default = "ProbeConfig::default_timeout_secs")]
+        timeout_secs: u64,
+    },
+}
+
+impl ProbeConfig {
+    fn default_expected_status() -> u16 {
+        200
+    }
+
+    fn default_timeout_secs() -> u64 {
+        DEFAULT_PROBE_TIMEOUT.as_secs()
+    }
+
+    /// Builds the probe this config describes.
+    pub fn build(&self) -> Box<dyn HealthProbe> {
+        match self {
+            ProbeConfig::Http {
+                path,
+                expected_status,
+                timeout_secs,
+            } => Box::new(HttpProbe {
+                path: path.clone(),
+                expected_status: *expected_status,
+                timeout: Duration::from_secs(*timeout_secs),
+            }),
+            ProbeConfig::Tcp { timeout_secs } => Box::new(TcpProbe {
+                timeout: Duration::from_secs(*timeout_secs),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+
+    use super::*;
+
+    async fn serve() -> u16 {
+        let app = Router::new()
+            .route("/health", get(|| async { StatusCode::OK }))
+            .route("/broken", get(|| async { StatusCode::SERVICE_UNAVAILABLE }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_http_probe_passes_on_expected_status() {
+        let port = serve().await;
+        let config: ProbeConfig =
+            serde_json::from_str(r#"{"type": "http", "path": "/health"}"#).unwrap();
+
+        assert_eq!(config.build().probe("127.0.0.1", port).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_http_probe_fails_on_other_status_or_closed_port() {
+        let port = serve().await;
+        let probe = HttpProbe {
+            path: "/broken".into(),
+            expected_status: 200,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+        };
+
+        assert_eq!(
+            probe.probe("127.0.0.1", port).await,
+            Err(ProbeError::UnexpectedStatus {
+                expected: 200,
+                actual: 503,
+            })
+        );
+
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        assert!(matches!(
+            probe.probe("127.0.0.1", closed).await,
+            Err(ProbeError::Unreachable(_))
+        ));
+        assert!(matches!(
+            TcpProbe {
+                timeout: DEFAULT_PROBE_TIMEOUT
+            }
+            .probe("127.0.0.1", closed)
+            .await,
+            Err(ProbeError::Unreachable(_))
+        ));
+    }
+}
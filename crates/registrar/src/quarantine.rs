@@ -0,0 +1,131 @@
+//! Re-verifying registered modules, for when the verification policy
+//! changes after they were registered.
+
+use crate::config::ModuleDefinition;
+use crate::error::RegistryError;
+use crate::module::{Module, ModuleStatus};
+use crate::registry::Registry;
+use crate::verify::{ModuleVerifier, VerificationError};
+
+/// Outcome of re-verifying every registered module.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuarantineReport {
+    /// Modules quarantined by this run, with the problems found.
+    pub quarantined: Vec<(String, Vec<VerificationError>)>,
+    /// Modules that were already quarantined and still fail verification.
+    pub still_quarantined: Vec<String>,
+    /// Quarantined modules that pass verification again and were released
+    /// as stopped.
+    pub released: Vec<String>,
+    /// Number of modules that passed verification.
+    pub compliant: usize,
+}
+
+fn definition(module: &Module) -> ModuleDefinition {
+    ModuleDefinition {
+        name: module.name.clone(),
+        module_type: module.module_type,
+        config: module.config.clone(),
+    }
+}
+
+/// Verifies every registered module against `verifier`. Modules that fail
+/// are quarantined, which keeps them listed but prevents them from being
+/// started; quarantined modules that pass again are released. A running
+/// module's container is left running.
+pub async fn verify_registered(
+    registry: &dyn Registry,
+    verifier: &ModuleVerifier,
+) -> Result<QuarantineReport, RegistryError> {
+    let mut report = QuarantineReport::default();
+    for module in registry.list_modules().await? {
+        let errors = verifier.verify_all(&definition(&module));
+        let quarantined = module.status == ModuleStatus::Quarantined;
+        match (errors.is_empty(), quarantined) {
+            (true, false) => report.compliant += 1,
+            (true, true) => {
+                registry
+                    .update_module_status(&module.name, ModuleStatus::Stopped)
+                    .await?;
+                report.compliant += 1;
+                report.released.push(module.name);
+            }
+            (false, true) => report.still_quarantined.push(module.name),
+            (false, false) => {
+                registry
+                    .update_module_status(&module.name, ModuleStatus::Quarantined)
+                    .await?;
+                report.quarantined.push((module.name, errors));
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::module::{ModuleConfig, ModuleType};
+    use crate::registry::SqliteRegistry;
+    use crate::verify::VerificationConfig;
+
+    fn module(name: &str, image: &str) -> Module {
+        Module::new(name, ModuleType::Docker).with_config(ModuleConfig {
+            image: Some(image.into()),
+            env: BTreeMap::from([("MODULE_PORT".into(), "8080".into())]),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_tightened_registry_policy_quarantines_module() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        registry
+            .create_module(&module("echo", "synapse/echo:1.0"))
+            .await
+            .unwrap();
+        registry
+            .create_module(&module("trusted", "ghcr.io/synapse/trusted:1.0"))
+            .await
+            .unwrap();
+
+        let report = verify_registered(&registry, &ModuleVerifier::default())
+            .await
+            .unwrap();
+        assert_eq!(report.compliant, 2);
+
+        let strict = ModuleVerifier::new(VerificationConfig {
+            allowed_registries: vec!["ghcr.io".into()],
+            ..Default::default()
+        });
+        let report = verify_registered(&registry, &strict).await.unwrap();
+        assert_eq!(
+            report.quarantined,
+            vec![(
+                "echo".to_string(),
+                vec![VerificationError::DisallowedRegistry {
+                    image: "synapse/echo:1.0".into(),
+                    registry: "docker.io".into(),
+                }]
+            )]
+        );
+        assert_eq!(report.compliant, 1);
+
+        let echo = registry.get_module("echo").await.unwrap();
+        assert_eq!(echo.status, ModuleStatus::Quarantined);
+        assert!(!echo.status.can_transition_to(ModuleStatus::Starting));
+        assert!(!echo.status.can_transition_to(ModuleStatus::Stopped));
+        assert_eq!(registry.list_modules().await.unwrap().len(), 2);
+
+        let report = verify_registered(&registry, &ModuleVerifier::default())
+            .await
+            .unwrap();
+        assert_eq!(report.released, vec!["echo".to_string()]);
+        assert_eq!(
+            registry.get_module("echo").await.unwrap().status,
+            ModuleStatus::Stopped
+        );
+    }
+}
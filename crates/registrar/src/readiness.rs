@@ -0,0 +1,43 @@
+//! Whether the registrar has finished starting up.
+//!
+//! The server starts listening before every part of it is initialized, so
+//! that liveness checks pass early. Orchestrators should route traffic only
+//! once `GET /ready` answers 200.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Gauge set to 1 once the registrar is ready and 0 before.
+pub const READY_METRIC: &str = "synapse_registrar_ready";
+
+/// Shared readiness flag. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness {
+    ready: Arc<AtomicBool>,
+}
+
+impl Readiness {
+    /// A flag that is not yet ready.
+    pub fn new() -> Self {
+        metrics::gauge!(READY_METRIC).set(0.0);
+        Self::default()
+    }
+
+    /// A flag that is already ready, for servers with nothing left to
+    /// initialize.
+    pub fn ready() -> Self {
+        let readiness = Self::default();
+        readiness.set_ready();
+        readiness
+    }
+
+    /// Marks startup as complete.
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+        metrics::gauge!(READY_METRIC).set(1.0);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,222 @@
+//! Running without Docker until it becomes available.
+//!
+//! Reading the registry and serving packages need no Docker daemon, so a
+//! server should not refuse to start without one. [`ReconnectingContainers`]
+//! fails container operations with [`DockerError::Unavailable`] while the
+//! daemon is unreachable and connects as soon as it is back.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::container::{
+    ContainerConfig, ContainerEvent, ContainerManager, ContainerStats, ContainerStatus, DockerError,
+};
+use crate::docker::DockerManager;
+
+/// Default shortest time between attempts to connect.
+pub const DEFAULT_RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+type Connect =
+    dyn Fn() -> BoxFuture<'static, Result<Arc<dyn ContainerManager>, DockerError>> + Send + Sync;
+
+struct Connection {
+    manager: Option<Arc<dyn ContainerManager>>,
+    last_attempt: Option<Instant>,
+}
+
+/// A [`ContainerManager`] that connects on first use and keeps trying,
+/// at most once per interval, until it succeeds.
+pub struct ReconnectingContainers {
+    connect: Box<Connect>,
+    connection: Mutex<Connection>,
+    interval: Duration,
+}
+
+impl ReconnectingContainers {
+    /// Connects through `connect`, which is retried until it succeeds.
+    pub fn new<F>(connect: F) -> Self
+    where
+        F: Fn() -> BoxFuture<'static, Result<Arc<dyn ContainerManager>, DockerError>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            connect: Box::new(connect),
+            connection: Mutex::new(Connection {
+                manager: None,
+                last_attempt: None,
+            }),
+            interval: DEFAULT_RECONNECT_INTERVAL,
+        }
+    }
+
+    /// Connects to the local Docker daemon with [`DockerManager::connect`].
+    pub fn docker() -> Self {
+        Self::new(|| {
+            Box::pin(async {
+                let docker: Arc<dyn ContainerManager> = Arc::new(DockerManager::connect().await?);
+                Ok(docker)
+            })
+        })
+    }
+
+    /// Sets the shortest time between attempts to connect.
+    pub fn with_reconnect_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the connected manager, connecting first if the last attempt
+    /// was long enough ago.
+    pub async fn connected(&self) -> Result<Arc<dyn ContainerManager>, DockerError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(manager) = &connection.manager {
+            return Ok(manager.clone());
+        }
+        if let Some(last) = connection.last_attempt {
+            if last.elapsed() < self.interval {
+                return Err(DockerError::Unavailable(
+                    "not connected to the Docker daemon".into(),
+                ));
+            }
+        }
+        connection.last_attempt = Some(Instant::now());
+        match (self.connect)().await {
+            Ok(manager) => {
+                tracing::info!("Connected to the Docker daemon");
+                connection.manager = Some(manager.clone());
+                Ok(manager)
+            }
+            Err(DockerError::Unavailable(e)) | Err(DockerError::Api(e)) => {
+                Err(DockerError::Unavailable(e))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The manager if already connected, without attempting to connect.
+    fn current(&self) -> Option<Arc<dyn ContainerManager>> {
+        self.connection
+            .try_lock()
+            .ok()
+            .and_then(|connection| connection.manager.clone())
+    }
+}
+
+#[async_trait]
+impl ContainerManager for ReconnectingContainers {
+    async fn create_container(
+        &self,
+        name: &str,
+        config: &ContainerConfig,
+    ) -> Result<(), DockerError> {
+        self.connected().await?.create_container(name, config).await
+    }
+
+    async fn start_container(&self, name: &str) -> Result<(), DockerError> {
+        self.connected().await?.start_container(name).await
+    }
+
+    async fn stop_container(&self, name: &str) -> Result<(), DockerError> {
+        self.connected().await?.stop_container(name).await
+    }
+
+    async fn stop_container_with_timeout(
+        &self,
+        name: &str,
+        timeout_secs: u64,
+    ) -> Result<(), DockerError> {
+        self.connected()
+            .await?
+            .stop_container_with_timeout(name, timeout_secs)
+            .await
+    }
+
+    async fn remove_container(&self, name: &str) -> Result<(), DockerError> {
+        self.connected().await?.remove_container(name).await
+    }
+
+    async fn get_container_status(&self, name: &str) -> Result<ContainerStatus, DockerError> {
+        self.connected().await?.get_container_status(name).await
+    }
+
+    async fn get_container_stats(&self, name: &str) -> Result<ContainerStats, DockerError> {
+        self.connected().await?.get_container_stats(name).await
+    }
+
+    /// Events of the connected daemon. While disconnected the stream yields
+    /// a single [`DockerError::Unavailable`] and ends; consumers fall back
+    /// to polling.
+    fn events(&self) -> BoxStream<'static, Result<ContainerEvent, DockerError>> {
+        match self.current() {
+            Some(manager) => manager.events(),
+            None => stream::once(async {
+                Err(DockerError::Unavailable(
+                    "not connected to the Docker daemon".into(),
+                ))
+            })
+            .boxed(),
+        }
+    }
+
+    /// Logs from the connected daemon; like [`events`](Self::events), a
+    /// single [`DockerError::Unavailable`] while disconnected.
+    fn logs(&self, name: &str, tail: usize) -> BoxStream<'static, Result<String, DockerError>> {
+        match self.current() {
+            Some(manager) => manager.logs(name, tail),
+            None => stream::once(async {
+                Err(DockerError::Unavailable(
+                    "not connected to the Docker daemon".into(),
+                ))
+            })
+            .boxed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::container::fake::FakeContainers;
+    use crate::container::ContainerState;
+
+    #[tokio::test]
+    async fn test_connects_once_docker_is_back() {
+        let available = Arc::new(AtomicBool::new(false));
+        let fake: Arc<dyn ContainerManager> =
+            Arc::new(FakeContainers::default().with_container("a", ContainerState::Running));
+        let containers = {
+            let available = available.clone();
+            ReconnectingContainers::new(move || {
+                let available = available.load(Ordering::SeqCst);
+                let fake = fake.clone();
+                Box::pin(async move {
+                    if available {
+                        Ok(fake)
+                    } else {
+                        Err(DockerError::Api("connection refused".into()))
+                    }
+                })
+            })
+            .with_reconnect_interval(Duration::ZERO)
+        };
+
+        assert!(matches!(
+            containers.get_container_status("a").await,
+            Err(DockerError::Unavailable(_))
+        ));
+
+        available.store(true, Ordering::SeqCst);
+        let status = containers.get_container_status("a").await.unwrap();
+        assert_eq!(status.state, ContainerState::Running);
+    }
+}
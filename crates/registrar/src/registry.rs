@@ -0,0 +1,1035 @@
+//! Module registry storage.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{Row, Sqlite, Transaction};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::audit::{AuditEntry, AuditPage, AuditQuery, NewAuditEntry, DEFAULT_AUDIT_LIMIT};
+use crate::error::{DbError, RegistryError};
+use crate::miner::{Miner, RegisteredMiner, Registration};
+use crate::module::{
+    Module, ModuleConfig, ModuleMetadata, ModuleSource, ModuleStatus, ModuleType, UnknownVariant,
+    DEFAULT_NAMESPACE, NAMESPACE_SEPARATOR,
+};
+use crate::page::Page;
+use crate::retry::{retry_if, RetryConfig};
+
+/// Field modules are listed by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleSort {
+    #[default]
+    Name,
+    CreatedAt,
+    Downloads,
+    Status,
+}
+
+impl ModuleSort {
+    fn column(self) -> &'static str {
+        match self {
+            ModuleSort::Name => "name",
+            ModuleSort::CreatedAt => "created_at",
+            ModuleSort::Downloads => "downloads",
+            ModuleSort::Status => "status",
+        }
+    }
+}
+
+/// Direction modules are listed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn keyword(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Which modules to list and in what order. Modules that tie on the sort
+/// field are ordered by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleQuery {
+    /// Only list modules whose config declares this capability.
+    pub capability: Option<String>,
+    /// Only list modules in this namespace; [`DEFAULT_NAMESPACE`] selects
+    /// modules registered without one.
+    pub namespace: Option<String>,
+    pub sort: ModuleSort,
+    pub order: SortOrder,
+}
+
+/// Storage backend for registered modules.
+#[async_trait]
+pub trait Registry: Send + Sync {
+    /// Registers a new module, returning its id.
+    ///
+    /// Implementations must return [`RegistryError::ModuleExists`] when a
+    /// module with the same name is already registered.
+    async fn create_module(&self, module: &Module) -> Result<i64, RegistryError> {
+        self.create_module_with_source(module, None).await
+    }
+
+    /// Registers a new module together with the source it was ingested
+    /// from, in one write, so the module is never visible without it.
+    async fn create_module_with_source(
+        &self,
+        module: &Module,
+        source: Option<&ModuleSource>,
+    ) -> Result<i64, RegistryError>;
+
+    /// Looks up a module by name.
+    async fn get_module(&self, name: &str) -> Result<Module, RegistryError>;
+
+    /// Lists all registered modules ordered by name.
+    async fn list_modules(&self) -> Result<Vec<Module>, RegistryError>;
+
+    /// Lists the modules matching `query`, in its order.
+    async fn query_modules(&self, query: &ModuleQuery) -> Result<Vec<Module>, RegistryError>;
+
+    /// Updates the status of a module.
+    async fn update_module_status(
+        &self,
+        name: &str,
+        status: ModuleStatus,
+    ) -> Result<(), RegistryError>;
+
+    /// Replaces the configuration of a module.
+    async fn update_module_config(
+        &self,
+        name: &str,
+        config: &ModuleConfig,
+    ) -> Result<(), RegistryError>;
+
+    /// Records where a module's code was ingested from.
+    async fn set_module_source(
+        &self,
+        name: &str,
+        source: &ModuleSource,
+    ) -> Result<(), RegistryError>;
+
+    /// Replaces a registered module's type, status, config, tags and source
+    /// in one write. Its download count, creation time and audit history
+    /// are kept, as is its owner unless `module` names one.
+    async fn replace_module(
+        &self,
+        module: &Module,
+        source: Option<&ModuleSource>,
+    ) -> Result<(), RegistryError>;
+
+    /// Returns registry bookkeeping about a module.
+    async fn get_module_metadata(&self, name: &str) -> Result<ModuleMetadata, RegistryError>;
+
+    /// Adds one to the number of times a module's package was served.
+    async fn increment_downloads(&self, name: &str) -> Result<(), RegistryError>;
+
+    /// Renames a module, keeping its metadata and audit history. Other
+    /// modules' `depends_on` entries naming it are rewritten along with it.
+    ///
+    /// Implementations must return [`RegistryError::ModuleExists`] when
+    /// `new_name` is already registered.
+    async fn rename_module(&self, old_name: &str, new_name: &str) -> Result<(), RegistryError>;
+
+    /// Removes a module from the registry.
+    async fn delete_module(&self, name: &str) -> Result<(), RegistryError>;
+
+    /// Removes several modules at once: either all are removed or, when
+    /// one is not registered, none are.
+    async fn delete_modules(&self, names: &[String]) -> Result<(), RegistryError>;
+
+    /// Registers a miner, or updates its name if the uid is already
+    /// registered with the same key.
+    ///
+    /// Implementations must return [`RegistryError::MinerExists`] when the
+    /// uid is registered with a different key, or the key with a different
+    /// uid.
+    async fn register_miner(&self, miner: &Miner) -> Result<Registration, RegistryError>;
+
+    /// Lists registered miners ordered by uid.
+    async fn list_miners(&self) -> Result<Vec<RegisteredMiner>, RegistryError>;
+
+    /// Appends an entry to the audit log, returning its id.
+    async fn record_audit(&self, entry: NewAuditEntry) -> Result<i64, RegistryError>;
+
+    /// Reads a page of audit entries, newest first.
+    async fn list_audit(&self, query: &AuditQuery) -> Result<AuditPage, RegistryError>;
+}
+
+/// How a [`SqliteRegistry`] copes with a database locked by another
+/// writer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusyConfig {
+    /// How long SQLite itself waits for a lock (`PRAGMA busy_timeout`)
+    /// before reporting the database busy.
+    pub timeout: Duration,
+    /// How often a write that found the database busy is retried.
+    pub max_retries: u32,
+    /// Delay before the first retry; later retries back off from it.
+    pub initial_delay: Duration,
+}
+
+impl Default for BusyConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            max_retries: 5,
+            initial_delay: Duration::from_millis(20),
+        }
+    }
+}
+
+impl BusyConfig {
+    fn retry_config(&self) -> RetryConfig {
+        RetryConfig {
+            max_retries: self.max_retries,
+            initial_delay: self.initial_delay,
+            max_delay: Duration::from_secs(1),
+            ..RetryConfig::default()
+        }
+        .with_jitter(true)
+    }
+}
+
+/// SQLite-backed registry.
+#[derive(Clone)]
+pub struct SqliteRegistry {
+    pool: SqlitePool,
+    busy_retry: RetryConfig,
+}
+
+impl SqliteRegistry {
+    /// Connects to the database at `url`, creating it if missing, and runs
+    /// pending migrations.
+    pub async fn connect(url: &str) -> Result<Self, RegistryError> {
+        Self::connect_with(url, BusyConfig::default()).await
+    }
+
+    /// Like [`connect`](Self::connect), with explicit handling of a busy
+    /// database.
+    pub async fn connect_with(url: &str, busy: BusyConfig) -> Result<Self, RegistryError> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .busy_timeout(busy.timeout);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await?;
+        let mut registry = Self::from_pool(pool).await?;
+        registry.busy_retry = busy.retry_config();
+        Ok(registry)
+    }
+
+    /// Creates a registry backed by a private in-memory database.
+    pub async fn in_memory() -> Result<Self, RegistryError> {
+        // Every in-memory connection is a separate database, so keep exactly one.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        Self::from_pool(pool).await
+    }
+
+    async fn from_pool(pool: SqlitePool) -> Result<Self, RegistryError> {
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        Ok(Self {
+            pool,
+            busy_retry: BusyConfig::default().retry_config(),
+        })
+    }
+
+    /// Runs a write, retrying it while the database is busy.
+    async fn write<T, F, Fut>(&self, operation: &str, write: F) -> Result<T, RegistryError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RegistryError>>,
+    {
+        retry_if(
+            &format!("registry.{}", operation),
+            &self.busy_retry,
+            RegistryError::is_busy,
+            write,
+        )
+        .await
+    }
+
+    /// Returns the underlying connection pool.
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+}
+
+fn parse_status(value: &str) -> Result<ModuleStatus, RegistryError> {
+    value
+        .parse()
+        .map_err(|e: UnknownVariant| DbError::Other(e.to_string()).into())
+}
+
+fn module_from_row(row: &SqliteRow) -> Result<Module, RegistryError> {
+    let module_type: String = row.try_get("module_type")?;
+    let status: String = row.try_get("status")?;
+    let config: String = row.try_get("config")?;
+    let tags: String = row.try_get("tags")?;
+    Ok(Module {
+        name: row.try_get("name")?,
+        module_type: ModuleType::try_from(module_type)
+            .map_err(|e| DbError::Other(e.to_string()))?,
+        status: parse_status(&status)?,
+        config: serde_json::from_str(&config)
+            .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?,
+        tags: serde_json::from_str(&tags)
+            .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?,
+        owner: row.try_get("owner")?,
+    })
+}
+
+fn metadata_from_row(row: &SqliteRow) -> Result<ModuleMetadata, RegistryError> {
+    let module_type: String = row.try_get("module_type")?;
+    let repo_url: Option<String> = row.try_get("repo_url")?;
+    let source = match repo_url {
+        Some(repo_url) => Some(ModuleSource {
+            repo_url,
+            git_ref: row
+                .try_get::<Option<String>, _>("git_ref")?
+                .unwrap_or_default(),
+            commit: row
+                .try_get::<Option<String>, _>("commit_sha")?
+                .unwrap_or_default(),
+            path: row
+                .try_get::<Option<String>, _>("source_path")?
+                .unwrap_or_default(),
+        }),
+        None => None,
+    };
+    Ok(ModuleMetadata {
+        name: row.try_get("name")?,
+        module_type: ModuleType::try_from(module_type)
+            .map_err(|e| DbError::Other(e.to_string()))?,
+        source,
+        downloads: row.try_get::<i64, _>("downloads")? as u64,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn audit_from_row(row: &SqliteRow) -> Result<AuditEntry, RegistryError> {
+    let action: String = row.try_get("action")?;
+    let before: Option<String> = row.try_get("before_status")?;
+    let after: Option<String> = row.try_get("after_status")?;
+    Ok(AuditEntry {
+        id: row.try_get("id")?,
+        module: row.try_get("module")?,
+        action: action.parse().map_err(DbError::Other)?,
+        actor: row.try_get("actor")?,
+        timestamp: row.try_get::<DateTime<Utc>, _>("timestamp")?,
+        before_status: before.as_deref().map(parse_status).transpose()?,
+        after_status: after.as_deref().map(parse_status).transpose()?,
+    })
+}
+
+/// Records the capabilities of the module with row id `module_id` in the
+/// capability index.
+async fn index_capabilities(
+    tx: &mut Transaction<'_, Sqlite>,
+    module_id: i64,
+    capabilities: &[String],
+) -> Result<(), RegistryError> {
+    for capability in capabilities {
+        sqlx::query(
+            "INSERT OR IGNORE INTO module_capabilities (module_id, capability) VALUES (?, ?)",
+        )
+        .bind(module_id)
+        .bind(capability)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Registry for SqliteRegistry {
+    async fn create_module_with_source(
+        &self,
+        module: &Module,
+        source: Option<&ModuleSource>,
+    ) -> Result<i64, RegistryError> {
+        let config = serde_json::to_string(&module.config)
+            .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?;
+        let tags = serde_json::to_string(&module.tags)
+            .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?;
+        let (config, tags) = (&config, &tags);
+        self.write("create_module", move || async move {
+            let now = Utc::now();
+            let mut tx = self.pool.begin().await?;
+            let result = sqlx::query(
+                "INSERT INTO modules (name, module_type, status, config, tags, owner,
+                                      repo_url, git_ref, commit_sha, source_path, created_at, updated_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&module.name)
+            .bind(module.module_type.to_string())
+            .bind(module.status.to_string())
+            .bind(config)
+            .bind(tags)
+            .bind(&module.owner)
+            .bind(source.map(|s| &s.repo_url))
+            .bind(source.map(|s| &s.git_ref))
+            .bind(source.map(|s| &s.commit))
+            .bind(source.map(|s| &s.path))
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| match DbError::from(e) {
+                DbError::UniqueViolation(_) => RegistryError::ModuleExists(module.name.clone()),
+                other => other.into(),
+            })?;
+            let id = result.last_insert_rowid();
+            index_capabilities(&mut tx, id, &module.config.capabilities).await?;
+            tx.commit().await?;
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn get_module(&self, name: &str) -> Result<Module, RegistryError> {
+        let row = sqlx::query(
+            "SELECT name, module_type, status, config, tags, owner FROM modules WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| RegistryError::ModuleNotFound(name.to_string()))?;
+        module_from_row(&row)
+    }
+
+    async fn list_modules(&self) -> Result<Vec<Module>, RegistryError> {
+        self.query_modules(&ModuleQuery::default()).await
+    }
+
+    async fn query_modules(&self, query: &ModuleQuery) -> Result<Vec<Module>, RegistryError> {
+        let mut join = "";
+        let mut conditions = Vec::new();
+        if query.capability.is_some() {
+            join = "JOIN module_capabilities ON module_capabilities.module_id = modules.id";
+            conditions.push("module_capabilities.capability = ?");
+        }
+        // Namespaces are matched as a name prefix. substr is used rather
+        // than LIKE, where '_' in a namespace would be a wildcard.
+        let prefix = match query.namespace.as_deref() {
+            Some(DEFAULT_NAMESPACE) => {
+                conditions.push("instr(modules.name, '/') = 0");
+                None
+            }
+            Some(namespace) => {
+                conditions.push("substr(modules.name, 1, ?) = ?");
+                Some(format!("{}{}", namespace, NAMESPACE_SEPARATOR))
+            }
+            None => None,
+        };
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        // The sort column and direction come from enums, never from input.
+        let sql = format!(
+            "SELECT name, module_type, status, config, tags, owner FROM modules {} {}
+             ORDER BY {} {}, name ASC",
+            join,
+            filter,
+            query.sort.column(),
+            query.order.keyword()
+        );
+        let mut statement = sqlx::query(&sql);
+        if let Some(capability) = &query.capability {
+            statement = statement.bind(capability);
+        }
+        if let Some(prefix) = &prefix {
+            statement = statement.bind(prefix.chars().count() as i64).bind(prefix);
+        }
+        let rows = statement.fetch_all(&self.pool).await?;
+        rows.iter().map(module_from_row).collect()
+    }
+
+    async fn update_module_status(
+        &self,
+        name: &str,
+        status: ModuleStatus,
+    ) -> Result<(), RegistryError> {
+        self.write("update_module_status", move || async move {
+            let result =
+                sqlx::query("UPDATE modules SET status = ?, updated_at = ? WHERE name = ?")
+                    .bind(status.to_string())
+                    .bind(Utc::now())
+                    .bind(name)
+                    .execute(&self.pool)
+                    .await?;
+            if result.rows_affected() == 0 {
+                return Err(RegistryError::ModuleNotFound(name.to_string()));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_module_config(
+        &self,
+        name: &str,
+        config: &ModuleConfig,
+    ) -> Result<(), RegistryError> {
+        let config_json = serde_json::to_string(config)
+            .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?;
+        let (config, capabilities) = (&config_json, &config.capabilities);
+        self.write("update_module_config", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let id: i64 = sqlx::query(
+                "UPDATE modules SET config = ?, updated_at = ? WHERE name = ? RETURNING id",
+            )
+            .bind(config)
+            .bind(Utc::now())
+            .bind(name)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| RegistryError::ModuleNotFound(name.to_string()))?
+            .try_get("id")?;
+            sqlx::query("DELETE FROM module_capabilities WHERE module_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            index_capabilities(&mut tx, id, capabilities).await?;
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_module_source(
+        &self,
+        name: &str,
+        source: &ModuleSource,
+    ) -> Result<(), RegistryError> {
+        self.write("set_module_source", move || async move {
+            let result = sqlx::query(
+                "UPDATE modules
+                 SET repo_url = ?, git_ref = ?, commit_sha = ?, source_path = ?, updated_at = ?
+                 WHERE name = ?",
+            )
+            .bind(&source.repo_url)
+            .bind(&source.git_ref)
+            .bind(&source.commit)
+            .bind(&source.path)
+            .bind(Utc::now())
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Err(RegistryError::ModuleNotFound(name.to_string()));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn replace_module(
+        &self,
+        module: &Module,
+        source: Option<&ModuleSource>,
+    ) -> Result<(), RegistryError> {
+        let config = serde_json::to_string(&module.config)
+            .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?;
+        let tags = serde_json::to_string(&module.tags)
+            .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?;
+        let (config, tags) = (&config, &tags);
+        self.write("replace_module", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let id: i64 = sqlx::query(
+                "UPDATE modules
+                 SET module_type = ?, status = ?, config = ?, tags = ?, owner = COALESCE(?, owner),
+                     repo_url = ?, git_ref = ?, commit_sha = ?, source_path = ?, updated_at = ?
+                 WHERE name = ? RETURNING id",
+            )
+            .bind(module.module_type.to_string())
+            .bind(module.status.to_string())
+            .bind(config)
+            .bind(tags)
+            .bind(&module.owner)
+            .bind(source.map(|s| &s.repo_url))
+            .bind(source.map(|s| &s.git_ref))
+            .bind(source.map(|s| &s.commit))
+            .bind(source.map(|s| &s.path))
+            .bind(Utc::now())
+            .bind(&module.name)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| RegistryError::ModuleNotFound(module.name.clone()))?
+            .try_get("id")?;
+            sqlx::query("DELETE FROM module_capabilities WHERE module_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            index_capabilities(&mut tx, id, &module.config.capabilities).await?;
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_module_metadata(&self, name: &str) -> Result<ModuleMetadata, RegistryError> {
+        let row = sqlx::query(
+            "SELECT name, module_type, repo_url, git_ref, commit_sha, source_path,
+                    downloads, created_at, updated_at
+             FROM modules WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| RegistryError::ModuleNotFound(name.to_string()))?;
+        metadata_from_row(&row)
+    }
+
+    async fn increment_downloads(&self, name: &str) -> Result<(), RegistryError> {
+        self.write("increment_downloads", move || async move {
+            let result = sqlx::query("UPDATE modules SET downloads = downloads + 1 WHERE name = ?")
+                .bind(name)
+                .execute(&self.pool)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Err(RegistryError::ModuleNotFound(name.to_string()));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn rename_module(&self, old_name: &str, new_name: &str) -> Result<(), RegistryError> {
+        self.write("rename_module", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let result = sqlx::query("UPDATE modules SET name = ?, updated_at = ? WHERE name = ?")
+                .bind(new_name)
+                .bind(Utc::now())
+                .bind(old_name)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| match DbError::from(e) {
+                    DbError::UniqueViolation(_) => {
+                        RegistryError::ModuleExists(new_name.to_string())
+                    }
+                    other => other.into(),
+                })?;
+            if result.rows_affected() == 0 {
+                return Err(RegistryError::ModuleNotFound(old_name.to_string()));
+            }
+            sqlx::query("UPDATE audit_log SET module = ? WHERE module = ?")
+                .bind(new_name)
+                .bind(old_name)
+                .execute(&mut *tx)
+                .await?;
+            let rows = sqlx::query("SELECT id, config FROM modules")
+                .fetch_all(&mut *tx)
+                .await?;
+            for row in rows {
+                let config: String = row.try_get("config")?;
+                let mut config: ModuleConfig = serde_json::from_str(&config)
+                    .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?;
+                if !config.depends_on.iter().any(|d| d == old_name) {
+                    continue;
+                }
+                for dependency in &mut config.depends_on {
+                    if dependency == old_name {
+                        *dependency = new_name.to_string();
+                    }
+                }
+                let config = serde_json::to_string(&config)
+                    .map_err(|e| RegistryError::Database(DbError::Other(e.to_string())))?;
+                sqlx::query("UPDATE modules SET config = ? WHERE id = ?")
+                    .bind(config)
+                    .bind(row.try_get::<i64, _>("id")?)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_module(&self, name: &str) -> Result<(), RegistryError> {
+        self.write("delete_module", move || async move {
+            let result = sqlx::query("DELETE FROM modules WHERE name = ?")
+                .bind(name)
+                .execute(&self.pool)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Err(RegistryError::ModuleNotFound(name.to_string()));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_modules(&self, names: &[String]) -> Result<(), RegistryError> {
+        self.write("delete_modules", move || async move {
+            let mut tx = self.pool.begin().await?;
+            for name in names {
+                let result = sqlx::query("DELETE FROM modules WHERE name = ?")
+                    .bind(name)
+                    .execute(&mut *tx)
+                    .await?;
+                if result.rows_affected() == 0 {
+                    return Err(RegistryError::ModuleNotFound(name.clone()));
+                }
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn register_miner(&self, miner: &Miner) -> Result<Registration, RegistryError> {
+        self.write("register_miner", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let existing: Option<String> =
+                sqlx::query_scalar("SELECT key FROM miners WHERE uid = ?")
+                    .bind(i64::from(miner.uid))
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            let registration = match existing {
+                Some(key) if key == miner.key => {
+                    sqlx::query("UPDATE miners SET name = ? WHERE uid = ?")
+                        .bind(&miner.name)
+                        .bind(i64::from(miner.uid))
+                        .execute(&mut *tx)
+                        .await?;
+                    Registration::Updated
+                }
+                Some(_) => return Err(RegistryError::MinerExists(miner.uid)),
+                None => {
+                    sqlx::query(
+                        "INSERT INTO miners (uid, key, name, registered_at) VALUES (?, ?, ?, ?)",
+                    )
+                    .bind(i64::from(miner.uid))
+                    .bind(&miner.key)
+                    .bind(&miner.name)
+                    .bind(Utc::now())
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| match DbError::from(e) {
+                        DbError::UniqueViolation(_) => RegistryError::MinerExists(miner.uid),
+                        other => other.into(),
+                    })?;
+                    Registration::Created
+                }
+            };
+            tx.commit().await?;
+            Ok(registration)
+        })
+        .await
+    }
+
+    async fn list_miners(&self) -> Result<Vec<RegisteredMiner>, RegistryError> {
+        let rows = sqlx::query("SELECT uid, key, name, registered_at FROM miners ORDER BY uid")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                let uid: i64 = row.try_get("uid")?;
+                Ok(RegisteredMiner {
+                    miner: Miner {
+                        uid: u16::try_from(uid).map_err(|e| DbError::Other(e.to_string()))?,
+                        key: row.try_get("key")?,
+                        name: row.try_get("name")?,
+                    },
+                    registered_at: row.try_get("registered_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn record_audit(&self, entry: NewAuditEntry) -> Result<i64, RegistryError> {
+        let entry = &entry;
+        self.write("record_audit", move || async move {
+            let result = sqlx::query(
+                "INSERT INTO audit_log (module, action, actor, timestamp, before_status, after_status)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&entry.module)
+            .bind(entry.action.to_string())
+            .bind(&entry.actor)
+            .bind(entry.timestamp)
+            .bind(entry.before_status.map(|s| s.to_string()))
+            .bind(entry.after_status.map(|s| s.to_string()))
+            .execute(&self.pool)
+            .await?;
+            Ok(result.last_insert_rowid())
+        })
+        .await
+    }
+
+    async fn list_audit(&self, query: &AuditQuery) -> Result<AuditPage, RegistryError> {
+        let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT);
+        // Fetch one extra row to learn whether another page exists.
+        let rows = sqlx::query(
+            "SELECT id, module, action, actor, timestamp, before_status, after_status
+             FROM audit_log
+             WHERE (?1 IS NULL OR module = ?1)
+               AND (?2 IS NULL OR timestamp >= ?2)
+               AND (?3 IS NULL OR timestamp <= ?3)
+               AND (?4 IS NULL OR id < ?4)
+             ORDER BY id DESC
+             LIMIT ?5",
+        )
+        .bind(&query.module)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(query.before_id)
+        .bind(i64::from(limit) + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = rows
+            .iter()
+            .map(audit_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        let next_cursor = if entries.len() > limit as usize {
+            entries.truncate(limit as usize);
+            entries.last().map(|e| e.id.to_string())
+        } else {
+            None
+        };
+
+        // Entries matching the filter, and how many of them come before
+        // this page.
+        let counts = sqlx::query(
+            "SELECT COUNT(*) AS total,
+                    COALESCE(SUM(?4 IS NOT NULL AND id >= ?4), 0) AS preceding
+             FROM audit_log
+             WHERE (?1 IS NULL OR module = ?1)
+               AND (?2 IS NULL OR timestamp >= ?2)
+               AND (?3 IS NULL OR timestamp <= ?3)",
+        )
+        .bind(&query.module)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(query.before_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let total: i64 = counts.try_get("total")?;
+        let preceding: i64 = counts.try_get("preceding")?;
+        let page = preceding as u64 / u64::from(limit.max(1)) + 1;
+        Ok(Page::new(entries, total as u64, page as u32, limit).with_next_cursor(next_cursor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditAction;
+
+    #[tokio::test]
+    async fn test_module_crud() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        let module = Module::new("echo", ModuleType::Docker);
+
+        registry.create_module(&module).await.unwrap();
+        assert_eq!(registry.get_module("echo").await.unwrap(), module);
+
+        registry
+            .update_module_status("echo", ModuleStatus::Running)
+            .await
+            .unwrap();
+        assert_eq!(
+            registry.get_module("echo").await.unwrap().status,
+            ModuleStatus::Running
+        );
+
+        registry.delete_module("echo").await.unwrap();
+        assert!(matches!(
+            registry.get_module("echo").await,
+            Err(RegistryError::ModuleNotFound(_))
+        ));
+    }
+
+    /// Details of SQLite's plan for `sql`.
+    async fn query_plan(registry: &SqliteRegistry, sql: &str) -> String {
+        let rows = sqlx::query(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .fetch_all(&registry.pool)
+            .await
+            .unwrap();
+        rows.iter()
+            .map(|row| row.get::<String, _>("detail"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[tokio::test]
+    async fn test_lookups_use_indexes() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+
+        let plan = query_plan(&registry, "SELECT config FROM modules WHERE name = 'echo'").await;
+        assert!(
+            plan.contains("USING INDEX sqlite_autoindex_modules"),
+            "{}",
+            plan
+        );
+        let plan = query_plan(
+            &registry,
+            "SELECT name FROM modules WHERE status = 'running'",
+        )
+        .await;
+        assert!(plan.contains("idx_modules_status"), "{}", plan);
+        let plan = query_plan(
+            &registry,
+            "SELECT name FROM modules WHERE module_type = 'docker'",
+        )
+        .await;
+        assert!(plan.contains("idx_modules_module_type"), "{}", plan);
+        let plan = query_plan(&registry, "SELECT id FROM audit_log WHERE module = 'echo'").await;
+        assert!(plan.contains("idx_audit_log_module"), "{}", plan);
+        let plan = query_plan(
+            &registry,
+            "SELECT module_id FROM module_capabilities WHERE capability = 'streaming'",
+        )
+        .await;
+        assert!(
+            plan.contains("sqlite_autoindex_module_capabilities"),
+            "{}",
+            plan
+        );
+    }
+
+    #[tokio::test]
+    async fn test_module_source_survives_round_trip() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        let module = Module::new("echo", ModuleType::Docker)
+            .with_tags(vec!["team-a".into()])
+            .with_owner(Some("5Alice".into()));
+        let source = ModuleSource {
+            repo_url: "https://example.com/echo.git".into(),
+            git_ref: "v1.0".into(),
+            commit: "0123abcd".into(),
+            path: "/var/cache/echo".into(),
+        };
+
+        registry
+            .create_module_with_source(&module, Some(&source))
+            .await
+            .unwrap();
+        assert_eq!(registry.get_module("echo").await.unwrap(), module);
+        assert_eq!(
+            registry.get_module_metadata("echo").await.unwrap().source,
+            Some(source)
+        );
+
+        registry
+            .create_module(&Module::new("bare", ModuleType::Local))
+            .await
+            .unwrap();
+        assert_eq!(
+            registry.get_module_metadata("bare").await.unwrap().source,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_duplicate_module() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        let module = Module::new("echo", ModuleType::Docker);
+
+        registry.create_module(&module).await.unwrap();
+        match registry.create_module(&module).await {
+            Err(RegistryError::ModuleExists(name)) => assert_eq!(name, "echo"),
+            other => panic!("expected ModuleExists, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_insert_is_unique_violation() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        registry
+            .create_module(&Module::new("echo", ModuleType::Docker))
+            .await
+            .unwrap();
+
+        let err: RegistryError = sqlx::query(
+            "INSERT INTO modules (name, module_type, status, config, created_at, updated_at)
+             VALUES ('echo', 'docker', 'stopped', '{}', 0, 0)",
+        )
+        .execute(registry.pool())
+        .await
+        .unwrap_err()
+        .into();
+        assert!(matches!(
+            err,
+            RegistryError::Database(DbError::UniqueViolation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_audit_filtered_by_module() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        for module in ["a", "b", "a"] {
+            registry
+                .record_audit(NewAuditEntry {
+                    module: module.into(),
+                    action: AuditAction::Create,
+                    actor: "tester".into(),
+                    timestamp: Utc::now(),
+                    before_status: None,
+                    after_status: Some(ModuleStatus::Stopped),
+                })
+                .await
+                .unwrap();
+        }
+
+        let query = AuditQuery {
+            module: Some("a".into()),
+            ..Default::default()
+        };
+        let entries = registry.list_audit(&query).await.unwrap().items;
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| e.module == "a"));
+        assert!(entries[0].id > entries[1].id);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_creates_survive_busy_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("registry.db").display());
+        // Without a busy timeout SQLite reports contention immediately, so
+        // only the retries keep these writes from failing.
+        let busy = BusyConfig {
+            timeout: Duration::ZERO,
+            max_retries: 50,
+            initial_delay: Duration::from_millis(5),
+        };
+        let registry = SqliteRegistry::connect_with(&url, busy).await.unwrap();
+
+        let creates = (0..20).map(|i| {
+            let registry = registry.clone();
+            async move {
+                registry
+                    .create_module(&Module::new(format!("m{}", i), ModuleType::Docker))
+                    .await
+            }
+        });
+        let results = futures::future::join_all(creates).await;
+
+        for result in &results {
+            assert!(result.is_ok(), "{:?}", result);
+        }
+        assert_eq!(registry.list_modules().await.unwrap().len(), 20);
+    }
+}
@@ -0,0 +1,333 @@
+//! Cluster-level resource metrics aggregated across running modules.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::api::ws::{WsMessage, WsState};
+use crate::container::{ContainerManager, ContainerStats, DockerError};
+use crate::error::RegistryError;
+use crate::module::{dotted_name, ModuleStatus};
+use crate::registry::Registry;
+use crate::tasks::BackgroundTasks;
+
+/// Default number of containers queried for stats at the same time.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 8;
+
+/// Resource usage summed across all running modules.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceMetrics {
+    /// Number of modules whose stats were collected.
+    pub modules: usize,
+    /// Sum of CPU usage percentages.
+    pub cpu_percent: f64,
+    /// Sum of memory in use, in bytes.
+    pub memory_usage_bytes: u64,
+    /// Sum of memory limits, in bytes.
+    pub memory_limit_bytes: u64,
+    /// Sum of bytes received.
+    pub network_rx_bytes: u64,
+    /// Sum of bytes sent.
+    pub network_tx_bytes: u64,
+}
+
+impl ResourceMetrics {
+    /// Adds a container's stats to the aggregate.
+    pub fn add(&mut self, stats: &ContainerStats) {
+        self.modules += 1;
+        self.cpu_percent += stats.cpu_percent;
+        self.memory_usage_bytes += stats.memory_usage_bytes;
+        self.memory_limit_bytes += stats.memory_limit_bytes;
+        self.network_rx_bytes += stats.network_rx_bytes;
+        self.network_tx_bytes += stats.network_tx_bytes;
+    }
+}
+
+/// Resource usage of one running module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleUsage {
+    pub module: String,
+    /// The container's stats; `None` when they could not be fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ContainerStats>,
+    /// Bytes received per second since the previous collection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_rx_rate: Option<f64>,
+    /// Bytes sent per second since the previous collection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_tx_rate: Option<f64>,
+    /// Why the stats could not be fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Network counters of a module at the time they were collected.
+#[derive(Debug, Clone, Copy)]
+struct NetworkSample {
+    at: Instant,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+impl NetworkSample {
+    /// Bytes per second received and sent between `self` and `later`.
+    fn rates(&self, later: &NetworkSample) -> Option<(f64, f64)> {
+        let secs = later.at.duration_since(self.at).as_secs_f64();
+        (secs > 0.0).then(|| {
+            (
+                later.rx_bytes.saturating_sub(self.rx_bytes) as f64 / secs,
+                later.tx_bytes.saturating_sub(self.tx_bytes) as f64 / secs,
+            )
+        })
+    }
+}
+
+/// Collects [`ContainerStats`] of running modules into [`ResourceMetrics`].
+#[derive(Clone)]
+pub struct ResourceAggregator {
+    registry: Arc<dyn Registry>,
+    containers: Arc<dyn ContainerManager>,
+    max_concurrency: usize,
+    /// Last network counters seen per module, for [`Self::usage`] rates.
+    samples: Arc<Mutex<HashMap<String, NetworkSample>>>,
+}
+
+impl ResourceAggregator {
+    /// Creates an aggregator querying at most [`DEFAULT_MAX_CONCURRENCY`]
+    /// containers at once.
+    pub fn new(registry: Arc<dyn Registry>, containers: Arc<dyn ContainerManager>) -> Self {
+        Self {
+            registry,
+            containers,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            samples: Arc::default(),
+        }
+    }
+
+    /// Sets how many containers are queried concurrently.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Fetches the stats of every running module.
+    async fn sample(
+        &self,
+    ) -> Result<Vec<(String, Result<ContainerStats, DockerError>)>, RegistryError> {
+        let running: Vec<String> = self
+            .registry
+            .list_modules()
+            .await?
+            .into_iter()
+            .filter(|m| m.status == ModuleStatus::Running)
+            .map(|m| m.name)
+            .collect();
+
+        Ok(stream::iter(running)
+            .map(|name| async move {
+                let stats = self
+                    .containers
+                    .get_container_stats(&dotted_name(&name))
+                    .await;
+                (name, stats)
+            })
+            .buffer_unordered(self.max_concurrency)
+            .collect()
+            .await)
+    }
+
+    /// Collects stats of every running module and sums them. Modules whose
+    /// stats cannot be fetched are logged and left out of the aggregate.
+    pub async fn collect(&self) -> Result<ResourceMetrics, RegistryError> {
+        let mut metrics = ResourceMetrics::default();
+        for (name, stats) in self.sample().await? {
+            match stats {
+                Ok(stats) => metrics.add(&stats),
+                Err(e) => tracing::warn!("Failed to collect stats for {}: {}", name, e),
+            }
+        }
+        Ok(metrics)
+    }
+
+    /// Lists the usage of every running module, busiest CPU first. Network
+    /// rates cover the time since the previous call and are absent on the
+    /// first. Modules whose stats cannot be fetched are listed last, with
+    /// the error.
+    pub async fn usage(&self) -> Result<Vec<ModuleUsage>, RegistryError> {
+        let results = self.sample().await?;
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+        let mut usage: Vec<ModuleUsage> = results
+            .into_iter()
+            .map(|(module, stats)| match stats {
+                Ok(stats) => {
+                    let sample = NetworkSample {
+                        at: now,
+                        rx_bytes: stats.network_rx_bytes,
+                        tx_bytes: stats.network_tx_bytes,
+                    };
+                    let rates = samples
+                        .insert(module.clone(), sample)
+                        .and_then(|previous| previous.rates(&sample));
+                    ModuleUsage {
+                        module,
+                        stats: Some(stats),
+                        network_rx_rate: rates.map(|(rx, _)| rx),
+                        network_tx_rate: rates.map(|(_, tx)| tx),
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    samples.remove(&module);
+                    ModuleUsage {
+                        module,
+                        stats: None,
+                        network_rx_rate: None,
+                        network_tx_rate: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            })
+            .collect();
+        let names: Vec<&str> = usage.iter().map(|u| u.module.as_str()).collect();
+        samples.retain(|module, _| names.contains(&module.as_str()));
+
+        usage.sort_by(|a, b| {
+            let cpu = |u: &ModuleUsage| u.stats.as_ref().map(|s| s.cpu_percent);
+            match (cpu(a), cpu(b)) {
+                (Some(a), Some(b)) => b.total_cmp(&a),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+            .then_with(|| a.module.cmp(&b.module))
+        });
+        Ok(usage)
+    }
+
+    /// Spawns one of `tasks` that collects metrics every `interval` and
+    /// broadcasts them to WebSocket clients until the tasks are shut down.
+    pub fn spawn(self, interval: Duration, ws: WsState, tasks: &BackgroundTasks) -> JoinHandle<()> {
+        tasks.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.collect().await {
+                    Ok(metrics) => ws.broadcast(WsMessage::ResourceMetrics(metrics)),
+                    Err(e) => tracing::warn!("Failed to collect resource metrics: {}", e),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::fake::FakeContainers;
+    use crate::module::{Module, ModuleType};
+    use crate::registry::SqliteRegistry;
+
+    fn stats(cpu: f64, mem: u64, rx: u64, tx: u64) -> ContainerStats {
+        ContainerStats {
+            cpu_percent: cpu,
+            memory_usage_bytes: mem,
+            memory_limit_bytes: mem * 2,
+            network_rx_bytes: rx,
+            network_tx_bytes: tx,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_is_sum_of_running_containers() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        for name in ["a", "b", "idle"] {
+            registry
+                .create_module(&Module::new(name, ModuleType::Docker))
+                .await
+                .unwrap();
+        }
+        for name in ["a", "b"] {
+            registry
+                .update_module_status(name, ModuleStatus::Running)
+                .await
+                .unwrap();
+        }
+
+        let containers = FakeContainers::default()
+            .with_stats("a", stats(10.0, 100, 1, 2))
+            .with_stats("b", stats(25.5, 300, 3, 4))
+            .with_stats("idle", stats(99.0, 999, 9, 9));
+        let aggregator = ResourceAggregator::new(registry, Arc::new(containers));
+
+        let metrics = aggregator.collect().await.unwrap();
+        assert_eq!(
+            metrics,
+            ResourceMetrics {
+                modules: 2,
+                cpu_percent: 35.5,
+                memory_usage_bytes: 400,
+                memory_limit_bytes: 800,
+                network_rx_bytes: 4,
+                network_tx_bytes: 6,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_usage_lists_each_running_module() {
+        let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+        for name in ["a", "b", "gone", "idle"] {
+            registry
+                .create_module(&Module::new(name, ModuleType::Docker))
+                .await
+                .unwrap();
+        }
+        for name in ["a", "b", "gone"] {
+            registry
+                .update_module_status(name, ModuleStatus::Running)
+                .await
+                .unwrap();
+        }
+
+        // "gone" has no container, so its stats cannot be fetched.
+        let containers = Arc::new(
+            FakeContainers::default()
+                .with_stats("a", stats(10.0, 100, 1000, 0))
+                .with_stats("b", stats(25.5, 300, 0, 0))
+                .with_stats("idle", stats(99.0, 999, 9, 9)),
+        );
+        let aggregator = ResourceAggregator::new(registry, containers);
+
+        let usage = aggregator.usage().await.unwrap();
+        let modules: Vec<&str> = usage.iter().map(|u| u.module.as_str()).collect();
+        assert_eq!(modules, ["b", "a", "gone"]);
+        assert_eq!(usage[1].stats, Some(stats(10.0, 100, 1000, 0)));
+        assert_eq!(usage[1].network_rx_rate, None);
+        assert!(usage[2].stats.is_none());
+        assert!(usage[2].error.is_some());
+
+        // Later collections report rates against the previous one.
+        let usage = aggregator.usage().await.unwrap();
+        assert!(usage[1].network_rx_rate.is_some());
+
+        let at = Instant::now();
+        let earlier = NetworkSample {
+            at,
+            rx_bytes: 1000,
+            tx_bytes: 0,
+        };
+        let later = NetworkSample {
+            at: at + Duration::from_secs(2),
+            rx_bytes: 3000,
+            tx_bytes: 0,
+        };
+        assert_eq!(earlier.rates(&later), Some((1000.0, 0.0)));
+    }
+}
@@ -0,0 +1,379 @@
+//! Retrying fallible async operations with exponential backoff.
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::error::Elapsed;
+use tokio::time::Instant;
+
+/// How [`retry`] spaces out and bounds its attempts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryConfig {
+    /// Retries after the first attempt; `0` disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound on any single delay.
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each retry.
+    pub multiplier: f64,
+    /// Sleep a random duration between zero and the computed delay ("full
+    /// jitter"), so clients failing together do not retry in lockstep.
+    pub jitter: bool,
+    /// Total time budget across all attempts and delays. No retry is made
+    /// once it would start after the budget is spent, and an attempt still
+    /// running when it runs out is cut off with an [`Elapsed`] error.
+    pub max_elapsed: Option<Duration>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: false,
+            max_elapsed: None,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Enables or disables full jitter.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Gives up once `max_elapsed` has passed since the first attempt.
+    pub fn with_max_elapsed(mut self, max_elapsed: Duration) -> Self {
+        self.max_elapsed = Some(max_elapsed);
+        self
+    }
+
+    /// Delay before retry number `retry` (zero-based), capped at
+    /// `max_delay` and without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.min(i32::MAX as u32) as i32);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+        if delay.is_finite() && delay < self.max_delay.as_secs_f64() {
+            Duration::from_secs_f64(delay)
+        } else {
+            self.max_delay
+        }
+    }
+
+    /// Time actually slept before retry number `retry`.
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        if self.jitter {
+            backoff.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Counter incremented each time an operation is retried.
+pub const RETRIES_METRIC: &str = "synapse_retry_attempts_total";
+
+/// Histogram of the number of attempts an operation took to succeed.
+pub const ATTEMPTS_METRIC: &str = "synapse_retry_attempts_to_success";
+
+/// Errors that know whether the failed operation is worth retrying.
+pub trait Retryable {
+    /// Whether the operation may succeed if attempted again.
+    fn is_retryable(&self) -> bool;
+}
+
+/// Runs `operation` until it succeeds or `config.max_retries` retries have
+/// failed, sleeping between attempts. Only errors classified as
+/// [`Retryable`] are retried; any other error is returned immediately.
+/// Returns the last error on failure. Metrics are labeled with `name`.
+pub async fn retry<T, E, F, Fut>(name: &str, config: &RetryConfig, operation: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Retryable + fmt::Display + From<Elapsed>,
+{
+    retry_if(name, config, E::is_retryable, operation).await
+}
+
+/// Like [`retry`], but retries every error.
+pub async fn retry_all<T, E, F, Fut>(name: &str, config: &RetryConfig, operation: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: fmt::Display + From<Elapsed>,
+{
+    retry_if(name, config, |_: &E| true, operation).await
+}
+
+/// Like [`retry`], but only retries errors for which `is_retryable`
+/// returns true; any other error is returned immediately.
+pub async fn retry_if<T, E, P, F, Fut>(
+    name: &str,
+    config: &RetryConfig,
+    is_retryable: P,
+    mut operation: F,
+) -> Result<T, E>
+where
+    P: Fn(&E) -> bool,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: fmt::Display + From<Elapsed>,
+{
+    let started = Instant::now();
+    let mut retries = 0;
+    loop {
+        let result = match config.max_elapsed {
+            Some(budget) => {
+                let remaining = budget.saturating_sub(started.elapsed());
+                match tokio::time::timeout(remaining, operation()).await {
+                    Ok(result) => result,
+                    Err(elapsed) => {
+                        tracing::warn!(
+                            "{} attempt {} cut off by its {:?} retry budget",
+                            name,
+                            retries + 1,
+                            budget
+                        );
+                        return Err(elapsed.into());
+                    }
+                }
+            }
+            None => operation().await,
+        };
+        let e = match result {
+            Ok(value) => {
+                metrics::histogram!(ATTEMPTS_METRIC, "operation" => name.to_string())
+                    .record(f64::from(retries + 1));
+                return Ok(value);
+            }
+            Err(e) if is_retryable(&e) => e,
+            Err(e) => return Err(e),
+        };
+        if retries == config.max_retries {
+            tracing::warn!("{} failed after {} attempts: {}", name, retries + 1, e);
+            return Err(e);
+        }
+        let delay = config.delay(retries);
+        if let Some(budget) = config.max_elapsed {
+            if started.elapsed() + delay > budget {
+                tracing::warn!(
+                    "{} failed within its {:?} retry budget: {}",
+                    name,
+                    budget,
+                    e
+                );
+                return Err(e);
+            }
+        }
+        tracing::debug!(
+            "{} attempt {} failed, retrying in {:?}: {}",
+            name,
+            retries + 1,
+            delay,
+            e
+        );
+        metrics::counter!(RETRIES_METRIC, "operation" => name.to_string()).increment(1);
+        tokio::time::sleep(delay).await;
+        retries += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use reqwest::StatusCode;
+
+    use crate::client::ClientError;
+
+    #[derive(Debug, PartialEq)]
+    enum Failure {
+        Unavailable,
+        Slow,
+        TimedOut,
+    }
+
+    impl fmt::Display for Failure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl From<Elapsed> for Failure {
+        fn from(_: Elapsed) -> Self {
+            Failure::TimedOut
+        }
+    }
+
+    impl Retryable for Failure {
+        fn is_retryable(&self) -> bool {
+            *self != Failure::TimedOut
+        }
+    }
+
+    #[test]
+    fn test_jittered_delay_within_backoff() {
+        let config = RetryConfig::default().with_jitter(true);
+        for retry in 0..10 {
+            let computed = config.backoff(retry);
+            assert!(computed <= config.max_delay);
+            for _ in 0..100 {
+                assert!(config.delay(retry) <= computed);
+            }
+        }
+        assert_eq!(config.backoff(0), Duration::from_millis(100));
+        assert_eq!(config.backoff(2), Duration::from_millis(400));
+        assert_eq!(config.backoff(20), Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_jittered_retry_eventually_succeeds() {
+        let config = RetryConfig {
+            max_retries: 5,
+            ..RetryConfig::default().with_jitter(true)
+        };
+        let attempts = AtomicU32::new(0);
+        let result = retry("test", &config, || async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0..=2 => Err(Failure::Unavailable),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(3));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gives_up_after_max_retries() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry("test", &RetryConfig::default(), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(Failure::Unavailable)
+        })
+        .await;
+        assert_eq!(result, Err(Failure::Unavailable));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_only_retryable_errors_retried_by_default() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry("test", &RetryConfig::default(), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(Failure::TimedOut)
+        })
+        .await;
+        assert_eq!(result, Err(Failure::TimedOut));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry_all("test", &RetryConfig::default(), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(Failure::TimedOut)
+        })
+        .await;
+        assert_eq!(result, Err(Failure::TimedOut));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_predicate_decides_what_is_retried() {
+        let unauthorized = || ClientError::Status(StatusCode::UNAUTHORIZED);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry("test", &RetryConfig::default(), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(unauthorized())
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(ClientError::Status(StatusCode::UNAUTHORIZED))
+        ));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // A caller that expects its token to be refreshed retries auth errors.
+        let attempts = AtomicU32::new(0);
+        let result = retry_if(
+            "test",
+            &RetryConfig::default(),
+            |e| matches!(e, ClientError::Status(StatusCode::UNAUTHORIZED)),
+            || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(unauthorized()),
+                    _ => Ok("token"),
+                }
+            },
+        )
+        .await;
+        assert_eq!(result.unwrap(), "token");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_elapsed_budget_cuts_off_slow_operation() {
+        let config = RetryConfig {
+            max_retries: 10,
+            ..RetryConfig::default().with_max_elapsed(Duration::from_secs(5))
+        };
+        let attempts = AtomicU32::new(0);
+        let started = Instant::now();
+        // Each attempt takes two seconds and only the fifth would succeed;
+        // the third is cut off when the budget runs out.
+        let result = retry("test", &config, || async {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0..=3 => Err(Failure::Slow),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(result, Err(Failure::TimedOut));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retries_are_counted() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .start_paused(true)
+                .build()
+                .unwrap();
+            let attempts = AtomicU32::new(0);
+            let result = runtime.block_on(retry("fetch", &RetryConfig::default(), || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(Failure::Unavailable),
+                    _ => Ok(()),
+                }
+            }));
+            assert_eq!(result, Ok(()));
+        });
+
+        let metrics: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key.key().name().to_string(), value))
+            .collect();
+        assert!(metrics.contains(&(RETRIES_METRIC.to_string(), DebugValue::Counter(1))));
+        let (_, attempts) = metrics
+            .iter()
+            .find(|(name, _)| name == ATTEMPTS_METRIC)
+            .unwrap();
+        assert_eq!(*attempts, DebugValue::Histogram(vec![2.0.into()]));
+    }
+}
@@ -0,0 +1,307 @@
+//! Running modules as Docker containers.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::container::{
+    ContainerConfig, ContainerConfigBuilder, ContainerConfigError, ContainerManager,
+    ContainerState, DockerError,
+};
+use crate::module::{dotted_name, Module, ModuleConfig};
+use crate::verify::expand_env_placeholders;
+
+/// Returns `config` with the `${VAR}` placeholders in its env values
+/// expanded from the registrar's environment, as it is when the module's
+/// container is created.
+pub fn resolve_env(config: &ModuleConfig) -> ModuleConfig {
+    let mut config = config.clone();
+    for value in config.env.values_mut() {
+        *value = expand_env_placeholders(value, |name| std::env::var(name).ok());
+    }
+    config
+}
+
+/// Errors produced by [`DockerModuleRuntime`].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// The module has no container image configured.
+    #[error("Module {0} has no image configured")]
+    MissingImage(String),
+
+    /// The module's config does not describe a valid container.
+    #[error("Invalid container config: {0}")]
+    InvalidConfig(#[from] ContainerConfigError),
+
+    #[error(transparent)]
+    Docker(#[from] DockerError),
+}
+
+/// Runtime state of a module, derived from its container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleState {
+    /// The container is running and not reported unhealthy.
+    Running,
+    /// The container is running but its health check is failing.
+    Unhealthy,
+    /// The container is stopped or does not exist.
+    Stopped,
+    /// The container is dead, restarting, or in an unknown state.
+    Failed,
+}
+
+impl fmt::Display for ModuleState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ModuleState::Running => "running",
+            ModuleState::Unhealthy => "unhealthy",
+            ModuleState::Stopped => "stopped",
+            ModuleState::Failed => "failed",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Default time a module is given to exit after SIGTERM before it is killed.
+pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Starts, stops and inspects the containers backing Docker modules. Each
+/// module runs in a container named after it.
+#[derive(Clone)]
+pub struct DockerModuleRuntime {
+    containers: Arc<dyn ContainerManager>,
+    stop_timeout: Duration,
+    recreate: bool,
+}
+
+impl DockerModuleRuntime {
+    pub fn new(containers: Arc<dyn ContainerManager>) -> Self {
+        Self {
+            containers,
+            stop_timeout: DEFAULT_STOP_TIMEOUT,
+            recreate: false,
+        }
+    }
+
+    /// Sets how long a module may take to exit after SIGTERM before it is
+    /// killed.
+    pub fn with_stop_timeout(mut self, timeout: Duration) -> Self {
+        self.stop_timeout = timeout;
+        self
+    }
+
+    /// Makes `start` remove any existing container and create a fresh one
+    /// from the module's current config, rather than reusing it. A stale
+    /// container left by a previous run is replaced instead of restarted.
+    pub fn with_recreate(mut self, recreate: bool) -> Self {
+        self.recreate = recreate;
+        self
+    }
+
+    fn container_config(module: &Module) -> Result<ContainerConfig, RuntimeError> {
+        let image = module
+            .config
+            .image
+            .clone()
+            .ok_or_else(|| RuntimeError::MissingImage(module.name.clone()))?;
+        let mut builder = ContainerConfigBuilder::new().image(image);
+        for (key, value) in &resolve_env(&module.config).env {
+            builder = builder.env(key, value);
+        }
+        for port in &module.config.ports {
+            builder = builder.port(port);
+        }
+        if let Some(health_check) = &module.config.health_check {
+            builder = builder.health_check(health_check.clone());
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Creates the module's container unless it already exists. With
+    /// `recreate` set, an existing container is removed first.
+    async fn ensure_container_exists(&self, module: &Module) -> Result<(), RuntimeError> {
+        let config = Self::container_config(module)?;
+        let name = dotted_name(&module.name);
+        if self.recreate {
+            match self.containers.remove_container(&name).await {
+                Ok(()) | Err(DockerError::ContainerNotFound(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match self.containers.create_container(&name, &config).await {
+            Ok(()) | Err(DockerError::ContainerExists(_)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Starts the module. Starting a module whose container is already
+    /// running succeeds without doing anything; a stopped container is
+    /// started again rather than recreated. With `recreate` set the
+    /// container is always replaced, even if it is running.
+    pub async fn start(&self, module: &Module) -> Result<(), RuntimeError> {
+        let name = dotted_name(&module.name);
+        if self.recreate {
+            self.ensure_container_exists(module).await?;
+            self.containers.start_container(&name).await?;
+            return Ok(());
+        }
+        match self.containers.get_container_status(&name).await {
+            Ok(status) if status.state == ContainerState::Running => return Ok(()),
+            Ok(_) => {}
+            Err(DockerError::ContainerNotFound(_)) => self.ensure_container_exists(module).await?,
+            Err(e) => return Err(e.into()),
+        }
+        self.containers.start_container(&name).await?;
+        Ok(())
+    }
+
+    /// Stops the module's container, killing it if it does not exit within
+    /// the stop timeout, and then removes it. Stopping a module without a
+    /// container succeeds.
+    pub async fn stop(&self, name: &str) -> Result<(), RuntimeError> {
+        let name = &dotted_name(name);
+        match self
+            .containers
+            .stop_container_with_timeout(name, self.stop_timeout.as_secs())
+            .await
+        {
+            Ok(()) => {}
+            Err(DockerError::ContainerNotFound(_)) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        match self.containers.remove_container(name).await {
+            Ok(()) | Err(DockerError::ContainerNotFound(_)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reports the module's state from its container, taking the container's
+    /// health check into account.
+    pub async fn status(&self, name: &str) -> Result<ModuleState, RuntimeError> {
+        let name = &dotted_name(name);
+        let status = match self.containers.get_container_status(name).await {
+            Ok(status) => status,
+            Err(DockerError::ContainerNotFound(_)) => return Ok(ModuleState::Stopped),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(match status.state {
+            ContainerState::Running if status.health.as_deref() == Some("unhealthy") => {
+                ModuleState::Unhealthy
+            }
+            ContainerState::Running => ModuleState::Running,
+            ContainerState::Created | ContainerState::Paused | ContainerState::Exited => {
+                ModuleState::Stopped
+            }
+            ContainerState::Restarting
+            | ContainerState::Removing
+            | ContainerState::Dead
+            | ContainerState::Unknown => ModuleState::Failed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::fake::FakeContainers;
+    use crate::module::{ModuleConfig, ModuleType};
+
+    fn module(name: &str) -> Module {
+        Module::new(name, ModuleType::Docker).with_config(ModuleConfig {
+            image: Some("synapse/echo:latest".into()),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_start_twice_is_idempotent() {
+        let containers = Arc::new(FakeContainers::default());
+        let runtime = DockerModuleRuntime::new(containers.clone());
+
+        runtime.start(&module("a")).await.unwrap();
+        runtime.start(&module("a")).await.unwrap();
+
+        assert_eq!(containers.calls(), vec!["create a", "start a"]);
+        assert_eq!(
+            containers.containers.lock().unwrap()["a"].config.image,
+            "synapse/echo:latest"
+        );
+        assert_eq!(runtime.status("a").await.unwrap(), ModuleState::Running);
+    }
+
+    #[tokio::test]
+    async fn test_start_restarts_stopped_container_without_recreating() {
+        let containers =
+            Arc::new(FakeContainers::default().with_container("a", ContainerState::Exited));
+        let runtime = DockerModuleRuntime::new(containers.clone());
+
+        runtime.start(&module("a")).await.unwrap();
+
+        assert_eq!(containers.calls(), vec!["start a"]);
+        assert_eq!(runtime.status("a").await.unwrap(), ModuleState::Running);
+    }
+
+    #[tokio::test]
+    async fn test_recreate_replaces_stale_container() {
+        let containers =
+            Arc::new(FakeContainers::default().with_container("a", ContainerState::Exited));
+        let runtime = DockerModuleRuntime::new(containers.clone()).with_recreate(true);
+
+        runtime.start(&module("a")).await.unwrap();
+
+        assert_eq!(containers.calls(), vec!["remove a", "create a", "start a"]);
+        assert_eq!(
+            containers.containers.lock().unwrap()["a"].config.image,
+            "synapse/echo:latest"
+        );
+        assert_eq!(runtime.status("a").await.unwrap(), ModuleState::Running);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_kills_container_ignoring_sigterm_after_timeout() {
+        let containers = Arc::new(
+            FakeContainers::default()
+                .with_container("a", ContainerState::Running)
+                .ignoring_sigterm("a"),
+        );
+        let runtime =
+            DockerModuleRuntime::new(containers.clone()).with_stop_timeout(Duration::from_secs(3));
+
+        let started = tokio::time::Instant::now();
+        runtime.stop("a").await.unwrap();
+
+        assert!(started.elapsed() >= Duration::from_secs(3));
+        assert_eq!(containers.calls(), vec!["stop a", "kill a", "remove a"]);
+        assert_eq!(runtime.status("a").await.unwrap(), ModuleState::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_running_but_unhealthy_container_is_unhealthy() {
+        let containers = FakeContainers::default()
+            .with_container("sick", ContainerState::Running)
+            .with_health("sick", "unhealthy")
+            .with_container("warming", ContainerState::Running)
+            .with_health("warming", "starting");
+        let runtime = DockerModuleRuntime::new(Arc::new(containers));
+
+        assert_eq!(
+            runtime.status("sick").await.unwrap(),
+            ModuleState::Unhealthy
+        );
+        assert_eq!(
+            runtime.status("warming").await.unwrap(),
+            ModuleState::Running
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_without_image_fails() {
+        let runtime = DockerModuleRuntime::new(Arc::new(FakeContainers::default()));
+        let result = runtime.start(&Module::new("a", ModuleType::Docker)).await;
+        assert_eq!(result, Err(RuntimeError::MissingImage("a".into())));
+    }
+}
@@ -0,0 +1,165 @@
+//! Scaffolding for new module directories.
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::module::ModuleType;
+use crate::verify::VerificationConfig;
+
+/// Name of the module config written by [`scaffold_module`].
+pub const CONFIG_FILE: &str = "config.yaml";
+
+/// Errors produced by [`scaffold_module`].
+#[derive(Debug, Error)]
+pub enum ScaffoldError {
+    /// The target directory exists and overwriting was not requested.
+    #[error("{0} already exists; pass --force to overwrite")]
+    AlreadyExists(PathBuf),
+
+    /// Only Docker and local modules can be scaffolded.
+    #[error("Cannot scaffold a module of type {0}")]
+    UnsupportedType(ModuleType),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+fn example_value(var: &str) -> &'static str {
+    match var {
+        "MODULE_PORT" => "8080",
+        _ => "",
+    }
+}
+
+fn config_yaml(name: &str, module_type: ModuleType, required_env: &[String]) -> String {
+    let mut yaml = format!("name: {}\ntype: {}\n", name, module_type);
+    if module_type == ModuleType::Docker {
+        yaml.push_str(&format!("image: synapse/{}:0.1.0\n", name));
+    }
+    yaml.push_str("ports:\n  - \"8080/tcp\"\ndepends_on: []\nenv:\n");
+    if required_env.is_empty() {
+        yaml.push_str("  {}\n");
+    }
+    for var in required_env {
+        yaml.push_str(&format!("  {}: \"${{{}}}\"\n", var, var));
+    }
+    yaml
+}
+
+fn env_example(required_env: &[String]) -> String {
+    required_env
+        .iter()
+        .map(|var| format!("{}={}\n", var, example_value(var)))
+        .collect()
+}
+
+const DOCKERFILE: &str = "FROM python:3.11-slim\n\
+WORKDIR /app\n\
+COPY . .\n\
+# RUN pip install -r requirements.txt\n\
+EXPOSE 8080\n\
+CMD [\"python\", \"main.py\"]\n";
+
+const INSTALL_SH: &str = "#!/usr/bin/env bash\n\
+set -euo pipefail\n\
+# Install the module's dependencies here.\n";
+
+/// Creates `<parent>/<name>` containing a starter `config.yaml`, an
+/// `.env.example` listing the variables required by `verification`, and a
+/// `Dockerfile` (Docker modules) or `install.sh` (local modules). Returns
+/// the module directory.
+pub fn scaffold_module(
+    parent: &Path,
+    name: &str,
+    module_type: ModuleType,
+    verification: &VerificationConfig,
+    force: bool,
+) -> Result<PathBuf, ScaffoldError> {
+    if module_type == ModuleType::Observer {
+        return Err(ScaffoldError::UnsupportedType(module_type));
+    }
+    let dir = parent.join(name);
+    if dir.exists() {
+        if !force {
+            return Err(ScaffoldError::AlreadyExists(dir));
+        }
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+
+    let required_env = &verification.required_env;
+    std::fs::write(
+        dir.join(CONFIG_FILE),
+        config_yaml(name, module_type, required_env),
+    )?;
+    std::fs::write(dir.join(".env.example"), env_example(required_env))?;
+    match module_type {
+        ModuleType::Docker => std::fs::write(dir.join("Dockerfile"), DOCKERFILE)?,
+        _ => std::fs::write(dir.join("install.sh"), INSTALL_SH)?,
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::load_module_config;
+    use crate::verify::ModuleVerifier;
+
+    #[test]
+    fn test_scaffold_passes_verifier() {
+        let parent = tempfile::tempdir().unwrap();
+        let verifier = ModuleVerifier::default();
+        for (name, module_type) in [("echo", ModuleType::Docker), ("tool", ModuleType::Local)] {
+            let dir = scaffold_module(parent.path(), name, module_type, verifier.config(), false)
+                .unwrap();
+
+            let module = load_module_config(dir.join(CONFIG_FILE)).unwrap();
+            assert_eq!(module.module_type, module_type);
+            verifier.verify(&module).unwrap();
+
+            let env = std::fs::read_to_string(dir.join(".env.example")).unwrap();
+            assert_eq!(env, "MODULE_PORT=8080\n");
+        }
+        assert!(parent.path().join("echo/Dockerfile").is_file());
+        assert!(parent.path().join("tool/install.sh").is_file());
+    }
+
+    #[test]
+    fn test_existing_dir_needs_force() {
+        let parent = tempfile::tempdir().unwrap();
+        let verification = VerificationConfig::default();
+        scaffold_module(
+            parent.path(),
+            "echo",
+            ModuleType::Docker,
+            &verification,
+            false,
+        )
+        .unwrap();
+        std::fs::write(parent.path().join("echo/main.py"), "print()").unwrap();
+
+        assert!(matches!(
+            scaffold_module(
+                parent.path(),
+                "echo",
+                ModuleType::Docker,
+                &verification,
+                false
+            ),
+            Err(ScaffoldError::AlreadyExists(_))
+        ));
+        assert!(parent.path().join("echo/main.py").exists());
+
+        scaffold_module(
+            parent.path(),
+            "echo",
+            ModuleType::Docker,
+            &verification,
+            true,
+        )
+        .unwrap();
+        assert!(!parent.path().join("echo/main.py").exists());
+    }
+}
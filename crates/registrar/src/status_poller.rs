@@ -0,0 +1,156 @@
+//! Polling container status and reporting changes.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::container::{ContainerManager, ContainerStatus, DockerError};
+use crate::tasks::BackgroundTasks;
+
+/// Default time between polls.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Default longest time between polls while Docker keeps failing.
+pub const DEFAULT_MAX_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A container whose status differs from the previous poll.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusChange {
+    pub name: String,
+    /// Status at the previous poll; `None` if the container did not exist.
+    pub previous: Option<ContainerStatus>,
+    /// Status now; `None` if the container does not exist.
+    pub current: Option<ContainerStatus>,
+}
+
+/// Polls the status of a set of containers and sends a [`StatusChange`]
+/// whenever one changes. The first poll establishes the baseline and sends
+/// nothing.
+#[derive(Clone)]
+pub struct StatusPoller {
+    containers: Arc<dyn ContainerManager>,
+    interval: Duration,
+    max_interval: Duration,
+}
+
+impl StatusPoller {
+    pub fn new(containers: Arc<dyn ContainerManager>) -> Self {
+        Self {
+            containers,
+            interval: DEFAULT_POLL_INTERVAL,
+            max_interval: DEFAULT_MAX_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets the time between polls.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the longest time between polls. While Docker returns errors
+    /// the interval doubles after each failed poll, up to this bound.
+    pub fn with_max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Polls every container once. Returns `Err` if Docker itself failed
+    /// for any of them; a missing container is not an error.
+    async fn poll(
+        &self,
+        names: &[String],
+    ) -> Result<HashMap<String, Option<ContainerStatus>>, DockerError> {
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        self.containers
+            .get_statuses(&names)
+            .await
+            .into_iter()
+            .map(|(name, status)| match status {
+                Ok(status) => Ok((name, Some(status))),
+                Err(DockerError::ContainerNotFound(_)) => Ok((name, None)),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+
+    /// Starts polling `names` as one of `tasks`. Polling stops when the
+    /// receiver is dropped or the tasks are shut down.
+    pub fn spawn(
+        self,
+        names: Vec<String>,
+        tasks: &BackgroundTasks,
+    ) -> (mpsc::Receiver<StatusChange>, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(names.len().max(1) * 4);
+        let handle = tasks.spawn(async move {
+            let mut known: Option<HashMap<String, Option<ContainerStatus>>> = None;
+            let mut delay = self.interval;
+            loop {
+                match self.poll(&names).await {
+                    Ok(statuses) => {
+                        if let Some(known) = &known {
+                            for name in &names {
+                                let (previous, current) = (&known[name], &statuses[name]);
+                                if previous == current {
+                                    continue;
+                                }
+                                let change = StatusChange {
+                                    name: name.clone(),
+                                    previous: previous.clone(),
+                                    current: current.clone(),
+                                };
+                                if tx.send(change).await.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                        known = Some(statuses);
+                        delay = self.interval;
+                    }
+                    Err(e) => {
+                        delay = (delay * 2).min(self.max_interval);
+                        tracing::warn!(
+                            "Failed to poll container status, retrying in {:?}: {}",
+                            delay,
+                            e
+                        );
+                    }
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = tx.closed() => return,
+                }
+            }
+        });
+        (rx, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::fake::FakeContainers;
+    use crate::container::ContainerState;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_emits_change_on_transition() {
+        let containers =
+            Arc::new(FakeContainers::default().with_container("a", ContainerState::Running));
+        let poller = StatusPoller::new(containers.clone()).with_interval(Duration::from_secs(1));
+        let (mut changes, _handle) =
+            poller.spawn(vec!["a".into(), "b".into()], &BackgroundTasks::new());
+
+        // Let the baseline poll run before changing anything.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        containers.set_state("a", ContainerState::Exited);
+
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.name, "a");
+        assert_eq!(change.previous.unwrap().state, ContainerState::Running);
+        assert_eq!(change.current.unwrap().state, ContainerState::Exited);
+        assert!(changes.try_recv().is_err());
+    }
+}
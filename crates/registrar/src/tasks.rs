@@ -0,0 +1,101 @@
+//! Tracking background tasks so they can be stopped on shutdown.
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+/// Default time background tasks are given to finish during shutdown.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The set of background tasks owned by a process. Every task spawned
+/// through it is cancelled when [`shutdown`](Self::shutdown) is called or
+/// its token fires. Clones share the same tasks and token.
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundTasks {
+    tracker: TaskTracker,
+    token: CancellationToken,
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Token that fires when the tasks are told to stop. Long-running work
+    /// that is not spawned here can watch it to stop at the same time.
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Spawns `task`, dropping it at its next await point once the token
+    /// fires.
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let token = self.token.clone();
+        self.tracker.spawn(async move {
+            tokio::select! {
+                _ = token.cancelled() => {}
+                _ = task => {}
+            }
+        })
+    }
+
+    /// Number of tasks still running.
+    pub fn len(&self) -> usize {
+        self.tracker.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracker.is_empty()
+    }
+
+    /// Cancels every task and waits up to `timeout` for them to finish.
+    /// Returns `false` if some were still running when the timeout passed.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.token.cancel();
+        self.tracker.close();
+        tokio::time::timeout(timeout, self.tracker.wait())
+            .await
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spawned_task_is_cancelled_when_token_fires() {
+        let tasks = BackgroundTasks::new();
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(1);
+        let handle = tasks.spawn(async move {
+            // Holds the sender until the task is dropped.
+            let _tx = tx;
+            std::future::pending::<()>().await;
+        });
+        assert_eq!(tasks.len(), 1);
+
+        tasks.token().cancel();
+        handle.await.unwrap();
+        assert!(rx.recv().await.is_none());
+        assert!(tasks.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_waits_for_tasks_up_to_timeout() {
+        let tasks = BackgroundTasks::new();
+        tasks.spawn(tokio::time::sleep(Duration::from_secs(3600)));
+        assert!(tasks.shutdown(Duration::from_secs(1)).await);
+
+        // A task that ignores cancellation is not waited for forever.
+        let stubborn = BackgroundTasks::new();
+        let tracker = stubborn.tracker.clone();
+        tracker.spawn(tokio::time::sleep(Duration::from_secs(3600)));
+        assert!(!stubborn.shutdown(Duration::from_secs(1)).await);
+    }
+}
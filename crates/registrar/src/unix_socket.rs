@@ -0,0 +1,166 @@
+//! Serving the API on a unix domain socket.
+//!
+//! A socket keeps the API off the network entirely: only local processes
+//! allowed by its file permissions can connect.
+
+use std::future::Future;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use axum::extract::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use tokio::net::UnixListener;
+use tower::ServiceExt;
+
+/// Permissions given to the socket: read and write for its owner and
+/// group only.
+pub const SOCKET_MODE: u32 = 0o660;
+
+/// Binds a unix socket at `path`, replacing a stale socket left by an
+/// earlier run, and restricts it to [`SOCKET_MODE`]. Anything else at
+/// `path`, or a socket another process is still serving, is left alone
+/// and reported as an error.
+///
+/// The socket is bound inside a private directory and only moved to
+/// `path` once its permissions are set, so it is never reachable with the
+/// looser mode `bind` creates it with.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Ok(_) => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another process", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let staging = staging_dir(path);
+    // Left behind by an earlier run that crashed mid-bind.
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let result = bind_in(&staging, path);
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+/// Private directory next to `path` the socket is bound in before being
+/// moved into place.
+fn staging_dir(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}", name, std::process::id()))
+}
+
+fn bind_in(staging: &Path, path: &Path) -> io::Result<UnixListener> {
+    let staged = staging.join("sock");
+    let listener = UnixListener::bind(&staged)?;
+    std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(SOCKET_MODE))?;
+    std::fs::rename(&staged, path)?;
+    Ok(listener)
+}
+
+/// Serves `app` on `listener` until `shutdown` completes, then waits for
+/// open connections to finish their requests.
+pub async fn serve(
+    listener: UnixListener,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let (socket, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = &mut shutdown => break,
+        };
+        let service = app.clone();
+        let hyper_service = hyper::service::service_fn(move |request: Request<Incoming>| {
+            service.clone().oneshot(request.map(axum::body::Body::new))
+        });
+        let connection = Builder::new(TokioExecutor::new())
+            .serve_connection_with_upgrades(TokioIo::new(socket), hyper_service)
+            .into_owned();
+        let connection = graceful.watch(connection);
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::debug!("Unix socket connection failed: {}", e);
+            }
+        });
+    }
+    graceful.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_over_unix_socket_succeeds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registrar.sock");
+        let app = Router::new().route("/health", get(|| async { "ok" }));
+        let listener = bind(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, SOCKET_MODE);
+        let entries: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec!["registrar.sock"]);
+        tokio::spawn(serve(listener, app, std::future::pending()));
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+        let request = hyper::Request::get("/health")
+            .header("host", "localhost")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"ok");
+    }
+
+    #[tokio::test]
+    async fn test_bind_replaces_only_stale_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registrar.sock");
+
+        std::fs::write(&path, "not a socket").unwrap();
+        let err = bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+
+        let listener = bind(&path).unwrap();
+        let err = bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        drop(listener);
+        bind(&path).unwrap();
+    }
+}
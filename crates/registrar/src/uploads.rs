@@ -0,0 +1,297 @@
+//! Resumable uploads of large packages.
+//!
+//! An upload is created empty and filled by appending chunks in order. The
+//! bytes received so far are kept on disk, so a client whose connection
+//! drops asks for the current offset and continues from there. Completing
+//! an upload checks the SHA-256 digest of the assembled file; a completed
+//! upload is then ingested by id. Uploads left incomplete or never
+//! ingested are removed once they expire.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+/// Largest upload accepted by default, 1 GiB.
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// How long an upload may sit untouched, incomplete or not yet ingested,
+/// before it is removed.
+pub const DEFAULT_UPLOAD_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often expired uploads are looked for.
+pub const EXPIRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Errors produced by an [`UploadStore`].
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("Upload not found: {0}")]
+    NotFound(String),
+
+    /// A chunk did not start where the previous one ended.
+    #[error("Chunk starts at byte {got}, expected {expected}")]
+    OffsetMismatch { expected: u64, got: u64 },
+
+    #[error("Upload would exceed the {max} byte limit")]
+    TooLarge { max: u64 },
+
+    #[error("Digest mismatch: expected {expected}, got {actual}")]
+    DigestMismatch { expected: String, actual: String },
+
+    /// A completed upload is not a readable `.tar.gz` archive.
+    #[error("Failed to extract upload {id}: {reason}")]
+    Extract { id: String, reason: String },
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// An upload whose digest was verified.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletedUpload {
+    pub id: String,
+    pub size: u64,
+    /// Hex-encoded SHA-256 digest of the contents.
+    pub sha256: String,
+}
+
+/// A completed upload unpacked for ingest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedUpload {
+    pub upload: CompletedUpload,
+    /// Directory the archive was unpacked into.
+    pub path: PathBuf,
+}
+
+/// Uploads stored as files in a directory.
+#[derive(Debug, Clone)]
+pub struct UploadStore {
+    dir: PathBuf,
+    max_size: u64,
+    /// Serializes appends so concurrent chunks cannot interleave.
+    lock: Arc<Mutex<()>>,
+}
+
+fn valid_id(id: &str) -> bool {
+    id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl UploadStore {
+    /// Opens the store in `dir`, creating it if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, UploadError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            max_size: DEFAULT_MAX_UPLOAD_SIZE,
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Sets the largest upload accepted, in bytes.
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    fn partial_path(&self, id: &str) -> Result<PathBuf, UploadError> {
+        if !valid_id(id) {
+            return Err(UploadError::NotFound(id.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.part", id)))
+    }
+
+    fn completed_path(&self, id: &str) -> Result<PathBuf, UploadError> {
+        if !valid_id(id) {
+            return Err(UploadError::NotFound(id.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.upload", id)))
+    }
+
+    /// Directory completed uploads are unpacked into for ingest. Ingested
+    /// modules keep their sources here, so it is never expired.
+    fn sources_dir(&self) -> PathBuf {
+        self.dir.join("sources")
+    }
+
+    /// Starts an empty upload and returns its id.
+    pub async fn create(&self) -> Result<String, UploadError> {
+        let id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        tokio::fs::File::create(self.partial_path(&id)?).await?;
+        Ok(id)
+    }
+
+    /// Number of bytes received so far, where the next chunk must start.
+    pub async fn offset(&self, id: &str) -> Result<u64, UploadError> {
+        match tokio::fs::metadata(self.partial_path(id)?).await {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(UploadError::NotFound(id.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Appends a chunk starting at byte `start`, returning the new offset.
+    pub async fn append(&self, id: &str, start: u64, chunk: &[u8]) -> Result<u64, UploadError> {
+        let _guard = self.lock.lock().await;
+        let expected = self.offset(id).await?;
+        if start != expected {
+            return Err(UploadError::OffsetMismatch {
+                expected,
+                got: start,
+            });
+        }
+        let end = expected + chunk.len() as u64;
+        if end > self.max_size {
+            return Err(UploadError::TooLarge { max: self.max_size });
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(self.partial_path(id)?)
+            .await?;
+        file.write_all(chunk).await?;
+        file.flush().await?;
+        Ok(end)
+    }
+
+    /// Checks the assembled upload against `sha256` and, if it matches,
+    /// moves it aside as complete. A mismatched upload is discarded.
+    pub async fn complete(&self, id: &str, sha256: &str) -> Result<CompletedUpload, UploadError> {
+        let _guard = self.lock.lock().await;
+        let partial = self.partial_path(id)?;
+        let (size, actual) = digest(id, &partial).await?;
+        if !actual.eq_ignore_ascii_case(sha256) {
+            tokio::fs::remove_file(&partial).await?;
+            return Err(UploadError::DigestMismatch {
+                expected: sha256.to_string(),
+                actual,
+            });
+        }
+        tokio::fs::rename(&partial, self.completed_path(id)?).await?;
+        Ok(CompletedUpload {
+            id: id.to_string(),
+            size,
+            sha256: actual,
+        })
+    }
+
+    /// Unpacks completed upload `id`, a `.tar.gz` archive, into a directory
+    /// of its own. The upload is kept until [`UploadStore::remove`] is
+    /// called, so a failed ingest can be retried.
+    pub async fn extract(&self, id: &str) -> Result<ExtractedUpload, UploadError> {
+        let _guard = self.lock.lock().await;
+        let archive = self.completed_path(id)?;
+        let (size, sha256) = digest(id, &archive).await?;
+        let target = self.sources_dir().join(id);
+        if tokio::fs::try_exists(&target).await? {
+            tokio::fs::remove_dir_all(&target).await?;
+        }
+        tokio::fs::create_dir_all(&target).await?;
+        let output = tokio::process::Command::new("tar")
+            .arg("xzf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&target)
+            .arg("--no-same-owner")
+            .output()
+            .await?;
+        if !output.status.success() {
+            tokio::fs::remove_dir_all(&target).await?;
+            return Err(UploadError::Extract {
+                id: id.to_string(),
+                reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(ExtractedUpload {
+            upload: CompletedUpload {
+                id: id.to_string(),
+                size,
+                sha256,
+            },
+            path: target,
+        })
+    }
+
+    /// Removes completed upload `id` once it has been ingested.
+    pub async fn remove(&self, id: &str) -> Result<(), UploadError> {
+        let _guard = self.lock.lock().await;
+        match tokio::fs::remove_file(self.completed_path(id)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(UploadError::NotFound(id.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Removes uploads, incomplete or completed, that have not been written
+    /// to for `ttl`, returning how many were removed.
+    pub async fn remove_expired(&self, ttl: Duration) -> Result<usize, UploadError> {
+        let _guard = self.lock.lock().await;
+        let mut removed = 0;
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_upload = matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("part" | "upload")
+            ) && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(valid_id);
+            if !is_upload {
+                continue;
+            }
+            let modified = entry.metadata().await?.modified()?;
+            if modified.elapsed().is_ok_and(|age| age >= ttl) {
+                tokio::fs::remove_file(&path).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Removes expired uploads every [`EXPIRY_INTERVAL`], for running as a
+    /// background task.
+    pub async fn expire_periodically(self, ttl: Duration) {
+        let mut ticker = tokio::time::interval(EXPIRY_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match self.remove_expired(ttl).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("Removed {} expired upload(s)", removed),
+                Err(e) => tracing::warn!("Failed to remove expired uploads: {}", e),
+            }
+        }
+    }
+}
+
+/// Size and hex-encoded SHA-256 digest of upload `id`'s file at `path`.
+async fn digest(id: &str, path: &Path) -> Result<(u64, String), UploadError> {
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(UploadError::NotFound(id.to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok((size, hex::encode(hasher.finalize())))
+}
@@ -0,0 +1,574 @@
+//! Verification of module definitions before they are registered.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::{self, ModuleDefinition};
+use crate::module::{self, HealthCheck, ModuleType, NAMESPACE_SEPARATOR};
+use crate::port::Port;
+
+/// Reasons a module definition is rejected.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The module name is empty or contains unsupported characters.
+    #[error("Invalid module name {0:?}: use lowercase letters, digits, '-' or '_'")]
+    InvalidName(String),
+
+    /// The namespace prefix of a module name is empty or contains
+    /// unsupported characters.
+    #[error("Invalid module namespace {0:?}: use lowercase letters, digits, '-' or '_'")]
+    InvalidNamespace(String),
+
+    /// A Docker module has no image.
+    #[error("Docker module {0} has no image")]
+    MissingImage(String),
+
+    /// A port is not of the form `<1-65535>[/tcp|/udp]`.
+    #[error("Invalid port {0:?}: expected <1-65535>[/tcp|/udp]")]
+    InvalidPort(String),
+
+    /// The module lists itself as a dependency.
+    #[error("Module {0} depends on itself")]
+    SelfDependency(String),
+
+    /// The module's health check cannot be run.
+    #[error("Invalid health check for {module}: {reason}")]
+    InvalidHealthCheck { module: String, reason: String },
+
+    /// A required environment variable is not set in the module's env.
+    #[error("Required environment variable {0} is not set")]
+    MissingEnv(String),
+
+    /// The image is pulled from a registry that is not allowed.
+    #[error("Image {image} is pulled from {registry}, which is not an allowed registry")]
+    DisallowedRegistry { image: String, registry: String },
+}
+
+/// Problems that do not block registration but are likely mistakes.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum LintWarning {
+    /// The image is untagged or tagged `latest`, so what runs can change
+    /// without the module changing.
+    #[error("Image {0} is not pinned to a version; avoid :latest")]
+    UnpinnedImage(String),
+
+    /// A Docker module has no health check, so a hung container is
+    /// reported as running.
+    #[error("Docker module {0} has no health check")]
+    MissingHealthCheck(String),
+
+    /// A port below 1024, which needs elevated privileges on most hosts.
+    #[error("Port {0:?} is privileged; prefer a port of 1024 or above")]
+    PrivilegedPort(String),
+}
+
+/// Every problem found in a module definition. Errors block registration;
+/// warnings do not.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintReport {
+    pub errors: Vec<VerificationError>,
+    pub warnings: Vec<LintWarning>,
+}
+
+impl LintReport {
+    /// Whether the module may be registered.
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Requirements every module definition must meet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationConfig {
+    /// Environment variables every module must define.
+    pub required_env: Vec<String>,
+    /// Registries images may be pulled from, e.g. `ghcr.io` or `docker.io`;
+    /// any registry is allowed when empty.
+    #[serde(default)]
+    pub allowed_registries: Vec<String>,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            required_env: vec!["MODULE_PORT".to_string()],
+            allowed_registries: Vec::new(),
+        }
+    }
+}
+
+/// Checks module definitions against a [`VerificationConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct ModuleVerifier {
+    config: VerificationConfig,
+}
+
+pub(crate) fn valid_port(port: &str) -> bool {
+    port.parse::<Port>().is_ok()
+}
+
+/// Whether `namespace` may prefix a module name.
+pub fn valid_namespace(namespace: &str) -> bool {
+    name_part_ok(namespace)
+}
+
+fn name_part_ok(part: &str) -> bool {
+    !part.is_empty()
+        && part
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Checks a module name, optionally prefixed with a namespace as in
+/// `team-a/echo`. The namespace and base name are checked separately, so a
+/// bad prefix is reported as [`VerificationError::InvalidNamespace`].
+pub fn verify_name(name: &str) -> Vec<VerificationError> {
+    let mut errors = Vec::new();
+    if name.contains(NAMESPACE_SEPARATOR) && !name_part_ok(module::split_name(name).0) {
+        errors.push(VerificationError::InvalidNamespace(name.to_string()));
+    }
+    if !name_part_ok(module::split_name(name).1) {
+        errors.push(VerificationError::InvalidName(name.to_string()));
+    }
+    errors
+}
+
+fn health_check_problem(check: &HealthCheck) -> Option<String> {
+    match check.test.first().map(String::as_str) {
+        None => return Some("test is empty".to_string()),
+        Some("NONE") => {}
+        Some("CMD") | Some("CMD-SHELL") if check.test.len() > 1 => {}
+        Some("CMD") | Some("CMD-SHELL") => return Some("test has no command".to_string()),
+        Some(other) => {
+            return Some(format!(
+                "test must start with CMD, CMD-SHELL or NONE, not {:?}",
+                other
+            ))
+        }
+    }
+    if check.interval_secs == 0 || check.timeout_secs == 0 {
+        return Some("interval and timeout must be positive".to_string());
+    }
+    None
+}
+
+/// Whether `image` names a specific version: a tag other than `latest`,
+/// or a digest.
+fn image_pinned(image: &str) -> bool {
+    if image.contains('@') {
+        return true;
+    }
+    // A ':' before the last '/' belongs to a registry port, not a tag.
+    let name = image.rsplit('/').next().unwrap_or(image);
+    matches!(name.split_once(':'), Some((_, tag)) if tag != "latest")
+}
+
+/// Registry `image` is pulled from; Docker Hub, `docker.io`, when the
+/// image does not name one.
+pub fn image_registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((first, _)) if first.contains(['.', ':']) || first == "localhost" => first,
+        _ => "docker.io",
+    }
+}
+
+impl ModuleVerifier {
+    pub fn new(config: VerificationConfig) -> Self {
+        Self { config }
+    }
+
+    /// Returns the verifier's requirements.
+    pub fn config(&self) -> &VerificationConfig {
+        &self.config
+    }
+
+    /// Verifies a module definition, returning the first problem found.
+    pub fn verify(&self, module: &ModuleDefinition) -> Result<(), VerificationError> {
+        match self.verify_all(module).into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Verifies a module definition, returning every problem found in the
+    /// order [`verify`](Self::verify) checks for them.
+    pub fn verify_all(&self, module: &ModuleDefinition) -> Vec<VerificationError> {
+        let mut errors = Vec::new();
+        errors.extend(verify_name(&module.name));
+        if module.module_type == ModuleType::Docker && module.config.image.is_none() {
+            errors.push(VerificationError::MissingImage(module.name.clone()));
+        }
+        errors.extend(
+            module
+                .config
+                .ports
+                .iter()
+                .filter(|p| !valid_port(p))
+                .map(|p| VerificationError::InvalidPort(p.clone())),
+        );
+        if let Some(image) = &module.config.image {
+            let registry = image_registry(image);
+            if !self.config.allowed_registries.is_empty()
+                && !self.config.allowed_registries.iter().any(|r| r == registry)
+            {
+                errors.push(VerificationError::DisallowedRegistry {
+                    image: image.clone(),
+                    registry: registry.to_string(),
+                });
+            }
+        }
+        if module.config.depends_on.contains(&module.name) {
+            errors.push(VerificationError::SelfDependency(module.name.clone()));
+        }
+        if let Some(check) = &module.config.health_check {
+            if let Some(reason) = health_check_problem(check) {
+                errors.push(VerificationError::InvalidHealthCheck {
+                    module: module.name.clone(),
+                    reason,
+                });
+            }
+        }
+        errors.extend(
+            self.config
+                .required_env
+                .iter()
+                .filter(|var| !module.config.env.contains_key(*var))
+                .map(|var| VerificationError::MissingEnv(var.clone())),
+        );
+        errors
+    }
+
+    /// Checks a module definition for both errors and warnings.
+    pub fn lint(&self, module: &ModuleDefinition) -> LintReport {
+        let mut warnings = Vec::new();
+        if let Some(image) = &module.config.image {
+            if !image_pinned(image) {
+                warnings.push(LintWarning::UnpinnedImage(image.clone()));
+            }
+        }
+        if module.module_type == ModuleType::Docker && module.config.health_check.is_none() {
+            warnings.push(LintWarning::MissingHealthCheck(module.name.clone()));
+        }
+        warnings.extend(
+            module
+                .config
+                .ports
+                .iter()
+                .filter(|p| p.parse::<Port>().is_ok_and(|port| port.is_privileged()))
+                .map(|p| LintWarning::PrivilegedPort(p.clone())),
+        );
+        LintReport {
+            errors: self.verify_all(module),
+            warnings,
+        }
+    }
+}
+
+/// Names of the `${VAR}` placeholders in `value`.
+pub fn env_placeholders(value: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        match after.find('}') {
+            Some(end) => {
+                names.push(&after[..end]);
+                rest = &after[end + 1..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+/// Replaces each `${VAR}` placeholder in `value` with what `lookup`
+/// returns for it. Placeholders `lookup` cannot resolve are left as they
+/// are.
+pub fn expand_env_placeholders(value: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        match lookup(&after[..end]) {
+            Some(resolved) => expanded.push_str(&resolved),
+            None => expanded.push_str(&rest[start..start + end + 3]),
+        }
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Placeholders in the module's env values that `lookup` cannot resolve,
+/// sorted and deduplicated.
+pub fn unresolved_env_vars(
+    module: &ModuleDefinition,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    module
+        .config
+        .env
+        .values()
+        .flat_map(|value| env_placeholders(value))
+        .filter(|name| lookup(name).is_none())
+        .map(str::to_string)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// How serious a [`ValidationIssue`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Prevents the module from being registered.
+    Error,
+    /// Reported, but does not prevent registration.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// A problem found while validating a module config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// Description of the problem.
+    pub message: String,
+    /// 1-based line number and contents of the line the problem refers to,
+    /// when it can be located.
+    pub line: Option<(usize, String)>,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.line {
+            Some((number, line)) => write!(f, "{}\n  {:>4} | {}", self.message, number, line),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+fn locate(contents: &str, needle: &str) -> Option<(usize, String)> {
+    contents
+        .lines()
+        .enumerate()
+        .find(|(_, line)| line.contains(needle))
+        .map(|(i, line)| (i + 1, line.trim_end().to_string()))
+}
+
+fn issue(contents: &str, message: String, needle: Option<&str>) -> ValidationIssue {
+    ValidationIssue {
+        severity: Severity::Error,
+        line: needle.and_then(|needle| locate(contents, needle)),
+        message,
+    }
+}
+
+/// Loads the module config at `path` and reports every problem found:
+/// load or parse failures, verifier errors, env placeholders that `lookup`
+/// cannot resolve, and lint warnings. Errors are listed before warnings;
+/// the config is valid when there are no errors.
+pub fn validate_config_file(
+    path: &Path,
+    verifier: &ModuleVerifier,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<ValidationIssue> {
+    let module = match config::load_module_config(path) {
+        Ok(module) => module,
+        Err(e) => return vec![issue("", e.to_string(), None)],
+    };
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+
+    let report = verifier.lint(&module);
+    let mut issues = Vec::new();
+    for e in &report.errors {
+        let needle = match e {
+            VerificationError::InvalidName(name)
+            | VerificationError::InvalidNamespace(name)
+            | VerificationError::SelfDependency(name)
+            | VerificationError::MissingImage(name) => Some(name.as_str()),
+            VerificationError::InvalidHealthCheck { .. } => Some("health_check"),
+            VerificationError::InvalidPort(port) => Some(port.as_str()),
+            VerificationError::DisallowedRegistry { image, .. } => Some(image.as_str()),
+            VerificationError::MissingEnv(_) => None,
+        };
+        issues.push(issue(&contents, e.to_string(), needle));
+    }
+    for var in unresolved_env_vars(&module, lookup) {
+        let placeholder = format!("${{{}}}", var);
+        issues.push(issue(
+            &contents,
+            format!("Environment variable {} is not set", var),
+            Some(&placeholder),
+        ));
+    }
+    for warning in &report.warnings {
+        let needle = match warning {
+            LintWarning::UnpinnedImage(image) => Some(image.as_str()),
+            LintWarning::MissingHealthCheck(_) => None,
+            LintWarning::PrivilegedPort(port) => Some(port.as_str()),
+        };
+        issues.push(ValidationIssue {
+            severity: Severity::Warning,
+            ..issue(&contents, warning.to_string(), needle)
+        });
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"name: echo
+type: docker
+image: synapse/echo:1.0
+ports:
+  - "99999/tcp"
+env:
+  MODULE_PORT: "8080"
+  API_KEY: "${ECHO_API_KEY}"
+"#;
+
+    #[test]
+    fn test_bad_port_and_missing_env_var_both_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("echo.yaml");
+        std::fs::write(&path, CONFIG).unwrap();
+
+        let issues = validate_config_file(&path, &ModuleVerifier::default(), |_| None);
+
+        assert_eq!(issues.len(), 3, "{:?}", issues);
+        assert!(issues[0].message.contains("Invalid port \"99999/tcp\""));
+        assert_eq!(issues[0].line, Some((5, "  - \"99999/tcp\"".to_string())));
+        assert!(issues[1].message.contains("ECHO_API_KEY"));
+        assert_eq!(issues[1].line.as_ref().map(|(n, _)| *n), Some(8));
+        assert_eq!(issues[2].severity, Severity::Warning);
+        assert_eq!(issues[2].message, "Docker module echo has no health check");
+    }
+
+    #[test]
+    fn test_resolvable_config_is_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("echo.yaml");
+        std::fs::write(&path, CONFIG.replace("99999", "8080")).unwrap();
+
+        let issues = validate_config_file(&path, &ModuleVerifier::default(), |name| {
+            (name == "ECHO_API_KEY").then(|| "secret".to_string())
+        });
+        assert!(
+            issues.iter().all(|i| i.severity == Severity::Warning),
+            "{:?}",
+            issues
+        );
+    }
+
+    #[test]
+    fn test_lint_separates_warnings_from_errors() {
+        let module = config::parse_module_config(
+            "name: echo\ntype: docker\nimage: synapse/echo:latest\nports: [\"80\", \"0\"]\n",
+            config::ConfigFormat::Yaml,
+        )
+        .unwrap();
+
+        let report = ModuleVerifier::default().lint(&module);
+
+        assert_eq!(
+            report.errors,
+            vec![
+                VerificationError::InvalidPort("0".into()),
+                VerificationError::MissingEnv("MODULE_PORT".into()),
+            ]
+        );
+        assert_eq!(
+            report.warnings,
+            vec![
+                LintWarning::UnpinnedImage("synapse/echo:latest".into()),
+                LintWarning::MissingHealthCheck("echo".into()),
+                LintWarning::PrivilegedPort("80".into()),
+            ]
+        );
+        assert!(!report.is_valid());
+
+        // Warnings alone leave the module registrable.
+        let latest = config::parse_module_config(
+            "name: echo\ntype: docker\nimage: synapse/echo:latest\nenv:\n  MODULE_PORT: \"8080\"\n",
+            config::ConfigFormat::Yaml,
+        )
+        .unwrap();
+        let report = ModuleVerifier::default().lint(&latest);
+        assert!(report.is_valid());
+        assert!(report
+            .warnings
+            .contains(&LintWarning::UnpinnedImage("synapse/echo:latest".into())));
+        assert!(image_pinned("registry:5000/echo:1.0"));
+        assert!(!image_pinned("registry:5000/echo"));
+        assert!(image_pinned("echo@sha256:abc"));
+    }
+
+    #[test]
+    fn test_missing_required_env_rejected() {
+        let module =
+            config::parse_module_config("name: echo\ntype: local\n", config::ConfigFormat::Yaml)
+                .unwrap();
+        assert_eq!(
+            ModuleVerifier::default().verify(&module),
+            Err(VerificationError::MissingEnv("MODULE_PORT".into()))
+        );
+    }
+
+    #[test]
+    fn test_injected_health_check_is_valid_and_empty_test_rejected() {
+        use crate::module::HealthCheckDefaults;
+
+        let mut module = config::parse_module_config(
+            "name: echo\ntype: docker\nimage: synapse/echo:1.0\nports: [\"8080\"]\nenv:\n  MODULE_PORT: \"8080\"\n",
+            config::ConfigFormat::Yaml,
+        )
+        .unwrap();
+        assert!(HealthCheckDefaults::default().apply(module.module_type, &mut module.config));
+        assert_eq!(ModuleVerifier::default().verify(&module), Ok(()));
+
+        module.config.health_check.as_mut().unwrap().test = vec!["CMD".into()];
+        assert_eq!(
+            ModuleVerifier::default().verify(&module),
+            Err(VerificationError::InvalidHealthCheck {
+                module: "echo".into(),
+                reason: "test has no command".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_namespace_checked_separately_from_name() {
+        assert!(verify_name("echo").is_empty());
+        assert!(verify_name("team-a/echo").is_empty());
+        assert_eq!(
+            verify_name("Team A/echo"),
+            vec![VerificationError::InvalidNamespace("Team A/echo".into())]
+        );
+        assert_eq!(
+            verify_name("team-a/Echo"),
+            vec![VerificationError::InvalidName("team-a/Echo".into())]
+        );
+        assert_eq!(
+            verify_name("a/b/c"),
+            vec![VerificationError::InvalidName("a/b/c".into())]
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Outbound webhooks notifying operators of module and miner events
+//! without holding a WebSocket open.
+//!
+//! The registrar does not track miner liveness, so the only miner event is
+//! a registration; a miner going silent is not reported.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use thiserror::Error;
+
+use crate::events::RegistryEvent;
+use crate::miner::{Miner, Registration};
+use crate::retry::{retry_if, RetryConfig};
+use crate::tasks::BackgroundTasks;
+
+/// Header carrying `sha256=<hex>`, the HMAC-SHA256 of
+/// `<timestamp>.<body>` keyed with the webhook's secret, where the
+/// timestamp is the one sent in [`TIMESTAMP_HEADER`]. Only sent for
+/// webhooks with a secret.
+pub const SIGNATURE_HEADER: &str = "X-Synapse-Signature";
+
+/// Header carrying the Unix time, in seconds, a delivery was signed at.
+/// Receivers should reject deliveries whose timestamp is too old, so a
+/// captured delivery cannot be replayed later.
+pub const TIMESTAMP_HEADER: &str = "X-Synapse-Timestamp";
+
+/// How long a receiver is given to answer one delivery attempt.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events POSTed to webhooks, encoded like [`WsMessage`](crate::api::ws::WsMessage).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum WebhookEvent {
+    /// A module was created, changed, deleted or failed to start.
+    Module(RegistryEvent),
+    /// A miner was registered or re-registered. This is the only miner
+    /// event sent: missed heartbeats and stale miners are not reported.
+    Miner(MinerStatusChange),
+}
+
+/// A miner's registration, the only miner change webhooks report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinerStatusChange {
+    pub miner: Miner,
+    pub registration: Registration,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Request body for `POST /webhooks`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewWebhook {
+    /// `http` or `https` URL events are POSTed to.
+    pub url: String,
+    /// Key deliveries are signed with; unsigned when absent.
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+/// A registered webhook. Its secret is never reported back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: u64,
+    pub url: String,
+    /// Whether deliveries carry a [`SIGNATURE_HEADER`].
+    pub signed: bool,
+}
+
+/// Errors managing or delivering webhooks.
+#[derive(Debug, Error)]
+pub enum WebhookError {
+    #[error("Invalid webhook URL {url:?}: {reason}")]
+    InvalidUrl { url: String, reason: String },
+
+    #[error("Webhook {0} not found")]
+    NotFound(u64),
+
+    #[error("Webhook delivery failed: {0}")]
+    Delivery(#[from] reqwest::Error),
+
+    #[error("Webhook receiver answered {0}")]
+    Status(reqwest::StatusCode),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Webhook delivery timed out: {0}")]
+    TimedOut(#[from] tokio::time::error::Elapsed),
+}
+
+impl WebhookError {
+    /// Whether another delivery attempt may succeed: connection errors,
+    /// timeouts, 5xx and 429 are retried; other answers are final.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            WebhookError::Delivery(_) => true,
+            WebhookError::Status(status) => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns `sha256=<hex>`, the signature sent in [`SIGNATURE_HEADER`] for
+/// `body` sent at `timestamp` under `secret`.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Debug, Clone)]
+struct Target {
+    webhook: Webhook,
+    secret: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u64,
+    targets: Vec<Target>,
+}
+
+/// Registered webhooks and their delivery. Webhooks loaded with
+/// [`Webhooks::load`] are kept in the `webhooks` table and survive a
+/// restart; the default set lives only in memory. Clones share the same
+/// webhooks.
+#[derive(Debug, Clone)]
+pub struct Webhooks {
+    inner: Arc<Mutex<Inner>>,
+    pool: Option<SqlitePool>,
+    client: reqwest::Client,
+    retry: RetryConfig,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                next_id: 1,
+                targets: Vec::new(),
+            })),
+            pool: None,
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            retry: RetryConfig::default().with_jitter(true),
+        }
+    }
+}
+
+impl Webhooks {
+    /// Loads the webhooks stored in the `webhooks` table of `pool`, which
+    /// webhooks added or removed later are written to.
+    pub async fn load(pool: SqlitePool) -> Result<Self, WebhookError> {
+        let rows = sqlx::query("SELECT id, url, secret FROM webhooks ORDER BY id")
+            .fetch_all(&pool)
+            .await?;
+        let mut targets = Vec::with_capacity(rows.len());
+        for row in rows {
+            let secret: Option<String> = row.try_get("secret")?;
+            targets.push(Target {
+                webhook: Webhook {
+                    id: row.try_get::<i64, _>("id")? as u64,
+                    url: row.try_get("url")?,
+                    signed: secret.is_some(),
+                },
+                secret,
+            });
+        }
+        let webhooks = Self::default();
+        *webhooks.inner.lock().unwrap() = Inner {
+            next_id: targets.last().map_or(1, |t| t.webhook.id + 1),
+            targets,
+        };
+        Ok(Self {
+            pool: Some(pool),
+            ..webhooks
+        })
+    }
+
+    /// Sets how failed deliveries are retried.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Registers a webhook after checking its URL.
+    pub async fn add(&self, request: NewWebhook) -> Result<Webhook, WebhookError> {
+        let invalid = |reason: String| WebhookError::InvalidUrl {
+            url: request.url.clone(),
+            reason,
+        };
+        let url = url::Url::parse(&request.url).map_err(|e| invalid(e.to_string()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(invalid(format!(
+                "scheme {} is not http or https",
+                url.scheme()
+            )));
+        }
+        let secret = request.secret.filter(|secret| !secret.is_empty());
+        let stored_id = match &self.pool {
+            Some(pool) => Some(
+                sqlx::query("INSERT INTO webhooks (url, secret, created_at) VALUES (?, ?, ?)")
+                    .bind(&request.url)
+                    .bind(&secret)
+                    .bind(Utc::now())
+                    .execute(pool)
+                    .await?
+                    .last_insert_rowid() as u64,
+            ),
+            None => None,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let id = stored_id.unwrap_or(inner.next_id);
+        inner.next_id = id + 1;
+        let webhook = Webhook {
+            id,
+            url: request.url.clone(),
+            signed: secret.is_some(),
+        };
+        inner.targets.push(Target {
+            webhook: webhook.clone(),
+            secret,
+        });
+        Ok(webhook)
+    }
+
+    /// Registered webhooks, oldest first.
+    pub fn list(&self) -> Vec<Webhook> {
+        let inner = self.inner.lock().unwrap();
+        inner.targets.iter().map(|t| t.webhook.clone()).collect()
+    }
+
+    /// Removes a webhook. Deliveries already under way still finish.
+    pub async fn remove(&self, id: u64) -> Result<(), WebhookError> {
+        if let Some(pool) = &self.pool {
+            sqlx::query("DELETE FROM webhooks WHERE id = ?")
+                .bind(id as i64)
+                .execute(pool)
+                .await?;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.targets.len();
+        inner.targets.retain(|t| t.webhook.id != id);
+        if inner.targets.len() == before {
+            return Err(WebhookError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    /// Delivers `event` to every webhook on `tasks`, retrying with backoff
+    /// until the receiver answers 2xx. Deliveries are at least once: a
+    /// receiver that answered slowly may see the same event again.
+    pub fn notify(&self, tasks: &BackgroundTasks, event: WebhookEvent) {
+        let targets = self.inner.lock().unwrap().targets.clone();
+        if targets.is_empty() {
+            return;
+        }
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("Failed to encode webhook event: {}", e);
+                return;
+            }
+        };
+        for target in targets {
+            let client = self.client.clone();
+            let retry = self.retry.clone();
+            let body = body.clone();
+            tasks.spawn(async move {
+                let result = retry_if(
+                    "webhook_delivery",
+                    &retry,
+                    WebhookError::is_transient,
+                    || deliver(&client, &target, &body),
+                )
+                .await;
+                if let Err(e) = result {
+                    tracing::warn!(
+                        "Giving up on webhook {} ({}): {}",
+                        target.webhook.id,
+                        target.webhook.url,
+                        e
+                    );
+                }
+            });
+        }
+    }
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    target: &Target,
+    body: &[u8],
+) -> Result<(), WebhookError> {
+    let mut request = client
+        .post(&target.webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_vec());
+    if let Some(secret) = &target.secret {
+        // Signed per attempt, so a retry carries a fresh timestamp.
+        let timestamp = Utc::now().timestamp();
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, sign(secret, timestamp, body));
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(WebhookError::Status(response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::registry::SqliteRegistry;
+
+    #[tokio::test]
+    async fn test_add_rejects_non_http_urls() {
+        let webhooks = Webhooks::default();
+        for url in ["ftp://example.com/hook", "not a url"] {
+            let request = NewWebhook {
+                url: url.into(),
+                secret: None,
+            };
+            assert!(matches!(
+                webhooks.add(request).await,
+                Err(WebhookError::InvalidUrl { .. })
+            ));
+        }
+        let webhook = webhooks
+            .add(NewWebhook {
+                url: "https://example.com/hook".into(),
+                secret: Some("s3cret".into()),
+            })
+            .await
+            .unwrap();
+        assert!(webhook.signed);
+        assert_eq!(webhooks.list(), vec![webhook.clone()]);
+        webhooks.remove(webhook.id).await.unwrap();
+        assert!(matches!(
+            webhooks.remove(webhook.id).await,
+            Err(WebhookError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stored_webhooks_survive_reload() {
+        let registry = SqliteRegistry::in_memory().await.unwrap();
+        let webhooks = Webhooks::load(registry.pool().clone()).await.unwrap();
+        let kept = webhooks
+            .add(NewWebhook {
+                url: "https://example.com/kept".into(),
+                secret: Some("s3cret".into()),
+            })
+            .await
+            .unwrap();
+        let removed = webhooks
+            .add(NewWebhook {
+                url: "https://example.com/removed".into(),
+                secret: None,
+            })
+            .await
+            .unwrap();
+        webhooks.remove(removed.id).await.unwrap();
+
+        let reloaded = Webhooks::load(registry.pool().clone()).await.unwrap();
+        assert_eq!(reloaded.list(), vec![kept]);
+        let target = &reloaded.inner.lock().unwrap().targets[0];
+        assert_eq!(target.secret.as_deref(), Some("s3cret"));
+    }
+
+    #[test]
+    fn test_signature_covers_timestamp() {
+        let body = br#"{"type":"Module"}"#;
+        assert_eq!(
+            sign("s3cret", 1_700_000_000, body),
+            sign("s3cret", 1_700_000_000, body)
+        );
+        assert_ne!(
+            sign("s3cret", 1_700_000_000, body),
+            sign("s3cret", 1_700_000_001, body)
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! HTTP API for monitoring and controlling the validator.
+//!
+//! Challenge datasets can be large, so `POST /challenges` reads its body
+//! as a stream of newline-delimited JSON and hands each challenge on as it
+//! arrives. The queue it feeds is bounded: while it is full the body is not
+//! read further, so a fast client is slowed to the validator's pace.
+
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::module_logs::LogStore;
+
+/// Default largest request body, 16 MiB.
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Settings of the validator API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Largest request body accepted, in bytes; larger bodies get 413.
+    pub max_body_bytes: u64,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+}
+
+/// Shared state for API handlers.
+#[derive(Clone)]
+pub struct ApiState {
+    config: ApiConfig,
+    /// Receives submitted challenges, one JSON value each.
+    challenges: mpsc::Sender<Value>,
+    /// Recent container output served at `GET /logs/:module`.
+    logs: Arc<LogStore>,
+}
+
+impl ApiState {
+    pub fn new(challenges: mpsc::Sender<Value>) -> Self {
+        Self {
+            config: ApiConfig::default(),
+            challenges,
+            logs: Arc::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ApiConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Serves the module logs kept in `logs`.
+    pub fn with_logs(mut self, logs: Arc<LogStore>) -> Self {
+        self.logs = logs;
+        self
+    }
+}
+
+/// Query parameters for `GET /logs/:module`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogsParams {
+    /// Only return this many of the most recent lines.
+    pub tail: Option<usize>,
+}
+
+/// Response to a challenge submission.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmissionResponse {
+    /// Challenges queued.
+    pub accepted: usize,
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+fn too_large(max: u64) -> Response {
+    error(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Request body exceeds {} bytes", max),
+    )
+}
+
+/// Builds the API router.
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/health", get(|| async { StatusCode::OK }))
+        .route("/challenges", post(submit_challenges))
+        .route("/logs/:module", get(module_logs))
+        .with_state(state)
+}
+
+/// `GET /logs/:module?tail=`
+///
+/// The recent output of the module's container, oldest line first; empty
+/// for a module whose output has not been seen.
+async fn module_logs(
+    State(state): State<ApiState>,
+    Path(module): Path<String>,
+    Query(params): Query<LogsParams>,
+) -> Json<Vec<String>> {
+    Json(state.logs.tail(&module, params.tail))
+}
+
+/// `POST /challenges`
+///
+/// Queues every challenge in a newline-delimited JSON body. A body whose
+/// declared or actual size exceeds the limit is answered with 413; lines
+/// read before the limit was reached stay queued.
+async fn submit_challenges(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let max = state.config.max_body_bytes;
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|len| len > max) {
+        return too_large(max);
+    }
+
+    let mut stream = body.into_data_stream();
+    let mut read = 0u64;
+    let mut pending: Vec<u8> = Vec::new();
+    let mut accepted = 0;
+    let mut line_number = 0;
+    loop {
+        let chunk: Option<Bytes> = match stream.next().await {
+            Some(Ok(chunk)) => Some(chunk),
+            Some(Err(e)) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+            None => None,
+        };
+        let done = chunk.is_none();
+        if let Some(chunk) = chunk {
+            read += chunk.len() as u64;
+            if read > max {
+                return too_large(max);
+            }
+            pending.extend_from_slice(&chunk);
+        }
+        // Everything up to the last newline is complete lines; at the end
+        // of the body the remainder is a final line.
+        let complete = match pending.iter().rposition(|b| *b == b'\n') {
+            _ if done => pending.len(),
+            Some(i) => i + 1,
+            None => continue,
+        };
+        let lines: Vec<u8> = pending.drain(..complete).collect();
+        // Splitting after each newline, so the one ending the last line
+        // does not start an empty line of its own.
+        for line in lines.split_inclusive(|b| *b == b'\n') {
+            line_number += 1;
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let challenge: Value = match serde_json::from_slice(line) {
+                Ok(challenge) => challenge,
+                Err(e) => {
+                    return error(
+                        StatusCode::BAD_REQUEST,
+                        format!("Line {}: {}", line_number, e),
+                    )
+                }
+            };
+            if state.challenges.send(challenge).await.is_err() {
+                return error(StatusCode::SERVICE_UNAVAILABLE, "Challenge queue closed");
+            }
+            accepted += 1;
+        }
+        if done {
+            return Json(SubmissionResponse { accepted }).into_response();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(max_body_bytes: u64) -> (Router, mpsc::Receiver<Value>) {
+        let (tx, rx) = mpsc::channel(16);
+        let state = ApiState::new(tx).with_config(ApiConfig { max_body_bytes });
+        (router(state), rx)
+    }
+
+    async fn post(app: Router, body: Body, content_length: Option<usize>) -> (StatusCode, Value) {
+        let mut request =
+            Request::post("/challenges").header("content-type", "application/x-ndjson");
+        if let Some(len) = content_length {
+            request = request.header("content-length", len);
+        }
+        let response = app.oneshot(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_challenges_streamed_into_queue() {
+        let (app, mut rx) = app(1024);
+        let body = "{\"prompt\": \"a\"}\n\n{\"prompt\": \"b\"}";
+
+        let (status, response) = post(app, Body::from(body), Some(body.len())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!({"accepted": 2}));
+        assert_eq!(rx.recv().await.unwrap(), json!({"prompt": "a"}));
+        assert_eq!(rx.recv().await.unwrap(), json!({"prompt": "b"}));
+    }
+
+    #[tokio::test]
+    async fn test_oversize_challenge_body_rejected() {
+        let (app, _rx) = app(64);
+        let line = "{\"prompt\": \"0123456789\"}\n";
+
+        let body = line.repeat(10);
+        let (status, _) = post(app.clone(), Body::from(body.clone()), Some(body.len())).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Without a declared length the limit applies as the body streams.
+        let chunks =
+            futures::stream::iter((0..10).map(move |_| Ok::<_, std::io::Error>(Bytes::from(line))));
+        let (status, body) = post(app, Body::from_stream(chunks), None).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["error"], "Request body exceeds 64 bytes");
+    }
+
+    #[tokio::test]
+    async fn test_line_numbers_count_across_chunks() {
+        let (app, _rx) = app(1024);
+        let chunks = futures::stream::iter(
+            ["{\"prompt\": \"a\"}\n", "\n", "oops\n"]
+                .map(|chunk| Ok::<_, std::io::Error>(Bytes::from(chunk))),
+        );
+
+        let (status, body) = post(app, Body::from_stream(chunks), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(
+            body["error"].as_str().unwrap().starts_with("Line 3:"),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn test_module_logs_served_from_store() {
+        let (tx, _rx) = mpsc::channel(1);
+        let logs = Arc::new(LogStore::new());
+        for line in ["one", "two", "three"] {
+            logs.append("team/echo", line);
+        }
+        let app = router(ApiState::new(tx).with_logs(logs));
+
+        let request = Request::get("/logs/team%2Fecho?tail=2")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let lines: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(lines, json!(["two", "three"]));
+    }
+}
@@ -0,0 +1,156 @@
+//! Learning which challenge formats a miner accepts.
+//!
+//! Miners advertise the inference APIs they serve at `GET /capabilities`.
+//! The validator asks once, caches the answer, and challenges each miner
+//! in the first format both sides support.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Default time a miner's capabilities are cached.
+pub const DEFAULT_CAPABILITY_TTL: Duration = Duration::from_secs(600);
+
+/// What a miner reports at `GET /capabilities`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinerCapabilities {
+    /// Inference APIs the miner serves, e.g. `text-generation/v1`.
+    pub apis: Vec<String>,
+}
+
+impl MinerCapabilities {
+    /// The first of `preferred` the miner serves.
+    pub fn negotiate<'a>(&self, preferred: &'a [String]) -> Option<&'a str> {
+        preferred
+            .iter()
+            .find(|api| self.apis.contains(api))
+            .map(String::as_str)
+    }
+}
+
+/// Looks up a miner's capabilities.
+#[async_trait]
+pub trait CapabilitySource: Send + Sync {
+    async fn capabilities(&self, miner: &str) -> Result<MinerCapabilities, String>;
+}
+
+/// Queries `GET <miner>/capabilities`, where a miner is identified by its
+/// base URL.
+#[derive(Debug, Clone, Default)]
+pub struct HttpCapabilities {
+    client: reqwest::Client,
+}
+
+impl HttpCapabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CapabilitySource for HttpCapabilities {
+    async fn capabilities(&self, miner: &str) -> Result<MinerCapabilities, String> {
+        let url = format!("{}/capabilities", miner.trim_end_matches('/'));
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Caches what a [`CapabilitySource`] reports, per miner, for a fixed
+/// time. Failed lookups are not cached.
+pub struct CapabilityCache {
+    source: Box<dyn CapabilitySource>,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, MinerCapabilities)>>,
+}
+
+impl CapabilityCache {
+    pub fn new(source: impl CapabilitySource + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+            ttl: DEFAULT_CAPABILITY_TTL,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how long a miner's capabilities are reused before asking again.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Returns `miner`'s capabilities, asking it only when nothing fresh is
+    /// cached.
+    pub async fn get(&self, miner: &str) -> Result<MinerCapabilities, String> {
+        if let Some((at, capabilities)) = self.entries.lock().unwrap().get(miner) {
+            if at.elapsed() < self.ttl {
+                return Ok(capabilities.clone());
+            }
+        }
+        let capabilities = self.source.capabilities(miner).await?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(miner.to_string(), (Instant::now(), capabilities.clone()));
+        Ok(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    struct Counting(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl CapabilitySource for Counting {
+        async fn capabilities(&self, _miner: &str) -> Result<MinerCapabilities, String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(MinerCapabilities {
+                apis: vec!["text-generation/v1".into()],
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_capabilities_cached_until_ttl() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache = CapabilityCache::new(Counting(calls.clone())).with_ttl(Duration::from_secs(60));
+
+        cache.get("alice").await.unwrap();
+        cache.get("alice").await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        cache.get("alice").await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_negotiate_picks_first_preferred_api() {
+        let capabilities = MinerCapabilities {
+            apis: vec!["embeddings/v1".into(), "text-generation/v1".into()],
+        };
+        let preferred =
+            ["text-generation/v2", "text-generation/v1", "embeddings/v1"].map(String::from);
+        assert_eq!(
+            capabilities.negotiate(&preferred),
+            Some("text-generation/v1")
+        );
+        assert_eq!(capabilities.negotiate(&preferred[..1]), None);
+    }
+}
@@ -0,0 +1,489 @@
+//! Challenging miners in rounds.
+//!
+//! Every miner in a round is challenged at once, each under its own
+//! timeout, so a slow miner costs the round at most that timeout. With a
+//! [`CapabilityCache`], each miner is challenged in the first format it
+//! supports, and miners supporting none are left out of the round.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use synapse_registrar::tasks::BackgroundTasks;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::capabilities::{CapabilityCache, HttpCapabilities};
+use crate::response_log::ResponseLogger;
+
+/// Default time a miner has to answer a challenge, in milliseconds.
+pub const DEFAULT_CHALLENGE_TIMEOUT_MS: u64 = 10_000;
+
+/// Challenge format sent to miners that do not advertise capabilities.
+pub const DEFAULT_CHALLENGE_API: &str = "inference/v1";
+
+/// Challenges queued for rounds before submitters have to wait.
+pub const CHALLENGE_QUEUE_CAPACITY: usize = 64;
+
+/// How miners are challenged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChallengeConfig {
+    /// Time each miner has to answer, in milliseconds.
+    pub timeout_ms: u64,
+    /// Challenge formats the validator can send, most preferred first.
+    pub apis: Vec<String>,
+}
+
+impl Default for ChallengeConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: DEFAULT_CHALLENGE_TIMEOUT_MS,
+            apis: vec![DEFAULT_CHALLENGE_API.to_string()],
+        }
+    }
+}
+
+impl ChallengeConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// Sends a challenge to a miner and checks its answer.
+#[async_trait]
+pub trait Challenger: Send + Sync {
+    /// Sends `challenge` to `miner` in the `api` format, failing with a
+    /// reason when its answer is wrong or it cannot be reached.
+    async fn challenge(&self, miner: &str, api: &str, challenge: &Value) -> Result<(), String>;
+}
+
+/// POSTs `{"api": .., "challenge": ..}` to `<miner>/challenge`, where a
+/// miner is identified by its base URL. A miner passes by answering with a
+/// success status.
+#[derive(Debug, Clone, Default)]
+pub struct HttpChallenger {
+    client: reqwest::Client,
+}
+
+impl HttpChallenger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Challenger for HttpChallenger {
+    async fn challenge(&self, miner: &str, api: &str, challenge: &Value) -> Result<(), String> {
+        let url = format!("{}/challenge", miner.trim_end_matches('/'));
+        self.client
+            .post(&url)
+            .json(&json!({ "api": api, "challenge": challenge }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// How a miner answered a challenge.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", content = "reason", rename_all = "snake_case")]
+pub enum ChallengeOutcome {
+    Passed,
+    Failed(String),
+    TimedOut,
+    /// The miner supports none of the validator's challenge formats and
+    /// was not challenged.
+    Incompatible(String),
+}
+
+/// One miner's result in a round.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeResult {
+    pub miner: String,
+    pub outcome: ChallengeOutcome,
+    /// Time until the miner answered, or the timeout if it did not.
+    pub latency: Duration,
+}
+
+/// Results of a round, in the order the miners were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundReport {
+    pub results: Vec<ChallengeResult>,
+}
+
+impl RoundReport {
+    fn count(&self, matches: impl Fn(&ChallengeOutcome) -> bool) -> usize {
+        self.results.iter().filter(|r| matches(&r.outcome)).count()
+    }
+
+    pub fn passed(&self) -> usize {
+        self.count(|outcome| *outcome == ChallengeOutcome::Passed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, ChallengeOutcome::Failed(_)))
+    }
+
+    pub fn timed_out(&self) -> usize {
+        self.count(|outcome| *outcome == ChallengeOutcome::TimedOut)
+    }
+
+    pub fn incompatible(&self) -> usize {
+        self.count(|outcome| matches!(outcome, ChallengeOutcome::Incompatible(_)))
+    }
+}
+
+/// Challenges miners and records their responses.
+pub struct ChallengeRound {
+    challenger: Arc<dyn Challenger>,
+    logger: Arc<dyn ResponseLogger>,
+    timeout: Duration,
+    apis: Vec<String>,
+    capabilities: Option<Arc<CapabilityCache>>,
+}
+
+impl ChallengeRound {
+    pub fn new(challenger: Arc<dyn Challenger>, logger: Arc<dyn ResponseLogger>) -> Self {
+        Self {
+            challenger,
+            logger,
+            timeout: ChallengeConfig::default().timeout(),
+            apis: ChallengeConfig::default().apis,
+            capabilities: None,
+        }
+    }
+
+    /// Challenges miners over HTTP as `config` says, asking each which
+    /// formats it supports first.
+    pub fn http(config: &ChallengeConfig, logger: Arc<dyn ResponseLogger>) -> Self {
+        Self::new(Arc::new(HttpChallenger::new()), logger)
+            .with_timeout(config.timeout())
+            .with_apis(config.apis.clone())
+            .with_capabilities(Arc::new(CapabilityCache::new(HttpCapabilities::new())))
+    }
+
+    /// Gives each miner `timeout` to answer.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the challenge formats the validator can send, most preferred
+    /// first.
+    pub fn with_apis(mut self, apis: Vec<String>) -> Self {
+        self.apis = apis;
+        self
+    }
+
+    /// Asks miners which formats they support before challenging them.
+    /// Without this every miner is sent the most preferred format.
+    pub fn with_capabilities(mut self, capabilities: Arc<CapabilityCache>) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Sends `challenge` to every miner in `miners` at once. A miner that
+    /// does not answer within the timeout is recorded as failed and the
+    /// round goes on without it, as is a miner that supports none of the
+    /// formats.
+    pub async fn run(&self, miners: &[String], challenge: &Value) -> RoundReport {
+        let results = join_all(miners.iter().map(|miner| self.challenge(miner, challenge))).await;
+        let report = RoundReport { results };
+        tracing::info!(
+            "Challenge round: {} passed, {} failed, {} timed out, {} incompatible",
+            report.passed(),
+            report.failed(),
+            report.timed_out(),
+            report.incompatible()
+        );
+        report
+    }
+
+    /// Runs a round over `miners` for each challenge received on
+    /// `challenges`, one round at a time, as part of `tasks`. Stops when
+    /// every sender is dropped.
+    pub fn spawn(
+        self,
+        miners: Vec<String>,
+        mut challenges: mpsc::Receiver<Value>,
+        tasks: &BackgroundTasks,
+    ) -> JoinHandle<()> {
+        tasks.spawn(async move {
+            while let Some(challenge) = challenges.recv().await {
+                self.run(&miners, &challenge).await;
+            }
+        })
+    }
+
+    /// The format to challenge `miner` in.
+    async fn negotiate(&self, miner: &str) -> Result<&str, ChallengeOutcome> {
+        let Some(cache) = &self.capabilities else {
+            return self
+                .apis
+                .first()
+                .map(String::as_str)
+                .ok_or_else(|| ChallengeOutcome::Incompatible("no challenge formats".into()));
+        };
+        let capabilities = match tokio::time::timeout(self.timeout, cache.get(miner)).await {
+            Ok(Ok(capabilities)) => capabilities,
+            Ok(Err(reason)) => {
+                return Err(ChallengeOutcome::Failed(format!(
+                    "capabilities unavailable: {}",
+                    reason
+                )))
+            }
+            Err(_) => return Err(ChallengeOutcome::TimedOut),
+        };
+        capabilities.negotiate(&self.apis).ok_or_else(|| {
+            tracing::info!(
+                "Miner {} supports none of the challenge formats: {:?}",
+                miner,
+                capabilities.apis
+            );
+            ChallengeOutcome::Incompatible(format!("supports {:?}", capabilities.apis))
+        })
+    }
+
+    async fn challenge(&self, miner: &str, challenge: &Value) -> ChallengeResult {
+        self.logger.log_request(miner);
+        let api = match self.negotiate(miner).await {
+            Ok(api) => api,
+            Err(outcome) => {
+                self.logger.log_response(miner, false);
+                let latency = if outcome == ChallengeOutcome::TimedOut {
+                    self.timeout
+                } else {
+                    Duration::ZERO
+                };
+                return ChallengeResult {
+                    miner: miner.to_string(),
+                    outcome,
+                    latency,
+                };
+            }
+        };
+        let started = Instant::now();
+        let outcome = match tokio::time::timeout(
+            self.timeout,
+            self.challenger.challenge(miner, api, challenge),
+        )
+        .await
+        {
+            Ok(Ok(())) => ChallengeOutcome::Passed,
+            Ok(Err(reason)) => {
+                tracing::debug!("Miner {} failed its challenge: {}", miner, reason);
+                ChallengeOutcome::Failed(reason)
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "Miner {} did not answer its challenge within {:?}",
+                    miner,
+                    self.timeout
+                );
+                ChallengeOutcome::TimedOut
+            }
+        };
+        self.logger
+            .log_response(miner, outcome == ChallengeOutcome::Passed);
+        ChallengeResult {
+            miner: miner.to_string(),
+            outcome,
+            latency: started.elapsed().min(self.timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::capabilities::{CapabilitySource, MinerCapabilities};
+    use crate::response_log::DefaultResponseLogger;
+
+    /// Answers after a fixed delay per miner; unknown miners fail at once.
+    struct DelayedChallenger(HashMap<&'static str, Duration>);
+
+    #[async_trait]
+    impl Challenger for DelayedChallenger {
+        async fn challenge(
+            &self,
+            miner: &str,
+            _api: &str,
+            _challenge: &Value,
+        ) -> Result<(), String> {
+            let delay = self.0.get(miner).ok_or("unknown miner")?;
+            tokio::time::sleep(*delay).await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_round_completes_when_one_miner_times_out() {
+        let challenger = DelayedChallenger(HashMap::from([
+            ("fast", Duration::from_millis(200)),
+            ("slow", Duration::from_secs(600)),
+        ]));
+        let logger = Arc::new(DefaultResponseLogger::new());
+        let round = ChallengeRound::new(Arc::new(challenger), logger.clone())
+            .with_timeout(Duration::from_secs(5));
+        let miners = ["fast", "slow", "gone"].map(String::from);
+        let started = Instant::now();
+
+        let report = round.run(&miners, &json!({"prompt": "hi"})).await;
+
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+        assert_eq!(
+            report.results,
+            vec![
+                ChallengeResult {
+                    miner: "fast".into(),
+                    outcome: ChallengeOutcome::Passed,
+                    latency: Duration::from_millis(200),
+                },
+                ChallengeResult {
+                    miner: "slow".into(),
+                    outcome: ChallengeOutcome::TimedOut,
+                    latency: Duration::from_secs(5),
+                },
+                ChallengeResult {
+                    miner: "gone".into(),
+                    outcome: ChallengeOutcome::Failed("unknown miner".into()),
+                    latency: Duration::ZERO,
+                },
+            ]
+        );
+        assert_eq!(
+            (report.passed(), report.failed(), report.timed_out()),
+            (1, 1, 1)
+        );
+        assert_eq!(logger.get_success_rate("fast"), Some(1.0));
+        assert_eq!(logger.get_success_rate("slow"), Some(0.0));
+    }
+
+    /// Advertises a fixed set of APIs per miner.
+    struct FixedCapabilities(HashMap<&'static str, Vec<String>>);
+
+    #[async_trait]
+    impl CapabilitySource for FixedCapabilities {
+        async fn capabilities(&self, miner: &str) -> Result<MinerCapabilities, String> {
+            let apis = self.0.get(miner).cloned().ok_or("unreachable")?;
+            Ok(MinerCapabilities { apis })
+        }
+    }
+
+    /// Passes every challenge, recording the format each miner was sent.
+    #[derive(Default)]
+    struct RecordingChallenger(Mutex<Vec<(String, String)>>);
+
+    #[async_trait]
+    impl Challenger for RecordingChallenger {
+        async fn challenge(
+            &self,
+            miner: &str,
+            api: &str,
+            _challenge: &Value,
+        ) -> Result<(), String> {
+            self.0.lock().unwrap().push((miner.into(), api.into()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_miner_with_unsupported_capability_is_excluded() {
+        let capabilities = FixedCapabilities(HashMap::from([
+            ("modern", vec!["text-generation/v2".to_string()]),
+            ("legacy", vec!["text-generation/v1".to_string()]),
+            ("vision", vec!["image-generation/v1".to_string()]),
+        ]));
+        let challenger = Arc::new(RecordingChallenger::default());
+        let logger = Arc::new(DefaultResponseLogger::new());
+        let round = ChallengeRound::new(challenger.clone(), logger.clone())
+            .with_apis(vec![
+                "text-generation/v2".into(),
+                "text-generation/v1".into(),
+            ])
+            .with_capabilities(Arc::new(CapabilityCache::new(capabilities)));
+
+        let report = round
+            .run(
+                &["modern", "legacy", "vision"].map(String::from),
+                &json!({"prompt": "hi"}),
+            )
+            .await;
+
+        assert_eq!(
+            *challenger.0.lock().unwrap(),
+            vec![
+                ("modern".to_string(), "text-generation/v2".to_string()),
+                ("legacy".to_string(), "text-generation/v1".to_string()),
+            ]
+        );
+        assert_eq!(
+            report.results[2].outcome,
+            ChallengeOutcome::Incompatible("supports [\"image-generation/v1\"]".into())
+        );
+        assert_eq!((report.passed(), report.incompatible()), (2, 1));
+        assert_eq!(logger.get_success_rate("vision"), Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn test_queued_challenges_sent_to_miners_over_http() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = {
+            let received = received.clone();
+            axum::Router::new()
+                .route(
+                    "/capabilities",
+                    axum::routing::get(|| async {
+                        axum::Json(json!({"apis": ["text-generation/v2"]}))
+                    }),
+                )
+                .route(
+                    "/challenge",
+                    axum::routing::post(move |axum::Json(body): axum::Json<Value>| async move {
+                        received.lock().unwrap().push(body);
+                        axum::http::StatusCode::OK
+                    }),
+                )
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let miner = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let logger = Arc::new(DefaultResponseLogger::new());
+        let config = ChallengeConfig {
+            timeout_ms: 5_000,
+            apis: vec!["text-generation/v1".into(), "text-generation/v2".into()],
+        };
+        let round = ChallengeRound::http(&config, logger.clone());
+        let (tx, rx) = mpsc::channel(CHALLENGE_QUEUE_CAPACITY);
+        let handle = round.spawn(
+            vec![miner.clone(), "http://127.0.0.1:1".into()],
+            rx,
+            &BackgroundTasks::new(),
+        );
+        tx.send(json!({"prompt": "a"})).await.unwrap();
+        tx.send(json!({"prompt": "b"})).await.unwrap();
+        drop(tx);
+        handle.await.unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                json!({"api": "text-generation/v2", "challenge": {"prompt": "a"}}),
+                json!({"api": "text-generation/v2", "challenge": {"prompt": "b"}}),
+            ]
+        );
+        assert_eq!(logger.get_success_rate(&miner), Some(1.0));
+        assert_eq!(logger.get_success_rate("http://127.0.0.1:1"), Some(0.0));
+    }
+}
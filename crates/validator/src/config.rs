@@ -0,0 +1,343 @@
+//! Validator configuration files.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use synapse_registrar::config::ConfigFormat;
+use synapse_registrar::container::{
+    ContainerConfig, ContainerConfigBuilder, ContainerConfigError, RestartPolicy,
+};
+use synapse_registrar::module::HealthCheck;
+
+use crate::api::ApiConfig;
+use crate::challenge::ChallengeConfig;
+use crate::module_logs::LogRetention;
+use crate::restart::AutoRestartConfig;
+use crate::weights::WeightSettingConfig;
+
+/// Default port the validator serves on.
+pub const DEFAULT_VALIDATOR_PORT: u16 = 4000;
+
+/// Default URL of the registrar.
+pub const DEFAULT_REGISTRAR_URL: &str = "http://127.0.0.1:3000";
+
+/// Errors produced while loading a validator config file.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ValidatorConfigError {
+    /// The file extension does not map to a known format.
+    #[error("Unsupported config format for {path}: expected .yaml, .yml, .toml or .json")]
+    UnsupportedFormat { path: String },
+
+    /// The file could not be read.
+    #[error("Failed to read {path}: {reason}")]
+    Io { path: String, reason: String },
+
+    /// The file contents are not a valid validator config.
+    #[error("Invalid {format} validator config: {reason}")]
+    Parse {
+        format: ConfigFormat,
+        reason: String,
+    },
+
+    /// The scoring weights are negative, not finite or all zero.
+    #[error("Invalid scoring weights: {0}")]
+    InvalidWeights(String),
+
+    /// An auto-restart backoff is negative, not finite or too large.
+    #[error("Invalid auto-restart policy: {0}")]
+    InvalidAutoRestart(String),
+
+    /// The weight-setting epoch is zero.
+    #[error("Invalid weight setting: {0}")]
+    InvalidWeightSetting(String),
+}
+
+/// Name of the container the validator runs in.
+pub const VALIDATOR_CONTAINER: &str = "synapse-validator";
+
+/// The container the validator runs in.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerSpec {
+    pub image: String,
+    pub env: BTreeMap<String, String>,
+    /// Exposed ports, e.g. `"4000/tcp"`.
+    pub ports: Vec<String>,
+    /// Bind mounts, as `host:container` or `host:container:ro`.
+    pub volumes: Vec<String>,
+    pub health_check: Option<HealthCheck>,
+    pub restart_policy: RestartPolicy,
+    /// Keep the container after it exits, so the logs of a crash can be
+    /// inspected. It is then removed when the validator next starts.
+    /// Otherwise a container without a restart policy is removed by Docker
+    /// as soon as it exits.
+    pub keep_failed_containers: bool,
+}
+
+impl TryFrom<&ContainerSpec> for ContainerConfig {
+    type Error = ContainerConfigError;
+
+    fn try_from(spec: &ContainerSpec) -> Result<Self, Self::Error> {
+        let auto_remove = !spec.keep_failed_containers && spec.restart_policy == RestartPolicy::No;
+        let mut builder = ContainerConfigBuilder::new()
+            .image(&spec.image)
+            .restart_policy(spec.restart_policy)
+            .auto_remove(auto_remove);
+        for (key, value) in &spec.env {
+            builder = builder.env(key, value);
+        }
+        for port in &spec.ports {
+            builder = builder.port(port);
+        }
+        for volume in &spec.volumes {
+            builder = builder.volume(volume);
+        }
+        if let Some(health_check) = &spec.health_check {
+            builder = builder.health_check(health_check.clone());
+        }
+        builder.build()
+    }
+}
+
+/// How much each measurement contributes to a miner's score. Weights are
+/// relative; they are normalized before use.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringWeights {
+    /// Whether the miner's module is up and healthy.
+    pub availability: f64,
+    /// How quickly it answers.
+    pub latency: f64,
+    /// How good its answers are.
+    pub accuracy: f64,
+}
+
+impl Default for ScoringWeights {
+    fn default() -> Self {
+        Self {
+            availability: 0.3,
+            latency: 0.2,
+            accuracy: 0.5,
+        }
+    }
+}
+
+impl ScoringWeights {
+    fn validate(&self) -> Result<(), ValidatorConfigError> {
+        let weights = [self.availability, self.latency, self.accuracy];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(ValidatorConfigError::InvalidWeights(
+                "weights must be finite and non-negative".into(),
+            ));
+        }
+        if weights.iter().sum::<f64>() == 0.0 {
+            return Err(ValidatorConfigError::InvalidWeights(
+                "at least one weight must be positive".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The weights scaled to sum to one.
+    pub fn normalized(&self) -> Self {
+        let total = self.availability + self.latency + self.accuracy;
+        Self {
+            availability: self.availability / total,
+            latency: self.latency / total,
+            accuracy: self.accuracy / total,
+        }
+    }
+}
+
+/// Settings for running a validator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValidatorConfig {
+    /// Port the validator serves on.
+    pub port: u16,
+    /// Limits of the validator's HTTP API.
+    pub api: ApiConfig,
+    /// URL of the registrar modules are fetched from.
+    pub registrar_url: String,
+    /// Subnet the validator scores miners on.
+    pub netuid: u16,
+    /// Miners to challenge and score: each miner's base URL, by its uid on
+    /// the subnet.
+    pub miners: BTreeMap<u16, String>,
+    /// Container to run the validator in, if it is containerized.
+    pub container: Option<ContainerSpec>,
+    pub scoring: ScoringWeights,
+    /// Restarting of failing modules; off unless configured.
+    pub auto_restart: AutoRestartConfig,
+    /// Setting miners' weights on chain; off until a funded key is
+    /// configured.
+    pub weight_setting: WeightSettingConfig,
+    /// Challenging miners, including how long each has to answer.
+    pub challenge: ChallengeConfig,
+    /// How much of each module's container log is kept.
+    pub log_retention: LogRetention,
+}
+
+impl Default for ValidatorConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_VALIDATOR_PORT,
+            api: ApiConfig::default(),
+            registrar_url: DEFAULT_REGISTRAR_URL.into(),
+            netuid: 0,
+            miners: BTreeMap::new(),
+            container: None,
+            scoring: ScoringWeights::default(),
+            auto_restart: AutoRestartConfig::default(),
+            weight_setting: WeightSettingConfig::default(),
+            challenge: ChallengeConfig::default(),
+            log_retention: LogRetention::default(),
+        }
+    }
+}
+
+/// Parses a validator config in the given format.
+pub fn parse_validator_config(
+    contents: &str,
+    format: ConfigFormat,
+) -> Result<ValidatorConfig, ValidatorConfigError> {
+    let parsed: Result<ValidatorConfig, String> = match format {
+        ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        ConfigFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+        ConfigFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+    };
+    let config = parsed.map_err(|reason| ValidatorConfigError::Parse { format, reason })?;
+    config.scoring.validate()?;
+    config
+        .auto_restart
+        .validate()
+        .map_err(ValidatorConfigError::InvalidAutoRestart)?;
+    config
+        .weight_setting
+        .validate()
+        .map_err(ValidatorConfigError::InvalidWeightSetting)?;
+    Ok(config)
+}
+
+/// Loads a validator config from `path`, choosing the parser from the file
+/// extension.
+pub fn load_validator_config(
+    path: impl AsRef<Path>,
+) -> Result<ValidatorConfig, ValidatorConfigError> {
+    let path = path.as_ref();
+    let format =
+        ConfigFormat::from_path(path).ok_or_else(|| ValidatorConfigError::UnsupportedFormat {
+            path: path.display().to_string(),
+        })?;
+    let contents = std::fs::read_to_string(path).map_err(|e| ValidatorConfigError::Io {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    parse_validator_config(&contents, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_registrar::port::Port;
+
+    const YAML: &str = r#"
+port: 4100
+registrar_url: http://registrar:3000
+netuid: 7
+miners:
+  3: http://miner-a:8080
+  5: http://miner-b:8080
+container:
+  image: synapse/validator:1.0
+  ports: ["4100/tcp"]
+  env:
+    RUST_LOG: info
+scoring:
+  availability: 2
+  latency: 1
+  accuracy: 1
+"#;
+
+    #[test]
+    fn test_loads_config_with_scoring_weights() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("validator.yaml");
+        std::fs::write(&path, YAML).unwrap();
+
+        let config = load_validator_config(&path).unwrap();
+        assert_eq!(config.port, 4100);
+        assert_eq!(config.registrar_url, "http://registrar:3000");
+        assert_eq!(config.netuid, 7);
+        assert_eq!(config.miners[&5], "http://miner-b:8080");
+        assert_eq!(
+            config.scoring.normalized(),
+            ScoringWeights {
+                availability: 0.5,
+                latency: 0.25,
+                accuracy: 0.25,
+            }
+        );
+
+        let container = ContainerConfig::try_from(config.container.as_ref().unwrap()).unwrap();
+        assert_eq!(container.image, "synapse/validator:1.0");
+        assert_eq!(container.ports, vec![Port::tcp(4100)]);
+        assert_eq!(container.env["RUST_LOG"], "info");
+        assert!(container.auto_remove);
+    }
+
+    #[test]
+    fn test_keep_failed_containers_disables_auto_remove() {
+        let mut spec = ContainerSpec {
+            image: "synapse/validator:1.0".into(),
+            keep_failed_containers: true,
+            ..Default::default()
+        };
+        assert!(!ContainerConfig::try_from(&spec).unwrap().auto_remove);
+
+        // Docker cannot auto-remove a container it is meant to restart.
+        spec.keep_failed_containers = false;
+        spec.restart_policy = RestartPolicy::UnlessStopped;
+        assert!(!ContainerConfig::try_from(&spec).unwrap().auto_remove);
+    }
+
+    #[test]
+    fn test_invalid_configs_are_rejected() {
+        let defaults = parse_validator_config("{}", ConfigFormat::Json).unwrap();
+        assert_eq!(defaults, ValidatorConfig::default());
+
+        let err = parse_validator_config("port = \"x\"", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid TOML validator config"));
+
+        let zero = "scoring: {availability: 0, latency: 0, accuracy: 0}";
+        assert!(matches!(
+            parse_validator_config(zero, ConfigFormat::Yaml),
+            Err(ValidatorConfigError::InvalidWeights(_))
+        ));
+
+        for restart in [
+            "auto_restart: {default: {backoff_secs: -1}}",
+            "auto_restart: {modules: {echo: {max_backoff_secs: .nan}}}",
+            "auto_restart: {default: {max_backoff_secs: 1e300}}",
+        ] {
+            assert!(
+                matches!(
+                    parse_validator_config(restart, ConfigFormat::Yaml),
+                    Err(ValidatorConfigError::InvalidAutoRestart(_))
+                ),
+                "{}",
+                restart
+            );
+        }
+
+        assert_eq!(
+            parse_validator_config("weight_setting: {epoch_secs: 0}", ConfigFormat::Yaml),
+            Err(ValidatorConfigError::InvalidWeightSetting(
+                "epoch_secs must be positive".into()
+            ))
+        );
+    }
+}
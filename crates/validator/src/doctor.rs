@@ -0,0 +1,81 @@
+//! `validator doctor`: checking the validator's environment before it is
+//! started.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Args;
+
+use synapse_registrar::client::RegistrarClient;
+use synapse_registrar::doctor::{check_docker, check_python, Check, DoctorReport};
+use synapse_registrar::reconnect::ReconnectingContainers;
+
+use crate::config::{load_validator_config, ValidatorConfig};
+
+/// How long the registrar is given to answer its health check.
+const REGISTRAR_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Check that the validator's dependencies are usable
+#[derive(Debug, Clone, Args)]
+pub struct DoctorCommand {
+    /// Validator config file (.yaml, .yml, .toml or .json)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// URL of the registrar, overriding the config file [default: http://127.0.0.1:3000]
+    #[arg(long)]
+    pub registrar_url: Option<String>,
+}
+
+impl DoctorCommand {
+    /// Runs the checks and prints the report. Returns whether every check
+    /// passed.
+    pub async fn run(&self) -> bool {
+        let mut report = DoctorReport::default();
+        let config = match &self.config {
+            Some(path) => match load_validator_config(path) {
+                Ok(config) => {
+                    report.push(Check::pass("config", format!("{} loaded", path.display())));
+                    config
+                }
+                Err(e) => {
+                    report.push(Check::fail(
+                        "config",
+                        e.to_string(),
+                        "fix the config file or pass --config with a valid one",
+                    ));
+                    ValidatorConfig::default()
+                }
+            },
+            None => ValidatorConfig::default(),
+        };
+        let registrar_url = self.registrar_url.clone().unwrap_or(config.registrar_url);
+
+        report.push(check_docker(&ReconnectingContainers::docker()).await);
+        report.push(check_registrar(&registrar_url).await);
+        report.push(check_python().await);
+        println!("{}", report);
+        report.passed()
+    }
+}
+
+/// Checks that the registrar at `url` answers its health check.
+async fn check_registrar(url: &str) -> Check {
+    const NAME: &str = "registrar";
+    let hint = "start the registrar (registrar serve) or pass --registrar-url";
+    let client = match RegistrarClient::new(url) {
+        Ok(client) => client,
+        Err(e) => return Check::fail(NAME, e.to_string(), hint),
+    };
+    match tokio::time::timeout(REGISTRAR_CHECK_TIMEOUT, client.health()).await {
+        Ok(Ok(())) => Check::pass(NAME, format!("{} is healthy", url)),
+        Ok(Err(e)) => Check::fail(NAME, format!("{}: {}", url, e), hint),
+        Err(_) => Check::fail(
+            NAME,
+            format!(
+                "{} did not answer within {:?}",
+                url, REGISTRAR_CHECK_TIMEOUT
+            ),
+            hint,
+        ),
+    }
+}
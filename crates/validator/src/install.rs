@@ -0,0 +1,601 @@
+//! Installing modules from packages served by a registrar.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use synapse_registrar::api::packages::{COMMIT_HEADER, SHA256_HEADER};
+use synapse_registrar::package::{INSTALLER, MODULES_DIR_ENV};
+use synapse_registrar::retry::{retry_if, RetryConfig};
+
+/// Errors produced while installing a module.
+#[derive(Debug, Error)]
+pub enum InstallError {
+    /// The registrar URL could not be parsed.
+    #[error("Invalid registrar URL: {0}")]
+    InvalidUrl(String),
+
+    /// The connection failed or broke off while downloading the package.
+    #[error("Failed to download {name}: {reason}")]
+    Download { name: String, reason: String },
+
+    /// The registrar answered with an unsuccessful status.
+    #[error("Failed to download {name}: status {status}")]
+    Status {
+        name: String,
+        status: reqwest::StatusCode,
+    },
+
+    /// The registrar's response is not a usable package.
+    #[error("Invalid package response for {name}: {reason}")]
+    InvalidResponse { name: String, reason: String },
+
+    /// The downloaded archive does not match the hash the registrar
+    /// advertised for it.
+    #[error("Hash mismatch for {name}: expected {expected}, got {actual}")]
+    HashMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// The archive could not be unpacked.
+    #[error("Failed to extract {name}: {reason}")]
+    Extract { name: String, reason: String },
+
+    /// The package's installer is missing or does not look like a shell
+    /// script.
+    #[error("Invalid installer for {name}: {reason}")]
+    InvalidInstaller { name: String, reason: String },
+
+    /// The installer exited unsuccessfully.
+    #[error("Installer for {name} failed: {reason}")]
+    Installer { name: String, reason: String },
+
+    /// The download was cut off by its retry budget.
+    #[error("Download timed out: {0}")]
+    TimedOut(#[from] tokio::time::error::Elapsed),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl InstallError {
+    /// Whether downloading again may succeed: the connection failed or the
+    /// registrar reported a server error.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            InstallError::Download { .. } => true,
+            InstallError::Status { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+/// A package downloaded from the registrar whose hash has been checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadedPackage {
+    pub name: String,
+    /// Commit the package was built from, if the registrar reported it.
+    pub commit: Option<String>,
+    /// Hex-encoded SHA-256 of the archive.
+    pub sha256: String,
+    pub archive: Vec<u8>,
+}
+
+/// Interpreter lines an installer may start with.
+pub const KNOWN_SHEBANGS: &[&str] = &[
+    "#!/usr/bin/env bash",
+    "#!/usr/bin/env sh",
+    "#!/bin/bash",
+    "#!/bin/sh",
+];
+
+/// Default directory modules are installed into, `~/.synapsis/modules`,
+/// matching the default of the generated installer.
+pub fn default_modules_dir() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    PathBuf::from(home).join(".synapsis").join("modules")
+}
+
+/// Install a module package from the registrar
+#[derive(Debug, Clone, Args)]
+pub struct InstallCommand {
+    /// Name of the module to install
+    pub module: String,
+    /// URL of the registrar serving the package
+    #[arg(long, default_value = "http://127.0.0.1:3000")]
+    pub registrar_url: String,
+    /// Directory modules are installed into [default: ~/.synapsis/modules]
+    #[arg(long, env = MODULES_DIR_ENV)]
+    pub modules_dir: Option<PathBuf>,
+    /// Download and verify the package and print its installer without
+    /// extracting or running anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Times a failed download is retried when the connection fails or the
+    /// registrar answers with a server error
+    #[arg(long, default_value_t = 3)]
+    pub retries: u32,
+    /// Milliseconds to wait before the first retry; doubles with each retry
+    #[arg(long, default_value_t = 500)]
+    pub retry_delay_ms: u64,
+}
+
+impl InstallCommand {
+    fn modules_dir(&self) -> PathBuf {
+        self.modules_dir.clone().unwrap_or_else(default_modules_dir)
+    }
+
+    fn retry_config(&self) -> RetryConfig {
+        RetryConfig {
+            max_retries: self.retries,
+            initial_delay: Duration::from_millis(self.retry_delay_ms),
+            ..Default::default()
+        }
+    }
+
+    /// Downloads the module's package, checks its hash and, unless this is
+    /// a dry run, extracts it and runs its installer.
+    pub async fn run(&self) -> Result<(), InstallError> {
+        let package = self.download().await?;
+        if self.dry_run {
+            let script = read_installer(&package)?;
+            println!("module:  {}", package.name);
+            if let Some(commit) = &package.commit {
+                println!("commit:  {}", commit);
+            }
+            println!("sha256:  {}", package.sha256);
+            println!("size:    {} bytes", package.archive.len());
+            println!(
+                "target:  {}",
+                self.modules_dir().join(&package.name).display()
+            );
+            println!("--- {} ---", INSTALLER);
+            print!("{}", script);
+            return Ok(());
+        }
+
+        let modules_dir = self.modules_dir();
+        let target = modules_dir.join(&package.name);
+        extract(&package, &target)?;
+        verify_installer(&package.name, &target)?;
+        run_installer(&package.name, &modules_dir, &target)?;
+        println!("Installed {} to {}", package.name, target.display());
+        Ok(())
+    }
+
+    /// Downloads the module's package archive, showing progress, and checks
+    /// it against the hash the registrar advertises. Transient failures are
+    /// retried with backoff; the hash of the download that completes is
+    /// checked once it has.
+    pub async fn download(&self) -> Result<DownloadedPackage, InstallError> {
+        let base = reqwest::Url::parse(&self.registrar_url)
+            .map_err(|e| InstallError::InvalidUrl(e.to_string()))?;
+        let mut url = base
+            .join("modules/")
+            .map_err(|e| InstallError::InvalidUrl(e.to_string()))?;
+        // One segment for the name, so a namespace separator is encoded.
+        url.path_segments_mut()
+            .map_err(|_| InstallError::InvalidUrl(self.registrar_url.clone()))?
+            .pop_if_empty()
+            .extend([self.module.as_str(), "package", "archive"]);
+        let client = reqwest::Client::new();
+        let (package, expected) = retry_if(
+            "package_download",
+            &self.retry_config(),
+            InstallError::is_transient,
+            || self.fetch(&client, url.clone()),
+        )
+        .await?;
+
+        if !package.sha256.eq_ignore_ascii_case(&expected) {
+            return Err(InstallError::HashMismatch {
+                name: self.module.clone(),
+                expected,
+                actual: package.sha256,
+            });
+        }
+        Ok(package)
+    }
+
+    /// Makes one attempt at downloading the archive, returning it with the
+    /// hash the registrar advertised for it. The archive's own hash is
+    /// computed as chunks arrive rather than over the finished buffer.
+    async fn fetch(
+        &self,
+        client: &reqwest::Client,
+        url: reqwest::Url,
+    ) -> Result<(DownloadedPackage, String), InstallError> {
+        let download_error = |reason: String| InstallError::Download {
+            name: self.module.clone(),
+            reason,
+        };
+        let invalid = |reason: String| InstallError::InvalidResponse {
+            name: self.module.clone(),
+            reason,
+        };
+
+        let mut response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| download_error(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(InstallError::Status {
+                name: self.module.clone(),
+                status: response.status(),
+            });
+        }
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let expected = header(SHA256_HEADER)
+            .ok_or_else(|| invalid(format!("response has no {} header", SHA256_HEADER)))?;
+        let commit = header(COMMIT_HEADER);
+        let total = response.content_length();
+
+        let progress = match total {
+            Some(len) => ProgressBar::new(len).with_style(
+                ProgressStyle::with_template("{msg} [{bar:40}] {bytes}/{total_bytes}")
+                    .expect("valid progress template")
+                    .progress_chars("=> "),
+            ),
+            None => ProgressBar::new_spinner(),
+        };
+        progress.set_message(format!("Downloading {}", self.module));
+
+        let mut hasher = Sha256::new();
+        let mut archive = Vec::with_capacity(total.unwrap_or(0) as usize);
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => {
+                    progress.abandon();
+                    return Err(download_error(e.to_string()));
+                }
+            };
+            hasher.update(&chunk);
+            archive.extend_from_slice(&chunk);
+            progress.inc(chunk.len() as u64);
+            if let Some(total) = total {
+                if archive.len() as u64 > total {
+                    progress.abandon();
+                    return Err(invalid(format!(
+                        "received more than the advertised {} bytes",
+                        total
+                    )));
+                }
+            }
+        }
+        progress.finish_and_clear();
+
+        let package = DownloadedPackage {
+            name: self.module.clone(),
+            commit,
+            sha256: hex::encode(hasher.finalize()),
+            archive,
+        };
+        Ok((package, expected))
+    }
+}
+
+/// Reads the installer out of the archive without unpacking it anywhere.
+fn read_installer(package: &DownloadedPackage) -> Result<String, InstallError> {
+    let scratch = tempfile::NamedTempFile::new()?;
+    std::fs::write(scratch.path(), &package.archive)?;
+    let output = Command::new("tar")
+        .arg("xzOf")
+        .arg(scratch.path())
+        .arg(format!("./{}", INSTALLER))
+        .output()?;
+    if !output.status.success() {
+        return Err(InstallError::Extract {
+            name: package.name.clone(),
+            reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn extract(package: &DownloadedPackage, target: &Path) -> Result<(), InstallError> {
+    std::fs::create_dir_all(target)?;
+    let scratch = tempfile::NamedTempFile::new()?;
+    std::fs::write(scratch.path(), &package.archive)?;
+    let output = Command::new("tar")
+        .arg("xzf")
+        .arg(scratch.path())
+        .arg("-C")
+        .arg(target)
+        .output()?;
+    if !output.status.success() {
+        return Err(InstallError::Extract {
+            name: package.name.clone(),
+            reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the extracted package has an installer that is a regular
+/// file starting with one of [`KNOWN_SHEBANGS`].
+fn verify_installer(name: &str, target: &Path) -> Result<(), InstallError> {
+    let invalid = |reason: String| InstallError::InvalidInstaller {
+        name: name.to_string(),
+        reason,
+    };
+    let path = target.join(INSTALLER);
+    let metadata = match std::fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(invalid(format!("package has no {}", INSTALLER)))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !metadata.is_file() {
+        return Err(invalid(format!("{} is not a regular file", INSTALLER)));
+    }
+    let contents = std::fs::read(&path)?;
+    let first_line = contents.split(|b| *b == b'\n').next().unwrap_or_default();
+    let first_line = String::from_utf8_lossy(first_line);
+    if !KNOWN_SHEBANGS.contains(&first_line.trim_end()) {
+        return Err(invalid(format!(
+            "{} starts with {:?}, expected one of {}",
+            INSTALLER,
+            first_line.trim_end(),
+            KNOWN_SHEBANGS.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Runs the extracted installer, pointing it at `modules_dir` so it works
+/// in the directory the package was extracted to.
+fn run_installer(name: &str, modules_dir: &Path, target: &Path) -> Result<(), InstallError> {
+    let output = Command::new("bash")
+        .arg(target.join(INSTALLER))
+        .env(MODULES_DIR_ENV, modules_dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(InstallError::Installer {
+            name: name.to_string(),
+            reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use axum::http::header::HeaderMap;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+
+    /// Builds a package archive containing `files`, the way the registrar
+    /// does.
+    fn archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let root = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            std::fs::write(root.path().join(path), contents).unwrap();
+        }
+        let out = tempfile::tempdir().unwrap();
+        let archive = out.path().join("package.tar.gz");
+        let status = Command::new("tar")
+            .arg("czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(root.path())
+            .arg(".")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        std::fs::read(archive).unwrap()
+    }
+
+    /// Serves `archive` as the package of every module, advertising `sha256`.
+    async fn serve(archive: Vec<u8>, sha256: String) -> String {
+        let app = Router::new().route(
+            "/modules/:name/package/archive",
+            get(move || {
+                let archive = archive.clone();
+                let sha256 = sha256.clone();
+                async move {
+                    let mut headers = HeaderMap::new();
+                    headers.insert(SHA256_HEADER, sha256.parse().unwrap());
+                    headers.insert(COMMIT_HEADER, "0123456789abcdef".parse().unwrap());
+                    (headers, archive)
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/", addr)
+    }
+
+    fn command(url: String, modules_dir: &Path, dry_run: bool) -> InstallCommand {
+        InstallCommand {
+            module: "echo".into(),
+            registrar_url: url,
+            modules_dir: Some(modules_dir.to_path_buf()),
+            dry_run,
+            retries: 3,
+            retry_delay_ms: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_leaves_filesystem_untouched() {
+        let marker = tempfile::tempdir().unwrap();
+        let script = format!(
+            "#!/usr/bin/env bash\ntouch {}\n",
+            marker.path().join("ran").display()
+        );
+        let archive = archive(&[(INSTALLER, &script), ("main.py", "print()")]);
+        let sha256 = hex::encode(Sha256::digest(&archive));
+        let url = serve(archive, sha256.clone()).await;
+        let home = tempfile::tempdir().unwrap();
+        let modules_dir = home.path().join(".synapsis/modules");
+
+        let command = command(url, &modules_dir, true);
+        let package = command.download().await.unwrap();
+        assert_eq!(package.sha256, sha256);
+        assert_eq!(package.commit.as_deref(), Some("0123456789abcdef"));
+        assert_eq!(read_installer(&package).unwrap(), script);
+        command.run().await.unwrap();
+
+        assert!(!home.path().join(".synapsis").exists());
+        assert!(!marker.path().join("ran").exists());
+    }
+
+    #[tokio::test]
+    async fn test_install_extracts_and_runs_installer() {
+        let archive = archive(&[(
+            INSTALLER,
+            "#!/usr/bin/env bash\ntouch \"$SYNAPSE_MODULES_DIR/echo/ran\"\n",
+        )]);
+        let sha256 = hex::encode(Sha256::digest(&archive));
+        let url = serve(archive, sha256).await;
+        let modules_dir = tempfile::tempdir().unwrap();
+
+        command(url, modules_dir.path(), false).run().await.unwrap();
+
+        assert!(modules_dir.path().join("echo/ran").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_download_checks_advertised_hash() {
+        let archive = archive(&[(INSTALLER, "#!/usr/bin/env bash\n")]);
+        let actual = hex::encode(Sha256::digest(&archive));
+        let advertised = hex::encode(Sha256::digest(b"something else"));
+        let url = serve(archive, advertised.clone()).await;
+        let modules_dir = tempfile::tempdir().unwrap();
+
+        let result = command(url, modules_dir.path(), false).run().await;
+
+        match result {
+            Err(InstallError::HashMismatch {
+                expected,
+                actual: got,
+                ..
+            }) => {
+                assert_eq!(expected, advertised);
+                assert_eq!(got, actual);
+            }
+            other => panic!("expected a hash mismatch, got {:?}", other),
+        }
+        assert!(!modules_dir.path().join("echo").exists());
+    }
+
+    #[tokio::test]
+    async fn test_package_without_installer_is_rejected() {
+        let archive = archive(&[("main.py", "print()")]);
+        let sha256 = hex::encode(Sha256::digest(&archive));
+        let url = serve(archive, sha256).await;
+        let modules_dir = tempfile::tempdir().unwrap();
+
+        let err = command(url, modules_dir.path(), false)
+            .run()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, InstallError::InvalidInstaller { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Invalid installer for echo: package has no install.sh"
+        );
+    }
+
+    /// Serves `archive` once `failures` requests have been answered with
+    /// `status`, counting requests in `hits`.
+    async fn serve_flaky(
+        archive: Vec<u8>,
+        status: StatusCode,
+        failures: usize,
+        hits: Arc<AtomicUsize>,
+    ) -> String {
+        let sha256 = hex::encode(Sha256::digest(&archive));
+        let app = Router::new().route(
+            "/modules/:name/package/archive",
+            get(move || {
+                let archive = archive.clone();
+                let sha256 = sha256.clone();
+                let hit = hits.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if hit < failures {
+                        return status.into_response();
+                    }
+                    let mut headers = HeaderMap::new();
+                    headers.insert(SHA256_HEADER, sha256.parse().unwrap());
+                    (headers, archive).into_response()
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_install_retries_server_error() {
+        let archive = archive(&[(
+            INSTALLER,
+            "#!/usr/bin/env bash\ntouch \"$SYNAPSE_MODULES_DIR/echo/ran\"\n",
+        )]);
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = serve_flaky(archive, StatusCode::SERVICE_UNAVAILABLE, 1, hits.clone()).await;
+        let modules_dir = tempfile::tempdir().unwrap();
+
+        command(url, modules_dir.path(), false).run().await.unwrap();
+
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(modules_dir.path().join("echo/ran").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_missing_package_not_retried() {
+        let archive = archive(&[(INSTALLER, "#!/usr/bin/env bash\n")]);
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = serve_flaky(archive, StatusCode::NOT_FOUND, usize::MAX, hits.clone()).await;
+        let modules_dir = tempfile::tempdir().unwrap();
+
+        let err = command(url, modules_dir.path(), false)
+            .run()
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Failed to download echo: status 404 Not Found"
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_installer_needs_known_shebang() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(INSTALLER), "#!/usr/bin/python3\n").unwrap();
+        let err = verify_installer("echo", dir.path()).unwrap_err();
+        assert!(err.to_string().contains("\"#!/usr/bin/python3\""));
+
+        std::fs::write(dir.path().join(INSTALLER), "#!/bin/sh\nexit 0\n").unwrap();
+        verify_installer("echo", dir.path()).unwrap();
+    }
+}
@@ -1,8 +1,22 @@
 //! Validator implementation for the Synapse Subnet project.
-//! 
+//!
 //! This crate provides the validator functionality for managing and validating
 //! inference requests in the subnet.
 
+pub mod api;
+pub mod capabilities;
+pub mod challenge;
+pub mod config;
+pub mod doctor;
+pub mod install;
+pub mod module_logs;
+pub mod monitoring;
+pub mod response_log;
+pub mod restart;
+pub mod start;
+pub mod top;
+pub mod weights;
+
 #[cfg(test)]
 mod tests {
     #[test]
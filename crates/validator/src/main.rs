@@ -0,0 +1,45 @@
+use clap::{Parser, Subcommand};
+
+use synapse_registrar::logging::LogArgs;
+use synapse_validator::doctor::DoctorCommand;
+use synapse_validator::install::InstallCommand;
+use synapse_validator::start::StartCommand;
+use synapse_validator::top::TopCommand;
+
+#[derive(Parser)]
+#[command(name = "validator", about = "Synapse subnet validator")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that the validator's dependencies are usable
+    Doctor(DoctorCommand),
+    /// Install a module package from the registrar
+    Install(InstallCommand),
+    /// Start the validator
+    Start(StartCommand),
+    /// Show live resource usage of running modules
+    Top(TopCommand),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    cli.log.init()?;
+    match cli.command {
+        Command::Doctor(command) => {
+            if !command.run().await {
+                std::process::exit(1);
+            }
+        }
+        Command::Install(command) => command.run().await?,
+        Command::Start(command) => command.run().await?,
+        Command::Top(command) => command.run().await?,
+    }
+    Ok(())
+}
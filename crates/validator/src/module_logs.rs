@@ -0,0 +1,203 @@
+//! Recent container log lines kept per module.
+//!
+//! Each module's log is a ring buffer bounded by line count and total
+//! size, so the validator can serve recent history without holding
+//! everything a module ever printed.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use synapse_registrar::container::ContainerManager;
+use synapse_registrar::module::dotted_name;
+use synapse_registrar::tasks::BackgroundTasks;
+use tokio::task::JoinHandle;
+
+/// Default number of lines kept per module.
+pub const DEFAULT_MAX_LINES: usize = 10_000;
+
+/// Default size of the lines kept per module, 1 MiB.
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+
+/// Time waited before following a module's container again once its log
+/// stream ends.
+pub const REFOLLOW_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How much of each module's log is kept. The oldest lines are evicted
+/// first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogRetention {
+    pub max_lines: usize,
+    /// Total length of the lines kept, in bytes.
+    pub max_bytes: usize,
+}
+
+impl Default for LogRetention {
+    fn default() -> Self {
+        Self {
+            max_lines: DEFAULT_MAX_LINES,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ModuleLog {
+    lines: VecDeque<String>,
+    bytes: usize,
+}
+
+impl ModuleLog {
+    fn push(&mut self, line: String, retention: &LogRetention) {
+        self.bytes += line.len();
+        self.lines.push_back(line);
+        while self.lines.len() > retention.max_lines || self.bytes > retention.max_bytes {
+            match self.lines.pop_front() {
+                Some(evicted) => self.bytes -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// In-memory store of recent log lines, one ring buffer per module.
+#[derive(Debug, Default)]
+pub struct LogStore {
+    retention: LogRetention,
+    logs: Mutex<HashMap<String, ModuleLog>>,
+}
+
+impl LogStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how much of each module's log is kept.
+    pub fn with_retention(mut self, retention: LogRetention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Appends a line to `module`'s log, evicting its oldest lines once
+    /// the log is over either limit. A line longer than `max_bytes` on its
+    /// own is not kept.
+    pub fn append(&self, module: &str, line: impl Into<String>) {
+        self.logs
+            .lock()
+            .unwrap()
+            .entry(module.to_string())
+            .or_default()
+            .push(line.into(), &self.retention);
+    }
+
+    /// The last `n` lines kept for `module`, oldest first; every kept line
+    /// when `n` is `None`.
+    pub fn tail(&self, module: &str, n: Option<usize>) -> Vec<String> {
+        let logs = self.logs.lock().unwrap();
+        let Some(log) = logs.get(module) else {
+            return Vec::new();
+        };
+        let skip = n.map_or(0, |n| log.lines.len().saturating_sub(n));
+        log.lines.iter().skip(skip).cloned().collect()
+    }
+
+    /// Drops everything kept for `module`, e.g. once it is removed.
+    pub fn clear(&self, module: &str) {
+        self.logs.lock().unwrap().remove(module);
+    }
+
+    /// Appends what `module`'s container prints, as part of `tasks`. The
+    /// first lines read are the most recent ones the retention keeps. When
+    /// the stream ends, e.g. because the container stopped, the container
+    /// is followed again after [`REFOLLOW_INTERVAL`], reading only new
+    /// lines once any have been read.
+    pub fn follow(
+        self: &Arc<Self>,
+        containers: Arc<dyn ContainerManager>,
+        module: String,
+        tasks: &BackgroundTasks,
+    ) -> JoinHandle<()> {
+        let store = self.clone();
+        tasks.spawn(async move {
+            let container = dotted_name(&module);
+            let mut tail = store.retention.max_lines;
+            loop {
+                let mut lines = containers.logs(&container, tail);
+                while let Some(line) = lines.next().await {
+                    match line {
+                        Ok(line) => {
+                            store.append(&module, line);
+                            tail = 0;
+                        }
+                        Err(e) => {
+                            tracing::debug!("Failed to read logs of {}: {}", module, e);
+                            break;
+                        }
+                    }
+                }
+                tokio::time::sleep(REFOLLOW_INTERVAL).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_most_recent_lines_are_retained() {
+        let store = LogStore::new().with_retention(LogRetention {
+            max_lines: 3,
+            max_bytes: 1024,
+        });
+        for i in 0..10 {
+            store.append("echo", format!("line {}", i));
+        }
+
+        assert_eq!(store.tail("echo", None), ["line 7", "line 8", "line 9"]);
+        assert_eq!(store.tail("echo", Some(2)), ["line 8", "line 9"]);
+        assert!(store.tail("other", None).is_empty());
+    }
+
+    #[test]
+    fn test_byte_limit_evicts_oldest_lines() {
+        let store = LogStore::new().with_retention(LogRetention {
+            max_lines: 100,
+            max_bytes: 10,
+        });
+        store.append("echo", "aaaa");
+        store.append("echo", "bbbb");
+        store.append("echo", "cccc");
+        store.append("echo", "this line is too long");
+
+        assert!(store.tail("echo", None).is_empty());
+        store.append("echo", "dddd");
+        assert_eq!(store.tail("echo", None), ["dddd"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_container_output_followed_into_store() {
+        use synapse_registrar::container::fake::FakeContainers;
+        use synapse_registrar::container::ContainerState;
+
+        let containers = FakeContainers::default()
+            .with_container("team.echo", ContainerState::Running)
+            .with_logs("team.echo", &["one", "two", "three"]);
+        let store = Arc::new(LogStore::new().with_retention(LogRetention {
+            max_lines: 2,
+            max_bytes: 1024,
+        }));
+        let tasks = BackgroundTasks::new();
+
+        store.follow(Arc::new(containers), "team/echo".into(), &tasks);
+        tokio::time::sleep(REFOLLOW_INTERVAL * 3).await;
+
+        // Following again after the stream ends reads nothing twice.
+        assert_eq!(store.tail("team/echo", None), ["two", "three"]);
+        tasks.shutdown(Duration::from_secs(1)).await;
+    }
+}
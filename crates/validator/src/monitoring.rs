@@ -0,0 +1,403 @@
+//! Monitoring of the modules miners run.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use synapse_registrar::container::{
+    ContainerEventKind, ContainerManager, ContainerState, ContainerStatus,
+};
+use synapse_registrar::module::{dotted_name, Module};
+use synapse_registrar::probe::HealthProbe;
+use synapse_registrar::status_poller::StatusPoller;
+use synapse_registrar::tasks::BackgroundTasks;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Fraction of its score a module keeps while its health check fails.
+pub const UNHEALTHY_SCORE_FACTOR: f64 = 0.25;
+
+/// Fraction of its score a module keeps while its health check has not yet
+/// passed for the first time.
+pub const STARTING_SCORE_FACTOR: f64 = 0.5;
+
+/// How active a module is considered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActiveStatus {
+    /// Running, and healthy or without a health check.
+    Active,
+    /// Running, but its health check is failing or still starting.
+    Degraded,
+    /// Not running.
+    Inactive,
+}
+
+/// Monitoring view of a single module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitoringStatus {
+    pub module: String,
+    pub active_status: ActiveStatus,
+    /// Score after accounting for the module's health.
+    pub score: f64,
+    /// Health check result reported by the container, if any.
+    pub health: Option<String>,
+    /// Whether the module's readiness probe passed; `None` when it has no
+    /// probe or its container is not running.
+    pub ready: Option<bool>,
+}
+
+/// A module whose active status changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveStatusChange {
+    pub module: String,
+    pub previous: ActiveStatus,
+    pub current: ActiveStatus,
+}
+
+/// Derives a module's active status and the factor its score is scaled by
+/// from its container status.
+fn assess(status: Option<&ContainerStatus>) -> (ActiveStatus, f64) {
+    let Some(status) = status.filter(|s| s.state == ContainerState::Running) else {
+        return (ActiveStatus::Inactive, 0.0);
+    };
+    match status.health.as_deref() {
+        Some("unhealthy") => (ActiveStatus::Degraded, UNHEALTHY_SCORE_FACTOR),
+        Some("starting") => (ActiveStatus::Degraded, STARTING_SCORE_FACTOR),
+        _ => (ActiveStatus::Active, 1.0),
+    }
+}
+
+/// A module's readiness probe and where to run it.
+#[derive(Clone)]
+struct ModuleProbe {
+    probe: Arc<dyn HealthProbe>,
+    host: String,
+    port: u16,
+}
+
+/// Reports the status of modules from their containers and, for modules
+/// with a readiness probe, from the modules themselves.
+#[derive(Clone)]
+pub struct Monitor {
+    containers: Arc<dyn ContainerManager>,
+    probes: HashMap<String, ModuleProbe>,
+}
+
+impl Monitor {
+    pub fn new(containers: Arc<dyn ContainerManager>) -> Self {
+        Self {
+            containers,
+            probes: HashMap::new(),
+        }
+    }
+
+    /// Probes `module` at `host:port` to decide whether it is ready.
+    pub fn with_probe(
+        mut self,
+        module: &str,
+        probe: Arc<dyn HealthProbe>,
+        host: &str,
+        port: u16,
+    ) -> Self {
+        self.probes.insert(
+            module.to_string(),
+            ModuleProbe {
+                probe,
+                host: host.to_string(),
+                port,
+            },
+        );
+        self
+    }
+
+    /// Probes `module` as its config declares, on its first TCP port on
+    /// this host. Modules without a probe or a TCP port are left as they
+    /// are.
+    pub fn with_configured_probe(self, module: &Module) -> Self {
+        match (&module.config.probe, module.tcp_port()) {
+            (Some(probe), Some(port)) => {
+                self.with_probe(&module.name, probe.build().into(), "127.0.0.1", port)
+            }
+            _ => self,
+        }
+    }
+
+    /// Reports `module`'s status, scaling `score` down when its container
+    /// is not running or is failing its health check. A container whose
+    /// status cannot be read is treated as not running. A running module
+    /// whose readiness probe fails is degraded, whatever Docker reports.
+    pub async fn get_monitoring_status(&self, module: &str, score: f64) -> MonitoringStatus {
+        let status = match self
+            .containers
+            .get_container_status(&dotted_name(module))
+            .await
+        {
+            Ok(status) => Some(status),
+            Err(e) => {
+                tracing::debug!("No container status for {}: {}", module, e);
+                None
+            }
+        };
+        let (mut active_status, mut factor) = assess(status.as_ref());
+        let mut ready = None;
+        if let Some(probe) = self.probes.get(module) {
+            if active_status != ActiveStatus::Inactive {
+                let result = probe.probe.probe(&probe.host, probe.port).await;
+                if let Err(e) = &result {
+                    tracing::debug!("Readiness probe of {} failed: {}", module, e);
+                    active_status = ActiveStatus::Degraded;
+                    factor = factor.min(UNHEALTHY_SCORE_FACTOR);
+                }
+                ready = Some(result.is_ok());
+            }
+        }
+        MonitoringStatus {
+            module: module.to_string(),
+            active_status,
+            score: score * factor,
+            health: status.and_then(|s| s.health),
+            ready,
+        }
+    }
+
+    /// Watches the containers of `modules` and sends a change whenever a
+    /// module's active status changes, e.g. when its health check starts
+    /// failing. Container events are applied as they arrive; polling every
+    /// `interval` catches anything the event stream missed. The loop and
+    /// its poller run as part of `tasks`, and stop when the receiver is
+    /// dropped or the tasks are shut down.
+    pub fn spawn_health_loop(
+        &self,
+        modules: Vec<String>,
+        interval: Duration,
+        tasks: &BackgroundTasks,
+    ) -> (mpsc::Receiver<ActiveStatusChange>, JoinHandle<()>) {
+        let containers = self.containers.clone();
+        // Containers are watched under their own names, which differ from
+        // the module names for namespaced modules.
+        let modules_by_container: HashMap<String, String> = modules
+            .into_iter()
+            .map(|module| (dotted_name(&module), module))
+            .collect();
+        let container_names: Vec<String> = modules_by_container.keys().cloned().collect();
+        let poller = StatusPoller::new(containers.clone()).with_interval(interval);
+        let (mut changes, poll_handle) = poller.spawn(container_names.clone(), tasks);
+        let mut events = containers.events();
+        let (tx, rx) = mpsc::channel(16);
+        let handle = tasks.spawn(async move {
+            let names: Vec<&str> = container_names.iter().map(String::as_str).collect();
+            let mut known: HashMap<String, ActiveStatus> = containers
+                .get_statuses(&names)
+                .await
+                .into_iter()
+                .map(|(name, status)| (name, assess(status.ok().as_ref()).0))
+                .collect();
+            loop {
+                let (container, current) = tokio::select! {
+                    change = changes.recv() => match change {
+                        Some(change) => (change.name, assess(change.current.as_ref()).0),
+                        None => break,
+                    },
+                    event = events.next() => match event {
+                        Some(Ok(event)) => (event.name, event_status(&event.kind)),
+                        Some(Err(e)) => {
+                            tracing::warn!("Container event stream failed: {}", e);
+                            continue;
+                        }
+                        None => {
+                            events = stream::pending().boxed();
+                            continue;
+                        }
+                    },
+                };
+                let (Some(previous), Some(module)) = (
+                    known.get_mut(&container),
+                    modules_by_container.get(&container),
+                ) else {
+                    continue;
+                };
+                if *previous == current {
+                    continue;
+                }
+                if current != ActiveStatus::Active {
+                    tracing::warn!("Module {} is now {:?}", module, current);
+                }
+                let change = ActiveStatusChange {
+                    module: module.clone(),
+                    previous: std::mem::replace(previous, current),
+                    current,
+                };
+                if tx.send(change).await.is_err() {
+                    break;
+                }
+            }
+            poll_handle.abort();
+        });
+        (rx, handle)
+    }
+}
+
+/// Active status implied by a container event.
+fn event_status(kind: &ContainerEventKind) -> ActiveStatus {
+    match kind {
+        ContainerEventKind::Start => ActiveStatus::Active,
+        ContainerEventKind::Stop | ContainerEventKind::Die => ActiveStatus::Inactive,
+        ContainerEventKind::HealthStatus(health) if health == "healthy" => ActiveStatus::Active,
+        ContainerEventKind::HealthStatus(_) => ActiveStatus::Degraded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_registrar::container::fake::FakeContainers;
+    use synapse_registrar::probe::ProbeError;
+
+    #[tokio::test]
+    async fn test_unhealthy_container_is_degraded() {
+        let containers = FakeContainers::default()
+            .with_container("healthy", ContainerState::Running)
+            .with_health("healthy", "healthy")
+            .with_container("sick", ContainerState::Running)
+            .with_health("sick", "unhealthy")
+            .with_container("plain", ContainerState::Running)
+            .with_container("stopped", ContainerState::Exited);
+        let monitor = Monitor::new(Arc::new(containers));
+
+        let healthy = monitor.get_monitoring_status("healthy", 0.8).await;
+        assert_eq!(healthy.active_status, ActiveStatus::Active);
+        assert_eq!(healthy.score, 0.8);
+
+        let sick = monitor.get_monitoring_status("sick", 0.8).await;
+        assert_eq!(sick.active_status, ActiveStatus::Degraded);
+        assert_eq!(sick.score, 0.8 * UNHEALTHY_SCORE_FACTOR);
+        assert_eq!(sick.health.as_deref(), Some("unhealthy"));
+
+        // Without a health check a running container is fully active.
+        let plain = monitor.get_monitoring_status("plain", 0.8).await;
+        assert_eq!(plain.active_status, ActiveStatus::Active);
+
+        for module in ["stopped", "missing"] {
+            let status = monitor.get_monitoring_status(module, 0.8).await;
+            assert_eq!(status.active_status, ActiveStatus::Inactive);
+            assert_eq!(status.score, 0.0);
+        }
+    }
+
+    struct FixedProbe(Result<(), ProbeError>);
+
+    #[async_trait::async_trait]
+    impl HealthProbe for FixedProbe {
+        async fn probe(&self, _host: &str, _port: u16) -> Result<(), ProbeError> {
+            self.0.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_probe_degrades_a_healthy_container() {
+        let containers = FakeContainers::default()
+            .with_container("ready", ContainerState::Running)
+            .with_container("hung", ContainerState::Running)
+            .with_health("hung", "healthy");
+        let monitor = Monitor::new(Arc::new(containers))
+            .with_probe("ready", Arc::new(FixedProbe(Ok(()))), "127.0.0.1", 8080)
+            .with_probe(
+                "hung",
+                Arc::new(FixedProbe(Err(ProbeError::Timeout(Duration::from_secs(2))))),
+                "127.0.0.1",
+                8081,
+            );
+
+        let ready = monitor.get_monitoring_status("ready", 0.8).await;
+        assert_eq!(ready.active_status, ActiveStatus::Active);
+        assert_eq!(ready.ready, Some(true));
+        assert_eq!(ready.score, 0.8);
+
+        let hung = monitor.get_monitoring_status("hung", 0.8).await;
+        assert_eq!(hung.active_status, ActiveStatus::Degraded);
+        assert_eq!(hung.ready, Some(false));
+        assert_eq!(hung.score, 0.8 * UNHEALTHY_SCORE_FACTOR);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_health_loop_reports_degradation() {
+        let containers = Arc::new(
+            FakeContainers::default()
+                .with_container("echo", ContainerState::Running)
+                .with_health("echo", "healthy"),
+        );
+        let monitor = Monitor::new(containers.clone());
+        let (mut changes, _handle) = monitor.spawn_health_loop(
+            vec!["echo".into()],
+            Duration::from_secs(1),
+            &BackgroundTasks::new(),
+        );
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        containers
+            .containers
+            .lock()
+            .unwrap()
+            .get_mut("echo")
+            .unwrap()
+            .status
+            .health = Some("unhealthy".into());
+
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.module, "echo");
+        assert_eq!(change.previous, ActiveStatus::Active);
+        assert_eq!(change.current, ActiveStatus::Degraded);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_die_event_marks_module_inactive() {
+        let containers =
+            Arc::new(FakeContainers::default().with_container("echo", ContainerState::Running));
+        let monitor = Monitor::new(containers.clone());
+        // Poll rarely, so only the event can explain a prompt change.
+        let (mut changes, _handle) = monitor.spawn_health_loop(
+            vec!["echo".into()],
+            Duration::from_secs(3600),
+            &BackgroundTasks::new(),
+        );
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        containers.emit("echo", ContainerEventKind::Die);
+        containers.emit("other", ContainerEventKind::Die);
+
+        let change = tokio::time::timeout(Duration::from_secs(1), changes.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.module, "echo");
+        assert_eq!(change.previous, ActiveStatus::Active);
+        assert_eq!(change.current, ActiveStatus::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_namespaced_module_watched_through_its_container() {
+        let containers = Arc::new(
+            FakeContainers::default().with_container("team-a.echo", ContainerState::Running),
+        );
+        let monitor = Monitor::new(containers.clone());
+        let (mut changes, _handle) = monitor.spawn_health_loop(
+            vec!["team-a/echo".into()],
+            Duration::from_secs(3600),
+            &BackgroundTasks::new(),
+        );
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        containers.emit("team-a.echo", ContainerEventKind::Die);
+
+        let change = tokio::time::timeout(Duration::from_secs(1), changes.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.module, "team-a/echo");
+        assert_eq!(change.previous, ActiveStatus::Active);
+        assert_eq!(change.current, ActiveStatus::Inactive);
+    }
+}
@@ -0,0 +1,173 @@
+//! Recording how miners answer the validator's requests.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Default number of requests and responses kept per miner.
+pub const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+/// Records requests sent to miners and the responses they returned.
+pub trait ResponseLogger: Send + Sync {
+    /// Records a request sent to `miner`.
+    fn log_request(&self, miner: &str);
+
+    /// Records a response from `miner` and whether it was successful.
+    fn log_response(&self, miner: &str, success: bool);
+
+    /// Fraction of `miner`'s recorded responses that were successful, or
+    /// `None` if none are recorded.
+    fn get_success_rate(&self, miner: &str) -> Option<f64>;
+}
+
+/// How much history [`DefaultResponseLogger`] keeps per miner. The oldest
+/// entries are evicted first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryLimits {
+    /// Most requests, and most responses, kept.
+    pub max_entries: usize,
+    /// Entries older than this are dropped; `None` keeps them until
+    /// `max_entries` is reached.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MinerHistory {
+    requests: VecDeque<Instant>,
+    responses: VecDeque<(Instant, bool)>,
+}
+
+impl MinerHistory {
+    fn evict(&mut self, limits: &HistoryLimits, now: Instant) {
+        if let Some(max_age) = limits.max_age {
+            while self
+                .requests
+                .front()
+                .is_some_and(|at| now.duration_since(*at) > max_age)
+            {
+                self.requests.pop_front();
+            }
+            while self
+                .responses
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) > max_age)
+            {
+                self.responses.pop_front();
+            }
+        }
+        while self.requests.len() > limits.max_entries {
+            self.requests.pop_front();
+        }
+        while self.responses.len() > limits.max_entries {
+            self.responses.pop_front();
+        }
+    }
+}
+
+/// In-memory [`ResponseLogger`] keeping a bounded history per miner.
+#[derive(Debug, Default)]
+pub struct DefaultResponseLogger {
+    limits: HistoryLimits,
+    history: Mutex<HashMap<String, MinerHistory>>,
+}
+
+impl DefaultResponseLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how much history is kept per miner.
+    pub fn with_limits(mut self, limits: HistoryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Number of requests and responses currently kept for `miner`.
+    pub fn retained(&self, miner: &str) -> (usize, usize) {
+        let mut history = self.history.lock().unwrap();
+        history.get_mut(miner).map_or((0, 0), |h| {
+            h.evict(&self.limits, Instant::now());
+            (h.requests.len(), h.responses.len())
+        })
+    }
+
+    fn update(&self, miner: &str, f: impl FnOnce(&mut MinerHistory, Instant)) {
+        let now = Instant::now();
+        let mut history = self.history.lock().unwrap();
+        let entry = history.entry(miner.to_string()).or_default();
+        f(entry, now);
+        entry.evict(&self.limits, now);
+    }
+}
+
+impl ResponseLogger for DefaultResponseLogger {
+    fn log_request(&self, miner: &str) {
+        self.update(miner, |h, now| h.requests.push_back(now));
+    }
+
+    fn log_response(&self, miner: &str, success: bool) {
+        self.update(miner, |h, now| h.responses.push_back((now, success)));
+    }
+
+    fn get_success_rate(&self, miner: &str) -> Option<f64> {
+        let mut history = self.history.lock().unwrap();
+        let h = history.get_mut(miner)?;
+        h.evict(&self.limits, Instant::now());
+        if h.responses.is_empty() {
+            return None;
+        }
+        let successes = h.responses.iter().filter(|(_, success)| *success).count();
+        Some(successes as f64 / h.responses.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_history_is_bounded_and_rate_covers_retained_entries() {
+        let logger = DefaultResponseLogger::new().with_limits(HistoryLimits {
+            max_entries: 4,
+            max_age: None,
+        });
+        for _ in 0..10 {
+            logger.log_request("alice");
+            logger.log_response("alice", false);
+        }
+        for success in [true, true, true, false] {
+            logger.log_request("alice");
+            logger.log_response("alice", success);
+        }
+
+        assert_eq!(logger.retained("alice"), (4, 4));
+        // The ten early failures were evicted.
+        assert_eq!(logger.get_success_rate("alice"), Some(0.75));
+        assert_eq!(logger.get_success_rate("bob"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_entries_older_than_max_age_are_dropped() {
+        let logger = DefaultResponseLogger::new().with_limits(HistoryLimits {
+            max_entries: 100,
+            max_age: Some(Duration::from_secs(60)),
+        });
+        logger.log_response("alice", false);
+        tokio::time::advance(Duration::from_secs(61)).await;
+        logger.log_response("alice", true);
+
+        assert_eq!(logger.get_success_rate("alice"), Some(1.0));
+        assert_eq!(logger.retained("alice"), (0, 1));
+    }
+}
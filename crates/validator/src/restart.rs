@@ -0,0 +1,335 @@
+//! Restarting modules whose containers fail.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use synapse_registrar::container::{ContainerManager, ContainerState, DockerError};
+use synapse_registrar::module::dotted_name;
+use synapse_registrar::retry::RetryConfig;
+use tokio::time::Instant;
+
+use crate::monitoring::{ActiveStatus, ActiveStatusChange};
+
+/// How a module is restarted after its container exits or turns unhealthy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoRestartPolicy {
+    /// Restarts made before the module is marked failed.
+    pub max_attempts: u32,
+    /// Seconds to wait before the first restart; doubles with each attempt.
+    pub backoff_secs: f64,
+    /// Upper bound on the wait before a restart, in seconds.
+    pub max_backoff_secs: f64,
+    /// Seconds after the last restart at which the attempt count starts
+    /// over.
+    pub cooldown_secs: u64,
+}
+
+impl Default for AutoRestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_secs: 1.0,
+            max_backoff_secs: 60.0,
+            cooldown_secs: 300,
+        }
+    }
+}
+
+impl AutoRestartPolicy {
+    /// Checks that both backoffs are valid durations.
+    pub fn validate(&self) -> Result<(), String> {
+        for (field, secs) in [
+            ("backoff_secs", self.backoff_secs),
+            ("max_backoff_secs", self.max_backoff_secs),
+        ] {
+            if Duration::try_from_secs_f64(secs).is_err() {
+                return Err(format!(
+                    "{} must be a non-negative number of seconds, got {}",
+                    field, secs
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait before restart number `attempt` (zero-based). Backoffs that
+    /// are not valid durations count as zero.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        RetryConfig {
+            initial_delay: Duration::try_from_secs_f64(self.backoff_secs).unwrap_or_default(),
+            max_delay: Duration::try_from_secs_f64(self.max_backoff_secs).unwrap_or_default(),
+            ..Default::default()
+        }
+        .backoff(attempt)
+    }
+}
+
+/// Which modules are restarted automatically. Modules without a policy of
+/// their own use `default`; with neither, nothing is restarted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoRestartConfig {
+    pub default: Option<AutoRestartPolicy>,
+    pub modules: BTreeMap<String, AutoRestartPolicy>,
+}
+
+impl AutoRestartConfig {
+    pub fn policy_for(&self, module: &str) -> Option<&AutoRestartPolicy> {
+        self.modules.get(module).or(self.default.as_ref())
+    }
+
+    /// Checks the default and every per-module policy.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(policy) = &self.default {
+            policy.validate().map_err(|e| format!("default: {}", e))?;
+        }
+        for (module, policy) in &self.modules {
+            policy
+                .validate()
+                .map_err(|e| format!("{}: {}", module, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// What [`AutoRestarter::handle`] did about a status change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartOutcome {
+    /// No restart was needed, one is already scheduled, or the module has
+    /// no policy.
+    Skipped,
+    /// The module's container is to be restarted after `delay`, by running
+    /// [`AutoRestarter::restart_after`].
+    Scheduled { attempt: u32, delay: Duration },
+    /// The module used up its attempts and is now marked failed.
+    Failed { attempts: u32 },
+}
+
+#[derive(Debug, Default)]
+struct RestartState {
+    attempts: u32,
+    /// When the latest restart was scheduled to happen; in the future while
+    /// it is pending.
+    last_restart: Option<Instant>,
+    failed: bool,
+}
+
+/// Restarts failing modules according to their [`AutoRestartPolicy`],
+/// giving up and marking a module failed once its attempts run out.
+pub struct AutoRestarter {
+    containers: Arc<dyn ContainerManager>,
+    config: AutoRestartConfig,
+    state: HashMap<String, RestartState>,
+}
+
+impl AutoRestarter {
+    pub fn new(containers: Arc<dyn ContainerManager>, config: AutoRestartConfig) -> Self {
+        Self {
+            containers,
+            config,
+            state: HashMap::new(),
+        }
+    }
+
+    /// Restarts made for `module` since its attempts were last reset.
+    pub fn attempts(&self, module: &str) -> u32 {
+        self.state.get(module).map_or(0, |s| s.attempts)
+    }
+
+    /// Whether `module` ran out of restart attempts.
+    pub fn is_failed(&self, module: &str) -> bool {
+        self.state.get(module).is_some_and(|s| s.failed)
+    }
+
+    /// Schedules a restart of the changed module if its container has
+    /// exited or is unhealthy and its policy allows another attempt. A
+    /// module still starting up, or with a restart pending, is left alone.
+    /// The backoff is not waited out here, so a caller handling changes in
+    /// a loop is not held up by it.
+    pub async fn handle(
+        &mut self,
+        change: &ActiveStatusChange,
+    ) -> Result<RestartOutcome, DockerError> {
+        if change.current == ActiveStatus::Active {
+            return Ok(RestartOutcome::Skipped);
+        }
+        let Some(policy) = self.config.policy_for(&change.module).cloned() else {
+            return Ok(RestartOutcome::Skipped);
+        };
+        let pending = self
+            .state
+            .get(&change.module)
+            .and_then(|s| s.last_restart)
+            .is_some_and(|at| at > Instant::now());
+        if self.is_failed(&change.module) || pending {
+            return Ok(RestartOutcome::Skipped);
+        }
+        let needs_restart = match self
+            .containers
+            .get_container_status(&dotted_name(&change.module))
+            .await
+        {
+            Ok(status) => {
+                status.state != ContainerState::Running
+                    || status.health.as_deref() == Some("unhealthy")
+            }
+            Err(DockerError::ContainerNotFound(_)) => true,
+            Err(e) => return Err(e),
+        };
+        if !needs_restart {
+            return Ok(RestartOutcome::Skipped);
+        }
+
+        let state = self.state.entry(change.module.clone()).or_default();
+        let cooldown = Duration::from_secs(policy.cooldown_secs);
+        if state
+            .last_restart
+            .is_some_and(|at| at.elapsed() >= cooldown)
+        {
+            state.attempts = 0;
+        }
+        if state.attempts >= policy.max_attempts {
+            state.failed = true;
+            tracing::error!(
+                "Module {} failed after {} restart(s), giving up",
+                change.module,
+                state.attempts
+            );
+            return Ok(RestartOutcome::Failed {
+                attempts: state.attempts,
+            });
+        }
+
+        let attempt = state.attempts + 1;
+        let delay = policy.backoff(attempt - 1);
+        state.attempts = attempt;
+        state.last_restart = Some(Instant::now() + delay);
+        tracing::warn!(
+            "Module {} is {:?}, restarting in {:?} (attempt {} of {})",
+            change.module,
+            change.current,
+            delay,
+            attempt,
+            policy.max_attempts
+        );
+        Ok(RestartOutcome::Scheduled { attempt, delay })
+    }
+
+    /// Waits `delay`, then stops and starts `module`'s container. Returned
+    /// rather than awaited so the caller can spawn it.
+    pub fn restart_after(
+        &self,
+        module: &str,
+        delay: Duration,
+    ) -> impl Future<Output = Result<(), DockerError>> + Send + 'static {
+        let containers = self.containers.clone();
+        let container = dotted_name(module);
+        async move {
+            tokio::time::sleep(delay).await;
+            match containers.stop_container(&container).await {
+                Ok(()) | Err(DockerError::ContainerNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+            containers.start_container(&container).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_registrar::container::fake::FakeContainers;
+
+    fn exited(module: &str) -> ActiveStatusChange {
+        ActiveStatusChange {
+            module: module.into(),
+            previous: ActiveStatus::Active,
+            current: ActiveStatus::Inactive,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flapping_module_is_restarted_up_to_limit_then_failed() {
+        let containers =
+            Arc::new(FakeContainers::default().with_container("echo", ContainerState::Running));
+        let config = AutoRestartConfig {
+            default: None,
+            modules: BTreeMap::from([(
+                "echo".into(),
+                AutoRestartPolicy {
+                    max_attempts: 2,
+                    ..Default::default()
+                },
+            )]),
+        };
+        let mut restarter = AutoRestarter::new(containers.clone(), config);
+
+        let started = Instant::now();
+        for (attempt, secs) in [(1, 1), (2, 2)] {
+            containers.set_state("echo", ContainerState::Exited);
+            let delay = Duration::from_secs(secs);
+            assert_eq!(
+                restarter.handle(&exited("echo")).await.unwrap(),
+                RestartOutcome::Scheduled { attempt, delay }
+            );
+            // Another change while the restart is pending does not add one.
+            assert_eq!(
+                restarter.handle(&exited("echo")).await.unwrap(),
+                RestartOutcome::Skipped
+            );
+            restarter.restart_after("echo", delay).await.unwrap();
+        }
+        // Backoff of one then two seconds, waited out only by the restarts.
+        assert_eq!(started.elapsed(), Duration::from_secs(3));
+
+        containers.set_state("echo", ContainerState::Exited);
+        assert_eq!(
+            restarter.handle(&exited("echo")).await.unwrap(),
+            RestartOutcome::Failed { attempts: 2 }
+        );
+        assert!(restarter.is_failed("echo"));
+        assert_eq!(
+            containers.calls(),
+            vec!["stop echo", "start echo", "stop echo", "start echo"]
+        );
+
+        // Modules without a policy are left alone.
+        assert_eq!(
+            restarter.handle(&exited("other")).await.unwrap(),
+            RestartOutcome::Skipped
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_attempts_reset_after_cooldown() {
+        let containers =
+            Arc::new(FakeContainers::default().with_container("echo", ContainerState::Exited));
+        let policy = AutoRestartPolicy {
+            max_attempts: 1,
+            cooldown_secs: 60,
+            ..Default::default()
+        };
+        let mut restarter = AutoRestarter::new(
+            containers.clone(),
+            AutoRestartConfig {
+                default: Some(policy),
+                modules: BTreeMap::new(),
+            },
+        );
+
+        restarter.handle(&exited("echo")).await.unwrap();
+        // The cooldown counts from the restart, a second after scheduling.
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert_eq!(
+            restarter.handle(&exited("echo")).await.unwrap(),
+            RestartOutcome::Scheduled {
+                attempt: 1,
+                delay: Duration::from_secs(1)
+            }
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Running the validator against a registrar.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Args;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use synapse_registrar::client::{ClientError, RegistrarClient};
+use synapse_registrar::container::{ContainerManager, ContainerState, DockerError};
+use synapse_registrar::module::ModuleStatus;
+use synapse_registrar::reconnect::ReconnectingContainers;
+use synapse_registrar::tasks::{BackgroundTasks, DEFAULT_SHUTDOWN_TIMEOUT};
+
+use crate::api::{self, ApiState};
+use crate::challenge::{ChallengeRound, CHALLENGE_QUEUE_CAPACITY};
+use crate::config::{
+    load_validator_config, ValidatorConfig, ValidatorConfigError, VALIDATOR_CONTAINER,
+};
+use crate::module_logs::LogStore;
+use crate::monitoring::Monitor;
+use crate::response_log::DefaultResponseLogger;
+use crate::restart::{AutoRestarter, RestartOutcome};
+use crate::weights::WeightSetter;
+
+/// Errors produced while running the validator.
+#[derive(Debug, Error)]
+pub enum StartError {
+    #[error(transparent)]
+    Config(#[from] ValidatorConfigError),
+
+    #[error(transparent)]
+    Registrar(#[from] ClientError),
+
+    #[error(transparent)]
+    Docker(#[from] DockerError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Removes the container `name` if it has exited, returning whether it
+/// did. Containers kept for debugging are cleaned up this way on the next
+/// start.
+pub async fn remove_exited_container(
+    containers: &dyn ContainerManager,
+    name: &str,
+) -> Result<bool, DockerError> {
+    let state = match containers.get_container_status(name).await {
+        Ok(status) => status.state,
+        Err(DockerError::ContainerNotFound(_)) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    if !matches!(
+        state,
+        ContainerState::Exited | ContainerState::Dead | ContainerState::Created
+    ) {
+        return Ok(false);
+    }
+    containers.remove_container(name).await?;
+    Ok(true)
+}
+
+/// Start the validator
+#[derive(Debug, Clone, Args)]
+pub struct StartCommand {
+    /// Validator config file (.yaml, .yml, .toml or .json)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// URL of the registrar, overriding the config file [default: http://127.0.0.1:3000]
+    #[arg(long)]
+    pub registrar_url: Option<String>,
+    /// Seconds to wait for the registrar to become reachable before giving up
+    #[arg(long, default_value_t = 30)]
+    pub registrar_connect_timeout: u64,
+    /// Seconds between health polls of the monitored modules
+    #[arg(long, default_value_t = 10)]
+    pub poll_interval: u64,
+    /// Keep the validator's container after it exits instead of having
+    /// Docker remove it, so a crash can be inspected
+    #[arg(long)]
+    pub no_auto_remove: bool,
+}
+
+impl StartCommand {
+    /// Loads the config file, if any, and applies the command-line
+    /// overrides.
+    pub fn config(&self) -> Result<ValidatorConfig, ValidatorConfigError> {
+        let mut config = match &self.config {
+            Some(path) => load_validator_config(path)?,
+            None => ValidatorConfig::default(),
+        };
+        if let Some(url) = &self.registrar_url {
+            config.registrar_url = url.clone();
+        }
+        if self.no_auto_remove {
+            if let Some(container) = &mut config.container {
+                container.keep_failed_containers = true;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Creates a client for the configured registrar once it answers its
+    /// health check, retrying with backoff for up to the connect timeout.
+    pub async fn connect(&self, config: &ValidatorConfig) -> Result<RegistrarClient, ClientError> {
+        let client = RegistrarClient::new(&config.registrar_url)?;
+        tracing::info!("Waiting for registrar at {}", config.registrar_url);
+        client
+            .wait_until_healthy(Duration::from_secs(self.registrar_connect_timeout))
+            .await?;
+        Ok(client)
+    }
+
+    /// Connects to the registrar and monitors the health of its modules,
+    /// challenges the configured miners with the challenges submitted to
+    /// its API and sets their weights every epoch, until interrupted.
+    pub async fn run(&self) -> Result<(), StartError> {
+        let config = self.config()?;
+        tracing::info!(
+            "Starting validator for netuid {} on port {}",
+            config.netuid,
+            config.port
+        );
+        let client = self.connect(&config).await?;
+        let modules: Vec<String> = client
+            .list_modules()
+            .await?
+            .into_iter()
+            .map(|module| module.name)
+            .collect();
+        tracing::info!("Monitoring {} module(s)", modules.len());
+
+        let tasks = BackgroundTasks::new();
+        let containers = Arc::new(ReconnectingContainers::docker());
+        if let Err(e) = containers.connected().await {
+            tracing::warn!("Docker unavailable, monitoring degraded: {}", e);
+        }
+        if config
+            .container
+            .as_ref()
+            .is_some_and(|spec| spec.keep_failed_containers)
+        {
+            match remove_exited_container(containers.as_ref(), VALIDATOR_CONTAINER).await {
+                Ok(true) => tracing::info!("Removed exited {} container", VALIDATOR_CONTAINER),
+                Ok(false) => {}
+                Err(e) => tracing::warn!(
+                    "Failed to remove exited {} container: {}",
+                    VALIDATOR_CONTAINER,
+                    e
+                ),
+            }
+        }
+        let logs = Arc::new(LogStore::new().with_retention(config.log_retention));
+        for module in &modules {
+            logs.follow(containers.clone(), module.clone(), &tasks);
+        }
+
+        let logger = Arc::new(DefaultResponseLogger::new());
+        let round = ChallengeRound::http(&config.challenge, logger.clone());
+        let (challenges, queued) = mpsc::channel(CHALLENGE_QUEUE_CAPACITY);
+        round.spawn(config.miners.values().cloned().collect(), queued, &tasks);
+        tracing::info!("Challenging {} miner(s)", config.miners.len());
+
+        match config.weight_setting.chain() {
+            Some(chain) => {
+                WeightSetter::new(
+                    Arc::new(chain),
+                    logger,
+                    config.netuid,
+                    config.miners.clone(),
+                )
+                .spawn(&config.weight_setting, &tasks);
+            }
+            None => tracing::warn!("No funded key configured; weights will not be set"),
+        }
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let app = api::router(
+            ApiState::new(challenges)
+                .with_config(config.api.clone())
+                .with_logs(logs),
+        );
+        tracing::info!("Validator API listening on {}", addr);
+        tasks.spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("Validator API failed: {}", e);
+            }
+        });
+
+        let monitor = Monitor::new(containers.clone());
+        let mut restarter = AutoRestarter::new(containers, config.auto_restart.clone());
+        let (mut changes, _handle) =
+            monitor.spawn_health_loop(modules, Duration::from_secs(self.poll_interval), &tasks);
+        loop {
+            tokio::select! {
+                change = changes.recv() => match change {
+                    Some(change) => {
+                        tracing::info!(
+                            "Module {} went from {:?} to {:?}",
+                            change.module,
+                            change.previous,
+                            change.current
+                        );
+                        match restarter.handle(&change).await {
+                            Ok(RestartOutcome::Scheduled { delay, .. }) => {
+                                let restart = restarter.restart_after(&change.module, delay);
+                                let module = change.module.clone();
+                                tasks.spawn(async move {
+                                    if let Err(e) = restart.await {
+                                        tracing::warn!("Failed to restart {}: {}", module, e);
+                                    }
+                                });
+                            }
+                            Ok(RestartOutcome::Failed { .. }) => {
+                                let client = client.clone();
+                                let module = change.module.clone();
+                                tasks.spawn(async move {
+                                    match client
+                                        .update_module_status(&module, ModuleStatus::Failed)
+                                        .await
+                                    {
+                                        Ok(()) => tracing::error!("Module {} marked failed", module),
+                                        Err(e) => tracing::warn!(
+                                            "Failed to mark {} failed in the registrar: {}",
+                                            module,
+                                            e
+                                        ),
+                                    }
+                                });
+                            }
+                            Ok(RestartOutcome::Skipped) => {}
+                            Err(e) => {
+                                tracing::warn!("Failed to restart {}: {}", change.module, e)
+                            }
+                        }
+                    }
+                    None => break,
+                },
+                result = tokio::signal::ctrl_c() => {
+                    result?;
+                    break;
+                }
+            }
+        }
+        if !tasks.shutdown(DEFAULT_SHUTDOWN_TIMEOUT).await {
+            tracing::warn!(
+                "Background tasks did not stop within {:?}",
+                DEFAULT_SHUTDOWN_TIMEOUT
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_registrar::api::{create_router, AppState};
+    use synapse_registrar::container::fake::FakeContainers;
+    use synapse_registrar::registry::SqliteRegistry;
+
+    #[tokio::test]
+    async fn test_connects_to_registrar_that_starts_late() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Free the port so connections are refused until the registrar binds it.
+        drop(listener);
+        let command = StartCommand {
+            config: None,
+            registrar_url: Some(format!("http://{}/", addr)),
+            registrar_connect_timeout: 10,
+            poll_interval: 10,
+            no_auto_remove: false,
+        };
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let registry = Arc::new(SqliteRegistry::in_memory().await.unwrap());
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            axum::serve(listener, create_router(AppState::new(registry)))
+                .await
+                .unwrap();
+        });
+        let client = command.connect(&command.config().unwrap()).await.unwrap();
+        assert!(client.list_modules().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exited_container_removed_on_start() {
+        let containers = FakeContainers::default()
+            .with_container(VALIDATOR_CONTAINER, ContainerState::Exited)
+            .with_container("running", ContainerState::Running);
+
+        assert!(remove_exited_container(&containers, VALIDATOR_CONTAINER)
+            .await
+            .unwrap());
+        assert!(!remove_exited_container(&containers, "running")
+            .await
+            .unwrap());
+        assert!(!remove_exited_container(&containers, VALIDATOR_CONTAINER)
+            .await
+            .unwrap());
+        let remaining: Vec<String> = containers
+            .containers
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(remaining, ["running"]);
+    }
+}
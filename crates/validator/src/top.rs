@@ -0,0 +1,160 @@
+//! A `top`-like view of the resources modules use.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use clap::Args;
+
+use synapse_registrar::client::{ClientError, RegistrarClient};
+use synapse_registrar::resources::ModuleUsage;
+
+/// Clears the terminal and moves the cursor to its top left.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Show live resource usage of running modules
+#[derive(Debug, Clone, Args)]
+pub struct TopCommand {
+    /// URL of the registrar
+    #[arg(long, default_value = "http://127.0.0.1:3000")]
+    pub registrar_url: String,
+    /// Seconds between refreshes
+    #[arg(long, default_value_t = 2)]
+    pub interval: u64,
+    /// Print the usage once and exit
+    #[arg(long)]
+    pub once: bool,
+}
+
+impl TopCommand {
+    /// Prints the usage table, refreshing it every interval until
+    /// interrupted. A failed refresh is shown in place of the table and
+    /// retried on the next tick.
+    pub async fn run(&self) -> Result<(), ClientError> {
+        let client = RegistrarClient::new(&self.registrar_url)?;
+        if self.once {
+            print!("{}", render(&client.resource_usage().await?));
+            return Ok(());
+        }
+
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.interval.max(1)));
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            }
+            let screen = match client.resource_usage().await {
+                Ok(usage) => render(&usage),
+                Err(e) => format!("Failed to fetch resource usage: {}\n", e),
+            };
+            print!("{}{}", CLEAR_SCREEN, screen);
+        }
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`.
+fn bytes(n: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = n;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Renders `usage` as a table, one row per module in the given order.
+/// Modules whose stats could not be fetched show the error instead.
+pub fn render(usage: &[ModuleUsage]) -> String {
+    let width = usage
+        .iter()
+        .map(|u| u.module.len())
+        .chain(["MODULE".len()])
+        .max()
+        .unwrap_or_default();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:<width$}  {:>7}  {:>21}  {:>12}  {:>12}",
+        "MODULE", "CPU %", "MEMORY", "NET RX/s", "NET TX/s"
+    );
+    let rate = |rate: Option<f64>| rate.map(bytes).unwrap_or_else(|| "-".into());
+    for u in usage {
+        match &u.stats {
+            Some(stats) => {
+                let memory = format!(
+                    "{} / {}",
+                    bytes(stats.memory_usage_bytes as f64),
+                    bytes(stats.memory_limit_bytes as f64)
+                );
+                let _ = writeln!(
+                    out,
+                    "{:<width$}  {:>7.1}  {:>21}  {:>12}  {:>12}",
+                    u.module,
+                    stats.cpu_percent,
+                    memory,
+                    rate(u.network_rx_rate),
+                    rate(u.network_tx_rate)
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    out,
+                    "{:<width$}  stats unavailable: {}",
+                    u.module,
+                    u.error.as_deref().unwrap_or("unknown error")
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use synapse_registrar::container::ContainerStats;
+
+    use super::*;
+
+    #[test]
+    fn test_render_marks_unavailable_modules() {
+        let usage = [
+            ModuleUsage {
+                module: "echo".into(),
+                stats: Some(ContainerStats {
+                    cpu_percent: 12.5,
+                    memory_usage_bytes: 512 * 1024 * 1024,
+                    memory_limit_bytes: 2 * 1024 * 1024 * 1024,
+                    network_rx_bytes: 0,
+                    network_tx_bytes: 0,
+                }),
+                network_rx_rate: Some(1536.0),
+                network_tx_rate: None,
+                error: None,
+            },
+            ModuleUsage {
+                module: "gone".into(),
+                stats: None,
+                network_rx_rate: None,
+                network_tx_rate: None,
+                error: Some("Container not found: gone".into()),
+            },
+        ];
+
+        let table = render(&usage);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("echo"));
+        assert!(lines[1].contains("12.5"));
+        assert!(lines[1].contains("512.0 MiB / 2.0 GiB"));
+        assert!(lines[1].contains("1.5 KiB"));
+        assert_eq!(
+            lines[2],
+            "gone    stats unavailable: Container not found: gone"
+        );
+    }
+}
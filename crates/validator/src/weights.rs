@@ -0,0 +1,216 @@
+//! Setting miners' weights on chain once per epoch.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use synapse_chain_api::commune::{CommuneRpc, CommuneWeights};
+use synapse_chain_api::weights::{set_weights, WeightPayload, WeightsChain, WeightsError};
+use synapse_registrar::tasks::BackgroundTasks;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::response_log::ResponseLogger;
+
+/// Default length of an epoch, in seconds.
+pub const DEFAULT_EPOCH_SECS: u64 = 360;
+
+/// Default chain script weights are set through.
+pub const DEFAULT_CHAIN_SCRIPT: &str = "scripts/commune_rpc.py";
+
+/// Default Commune node the chain script connects to.
+pub const DEFAULT_NODE_URL: &str = "wss://api.communeai.net";
+
+/// When and with which key weights are set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeightSettingConfig {
+    /// Name of the funded key that pays for submitting weights; weights
+    /// are not set without one.
+    pub key: Option<String>,
+    /// Seconds between submissions.
+    pub epoch_secs: u64,
+    /// Chain script that signs and submits the weights.
+    pub script: PathBuf,
+    /// Node the chain script connects to.
+    pub node_url: String,
+}
+
+impl Default for WeightSettingConfig {
+    fn default() -> Self {
+        Self {
+            key: None,
+            epoch_secs: DEFAULT_EPOCH_SECS,
+            script: PathBuf::from(DEFAULT_CHAIN_SCRIPT),
+            node_url: DEFAULT_NODE_URL.into(),
+        }
+    }
+}
+
+impl WeightSettingConfig {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.epoch_secs == 0 {
+            return Err("epoch_secs must be positive".into());
+        }
+        Ok(())
+    }
+
+    /// The chain weights are set on, signing with the configured key;
+    /// `None` without one.
+    pub fn chain(&self) -> Option<CommuneWeights> {
+        let key = self.key.as_ref()?;
+        let rpc = CommuneRpc::new(&self.script, &self.node_url);
+        Some(CommuneWeights::new(rpc, key))
+    }
+}
+
+/// Scores miners from their recorded responses and sets their weights.
+pub struct WeightSetter {
+    chain: Arc<dyn WeightsChain>,
+    logger: Arc<dyn ResponseLogger>,
+    netuid: u16,
+    /// Miner name in the response log, by uid.
+    miners: BTreeMap<u16, String>,
+}
+
+impl WeightSetter {
+    pub fn new(
+        chain: Arc<dyn WeightsChain>,
+        logger: Arc<dyn ResponseLogger>,
+        netuid: u16,
+        miners: BTreeMap<u16, String>,
+    ) -> Self {
+        Self {
+            chain,
+            logger,
+            netuid,
+            miners,
+        }
+    }
+
+    /// Each miner's score: its success rate. Miners without recorded
+    /// responses are left out.
+    pub fn scores(&self) -> (Vec<u16>, Vec<f64>) {
+        self.miners
+            .iter()
+            .filter_map(|(uid, miner)| Some((*uid, self.logger.get_success_rate(miner)?)))
+            .unzip()
+    }
+
+    /// Sets weights from the current scores. Returns the payload
+    /// submitted, or `None` when no miner has a positive score.
+    pub async fn run_epoch(&self) -> Result<Option<WeightPayload>, WeightsError> {
+        let (uids, scores) = self.scores();
+        match set_weights(self.chain.as_ref(), self.netuid, &uids, &scores).await {
+            Ok(payload) => Ok(Some(payload)),
+            Err(WeightsError::AllZero) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Runs [`run_epoch`](Self::run_epoch) every `config.epoch_secs`,
+    /// starting one epoch from now, as part of `tasks`. Returns `None`
+    /// without starting when `config` names no funded key.
+    pub fn spawn(
+        self,
+        config: &WeightSettingConfig,
+        tasks: &BackgroundTasks,
+    ) -> Option<JoinHandle<()>> {
+        if config.key.is_none() {
+            tracing::warn!("No funded key configured; weights will not be set");
+            return None;
+        }
+        let epoch = Duration::from_secs(config.epoch_secs);
+        Some(tasks.spawn(async move {
+            let mut ticks = tokio::time::interval_at(Instant::now() + epoch, epoch);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                match self.run_epoch().await {
+                    Ok(Some(payload)) => tracing::info!(
+                        "Set weights on subnet {}: uids {:?}, weights {:?}",
+                        payload.netuid,
+                        payload.uids,
+                        payload.weights
+                    ),
+                    Ok(None) => tracing::info!("No miner has a positive score; weights not set"),
+                    Err(e) => tracing::error!("Failed to set weights: {}", e),
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+
+    use crate::response_log::DefaultResponseLogger;
+
+    struct MockChain {
+        submitted: mpsc::UnboundedSender<(Instant, WeightPayload)>,
+    }
+
+    #[async_trait]
+    impl WeightsChain for MockChain {
+        async fn current_weights(&self, _netuid: u16) -> Result<BTreeMap<u16, u16>, WeightsError> {
+            Ok(BTreeMap::new())
+        }
+
+        async fn submit_weights(&self, payload: &WeightPayload) -> Result<(), WeightsError> {
+            let _ = self.submitted.send((Instant::now(), payload.clone()));
+            Ok(())
+        }
+    }
+
+    fn setter(chain: MockChain) -> WeightSetter {
+        let logger = DefaultResponseLogger::new();
+        for success in [true, true, false, true] {
+            logger.log_response("alice", success);
+        }
+        logger.log_response("bob", true);
+        let miners = BTreeMap::from([
+            (1, "alice".to_string()),
+            (2, "bob".to_string()),
+            (3, "carol".to_string()),
+        ]);
+        WeightSetter::new(Arc::new(chain), Arc::new(logger), 7, miners)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_weights_are_submitted_once_per_epoch() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let tasks = BackgroundTasks::new();
+        let config = WeightSettingConfig {
+            key: Some("validator".into()),
+            epoch_secs: 60,
+            ..Default::default()
+        };
+        let start = Instant::now();
+
+        let handle = setter(MockChain { submitted: tx }).spawn(&config, &tasks);
+        assert!(handle.is_some());
+
+        for epoch in 1..=3 {
+            let (at, payload) = rx.recv().await.unwrap();
+            assert_eq!(at - start, Duration::from_secs(60 * epoch));
+            assert_eq!(payload.netuid, 7);
+            // Carol has no responses and gets no weight.
+            assert_eq!(payload.uids, vec![1, 2]);
+            assert_eq!(payload.weights, vec![49151, u16::MAX]);
+        }
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_loop_does_not_start_without_funded_key() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let handle = setter(MockChain { submitted: tx })
+            .spawn(&WeightSettingConfig::default(), &BackgroundTasks::new());
+        assert!(handle.is_none());
+    }
+}